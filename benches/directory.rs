@@ -0,0 +1,72 @@
+//! Compare saving a directory by serializing it whole on every insertion against appending the
+//! new entry after the others, run with `cargo bench --bench directory`
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    os::unix::fs::FileExt,
+    time::{Duration, Instant},
+};
+
+use ferrix::simple_ext4::types::Directory;
+
+const ENTRIES: u32 = 5000;
+/// Inode of the root directory, the parent of the benchmarked one
+const ROOT_INODE: u32 = 1;
+
+fn name(i: u32) -> OsString {
+    OsString::from(format!("file-{i:05}"))
+}
+
+/// Write the whole directory at the start of `file` after inserting each entry
+fn full_rewrite(file: &File) -> std::io::Result<Duration> {
+    let mut dir = Directory::new(ROOT_INODE);
+    let mut data = Vec::new();
+
+    let start = Instant::now();
+    for i in 0..ENTRIES {
+        dir.entries.insert(name(i), i + 2);
+        data.clear();
+        dir.serialize_into(&mut data)
+            .map_err(std::io::Error::other)?;
+        file.write_all_at(&data, 0)?;
+    }
+    Ok(start.elapsed())
+}
+
+/// Write only each new entry after the others and the header before them
+fn incremental(file: &File) -> std::io::Result<Duration> {
+    let mut dir = Directory::new(ROOT_INODE);
+    let mut data = Vec::new();
+    dir.serialize_into(&mut data)
+        .map_err(std::io::Error::other)?;
+    file.write_all_at(&data, 0)?;
+    let mut end = data.len() as u64;
+
+    let start = Instant::now();
+    for i in 0..ENTRIES {
+        let (entry, header) = dir
+            .append(&name(i), i + 2)
+            .map_err(std::io::Error::other)?
+            .expect("the directory was just written");
+        file.write_all_at(&entry, end)?;
+        end += entry.len() as u64;
+        file.write_all_at(&bincode::serialize(&header).unwrap(), 0)?;
+    }
+    Ok(start.elapsed())
+}
+
+fn main() -> std::io::Result<()> {
+    let full = full_rewrite(&tempfile::tempfile()?)?;
+    let appended = incremental(&tempfile::tempfile()?)?;
+    println!(
+        "{:>12}: inserted {ENTRIES} entries in {full:?}",
+        "full rewrite"
+    );
+    println!(
+        "{:>12}: inserted {ENTRIES} entries in {appended:?}",
+        "incremental"
+    );
+
+    Ok(())
+}
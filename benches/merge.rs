@@ -0,0 +1,94 @@
+//! Compare the external merge reading one element at a time from each run against reading ahead
+//! a batch per run, run with `cargo bench --bench merge`
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, Write},
+    time::{Duration, Instant},
+};
+
+use ferrix::{ext_arr::ExtArr, sort::ExtSorter};
+use rand::Rng;
+
+const RUNS: usize = 64;
+const RUN_LEN: usize = 1 << 16;
+const BUFFERED_SIZE: usize = 8 << 20;
+
+/// Count the number of reads that reach the file
+struct CountingReader {
+    file: File,
+    reads: usize,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reads += 1;
+        self.file.read(buf)
+    }
+}
+
+impl Write for CountingReader {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn make_runs() -> std::io::Result<Vec<File>> {
+    let mut rng = rand::rng();
+
+    (0..RUNS)
+        .map(|_| {
+            let mut run: Vec<u64> = (0..RUN_LEN).map(|_| rng.random()).collect();
+            run.sort_unstable();
+
+            let mut file = tempfile::tempfile()?;
+            file.write_all(bytemuck::cast_slice(&run))?;
+            Ok(file)
+        })
+        .collect()
+}
+
+fn merge(runs: &[File], buf: &mut [u8]) -> std::io::Result<(Duration, usize)> {
+    let mut sources = runs
+        .iter()
+        .map(|run| {
+            let mut file = run.try_clone()?;
+            file.rewind()?;
+            Ok(ExtArr::<u64, _>::new(CountingReader { file, reads: 0 }))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut output = ExtArr::<u64, _>::new(BufWriter::new(tempfile::tempfile()?));
+
+    let start = Instant::now();
+    ExtSorter::merge_chunks(buf, &mut output, sources.iter_mut())?;
+    let elapsed = start.elapsed();
+
+    let reads = sources
+        .into_iter()
+        .map(|source| source.into_inner().reads)
+        .sum();
+    Ok((elapsed, reads))
+}
+
+fn main() -> std::io::Result<()> {
+    let runs = make_runs()?;
+    let inputs = [
+        ("one at a time", std::mem::size_of::<u64>()),
+        ("buffered", BUFFERED_SIZE),
+    ];
+
+    for (name, buf_size) in inputs {
+        let mut buf = vec![0u8; buf_size];
+        let (elapsed, reads) = merge(&runs, &mut buf)?;
+        println!(
+            "{name:>13}: merged {} elements in {elapsed:?} with {reads} reads",
+            RUNS * RUN_LEN
+        );
+    }
+
+    Ok(())
+}
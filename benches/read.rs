@@ -0,0 +1,62 @@
+//! Compare reading an external array through a buffered reader against a memory-mapped file, run
+//! with `cargo bench --bench read`
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    time::{Duration, Instant},
+};
+
+use ferrix::ext_arr::{ExtArr, MmapRO};
+use rand::Rng;
+
+const LEN: usize = 1 << 23;
+const READ_SIZES: [usize; 3] = [std::mem::size_of::<u64>(), 4 << 10, 2 << 20];
+
+fn make_file() -> std::io::Result<tempfile::NamedTempFile> {
+    let mut rng = rand::rng();
+    let numbers: Vec<u64> = (0..LEN).map(|_| rng.random()).collect();
+
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(bytemuck::cast_slice(&numbers))?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// Read the whole array `read_size` bytes at a time, returning the sum so the reads aren't
+/// optimized away
+fn read_all<R: Read>(
+    mut arr: ExtArr<u64, R>,
+    read_size: usize,
+) -> std::io::Result<(Duration, u64)> {
+    let mut buf = vec![0u8; read_size];
+    let mut sum = 0u64;
+
+    let start = Instant::now();
+    loop {
+        let read = arr.read(&mut buf)?;
+        if read.is_empty() {
+            break;
+        }
+        sum = read.iter().fold(sum, |sum, n| sum.wrapping_add(*n));
+    }
+    Ok((start.elapsed(), sum))
+}
+
+fn main() -> std::io::Result<()> {
+    let file = make_file()?;
+
+    for read_size in READ_SIZES {
+        let buffered = ExtArr::new(BufReader::new(File::open(file.path())?));
+        let (buffered_elapsed, buffered_sum) = read_all(buffered, read_size)?;
+        let mapped = ExtArr::new(MmapRO::new(file.path())?);
+        let (mapped_elapsed, mapped_sum) = read_all(mapped, read_size)?;
+        assert_eq!(buffered_sum, mapped_sum);
+
+        println!(
+            "{read_size:>8} bytes per read: buffered {buffered_elapsed:?}, mmap {mapped_elapsed:?} for {LEN} elements"
+        );
+    }
+
+    Ok(())
+}
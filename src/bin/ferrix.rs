@@ -1,23 +1,32 @@
+use anyhow::Result;
 use clap::Parser;
 use ferrix::{
     cli::FerrixCLI,
     fs::BasicFS,
-    repl_v2::{FerrixPromptSegment, ReplV2},
+    repl_v2::{FerrixPromptSegment, ReplV2, ReplV2Options},
     vdisk::VDisk,
 };
-use anyhow::Result;
 
 fn main() -> Result<()> {
     let cli = FerrixCLI::parse();
 
     let vdisk = VDisk::new(cli.vdisk_path, cli.size_in_bytes)?;
 
-    let basic_fs = BasicFS::new(vdisk);
+    let basic_fs = BasicFS::new(vdisk)?;
 
     let mut system = ferrix::system::BasicSystem::new(basic_fs);
     let segment = FerrixPromptSegment::WorkingDirectory;
 
-    ReplV2::run(&mut system, segment)?;
+    let options = ReplV2Options {
+        history_search_key: cli.history_search_key,
+        cwd_state_file: cli.cwd_state_file,
+        alias_file: cli.alias_file,
+        quiet: cli.quiet,
+        remove_confirm_threshold: cli.remove_confirm_threshold,
+        command: cli.command,
+    };
+
+    let status = ReplV2::run(&mut system, segment, options)?;
 
-    Ok(())
+    std::process::exit(status)
 }
@@ -1,53 +1,93 @@
-use std::sync::mpsc;
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
 use std::{path::PathBuf, thread};
 
 use anyhow::Result;
 use clap::Parser;
 use ferrix::complete_command::MakeDirCommand;
+use ferrix::simple_ext4::mkfs;
 use ferrix::system::System;
 use ferrix::vdisk::VDisk;
 use ferrix::{
-    cli::FerrixCLI,
-    repl_v2::{FerrixPromptSegment, ReplV2},
+    cli::{FerrixCLI, FsType},
+    repl_v2::{FerrixPromptSegment, ReplV2, ReplV2Options},
 };
-use fuser::{MountOption, Session};
+use fuser::{Filesystem, MountOption, Session, SessionUnmounter};
 use tracing::{info, Level};
 
+/// Mount the filesystem on a new thread, sending back the handle to unmount it
+fn spawn_session<FS>(
+    fs: FS,
+    mount_point: PathBuf,
+    sender: Sender<SessionUnmounter>,
+) -> JoinHandle<std::io::Result<()>>
+where
+    FS: Filesystem + Send + 'static,
+{
+    thread::spawn(move || {
+        let options = vec![MountOption::FSName("flemis".to_string())];
+        let mut session = Session::new(fs, &mount_point, &options).unwrap();
+        let session_end = session.unmount_callable();
+        sender.send(session_end).expect("failed to send");
+        session.run()
+    })
+}
+
 fn main() -> Result<()> {
     let cli = FerrixCLI::parse();
 
-    let storage = "/tmp/storage/";
-    if !cli.vdisk_path.exists() {
-        std::fs::remove_dir_all(storage)?;
-        VDisk::new(cli.vdisk_path.clone(), cli.size_in_bytes)?;
-    };
+    match cli.fs_type {
+        FsType::Flat => {
+            if cli.reset && cli.storage_dir.exists() {
+                std::fs::remove_dir_all(&cli.storage_dir)?;
+            }
+            if !cli.vdisk_path.exists() {
+                VDisk::new(cli.vdisk_path.clone(), cli.size_in_bytes)?;
+            };
+        }
+        FsType::Ext4 => {
+            mkfs::validate(&cli.vdisk_path)?;
+        }
+    }
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
 
-    let mount_point = PathBuf::from("/tmp/flemisfs");
-    let mount2 = mount_point.clone();
+    // Prepares the mount point, so it must happen before mounting over it
+    let mut system =
+        ferrix::simple_ext4::flemis_system::FlemisSystem::new(cli.mount_point.clone())?;
+    let mount_point = cli.mount_point.clone();
 
     let (sender, receiver) = mpsc::channel();
-    thread::spawn(move || {
-        let options = vec![MountOption::FSName("flemis".to_string())];
-        let fs = ferrix::simple_ext4::fs_in_fs::FSInFS::new(
-            "/tmp/storage".into(),
-            true,
-            false,
-            cli.block_size.into(),
-        );
-        // let fs = ferrix::simple_ext4::fs::SimpleExt4FS::new(&cli.vdisk_path).unwrap();
-        let mut session = Session::new(fs, &mount_point, &options).unwrap();
-        let session_end = session.unmount_callable();
-        sender.send(session_end).expect("failed to send");
-        session.run()
-    });
-    let mut system = ferrix::simple_ext4::flemis_system::FlemisSystem::new(mount2)?;
+    match cli.fs_type {
+        FsType::Flat => {
+            let fs = ferrix::simple_ext4::fs_in_fs::FSInFS::new(
+                cli.storage_dir.to_string_lossy().into_owned(),
+                true,
+                false,
+                cli.block_size.into(),
+            );
+            spawn_session(fs, mount_point, sender);
+        }
+        FsType::Ext4 => {
+            let fs = ferrix::simple_ext4::fs::SimpleExt4FS::new(&cli.vdisk_path)?;
+            spawn_session(fs, mount_point, sender);
+        }
+    }
     let segment = FerrixPromptSegment::WorkingDirectory;
 
-    ReplV2::run(&mut system, segment)?;
+    let options = ReplV2Options {
+        history_search_key: cli.history_search_key,
+        cwd_state_file: cli.cwd_state_file,
+        alias_file: cli.alias_file,
+        quiet: cli.quiet,
+        remove_confirm_threshold: cli.remove_confirm_threshold,
+        command: cli.command,
+    };
+
+    let status = ReplV2::run(&mut system, segment, options)?;
 
     let unmount = receiver.recv();
     unmount?.unmount()?;
 
-    Ok(())
+    // Exiting only once unmounted, so a failing script does not leave the mount behind
+    std::process::exit(status)
 }
@@ -1,8 +1,24 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use crate::{simple_ext4::DEFAULT_BLOCK_SIZE, vdisk::DEFAULT_SIZE_IN_BYTES};
+use crate::{
+    repl_v2::{DEFAULT_HISTORY_SEARCH_KEY, DEFAULT_REMOVE_CONFIRM_THRESHOLD},
+    simple_ext4::DEFAULT_BLOCK_SIZE,
+    vdisk::DEFAULT_SIZE_IN_BYTES,
+};
+
+pub const DEFAULT_STORAGE_DIR: &str = "/tmp/storage";
+pub const DEFAULT_MOUNT_POINT: &str = "/tmp/flemisfs";
+
+/// The filesystem implementation backing the mount
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FsType {
+    /// Passthrough filesystem storing every file in a host directory
+    Flat,
+    /// Native ext4 like filesystem stored in the virtual disk, which has to be formatted by mkfs
+    Ext4,
+}
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -18,4 +34,84 @@ pub struct FerrixCLI {
     /// Block size
     #[arg(short, long, default_value_t = DEFAULT_BLOCK_SIZE)]
     pub block_size: u32,
+
+    /// The filesystem implementation backing the mount
+    #[arg(long, value_enum, default_value_t = FsType::Flat)]
+    pub fs_type: FsType,
+
+    /// Directory where the flat filesystem stores its inodes and file contents
+    #[arg(long, default_value = DEFAULT_STORAGE_DIR)]
+    pub storage_dir: PathBuf,
+
+    /// Directory where the filesystem is mounted
+    #[arg(long, default_value = DEFAULT_MOUNT_POINT)]
+    pub mount_point: PathBuf,
+
+    /// Wipe the storage directory before mounting the flat filesystem
+    #[arg(long)]
+    pub reset: bool,
+
+    /// Key that, pressed together with Ctrl, starts a reverse search through the history
+    #[arg(long, default_value_t = DEFAULT_HISTORY_SEARCH_KEY)]
+    pub history_search_key: char,
+
+    /// File to save the working directory to on exit and restore it from on startup
+    #[arg(long)]
+    pub cwd_state_file: Option<PathBuf>,
+
+    /// File to load the aliases from on startup and save them to whenever they change
+    #[arg(long)]
+    pub alias_file: Option<PathBuf>,
+
+    /// Print only the message of a failed command, without the `Error <action>:` prefix
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Number of entries `rm` removes without asking for confirmation on a terminal
+    #[arg(long, default_value_t = DEFAULT_REMOVE_CONFIRM_THRESHOLD)]
+    pub remove_confirm_threshold: usize,
+
+    /// Run a single command line and exit with its status instead of starting the REPL, so `-`
+    /// can read piped data from the standard input
+    #[arg(short = 'c', long)]
+    pub command: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_paths() {
+        // Act
+        let cli = FerrixCLI::try_parse_from(["ferrix"]).unwrap();
+
+        // Assert
+        assert_eq!(cli.storage_dir, PathBuf::from(DEFAULT_STORAGE_DIR));
+        assert_eq!(cli.mount_point, PathBuf::from(DEFAULT_MOUNT_POINT));
+        assert!(!cli.reset);
+        assert_eq!(cli.history_search_key, DEFAULT_HISTORY_SEARCH_KEY);
+        assert_eq!(cli.cwd_state_file, None);
+        assert_eq!(cli.alias_file, None);
+        assert!(!cli.quiet);
+    }
+
+    #[test]
+    fn flags_override_default_paths() {
+        // Act
+        let cli = FerrixCLI::try_parse_from([
+            "ferrix",
+            "--storage-dir",
+            "/srv/storage",
+            "--mount-point",
+            "/mnt/ferrix",
+            "--reset",
+        ])
+        .unwrap();
+
+        // Assert
+        assert_eq!(cli.storage_dir, PathBuf::from("/srv/storage"));
+        assert_eq!(cli.mount_point, PathBuf::from("/mnt/ferrix"));
+        assert!(cli.reset);
+    }
 }
@@ -1,14 +1,159 @@
 use std::ffi::OsString;
+use std::num::NonZero;
+use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+use crate::{simple_ext4::DEFAULT_BLOCK_SIZE, vdisk::DEFAULT_SIZE_IN_BYTES};
+
+/// The kind of node kept by a `--type` filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NodeType {
+    /// Regular files
+    #[value(name = "f")]
+    File,
+    /// Directories
+    #[value(name = "d")]
+    Directory,
+}
+
+impl NodeType {
+    /// Returns true if a node, a directory or not, is of this type
+    pub fn matches(self, is_dir: bool) -> bool {
+        match self {
+            NodeType::File => !is_dir,
+            NodeType::Directory => is_dir,
+        }
+    }
+}
+
+/// Number of integers `head` reads when not given an end
+pub const DEFAULT_HEAD_END: u32 = 10;
+
+/// The base integers read by `head` and `tail` are printed in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NumberFormat {
+    /// Decimal, as the integers are written by hand
+    #[default]
+    Dec,
+    /// Hexadecimal with a `0x` prefix
+    Hex,
+    /// Binary with a `0b` prefix
+    Bin,
+}
+
+/// How the integers written by `touch` are generated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Distribution {
+    /// Every value equally likely
+    #[default]
+    Uniform,
+    /// Gaussian around the middle of the range, clamped to it
+    Normal,
+    /// Uniform values in ascending order, so values may repeat
+    Sorted,
+    /// Uniform values in descending order, so values may repeat
+    Reversed,
+}
+
+/// Standard deviation of the normal distribution, a sixth of the range so nearly every value
+/// falls in it before clamping
+pub const DEFAULT_STD_DEV: f64 = crate::system::Number::MAX as f64 / 6.0;
+
+/// Parse a `--std-dev`, which must be finite and positive
+pub fn parse_std_dev(std_dev: &str) -> Result<f64, String> {
+    let value: f64 = std_dev
+        .parse()
+        .map_err(|err| format!("{std_dev:?} is not a number: {err}"))?;
+
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("{std_dev:?} must be a finite positive number"));
+    }
+
+    Ok(value)
+}
+
+/// A `+N`, `-N` or `N` bound of a `find` predicate, matching values more than, less than or
+/// exactly `N`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    MoreThan(u64),
+    LessThan(u64),
+    Exactly(u64),
+}
+
+impl Bound {
+    pub fn matches(self, value: u64) -> bool {
+        match self {
+            Bound::MoreThan(bound) => value > bound,
+            Bound::LessThan(bound) => value < bound,
+            Bound::Exactly(bound) => value == bound,
+        }
+    }
+
+    /// Parse a bound whose number may end with one of the `units` suffixes, multiplying it by
+    /// the suffix factor or by `default_unit` without one
+    fn parse(bound: &str, units: &[(char, u64)], default_unit: u64) -> Result<Self, String> {
+        let (make, rest): (fn(u64) -> Self, _) = match bound.as_bytes().first() {
+            Some(b'+') => (Bound::MoreThan, &bound[1..]),
+            Some(b'-') => (Bound::LessThan, &bound[1..]),
+            _ => (Bound::Exactly, bound),
+        };
+
+        let (number, unit) = match units.iter().find(|(suffix, _)| rest.ends_with(*suffix)) {
+            Some((suffix, unit)) => (&rest[..rest.len() - suffix.len_utf8()], *unit),
+            None => (rest, default_unit),
+        };
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid number in {bound:?}"))?;
+        let value = number
+            .checked_mul(unit)
+            .ok_or_else(|| format!("{bound:?} is too large"))?;
+
+        Ok(make(value))
+    }
+}
+
+/// Parse a `--size` bound in bytes, with an optional `K`, `M` or `G` binary suffix
+pub fn parse_size_bound(bound: &str) -> Result<Bound, String> {
+    Bound::parse(bound, &[('K', 1 << 10), ('M', 1 << 20), ('G', 1 << 30)], 1)
+}
+
+/// Parse an `--mtime` bound in seconds, given in days or with an `s`, `m`, `h` or `d` suffix
+pub fn parse_age_bound(bound: &str) -> Result<Bound, String> {
+    const DAY: u64 = 24 * 60 * 60;
+    Bound::parse(
+        bound,
+        &[('s', 1), ('m', 60), ('h', 60 * 60), ('d', DAY)],
+        DAY,
+    )
+}
 
 #[derive(Debug, Parser)]
 pub struct TouchCommand {
-    /// The file to create
-    pub file: OsString,
+    /// The files to create
+    #[arg(required = true)]
+    pub files: Vec<OsString>,
     /// The number of integers to write to the file
     #[arg(short, long)]
     pub number_of_integers: u32,
+    /// Show a progress bar while writing, only when running in a terminal
+    #[arg(long)]
+    pub progress: bool,
+    /// Keep creating the remaining files after one fails, reporting every failure at the end
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Write the integers without the leading length header, their length is given
+    /// by the file size
+    #[arg(long, visible_alias = "no-header")]
+    pub raw: bool,
+    /// How the integers are generated, sorted and reversed order uniform values
+    #[arg(long, value_enum, default_value_t)]
+    pub dist: Distribution,
+    /// Standard deviation of the normal distribution
+    #[arg(long, default_value_t = DEFAULT_STD_DEV, value_parser = parse_std_dev)]
+    pub std_dev: f64,
 }
 
 #[derive(Debug, Parser)]
@@ -17,6 +162,34 @@ pub struct MoveCommand {
     pub from: OsString,
     /// The destination of the file
     pub to: OsString,
+    /// If true, print each action performed
+    #[arg(short, long)]
+    pub verbose: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct CopyCommand {
+    /// The file to copy
+    pub from: OsString,
+    /// The destination of the copy
+    pub to: OsString,
+    /// If true, print each action performed
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Give the copy the mode, owner and access and modification times of the file
+    #[arg(short, long)]
+    pub preserve: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct LinkCommand {
+    /// The file the link points to, a relative symbolic link target is followed from the link
+    pub target: OsString,
+    /// The link to create
+    pub link_name: OsString,
+    /// Create a symbolic link instead of a hard link
+    #[arg(short, long)]
+    pub symbolic: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -26,15 +199,35 @@ pub struct MakeDirCommand {
     /// Create all parent directories if they don't exist
     #[arg(short, long)]
     pub parents: bool,
+    /// If true, print each created directory
+    #[arg(short, long)]
+    pub verbose: bool,
 }
 
 #[derive(Debug, Parser)]
 pub struct RemoveCommand {
-    /// The file or path to remove
-    pub file_or_dir: OsString,
+    /// The files or directories to remove
+    #[arg(required = true)]
+    pub files_or_dirs: Vec<OsString>,
     /// If true, remove all files in the directory
     #[arg(short, long)]
     pub recursive: bool,
+    /// If true, print each removed file and directory
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Move the file or directory into the trash instead of deleting it
+    #[arg(long)]
+    pub trash: bool,
+    /// Never ask for confirmation, however many entries are removed
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct EmptyTrashCommand {
+    /// If true, print each permanently removed file and directory
+    #[arg(short, long)]
+    pub verbose: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -45,8 +238,45 @@ pub struct HeadCommand {
     #[arg(short, long, default_value = "0")]
     pub start: u32,
     /// The amount of lines to read
-    #[arg(short, long, default_value = "10")]
+    #[arg(short, long, default_value_t = DEFAULT_HEAD_END)]
     pub end: u32,
+    /// Read the first bytes of the file as they are stored, header included, instead of elements
+    #[arg(long, conflicts_with_all = ["start", "end"])]
+    pub bytes: Option<u64>,
+    /// Print the bytes read with `--bytes` as hex instead of decimal integers
+    #[arg(long, requires = "bytes")]
+    pub hex: bool,
+    /// The base the integers are printed in
+    #[arg(long, value_enum, default_value_t, conflicts_with = "bytes")]
+    pub format: NumberFormat,
+    /// Read the integers without the leading length header, their length is given
+    /// by the file size
+    #[arg(long, visible_alias = "no-header")]
+    pub raw: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct TailCommand {
+    /// The file to read
+    pub file: OsString,
+    /// The amount of lines to read from the end
+    #[arg(short = 'n', long, default_value = "10")]
+    pub lines: u32,
+    /// Output the lines in reverse order, the last line first
+    #[arg(short, long)]
+    pub reverse: bool,
+    /// Read the last bytes of the file as they are stored instead of elements
+    #[arg(long, conflicts_with_all = ["lines", "reverse"])]
+    pub bytes: Option<u64>,
+    /// Print the bytes read with `--bytes` as hex instead of decimal integers
+    #[arg(long, requires = "bytes")]
+    pub hex: bool,
+    /// The base the integers are printed in
+    #[arg(long, value_enum, default_value_t, conflicts_with = "bytes")]
+    pub format: NumberFormat,
+    /// Keep printing the values appended to the file as it grows
+    #[arg(short, long, conflicts_with_all = ["reverse", "bytes"])]
+    pub follow: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -56,6 +286,15 @@ pub struct ListCommand {
     /// If true, list all files including hidden files
     #[arg(short, long)]
     pub all: bool,
+    /// Separate the listed names with NUL bytes instead of rendering a table
+    #[arg(short = '0', long)]
+    pub null: bool,
+    /// Output the listing as JSON instead of rendering a table
+    #[arg(long)]
+    pub json: bool,
+    /// Only list regular files (f) or directories (d)
+    #[arg(long = "type", value_enum)]
+    pub node_type: Option<NodeType>,
 }
 
 #[derive(Debug, Parser)]
@@ -64,6 +303,81 @@ pub struct ChangeDirCommand {
     pub path: Option<OsString>,
 }
 
+#[derive(Debug, Parser)]
+pub struct PrintWorkingDirCommand {}
+
+#[derive(Debug, Parser)]
+pub struct HandlesCommand {}
+
+/// The structure of the mounted file system `debug` prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DebugTarget {
+    /// The inode of a file or directory
+    Inode,
+    /// The superblock of the file system
+    Superblock,
+}
+
+#[derive(Debug, Parser)]
+pub struct DebugCommand {
+    /// What to print
+    #[arg(value_enum)]
+    pub target: DebugTarget,
+    /// The file or directory to print the inode of
+    #[arg(required_if_eq("target", "inode"))]
+    pub path: Option<OsString>,
+}
+
+#[derive(Debug, Parser)]
+pub struct HistoryCommand {
+    /// The amount of recent commands to print, every command if omitted
+    pub count: Option<usize>,
+    /// Forget every command instead of printing them
+    #[arg(short, long, conflicts_with = "count")]
+    pub clear: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct MakeFsCommand {
+    /// The path of the new image on the host, which must not exist yet. A relative path is taken
+    /// from the directory ferrix was launched from
+    pub image: PathBuf,
+    /// Size of the image in bytes, rounded up to a whole number of block groups
+    #[arg(short, long, default_value_t = DEFAULT_SIZE_IN_BYTES.into())]
+    pub size: u64,
+    /// Size of a block in bytes, a power of two
+    #[arg(short, long, default_value_t = DEFAULT_BLOCK_SIZE)]
+    pub block_size: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct UseCommand {
+    /// The name of the mount to switch to
+    pub name: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct MountCommand {
+    /// The name paths refer to the mount by, as in `use name` or `@name:/path`
+    pub name: String,
+    /// Where the file system is mounted on the host, relative to the directory ferrix was
+    /// launched from
+    pub mount_point: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct AliasCommand {
+    /// The alias to define, as `name = command`, every alias is listed if omitted
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub definition: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct UnaliasCommand {
+    /// The name of the alias to remove
+    pub name: String,
+}
+
 #[derive(Debug, Parser)]
 pub struct SortCommand {
     /// The file to sort
@@ -71,6 +385,21 @@ pub struct SortCommand {
     /// If true, sort the file in reverse order
     #[arg(short, long)]
     pub inverse_order: bool,
+    /// Only check that the file is already sorted, reporting the first element out of order
+    #[arg(short, long)]
+    pub check: bool,
+    /// Read and write the integers without the leading length header, their length is given
+    /// by the file size
+    #[arg(long, visible_alias = "no-header")]
+    pub raw: bool,
+    /// Number of integers sorted at once into each run, the whole sort memory if unset. Smaller
+    /// chunks make more runs to merge but each one is cheaper to sort
+    #[arg(long)]
+    pub chunk_size: Option<NonZero<usize>>,
+    /// Number of threads sorting runs at once, each with an even share of the sort memory. A
+    /// single thread if unset
+    #[arg(short, long)]
+    pub workers: Option<NonZero<usize>>,
 }
 
 #[derive(Debug, Parser)]
@@ -81,6 +410,115 @@ pub struct CatCommand {
     /// The output file to write the concatenated content to
     #[arg(short, long)]
     pub output_file: Option<OsString>,
+    /// Keep the integers already in the output file and add the concatenated ones after them
+    #[arg(
+        short,
+        long,
+        requires = "output_file",
+        conflicts_with_all = ["interleave", "sorted_merge"]
+    )]
+    pub append: bool,
+    /// Take one element from each file in turn instead of appending the files one after another
+    #[arg(long, conflicts_with = "sorted_merge")]
+    pub interleave: bool,
+    /// Merge files that are already sorted into a single sorted file
+    #[arg(long)]
+    pub sorted_merge: bool,
+    /// Merge files sorted in descending order, as written by `sort --reverse`, into a single
+    /// descending file
+    #[arg(short, long, requires = "sorted_merge")]
+    pub reverse: bool,
+    /// Show a progress bar while writing, only when running in a terminal
+    #[arg(long)]
+    pub progress: bool,
+    /// Read and write the integers without the leading length header, their length is given
+    /// by the file size
+    #[arg(long, visible_alias = "no-header")]
+    pub raw: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct StatCommand {
+    /// The file or directory to display the status of
+    pub file: OsString,
+    /// Use the specified format instead of the default, supporting %s (size), %b (blocks),
+    /// %a (octal mode), %U (uid), %G (gid), %Y (mtime epoch) and %n (name)
+    #[arg(short = 'c', long)]
+    pub format: Option<String>,
+    /// Output the status as JSON instead of the format
+    #[arg(long, conflicts_with = "format")]
+    pub json: bool,
+    /// Display the status of a symbolic link itself instead of the file it points to
+    #[arg(short = 'L', long)]
+    pub no_follow: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct TestCommand {
+    /// The path to check
+    pub path: OsString,
+    /// True if the path exists, the default check
+    #[arg(short = 'e', conflicts_with_all = ["file", "dir"])]
+    pub exists: bool,
+    /// True if the path is a regular file
+    #[arg(short = 'f', conflicts_with = "dir")]
+    pub file: bool,
+    /// True if the path is a directory
+    #[arg(short = 'd')]
+    pub dir: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct FindCommand {
+    /// The directory to search in
+    pub dir: OsString,
+    /// The name of the entries to find, every entry matching the other predicates if omitted
+    pub name: Option<String>,
+    /// Separate the found paths with NUL bytes instead of newlines
+    #[arg(short = '0', long)]
+    pub null: bool,
+    /// Only find regular files (f) or directories (d)
+    #[arg(long = "type", value_enum)]
+    pub node_type: Option<NodeType>,
+    /// Only find entries of more (+N), less (-N) or exactly N bytes, with an optional K, M or G
+    /// suffix
+    #[arg(long, value_parser = parse_size_bound, allow_hyphen_values = true)]
+    pub size: Option<Bound>,
+    /// Only find entries last modified more (+N), less (-N) or exactly N days ago, or in the
+    /// unit of an s, m, h or d suffix
+    #[arg(long, value_parser = parse_age_bound, allow_hyphen_values = true)]
+    pub mtime: Option<Bound>,
+    /// Only find entries modified more recently than this file
+    #[arg(long)]
+    pub newer: Option<OsString>,
+    /// Go down the directories symbolic links point to, each directory is searched only once
+    #[arg(short = 'L', long)]
+    pub follow: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct DiffCommand {
+    /// The first file to compare
+    pub left: OsString,
+    /// The second file to compare
+    pub right: OsString,
+}
+
+#[derive(Debug, Parser)]
+pub struct WordCountCommand {
+    /// The files to count the integers of
+    #[arg(required = true)]
+    pub files: Vec<OsString>,
+}
+
+#[derive(Debug, Parser)]
+pub struct DiskFreeCommand {
+    /// Output the disk usage as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Report the inode usage instead of the block usage
+    #[arg(short, long)]
+    pub inodes: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -97,14 +535,24 @@ pub enum CompleteCommand {
     /// Move a file from one location to another
     #[command(name = "mv")]
     Move(MoveCommand),
+    /// Copy a file to another location, keeping the original
+    #[command(name = "cp")]
+    Copy(CopyCommand),
+    /// Create a hard or symbolic link to a file
+    #[command(name = "ln")]
+    Link(LinkCommand),
     /// Create a new directory
     #[command(name = "mkdir")]
     MakeDir(MakeDirCommand),
     /// Remove a given file from the ferrix fs
     #[command(name = "rm")]
     Remove(RemoveCommand),
+    /// Permanently remove every file and directory in the trash
+    EmptyTrash(EmptyTrashCommand),
     /// Read the content of a file and output it to stdout
     Head(HeadCommand),
+    /// Read the last lines of a file and output them to stdout
+    Tail(TailCommand),
     /// List directory contents with each file and dir with their size on the right size and system
     /// storage info at the bottom
     #[command(name = "ls")]
@@ -114,9 +562,263 @@ pub enum CompleteCommand {
     /// Concat a given list of files into a stream and output it's content to a output file or
     /// fd
     Cat(CatCommand),
+    /// Display the status of a file or directory
+    Stat(StatCommand),
+    /// Check that a path exists or is of a given type, failing the command if not
+    Test(TestCommand),
+    /// Recursively search a directory for entries with a given name
+    Find(FindCommand),
+    /// Compare two integer files and report the first difference
+    Diff(DiffCommand),
+    /// Count the integers of each given file and their total
+    #[command(name = "wc")]
+    WordCount(WordCountCommand),
+    /// Report the disk space usage of the file system
+    #[command(name = "df")]
+    DiskFree(DiskFreeCommand),
     /// Exit the ferrix repl
     Exit(ExitCommand),
     /// Change the current working directory
     #[command(name = "cd")]
     ChangeDir(ChangeDirCommand),
+    /// Print the absolute path of the current working directory
+    #[command(name = "pwd")]
+    PrintWorkingDir(PrintWorkingDirCommand),
+    /// Switch to another mount, later paths without a mount name refer to it
+    Use(UseCommand),
+    /// Register a file system mounted on the host under a name
+    Mount(MountCommand),
+    /// Define an alias expanded before its command runs, or list every alias
+    Alias(AliasCommand),
+    /// Remove an alias
+    Unalias(UnaliasCommand),
+    /// List the open file handles of the mounted file system, for debugging handle leaks
+    Handles(HandlesCommand),
+    /// Print the inode of a file or the superblock of the mounted file system, for debugging
+    Debug(DebugCommand),
+    /// Print the recent commands with the index `!n` expands
+    History(HistoryCommand),
+    /// Format a new image for the ext4 like file system, to mount with `--fs-type ext4`
+    #[command(name = "mkfs")]
+    MakeFs(MakeFsCommand),
+}
+
+/// A command of a script parsed by the Winnow Ferrix parser, run with the defaults of the flags
+/// it has no syntax for
+impl From<crate::parser::CompleteCommand> for CompleteCommand {
+    fn from(command: crate::parser::CompleteCommand) -> Self {
+        use crate::parser::CompleteCommand as Parsed;
+
+        match command {
+            Parsed::Touch {
+                file,
+                number_of_integers,
+            } => Self::Touch(TouchCommand {
+                files: vec![file.into_os_string()],
+                number_of_integers,
+                progress: false,
+                keep_going: false,
+                raw: false,
+                dist: Distribution::Uniform,
+                std_dev: DEFAULT_STD_DEV,
+            }),
+            Parsed::Move { from, to } => Self::Move(MoveCommand {
+                from: from.into_os_string(),
+                to: to.into_os_string(),
+                verbose: false,
+            }),
+            Parsed::Copy { from, to } => Self::Copy(CopyCommand {
+                from: from.into_os_string(),
+                to: to.into_os_string(),
+                verbose: false,
+                preserve: false,
+            }),
+            Parsed::Link {
+                target,
+                link_name,
+                symbolic,
+            } => Self::Link(LinkCommand {
+                target: target.into_os_string(),
+                link_name: link_name.into_os_string(),
+                symbolic,
+            }),
+            Parsed::MkDir { dir, parents } => Self::MakeDir(MakeDirCommand {
+                dir: dir.into_os_string(),
+                parents,
+                verbose: false,
+            }),
+            Parsed::Remove { file, recursive } => Self::Remove(RemoveCommand {
+                files_or_dirs: vec![file.into_os_string()],
+                recursive,
+                verbose: false,
+                trash: false,
+                force: false,
+            }),
+            Parsed::Head { file, start, end } => Self::Head(HeadCommand {
+                file: file.into_os_string(),
+                start,
+                end,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: false,
+            }),
+            Parsed::List { dir, all } => Self::List(ListCommand {
+                dir: dir.map(PathBuf::into_os_string),
+                all,
+                null: false,
+                json: false,
+                node_type: None,
+            }),
+            Parsed::Sort {
+                file,
+                inverse_order,
+            } => Self::Sort(SortCommand {
+                file: file.into_os_string(),
+                inverse_order,
+                check: false,
+                raw: false,
+                chunk_size: None,
+                workers: None,
+            }),
+            Parsed::Cat {
+                files,
+                output_file,
+                append,
+            } => Self::Cat(CatCommand {
+                files: files.into_iter().map(PathBuf::into_os_string).collect(),
+                output_file: output_file.map(PathBuf::into_os_string),
+                append,
+                interleave: false,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: false,
+            }),
+            Parsed::Exit { code } => Self::Exit(ExitCommand { code }),
+            Parsed::ChangeDir { path } => Self::ChangeDir(ChangeDirCommand {
+                path: path.map(PathBuf::into_os_string),
+            }),
+            Parsed::Pwd => Self::PrintWorkingDir(PrintWorkingDirCommand {}),
+            Parsed::Find { dir, name } => Self::Find(FindCommand {
+                dir: dir.into_os_string(),
+                name: Some(name),
+                null: false,
+                node_type: None,
+                size: None,
+                mtime: None,
+                newer: None,
+                follow: false,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bounds() {
+        // Arrange
+        let inputs = ["+1K", "-512", "3M", "+2", "-10m", "1h", "+K", "-1x"];
+        let outputs = [
+            (Ok(Bound::MoreThan(1024)), Err(())),
+            (Ok(Bound::LessThan(512)), Ok(Bound::LessThan(512 * 86_400))),
+            (Ok(Bound::Exactly(3u64 << 20)), Err(())),
+            (Ok(Bound::MoreThan(2)), Ok(Bound::MoreThan(2 * 86_400))),
+            (Err(()), Ok(Bound::LessThan(600))),
+            (Err(()), Ok(Bound::Exactly(3600))),
+            (Err(()), Err(())),
+            (Err(()), Err(())),
+        ];
+
+        for (input, (size, age)) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let size_result = parse_size_bound(input).map_err(|_| ());
+            let age_result = parse_age_bound(input).map_err(|_| ());
+
+            // Assert
+            assert_eq!(size_result, *size, "{input}");
+            assert_eq!(age_result, *age, "{input}");
+        }
+    }
+
+    #[test]
+    fn find_accepts_negative_bounds() {
+        // Act
+        let cmd = FindCommand::try_parse_from(["find", "/", "--size", "-1K", "--mtime", "-2h"]);
+
+        // Assert
+        let cmd = cmd.unwrap();
+        assert_eq!(cmd.name, None);
+        assert_eq!(cmd.size, Some(Bound::LessThan(1024)));
+        assert_eq!(cmd.mtime, Some(Bound::LessThan(2 * 60 * 60)));
+    }
+
+    #[test]
+    fn debug_inode_requires_path() {
+        // Arrange
+        let inputs: [&[&str]; 3] = [
+            &["debug", "superblock"],
+            &["debug", "inode", "/a"],
+            &["debug", "inode"],
+        ];
+        let outputs = [
+            Some((DebugTarget::Superblock, None)),
+            Some((DebugTarget::Inode, Some(OsString::from("/a")))),
+            None,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs) {
+            // Act
+            let cmd = DebugCommand::try_parse_from(input.iter());
+
+            // Assert
+            assert_eq!(cmd.ok().map(|cmd| (cmd.target, cmd.path)), output);
+        }
+    }
+
+    #[test]
+    fn touch_distribution_args() {
+        // Arrange
+        let inputs: [&[&str]; 4] = [
+            &["touch", "-n", "3", "a"],
+            &[
+                "touch",
+                "-n",
+                "3",
+                "--dist",
+                "normal",
+                "--std-dev",
+                "100",
+                "a",
+            ],
+            &[
+                "touch",
+                "-n",
+                "3",
+                "--dist",
+                "normal",
+                "--std-dev",
+                "0",
+                "a",
+            ],
+            &["touch", "-n", "3", "--dist", "gamma", "a"],
+        ];
+        let outputs = [
+            Some((Distribution::Uniform, DEFAULT_STD_DEV)),
+            Some((Distribution::Normal, 100.0)),
+            None,
+            None,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs) {
+            // Act
+            let cmd = TouchCommand::try_parse_from(input.iter());
+
+            // Assert
+            assert_eq!(cmd.ok().map(|cmd| (cmd.dist, cmd.std_dev)), output);
+        }
+    }
 }
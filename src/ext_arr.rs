@@ -1,32 +1,130 @@
 use std::{
     fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Seek, Write},
+    io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
+    os::{fd::AsRawFd, unix::fs::FileExt},
     path::Path,
     sync::{Arc, Mutex, MutexGuard},
 };
 
 use bytemuck::{AnyBitPattern, NoUninit};
+use memmap::{Mmap, MmapOptions};
+use nix::errno::Errno;
+use tracing::warn;
 
+/// An array of `T` stored in an external backing.
+///
+/// Writes can be buffered by the backing, so flush before reading them back and call
+/// [`ExtArr::finish`] once done writing to get the flush errors. Dropping an array drops its
+/// backing, [`FileBufRW`] flushes the pending writes then and only logs the errors.
 #[derive(Debug)]
 pub struct ExtArr<T, RW> {
     rw: RW,
+    checksum: Option<Checksum>,
     _marker: PhantomData<T>,
 }
 
+/// Size in bytes of the CRC32 trailer of a checksummed array
+const CHECKSUM_SIZE: usize = size_of::<u32>();
+
+/// CRC32 of the bytes written to an array, appended after them by the first flush and checked
+/// against the bytes read back once they have all been read from the start
+#[derive(Debug, Clone, Default)]
+struct Checksum {
+    written: crc32fast::Hasher,
+    len: u64,
+    sealed: bool,
+    /// Hash of the bytes read since the start, `None` after a seek elsewhere skipped some
+    read: Option<crc32fast::Hasher>,
+    position: u64,
+    verified: bool,
+}
+
+impl Checksum {
+    fn seek(&mut self, position: u64) {
+        self.position = position;
+        self.read = (position == 0).then(crc32fast::Hasher::new);
+        self.verified = false;
+    }
+}
+
 impl<T, RW> ExtArr<T, RW> {
     pub fn new(rw: RW) -> Self {
         Self {
             rw,
+            checksum: None,
             _marker: PhantomData,
         }
     }
 
+    /// Append a CRC32 of the elements on the first flush and fail reads with `InvalidData` when
+    /// the elements read back from the start don't match it.
+    ///
+    /// Meant for temporary arrays written once from the start then read back by the same array,
+    /// as the length of the elements is only known from the writes. Writing after the first
+    /// flush fails with `InvalidInput`.
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(Checksum::default());
+        self
+    }
+
     pub fn into_inner(self) -> RW {
         self.rw
     }
 }
 
+/// Reads of the backing bounded to the elements of a checksummed array, verifying the trailer
+/// once they are all read
+struct CheckedReader<'a, T, RW>(&'a mut ExtArr<T, RW>);
+
+impl<T, RW: Read> Read for CheckedReader<'_, T, RW> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let ExtArr { rw, checksum, .. } = &mut *self.0;
+        let Some(checksum) = checksum else {
+            return rw.read(buf);
+        };
+
+        let left = checksum.len.saturating_sub(checksum.position);
+        let len = buf.len().min(left.try_into().unwrap_or(usize::MAX));
+        let bytes_read = rw.read(&mut buf[..len])?;
+        checksum.position += bytes_read as u64;
+        if let Some(read) = &mut checksum.read {
+            read.update(&buf[..bytes_read]);
+        }
+
+        if checksum.sealed && checksum.position == checksum.len && !checksum.verified {
+            if let Some(read) = checksum.read.clone() {
+                let mut trailer = [0u8; CHECKSUM_SIZE];
+                rw.read_exact(&mut trailer)?;
+                if u32::from_le_bytes(trailer) != read.finalize() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "checksum mismatch, the external array is corrupted",
+                    ));
+                }
+                checksum.verified = true;
+            }
+        }
+        Ok(bytes_read)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+        if self.0.checksum.is_none() {
+            return self.0.rw.read_to_end(buf);
+        }
+
+        let start = buf.len();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let bytes_read = self.read(&mut chunk)?;
+            if bytes_read == 0 {
+                return Ok(buf.len() - start);
+            }
+            buf.extend_from_slice(&chunk[..bytes_read]);
+        }
+    }
+}
+
 impl<T, RW> ExtArr<T, RW>
 where
     T: NoUninit + AnyBitPattern,
@@ -34,7 +132,7 @@ where
 {
     pub fn read<'b, B: AsMut<[u8]>>(&mut self, buf: &'b mut B) -> std::io::Result<&'b mut [T]> {
         let buf = buf.as_mut();
-        let bytes_read = self.rw.read(buf)?;
+        let bytes_read = CheckedReader(self).read(buf)?;
 
         let (read, _) = buf.split_at_mut(bytes_read);
         let read: &mut [T] = bytemuck::try_cast_slice_mut(read).map_err(|_| {
@@ -46,8 +144,25 @@ where
         Ok(read)
     }
 
+    /// Fill the whole buffer, failing with `UnexpectedEof` if there are not enough bytes left
+    pub fn read_exact<'b, B: AsMut<[u8]>>(
+        &mut self,
+        buf: &'b mut B,
+    ) -> std::io::Result<&'b mut [T]> {
+        let buf = buf.as_mut();
+        CheckedReader(self).read_exact(buf)?;
+
+        let read: &mut [T] = bytemuck::try_cast_slice_mut(buf).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "The number of bytes readed cannot be casted into &mut [T]",
+            )
+        })?;
+        Ok(read)
+    }
+
     pub fn read_to_end<'b>(&mut self, buf: &'b mut Vec<u8>) -> std::io::Result<&'b mut [T]> {
-        self.rw.read_to_end(buf)?;
+        CheckedReader(self).read_to_end(buf)?;
 
         // Ensure the buffer size is a multiple of the size of T.
         let read = bytemuck::try_cast_slice_mut(buf.as_mut_slice()).map_err(|_| {
@@ -66,22 +181,80 @@ where
     RW: Write,
 {
     pub fn write(&mut self, buf: &[T]) -> std::io::Result<()> {
-        let buf: &[u8] = bytemuck::cast_slice(buf);
-        self.rw.write_all(buf)
+        self.write_raw(bytemuck::cast_slice(buf))
     }
 
     pub fn write_raw(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if let Some(checksum) = &mut self.checksum {
+            if checksum.sealed {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "cannot write to a checksummed external array after flushing it",
+                ));
+            }
+            checksum.written.update(buf);
+            checksum.len += buf.len() as u64;
+        }
         self.rw.write_all(buf)
     }
 
     pub fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(checksum) = self.checksum.as_mut().filter(|checksum| !checksum.sealed) {
+            let crc = checksum.written.clone().finalize();
+            self.rw.write_all(&crc.to_le_bytes())?;
+            checksum.sealed = true;
+        }
         self.rw.flush()
     }
+
+    /// Flush the pending writes and return the backing
+    pub fn finish(mut self) -> std::io::Result<RW> {
+        self.flush()?;
+        Ok(self.rw)
+    }
+}
+
+/// Positioned I/O, reading and writing at an element index without touching the cursor so
+/// several threads can share one array and work on disjoint regions
+impl<T, RW> ExtArr<T, RW>
+where
+    T: NoUninit + AnyBitPattern,
+    RW: FileExt,
+{
+    /// Fill `buf` with the elements starting at element `index`, failing with `UnexpectedEof` if
+    /// there are not enough elements
+    pub fn read_at(&self, index: u64, buf: &mut [T]) -> std::io::Result<()> {
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(buf);
+        self.rw.read_exact_at(bytes, Self::offset_of(index))
+    }
+
+    /// Write every element of `buf` starting at element `index`
+    pub fn write_at(&self, index: u64, buf: &[T]) -> std::io::Result<()> {
+        let bytes: &[u8] = bytemuck::cast_slice(buf);
+        self.rw.write_all_at(bytes, Self::offset_of(index))
+    }
+
+    fn offset_of(index: u64) -> u64 {
+        index * size_of::<T>() as u64
+    }
+}
+
+impl<T, RW: Reserve> ExtArr<T, RW> {
+    /// Set aside room for `elements` elements from the start of the backing, so writing them
+    /// doesn't extend it piece by piece. A shorter backing grows to that length, reading as zeros
+    /// past what was written.
+    pub fn reserve(&mut self, elements: u64) -> std::io::Result<()> {
+        self.rw.reserve(elements * size_of::<T>() as u64)
+    }
 }
 
 impl<T, RW: Seek> Seek for ExtArr<T, RW> {
     fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        self.rw.seek(pos)
+        let position = self.rw.seek(pos)?;
+        if let Some(checksum) = &mut self.checksum {
+            checksum.seek(position);
+        }
+        Ok(position)
     }
 }
 
@@ -89,11 +262,56 @@ impl<T, RW: Clone> Clone for ExtArr<T, RW> {
     fn clone(&self) -> Self {
         Self {
             rw: self.rw.clone(),
+            checksum: self.checksum.clone(),
             _marker: PhantomData,
         }
     }
 }
 
+/// A backing that can preallocate its space ahead of the writes
+pub trait Reserve {
+    /// Make room for `bytes` bytes from the start, never shrinking the backing
+    fn reserve(&mut self, bytes: u64) -> std::io::Result<()>;
+}
+
+impl Reserve for File {
+    fn reserve(&mut self, bytes: u64) -> std::io::Result<()> {
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        let len = bytes
+            .try_into()
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        match nix::fcntl::posix_fallocate(self.as_raw_fd(), 0, len) {
+            // Filesystems that can't preallocate still get the length, only not the blocks
+            Err(Errno::EOPNOTSUPP) if self.metadata()?.len() < bytes => self.set_len(bytes),
+            Err(Errno::EOPNOTSUPP) => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+impl Reserve for FileBufRW {
+    fn reserve(&mut self, bytes: u64) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().reserve(bytes)
+    }
+}
+
+/// In-memory backings grow as needed, there is nothing to preallocate
+impl Reserve for Cursor<Vec<u8>> {
+    fn reserve(&mut self, _bytes: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<RW: Reserve> Reserve for SyncRW<RW> {
+    fn reserve(&mut self, bytes: u64) -> std::io::Result<()> {
+        self.lock()?.reserve(bytes)
+    }
+}
+
 #[derive(Debug)]
 pub struct FileBufRW {
     reader: BufReader<File>,
@@ -139,6 +357,15 @@ impl Seek for FileBufRW {
     }
 }
 
+// Drop can't report errors, log them instead of losing the tail of the writes silently
+impl Drop for FileBufRW {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            warn!("failed to flush the pending writes on drop: {}", e);
+        }
+    }
+}
+
 impl TryFrom<File> for FileBufRW {
     type Error = std::io::Error;
 
@@ -149,11 +376,85 @@ impl TryFrom<File> for FileBufRW {
     }
 }
 
+/// A read only view over a memory-mapped file, avoiding a syscall per read
 #[derive(Debug, Clone)]
+pub struct MmapRO {
+    // Empty files cannot be mapped
+    mmap: Option<Arc<Mmap>>,
+    position: u64,
+}
+
+impl MmapRO {
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::try_from(File::open(path)?)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.mmap
+            .as_deref()
+            .map(|mmap| mmap.as_ref())
+            .unwrap_or(&[])
+    }
+}
+
+impl Read for MmapRO {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.as_slice();
+        let start = (self.position as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for MmapRO {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.as_slice().len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl TryFrom<File> for MmapRO {
+    type Error = std::io::Error;
+
+    fn try_from(value: File) -> Result<Self, Self::Error> {
+        let mmap = if value.metadata()?.len() == 0 {
+            None
+        } else {
+            Some(Arc::new(unsafe { MmapOptions::new().map(&value)? }))
+        };
+
+        Ok(Self { mmap, position: 0 })
+    }
+}
+
+#[derive(Debug)]
 pub struct SyncRW<RW> {
     rw: Arc<Mutex<RW>>,
 }
 
+// Clones share the same backing, so `RW` itself does not need to be `Clone`
+impl<RW> Clone for SyncRW<RW> {
+    fn clone(&self) -> Self {
+        Self {
+            rw: Arc::clone(&self.rw),
+        }
+    }
+}
+
 impl<RW> SyncRW<RW> {
     pub fn new(rw: RW) -> Self {
         Self {
@@ -189,3 +490,227 @@ impl<RW: Seek> Seek for SyncRW<RW> {
         self.lock()?.seek(pos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn dropped_array_keeps_unflushed_writes() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..100).collect();
+        let file = tempfile::NamedTempFile::new()?;
+        let mut arr = ExtArr::<u16, _>::new(FileBufRW::new(file.path())?);
+
+        // Act
+        arr.write(&numbers)?;
+        drop(arr);
+
+        // Assert
+        let data = std::fs::read(file.path())?;
+        assert_eq!(data, bytemuck::cast_slice::<u16, u8>(&numbers));
+        Ok(())
+    }
+
+    #[test]
+    fn finish_flushes_writes() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..100).collect();
+        let mut arr = ExtArr::<u16, _>::new(BufWriter::new(Vec::new()));
+
+        // Act
+        arr.write(&numbers)?;
+        let writer = arr.finish()?;
+
+        // Assert
+        assert_eq!(writer.buffer().len(), 0);
+        assert_eq!(writer.get_ref(), bytemuck::cast_slice::<u16, u8>(&numbers));
+        Ok(())
+    }
+
+    #[test]
+    fn checksum_detects_corruption() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..100).collect();
+        let inputs = [None, Some(10), Some(200)];
+        let outputs = [
+            None,
+            Some(std::io::ErrorKind::InvalidData),
+            Some(std::io::ErrorKind::InvalidData),
+        ];
+
+        for (flipped, output) in inputs.iter().zip(outputs.iter()) {
+            let mut arr = ExtArr::<u16, _>::new(Cursor::new(Vec::new())).with_checksum();
+            arr.write(&numbers)?;
+            arr.flush()?;
+            if let Some(index) = flipped {
+                arr.rw.get_mut()[*index] ^= 0xff;
+            }
+            arr.rewind()?;
+
+            // Act
+            let mut values = Vec::new();
+            let result = arr.read_to_end(&mut values).map(|read| read.to_vec());
+
+            // Assert
+            match output {
+                None => assert_eq!(result?, numbers),
+                Some(kind) => assert_eq!(result.unwrap_err().kind(), *kind, "{flipped:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn checksum_verified_by_chunked_reads() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..100).collect();
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(Vec::new())).with_checksum();
+        arr.write(&numbers)?;
+        arr.flush()?;
+        arr.rw.get_mut()[150] ^= 0xff;
+        arr.rewind()?;
+        let mut buf = [0u8; 64];
+
+        // Act
+        let mut result = arr.read(&mut buf).map(|read| read.len());
+        while matches!(result, Ok(1..)) {
+            result = arr.read(&mut buf).map(|read| read.len());
+        }
+        let write_after_flush = arr.write(&[1]);
+
+        // Assert
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            write_after_flush.unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_ro_read() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..100).collect();
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(bytemuck::cast_slice(&numbers))?;
+        file.flush()?;
+        let mut arr = ExtArr::<u16, _>::new(MmapRO::new(file.path())?);
+        let mut buf = [0u8; 20];
+
+        // Act
+        let first = arr.read(&mut buf)?.to_vec();
+        arr.seek(SeekFrom::Start(2 * 95))?;
+        let last = arr.read(&mut buf)?.to_vec();
+        let end = arr.read(&mut buf)?.len();
+        arr.rewind()?;
+        let mut all = Vec::new();
+        let read = arr.read_to_end(&mut all)?.to_vec();
+
+        // Assert
+        assert_eq!(first, (0..10).collect::<Vec<u16>>());
+        assert_eq!(last, (95..100).collect::<Vec<u16>>());
+        assert_eq!(end, 0);
+        assert_eq!(read, numbers);
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_ro_empty_file() -> std::io::Result<()> {
+        // Arrange
+        let file = tempfile::NamedTempFile::new()?;
+        let mut arr = ExtArr::<u16, _>::new(MmapRO::new(file.path())?);
+        let mut buf = [0u8; 4];
+
+        // Act
+        let read = arr.read(&mut buf)?.len();
+
+        // Assert
+        assert_eq!(read, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn read_at_from_two_threads() -> std::io::Result<()> {
+        // Arrange
+        let numbers: Vec<u16> = (0..10_000).collect();
+        let mut file = tempfile::tempfile()?;
+        file.write_all(bytemuck::cast_slice(&numbers))?;
+        let arr = ExtArr::<u16, _>::new(file);
+        let inputs = [(0, 5000), (5000, 5000)];
+
+        // Act
+        let regions = std::thread::scope(|s| {
+            let handles: Vec<_> = inputs
+                .iter()
+                .map(|&(index, len)| {
+                    let arr = &arr;
+                    s.spawn(move || {
+                        let mut buf = vec![0u16; len];
+                        arr.read_at(index, &mut buf).map(|_| buf)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<std::io::Result<Vec<_>>>()
+        })?;
+
+        // Assert
+        assert_eq!(regions[0], numbers[..5000]);
+        assert_eq!(regions[1], numbers[5000..]);
+        Ok(())
+    }
+
+    #[test]
+    fn reserve_file_backed() -> std::io::Result<()> {
+        // Arrange
+        let file = tempfile::NamedTempFile::new()?;
+        let mut arr = ExtArr::<u16, _>::new(file.reopen()?);
+        let mut buffered = ExtArr::<u16, _>::new(FileBufRW::try_from(tempfile::tempfile()?)?);
+        let mut memory = ExtArr::<u16, _>::new(Cursor::new(Vec::new()));
+
+        // Act
+        arr.reserve(100)?;
+        arr.write(&[1, 2, 3])?;
+        arr.reserve(10)?;
+        buffered.write(&[1, 2, 3])?;
+        buffered.reserve(50)?;
+        memory.reserve(100)?;
+
+        // Assert
+        assert_eq!(std::fs::metadata(file.path())?.len(), 200);
+        assert_eq!(
+            buffered.into_inner().writer.get_ref().metadata()?.len(),
+            100
+        );
+        assert!(memory.into_inner().into_inner().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn write_at_then_read_at() -> std::io::Result<()> {
+        // Arrange
+        let arr = ExtArr::<u16, _>::new(tempfile::tempfile()?);
+        let mut buf = [0u16; 3];
+        let mut past_end_buf = [0u16; 3];
+
+        // Act
+        arr.write_at(0, &[1, 2, 3, 4, 5])?;
+        arr.write_at(2, &[30, 40])?;
+        arr.read_at(1, &mut buf)?;
+        let past_end = arr.read_at(4, &mut past_end_buf);
+
+        // Assert
+        assert_eq!(buf, [2, 30, 40]);
+        assert_eq!(
+            past_end.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        Ok(())
+    }
+}
@@ -1,23 +1,147 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
 use crate::vdisk::VDisk;
 
-pub trait Filesystem {}
+/// Flat storage of whole files addressed by their path
+pub trait Filesystem {
+    fn exists(&self, path: &Path) -> bool;
+    /// Check if a path is a directory, directories exist as long as a file lives under them
+    fn is_dir(&self, path: &Path) -> bool;
+    /// Read the whole content of a file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    /// Create a file, or replace its content if it already exists
+    fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()>;
+}
+
+/// Marks a disk that holds a [`BasicFS`] index
+const INDEX_MAGIC: [u8; 8] = *b"FERRIXFS";
+
+/// Magic, offset and length of the index at the start of the disk
+const HEADER_SIZE: u64 = 24;
 
+/// Where a file lives on the disk
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Extent {
+    offset: u64,
+    len: u64,
+}
+
+/// Stores every file as a single extent, appended after the last one written.
+///
+/// The index of the files is written after the last file on every write and the header at the
+/// start of the disk points to it, so the next file written replaces it. The space of replaced
+/// files is not reclaimed
 pub struct BasicFS {
     vdisk: VDisk,
+    files: BTreeMap<PathBuf, Extent>,
+    next_offset: u64,
 }
 
 impl BasicFS {
-    pub fn new(vdisk: VDisk) -> Self {
-        Self { vdisk }
+    /// Open the files stored in the disk, a disk without an index starts empty
+    pub fn new(vdisk: VDisk) -> io::Result<Self> {
+        let mut fs = Self {
+            vdisk,
+            files: BTreeMap::new(),
+            next_offset: HEADER_SIZE,
+        };
+
+        let mut header = [0; HEADER_SIZE as usize];
+        if fs.vdisk.size as u64 >= HEADER_SIZE {
+            fs.vdisk.disk.read_exact_at(&mut header, 0)?;
+        }
+
+        if header[..8] == INDEX_MAGIC {
+            let offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+            let len = u64::from_le_bytes(header[16..24].try_into().unwrap());
+
+            let mut index = vec![0; len as usize];
+            fs.vdisk.disk.read_exact_at(&mut index, offset)?;
+
+            fs.files = bincode::deserialize(&index)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            fs.next_offset = offset;
+        }
+
+        Ok(fs)
+    }
+
+    /// Write the index after the last file and point the header to it
+    fn save_index(&mut self) -> io::Result<()> {
+        let index = bincode::serialize(&self.files)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut header = [0; HEADER_SIZE as usize];
+        header[..8].copy_from_slice(&INDEX_MAGIC);
+        header[8..16].copy_from_slice(&self.next_offset.to_le_bytes());
+        header[16..24].copy_from_slice(&(index.len() as u64).to_le_bytes());
+
+        self.vdisk.disk.write_all_at(&index, self.next_offset)?;
+        self.vdisk.disk.write_all_at(&header, 0)
     }
 }
 
-impl Filesystem for BasicFS {}
+impl Filesystem for BasicFS {
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path == Path::new("/")
+            || self
+                .files
+                .keys()
+                .any(|file| file != path && file.starts_with(path))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let extent = self
+            .files
+            .get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        let mut data = vec![0; extent.len as usize];
+        self.vdisk.disk.read_exact_at(&mut data, extent.offset)?;
+
+        Ok(data)
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let extent = Extent {
+            offset: self.next_offset,
+            len: data.len() as u64,
+        };
+
+        let previous = self.files.insert(path.to_path_buf(), extent);
+        let index_len = bincode::serialized_size(&self.files)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if extent.offset + extent.len + index_len > self.vdisk.size as u64 {
+            match previous {
+                Some(previous) => self.files.insert(path.to_path_buf(), previous),
+                None => self.files.remove(path),
+            };
+            return Err(io::Error::from_raw_os_error(libc::ENOSPC));
+        }
+
+        self.vdisk.disk.write_all_at(data, extent.offset)?;
+        self.next_offset += extent.len;
+
+        self.save_index()
+    }
+}
 
 impl Clone for BasicFS {
     fn clone(&self) -> Self {
         Self {
             vdisk: self.vdisk.clone(),
+            files: self.files.clone(),
+            next_offset: self.next_offset,
         }
     }
 }
@@ -0,0 +1,181 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Component that matches zero or more whole path components
+pub const RECURSIVE_WILDCARD: &str = "**";
+
+/// Returns true if the given argument contains a glob metacharacter (`*` or `?`)
+pub fn is_glob(pattern: impl AsRef<OsStr>) -> bool {
+    pattern
+        .as_ref()
+        .as_bytes()
+        .iter()
+        .any(|c| *c == b'*' || *c == b'?')
+}
+
+/// Returns the leading components of the pattern that have no glob metacharacter, which is the
+/// directory a walk looking for matches has to start from
+pub fn base_dir(pattern: &Path) -> PathBuf {
+    pattern.iter().take_while(|c| !is_glob(c)).collect()
+}
+
+/// Returns how many components below [`base_dir`] a match can be, or `None` if the pattern has a
+/// `**` and matches can be arbitrarily deep
+pub fn max_depth(pattern: &Path) -> Option<usize> {
+    let mut depth = 0;
+
+    for component in pattern.iter().skip_while(|c| !is_glob(c)) {
+        if component == RECURSIVE_WILDCARD {
+            return None;
+        }
+        depth += 1;
+    }
+
+    Some(depth)
+}
+
+/// Returns true if `path` matches `pattern`, both are compared component by component.
+///
+/// `**` matches zero or more whole components, `*` matches any run of bytes inside a component
+/// and `?` exactly one byte. Like in a shell, wildcards never match a leading `.` of a component
+pub fn matches(pattern: &Path, path: &Path) -> bool {
+    let pattern: Vec<&OsStr> = pattern.iter().collect();
+    let path: Vec<&OsStr> = path.iter().collect();
+
+    match_components(&pattern, &path)
+}
+
+fn match_components(pattern: &[&OsStr], path: &[&OsStr]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if *first == RECURSIVE_WILDCARD => (0..=path.len())
+            .take_while(|&skip| skip == 0 || !is_hidden(path[skip - 1]))
+            .any(|skip| match_components(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_component(first.as_bytes(), name.as_bytes())
+                    && match_components(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn is_hidden(component: &OsStr) -> bool {
+    component.as_bytes().first() == Some(&b'.')
+}
+
+fn match_component(pattern: &[u8], name: &[u8]) -> bool {
+    if name.first() == Some(&b'.') && pattern.first() != Some(&b'.') {
+        return false;
+    }
+
+    match_wildcard(pattern, name)
+}
+
+fn match_wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', pattern_rest)), _) => {
+            match_wildcard(pattern_rest, name)
+                || (!name.is_empty() && match_wildcard(pattern, &name[1..]))
+        }
+        (Some((b'?', pattern_rest)), Some((_, name_rest))) => {
+            match_wildcard(pattern_rest, name_rest)
+        }
+        (Some((p, pattern_rest)), Some((n, name_rest))) if p == n => {
+            match_wildcard(pattern_rest, name_rest)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_star() {
+        // Arrange
+        let inputs = [
+            ("/src/*.txt", "/src/a.txt", true),
+            ("/src/*.txt", "/src/.a.txt", false),
+            ("/src/*.txt", "/src/d/a.txt", false),
+            ("/src/*", "/src/a", true),
+            ("/*/a", "/src/a", true),
+            ("/src/a*b", "/src/ab", true),
+            ("/src/a*b", "/src/axxb", true),
+            ("/src/a*b", "/src/axxc", false),
+        ];
+
+        for (pattern, path, output) in inputs {
+            // Act
+            let result = matches(Path::new(pattern), Path::new(path));
+
+            // Assert
+            assert_eq!(result, output, "{pattern} {path}");
+        }
+    }
+
+    #[test]
+    fn match_question_mark() {
+        // Arrange
+        let inputs = [
+            ("/src/?.txt", "/src/a.txt", true),
+            ("/src/?.txt", "/src/ab.txt", false),
+            ("/src/?.txt", "/src/.txt", false),
+            ("/src/a??", "/src/abc", true),
+        ];
+
+        for (pattern, path, output) in inputs {
+            // Act
+            let result = matches(Path::new(pattern), Path::new(path));
+
+            // Assert
+            assert_eq!(result, output, "{pattern} {path}");
+        }
+    }
+
+    #[test]
+    fn match_recursive_wildcard() {
+        // Arrange
+        let inputs = [
+            ("/src/**/*.txt", "/src/a.txt", true),
+            ("/src/**/*.txt", "/src/d/a.txt", true),
+            ("/src/**/*.txt", "/src/d/e/a.txt", true),
+            ("/src/**/*.txt", "/src/.d/a.txt", false),
+            ("/src/**/*.txt", "/other/a.txt", false),
+            ("/**", "/src/d", true),
+            ("/src/**/e/*", "/src/d/e/a", true),
+        ];
+
+        for (pattern, path, output) in inputs {
+            // Act
+            let result = matches(Path::new(pattern), Path::new(path));
+
+            // Assert
+            assert_eq!(result, output, "{pattern} {path}");
+        }
+    }
+
+    #[test]
+    fn base_dir_and_depth() {
+        // Arrange
+        let inputs = ["/src/*.txt", "/src/d/?", "/src/**/*.txt", "/src/a"];
+        let outputs = [
+            ("/src", Some(1)),
+            ("/src/d", Some(1)),
+            ("/src", None),
+            ("/src/a", Some(0)),
+        ];
+
+        for (input, (base, depth)) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = (base_dir(Path::new(input)), max_depth(Path::new(input)));
+
+            // Assert
+            assert_eq!(result, (PathBuf::from(base), *depth));
+        }
+    }
+}
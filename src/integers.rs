@@ -0,0 +1,625 @@
+//! Format of the integer files, a bincode encoded vector of [`Number`]s: a `u64` length header
+//! followed by the elements. Raw files leave the header out, their length is given by their size.
+//!
+//! The helpers only need a `Read`/`Write`/`Seek` source so every [`System`] backend can share
+//! them.
+//!
+//! [`System`]: crate::system::System
+
+use std::cmp::Reverse;
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::complete_command::{Distribution, TouchCommand, DEFAULT_STD_DEV};
+use crate::ext_arr::ExtArr;
+use crate::progress::Progress;
+use crate::sort::ExtSorter;
+use crate::system::{Number, SystemError, SystemResult};
+
+/// Size of the length header before the elements
+pub const HEADER_SIZE: u64 = size_of::<u64>() as u64;
+
+/// Size of the header before the elements, none for raw files
+pub fn header_size(raw: bool) -> u64 {
+    if raw {
+        0
+    } else {
+        HEADER_SIZE
+    }
+}
+
+/// Width in bytes of a [`Number`] element
+const NUMBER_WIDTH: u64 = size_of::<Number>() as u64;
+
+/// Number of elements written between two progress reports
+pub const PROGRESS_STEP: u64 = 4096;
+
+/// How [`concat`] combines its sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcatMode {
+    /// Append the sources one after another
+    Append,
+    /// Take one element from each source in turn
+    Interleave,
+    /// Merge sources that are already sorted, in descending order if `reverse` is set
+    SortedMerge { reverse: bool },
+}
+
+/// How [`write_random`] generates its integers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    pub distribution: Distribution,
+    /// Only used by the normal distribution
+    pub std_dev: f64,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self {
+            distribution: Distribution::Uniform,
+            std_dev: DEFAULT_STD_DEV,
+        }
+    }
+}
+
+impl From<&TouchCommand> for Generator {
+    fn from(cmd: &TouchCommand) -> Self {
+        Self {
+            distribution: cmd.dist,
+            std_dev: cmd.std_dev,
+        }
+    }
+}
+
+impl Generator {
+    /// Generate `count` integers
+    pub fn generate(&self, count: u32) -> Vec<Number> {
+        let mut rng = rand::rng();
+        let mut uniform = || -> Vec<Number> {
+            (0..count)
+                .map(|_| rng.random_range(0..=Number::MAX))
+                .collect()
+        };
+
+        match self.distribution {
+            Distribution::Uniform => uniform(),
+            Distribution::Normal => {
+                let mean = Number::MAX as f64 / 2.0;
+                (0..count)
+                    .map(|_| {
+                        // Box-Muller transform, the first sample is kept out of zero for the log
+                        let radius = (-2.0 * (1.0 - rng.random::<f64>()).ln()).sqrt();
+                        let angle = std::f64::consts::TAU * rng.random::<f64>();
+                        let value = mean + self.std_dev * radius * angle.cos();
+                        value.round().clamp(0.0, Number::MAX as f64) as Number
+                    })
+                    .collect()
+            }
+            Distribution::Sorted => {
+                let mut data = uniform();
+                data.sort_unstable();
+                data
+            }
+            Distribution::Reversed => {
+                let mut data = uniform();
+                data.sort_unstable_by_key(|&value| Reverse(value));
+                data
+            }
+        }
+    }
+}
+
+/// Write `count` integers made by `generator`, reporting the elements written and the total to
+/// `on_progress` every [`PROGRESS_STEP`] elements
+pub fn write_random<W, F>(
+    writer: W,
+    count: u32,
+    generator: Generator,
+    raw: bool,
+    on_progress: F,
+) -> SystemResult<()>
+where
+    W: Write,
+    F: FnMut(u64, u64),
+{
+    let data = generator.generate(count);
+
+    let progress = Progress::new(count.into(), PROGRESS_STEP, on_progress);
+    let mut writer = ProgressWriter::new(writer, header_size(raw), NUMBER_WIDTH, progress);
+    write_numbers(&mut writer, &data, raw)?;
+    writer.flush()?;
+    writer.progress.finish();
+
+    Ok(())
+}
+
+/// Write the integers with the length header, or without it if `raw` is set
+pub fn write_numbers<W: Write>(mut writer: W, numbers: &[Number], raw: bool) -> SystemResult<()> {
+    if raw {
+        return Ok(writer.write_all(bytemuck::cast_slice(numbers))?);
+    }
+
+    Ok(bincode::serialize_into(writer, numbers)?)
+}
+
+/// Read every integer of the source, written with the length header or without it if `raw` is
+/// set
+pub fn read_numbers<R: Read + Seek>(mut reader: R, raw: bool) -> SystemResult<Vec<Number>> {
+    let (length, _) = read_header(&mut reader, raw)?;
+
+    // Allocated as numbers so the bytes are always aligned for the cast back
+    let mut numbers: Vec<Number> = vec![0; length.try_into()?];
+    reader.read_exact(bytemuck::cast_slice_mut(&mut numbers))?;
+
+    Ok(numbers)
+}
+
+/// Read the length header and infer the width in bytes of the elements from the size of the
+/// source, leaving the source right after the header. Empty sources have no width.
+///
+/// Raw sources have no header, they hold [`Number`]s and their length is given by their size,
+/// which must be a whole number of elements
+pub fn read_header<R: Read + Seek>(mut reader: R, raw: bool) -> SystemResult<(u64, Option<u64>)> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    reader.rewind()?;
+
+    if raw {
+        if size % NUMBER_WIDTH != 0 {
+            return Err(SystemError::PartialElement(size));
+        }
+
+        let length = size / NUMBER_WIDTH;
+        return Ok((length, (length > 0).then_some(NUMBER_WIDTH)));
+    }
+
+    let length: u64 = bincode::deserialize_from(&mut reader)?;
+    if length == 0 {
+        return Ok((length, None));
+    }
+
+    Ok((length, Some(size.saturating_sub(HEADER_SIZE) / length)))
+}
+
+/// Read the elements from `start` up to `end`, clamped to the length of the source. A `start`
+/// past `end` reads the 10 elements from `start`
+pub fn head<R: Read + Seek>(
+    mut reader: R,
+    start: u32,
+    end: u32,
+    raw: bool,
+) -> SystemResult<Vec<Number>> {
+    let start: u64 = start.into();
+    let mut end: u64 = end.into();
+    if start > end {
+        end = start + 10;
+    }
+
+    let (length, _) = read_header(&mut reader, raw)?;
+    let end = end.min(length);
+    let elements_to_read = end.saturating_sub(start);
+
+    // Skip the header and the elements before start, then read the range in one go
+    let mut arr = ExtArr::<Number, _>::new(reader);
+    arr.seek(SeekFrom::Start(header_size(raw) + start * NUMBER_WIDTH))?;
+
+    // Allocated as numbers so the bytes are always aligned for the cast back
+    let mut result: Vec<Number> = vec![0; elements_to_read.try_into()?];
+    arr.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(&mut result))?;
+
+    Ok(result)
+}
+
+/// Read up to `count` bytes as they are stored from the start of the source, header included,
+/// or from its end if `from_end` is set
+pub fn read_bytes<R: Read + Seek>(
+    mut reader: R,
+    count: u64,
+    from_end: bool,
+) -> SystemResult<Vec<u8>> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    let count = count.min(size);
+    let start = if from_end { size - count } else { 0 };
+    reader.seek(SeekFrom::Start(start))?;
+
+    let mut bytes = vec![0; count.try_into()?];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Name of the file `cat` writes to, the name of the first file with its extension, or `txt`,
+/// appended again
+pub fn concat_file_name(first_file: &Path) -> OsString {
+    let mut name = first_file
+        .file_name()
+        .expect("expected to be a file")
+        .to_os_string();
+    name.push(".");
+    name.push(first_file.extension().unwrap_or(OsStr::new("txt")));
+    name
+}
+
+/// Concatenate the integer sources into `writer` as a single integer file, returning its length.
+/// The elements written and the total are reported to `on_progress` every [`PROGRESS_STEP`]
+/// elements.
+///
+/// Every source must have elements of the same width, and only [`ConcatMode::Append`] accepts
+/// elements that are not [`Number`]s. With `raw` set the sources are read and the result
+/// written without the length header
+pub fn concat<R, W, F>(
+    sources: Vec<R>,
+    writer: W,
+    mode: ConcatMode,
+    raw: bool,
+    on_progress: F,
+) -> SystemResult<u64>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(u64, u64),
+{
+    let mut width = None;
+    let mut sources = sources
+        .into_iter()
+        .map(|mut source| {
+            let (length, source_width) = read_header(&mut source, raw)?;
+            match (width, source_width) {
+                (Some(width), Some(source_width)) if width != source_width => {
+                    return Err(SystemError::MixedElementWidths)
+                }
+                (None, Some(_)) => width = source_width,
+                _ => {}
+            }
+
+            Ok((length, source))
+        })
+        .collect::<SystemResult<Vec<_>>>()?;
+
+    let width = width.unwrap_or(NUMBER_WIDTH);
+    if mode != ConcatMode::Append && width != NUMBER_WIDTH {
+        return Err(SystemError::NotIntegerElements(width));
+    }
+
+    let total_numbers: u64 = sources.iter().map(|(length, _)| length).sum();
+    let progress = Progress::new(total_numbers, PROGRESS_STEP, on_progress);
+    let mut writer = ProgressWriter::new(writer, header_size(raw), width, progress);
+    if !raw {
+        bincode::serialize_into(&mut writer, &total_numbers)?;
+    }
+
+    match mode {
+        ConcatMode::Append => {
+            // Stream elements directly from input to output
+            for (length, source) in &mut sources {
+                std::io::copy(&mut source.take(*length * width), &mut writer)?;
+            }
+        }
+        ConcatMode::Interleave => interleave(sources, &mut writer)?,
+        ConcatMode::SortedMerge { reverse } => {
+            let sources = sources.into_iter().map(|(_, source)| source).collect();
+            merge_sorted(sources, &mut writer, reverse)?
+        }
+    }
+
+    writer.flush()?;
+    writer.progress.finish();
+
+    Ok(total_numbers)
+}
+
+/// Writer of an integer file that reports the elements written past the `header` bytes
+struct ProgressWriter<W: Write, F: FnMut(u64, u64)> {
+    inner: W,
+    header: u64,
+    width: u64,
+    written: u64,
+    progress: Progress<F>,
+}
+
+impl<W: Write, F: FnMut(u64, u64)> ProgressWriter<W, F> {
+    fn new(inner: W, header: u64, width: u64, progress: Progress<F>) -> Self {
+        Self {
+            inner,
+            header,
+            width,
+            written: 0,
+            progress,
+        }
+    }
+}
+
+impl<W: Write, F: FnMut(u64, u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        self.progress
+            .update(self.written.saturating_sub(self.header) / self.width);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write the integers of the sources taking one element from each source in turn, skipping the
+/// sources that ran out of elements
+fn interleave<R: Read, W: Write>(sources: Vec<(u64, R)>, writer: W) -> SystemResult<()> {
+    let mut sources: Vec<_> = sources
+        .into_iter()
+        .map(|(length, source)| (length, ExtArr::<Number, _>::new(source)))
+        .collect();
+    let mut output = ExtArr::<Number, _>::new(writer);
+    let mut num = [0 as Number];
+    let mut num_buffer = bytemuck::cast_slice_mut::<Number, u8>(&mut num);
+
+    while sources.iter().any(|(remaining, _)| *remaining > 0) {
+        for (remaining, source) in sources.iter_mut().filter(|(remaining, _)| *remaining > 0) {
+            let read = source.read_exact(&mut num_buffer)?;
+            output.write(read)?;
+            *remaining -= 1;
+        }
+    }
+
+    Ok(output.flush()?)
+}
+
+/// Merge the integers of sources that are already sorted into a single sorted sequence, in
+/// descending order if `reverse` is set
+fn merge_sorted<R: Read, W: Write>(sources: Vec<R>, writer: W, reverse: bool) -> SystemResult<()> {
+    let mut sources: Vec<_> = sources.into_iter().map(ExtArr::<Number, _>::new).collect();
+    let mut output = ExtArr::<Number, _>::new(writer);
+    let mut num = [0 as Number];
+    let buf = bytemuck::cast_slice_mut::<Number, u8>(&mut num);
+
+    if reverse {
+        ExtSorter::merge_chunks_by_key(buf, &mut output, sources.iter_mut(), Reverse)?;
+    } else {
+        ExtSorter::merge_chunks(buf, &mut output, sources.iter_mut())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn integers(data: &[Number]) -> Cursor<Vec<u8>> {
+        Cursor::new(bincode::serialize(data).unwrap())
+    }
+
+    fn decode(data: Cursor<Vec<u8>>) -> Vec<Number> {
+        bincode::deserialize(&data.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn write_random_integers() {
+        // Arrange
+        let mut data = Cursor::new(Vec::new());
+
+        // Act
+        write_random(&mut data, 5, Generator::default(), false, |_, _| {}).unwrap();
+
+        // Assert
+        assert_eq!(
+            read_header(&mut data, false).unwrap(),
+            (5, Some(NUMBER_WIDTH))
+        );
+        assert_eq!(decode(data).len(), 5);
+    }
+
+    #[test]
+    fn generate_distributions() {
+        // Arrange
+        let generator = |distribution| Generator {
+            distribution,
+            ..Generator::default()
+        };
+
+        // Act
+        let sorted = generator(Distribution::Sorted).generate(1000);
+        let reversed = generator(Distribution::Reversed).generate(100_000);
+        let normal = generator(Distribution::Normal).generate(1000);
+
+        // Assert
+        assert_eq!(sorted.len(), 1000);
+        assert!(sorted.is_sorted());
+        // More values than a Number holds, so some of them repeat
+        assert_eq!(reversed.len(), 100_000);
+        assert!(reversed.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(normal.len(), 1000);
+    }
+
+    #[test]
+    fn read_header_width() {
+        // Arrange
+        let wide = Cursor::new(bincode::serialize(&vec![1u32, 2, 3]).unwrap());
+        let inputs = [integers(&[1, 2, 3]), wide, integers(&[])];
+        let outputs = [(3, Some(2)), (3, Some(4)), (0, None)];
+
+        for (input, output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let header = read_header(input, false).unwrap();
+
+            // Assert
+            assert_eq!(header, output);
+        }
+    }
+
+    #[test]
+    fn head_window() {
+        // Arrange
+        let data: Vec<Number> = (0..20).collect();
+        let inputs = [(0, 3), (18, 30), (5, 2), (25, 30)];
+        let outputs: [&[Number]; 4] = [
+            &[0, 1, 2],
+            &[18, 19],
+            &[5, 6, 7, 8, 9, 10, 11, 12, 13, 14],
+            &[],
+        ];
+
+        for ((start, end), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let result = head(integers(&data), start, end, false).unwrap();
+
+            // Assert
+            assert_eq!(result, output, "{start} {end}");
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        // Arrange
+        let mut data = Cursor::new(Vec::new());
+        let numbers: Vec<Number> = (0..20).rev().collect();
+
+        // Act
+        write_numbers(&mut data, &numbers, true).unwrap();
+        let header = read_header(&mut data, true).unwrap();
+        let read = read_numbers(&mut data, true).unwrap();
+        let window = head(&mut data, 2, 5, true).unwrap();
+
+        // Assert
+        assert_eq!(data.get_ref().len(), numbers.len() * size_of::<Number>());
+        assert_eq!(header, (20, Some(NUMBER_WIDTH)));
+        assert_eq!(read, numbers);
+        assert_eq!(window, [17, 16, 15]);
+    }
+
+    #[test]
+    fn raw_header_from_size() {
+        // Arrange
+        let inputs = [vec![0; 6], vec![], vec![0; 5]];
+        let outputs = [Some((3, Some(NUMBER_WIDTH))), Some((0, None)), None];
+
+        for (input, output) in inputs.into_iter().zip(outputs) {
+            let size = input.len();
+
+            // Act
+            let result = read_header(Cursor::new(input), true);
+
+            // Assert
+            match output {
+                Some(header) => assert_eq!(result.unwrap(), header, "{size}"),
+                None => assert!(matches!(result, Err(SystemError::PartialElement(5)))),
+            }
+        }
+    }
+
+    #[test]
+    fn read_byte_prefix_and_suffix() {
+        // Arrange
+        let inputs = [(10, false), (4, true), (100, false)];
+        let outputs: [&[u8]; 3] = [
+            &[2, 0, 0, 0, 0, 0, 0, 0, 0x34, 0x12],
+            &[0x34, 0x12, 0xcd, 0xab],
+            &[2, 0, 0, 0, 0, 0, 0, 0, 0x34, 0x12, 0xcd, 0xab],
+        ];
+
+        for ((count, from_end), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let bytes = read_bytes(integers(&[0x1234, 0xabcd]), count, from_end).unwrap();
+
+            // Assert
+            assert_eq!(bytes, output, "{count} {from_end}");
+        }
+    }
+
+    #[test]
+    fn concat_modes() {
+        // Arrange
+        let inputs = [
+            ConcatMode::Append,
+            ConcatMode::Interleave,
+            ConcatMode::SortedMerge { reverse: false },
+        ];
+        let outputs: [&[Number]; 3] = [&[1, 4, 7, 2, 3], &[1, 2, 4, 3, 7], &[1, 2, 3, 4, 7]];
+
+        for (mode, output) in inputs.into_iter().zip(outputs) {
+            let mut result = Cursor::new(Vec::new());
+
+            // Act
+            let sources = vec![integers(&[1, 4, 7]), integers(&[]), integers(&[2, 3])];
+            let length = concat(sources, &mut result, mode, false, |_, _| {}).unwrap();
+
+            // Assert
+            assert_eq!(length, 5);
+            assert_eq!(decode(result), output, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn concat_mixed_widths() {
+        // Arrange
+        let wide = Cursor::new(bincode::serialize(&vec![1u32]).unwrap());
+        let sources = vec![integers(&[1]), wide];
+
+        // Act
+        let err = concat(
+            sources,
+            Cursor::new(Vec::new()),
+            ConcatMode::Append,
+            false,
+            |_, _| {},
+        )
+        .unwrap_err();
+
+        // Assert
+        assert!(matches!(err, SystemError::MixedElementWidths));
+    }
+
+    #[test]
+    fn progress_reports() {
+        // Arrange
+        let count = 2 * PROGRESS_STEP as u32 + 1;
+        let mut touched = Vec::new();
+        let mut concatenated = Vec::new();
+
+        // Act
+        write_random(
+            Cursor::new(Vec::new()),
+            count,
+            Generator::default(),
+            false,
+            |done, total| touched.push((done, total)),
+        )
+        .unwrap();
+        let sources = vec![integers(&[1; 3000]), integers(&[2; 3000])];
+        concat(
+            sources,
+            Cursor::new(Vec::new()),
+            ConcatMode::Interleave,
+            false,
+            |done, _| concatenated.push(done),
+        )
+        .unwrap();
+
+        // Assert
+        let step = PROGRESS_STEP;
+        let total = count as u64;
+        assert_eq!(touched, [(step, total), (2 * step, total), (total, total)]);
+        assert_eq!(concatenated, [step, 6000]);
+    }
+
+    #[test]
+    fn concat_file_names() {
+        // Arrange
+        let inputs = ["/a", "/dir/a.bin"];
+        let outputs = ["a.txt", "a.bin.bin"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let name = concat_file_name(Path::new(input));
+
+            // Assert
+            assert_eq!(name, *output);
+        }
+    }
+}
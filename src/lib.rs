@@ -3,8 +3,11 @@ pub mod complete_command;
 mod error;
 pub mod ext_arr;
 pub mod fs;
+pub mod glob;
+pub mod integers;
 pub mod mem;
 pub mod parser;
+pub mod progress;
 pub mod repl;
 pub mod repl_v2;
 pub mod simple_ext4;
@@ -4,19 +4,21 @@ use std::{num::ParseIntError, path::PathBuf};
 
 use miette::{Result as MietteResult, Severity, SourceSpan};
 use winnow::ascii::multispace0;
-use winnow::combinator::{delimited, eof, not, opt, repeat_till, trace};
+use winnow::combinator::{cut_err, delimited, eof, not, opt, peek, preceded, repeat_till, trace};
 use winnow::stream::StreamIsPartial;
 use winnow::{
     ascii::digit1,
     combinator::{alt, empty, fail, repeat},
-    error::{AddContext, ErrorKind, FromExternalError, FromRecoverableError, ParserError},
+    error::{AddContext, ErrMode, ErrorKind, FromExternalError, FromRecoverableError, ParserError},
     prelude::*,
     stream::{AsChar, Location, Recoverable, Stream},
     token::{any, literal, one_of, take_while},
     LocatingSlice,
 };
 
+use crate::complete_command::DEFAULT_HEAD_END;
 use crate::error::{FerrixDiagnostic, FerrixError};
+use crate::system::HOME_PATH_PREFIX;
 
 type Input<'a> = Recoverable<LocatingSlice<&'a str>, FerrixParserError>;
 type ParserResult<T> = winnow::PResult<T, FerrixParserError>;
@@ -143,6 +145,17 @@ pub enum CompleteCommand {
         from: PathBuf,
         to: PathBuf,
     },
+    /// Copy a file to another location, keeping the original
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Create a hard link to a file, or a symbolic link if symbolic is true
+    Link {
+        target: PathBuf,
+        link_name: PathBuf,
+        symbolic: bool,
+    },
     /// Create a new directory
     /// If parents is true, create all parent directories if they don't exist
     MkDir {
@@ -173,12 +186,27 @@ pub enum CompleteCommand {
     },
     /// Concat a given list of files into a stream and output it's content to a output file or
     /// fd
+    /// If append is true, the output file keeps its integers and the concatenation goes after
+    /// them
     Cat {
         files: Vec<PathBuf>,
         output_file: Option<PathBuf>,
+        append: bool,
     },
+    /// Exit the ferrix repl with the given code
     Exit {
-        code: u32,
+        code: i32,
+    },
+    /// Change the current working directory, to the default one without a path
+    ChangeDir {
+        path: Option<PathBuf>,
+    },
+    /// Print the absolute path of the current working directory
+    Pwd,
+    /// Recursively search a directory for entries with a given name
+    Find {
+        dir: PathBuf,
+        name: String,
     },
 }
 
@@ -232,10 +260,11 @@ impl<'a> WinnowFerrixParser<'a> {
         }
     }
 
-    /// Parse the input and return a list of commands
-    /// If there are any errors, return a FerrixError
+    /// Parse the input and return a list of commands, the whole input must be commands
+    /// If there are any errors, return a FerrixError. [`Self::get_commands_lenient`] keeps the
+    /// commands that parse instead
     pub fn get_commands(&mut self) -> MietteResult<&[CompleteCommand]> {
-        match try_parse(Self::parse_commands, self.input) {
+        match try_parse(Self::parse_script, self.input) {
             Ok(cmds) => self.commands = cmds,
             Err(err) => return Err(err.into()),
         };
@@ -243,6 +272,74 @@ impl<'a> WinnowFerrixParser<'a> {
         Ok(&self.commands)
     }
 
+    /// Parse the input going past the commands that fail, returning the commands that parsed
+    /// and a single error with the diagnostics of every command that didn't
+    pub fn get_commands_lenient(&mut self) -> (Vec<CompleteCommand>, Option<FerrixError>) {
+        let (_, parsed, errs) =
+            Self::parse_recovering.recoverable_parse(LocatingSlice::new(self.input));
+        let err = (!errs.is_empty()).then(|| failure_from_errs(errs, self.input));
+
+        (parsed.unwrap_or_default(), err)
+    }
+
+    /// Parse commands until the end of the input, skipping to the next line after a command
+    /// fails to parse and recording its error
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// recovering := (multispace0 (complete_command | skip_line))* multispace0 eof;
+    /// ```
+    fn parse_recovering(input: &mut Input<'_>) -> ParserResult<Vec<CompleteCommand>> {
+        repeat_till(
+            0..,
+            preceded(
+                multispace0,
+                Self::parse_complete_command.resume_after(Self::skip_line),
+            ),
+            (multispace0, eof),
+        )
+        .map(|(commands, _): (Vec<Option<CompleteCommand>>, _)| {
+            commands.into_iter().flatten().collect()
+        })
+        .parse_next(input)
+    }
+
+    /// Skip the rest of the line, leaving the newline to the next command
+    fn skip_line(input: &mut Input<'_>) -> ParserResult<()> {
+        repeat(0.., (not(Self::newline), any).void()).parse_next(input)
+    }
+
+    /// Parse commands until the end of the input
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// script := commands eof;
+    /// ```
+    fn parse_script(input: &mut Input<'_>) -> ParserResult<Vec<CompleteCommand>> {
+        let commands = Self::parse_commands(input)?;
+        Self::end_of_input(input)?;
+
+        Ok(commands)
+    }
+
+    /// Fail pointing at the first unconsumed byte unless the whole input was consumed
+    fn end_of_input(input: &mut Input<'_>) -> ParserResult<()> {
+        let Some((_, next)) = input.peek_token() else {
+            return Ok(());
+        };
+
+        let start = input.location();
+        Err(ErrMode::Cut(FerrixParserError {
+            message: Some("Unexpected input after the last command".into()),
+            span: Some((start..start + next.len_utf8()).into()),
+            label: Some("end of input".into()),
+            help: Some("Separate commands with a newline or ;".into()),
+            severity: Some(Severity::Error),
+        }))
+    }
+
     fn parse_commands(input: &mut Input<'_>) -> ParserResult<Vec<CompleteCommand>> {
         (repeat(1.., Self::parse_complete_command), multispace0)
             .map(|(cmds, _): (Vec<CompleteCommand>, _)| cmds)
@@ -256,15 +353,21 @@ impl<'a> WinnowFerrixParser<'a> {
     /// # Grammar
     ///
     /// ```md
-    /// complete_command := touch_command
+    /// complete_command := multispace0 command command_terminator;
+    /// command := touch_command
     ///                 | move_command
+    ///                 | copy_command
+    ///                 | link_command
     ///                 | mkdir_command
     ///                 | remove_command
     ///                 | head_command
     ///                 | list_command
     ///                 | sort_command
     ///                 | cat_command
-    ///                 | exit_command;
+    ///                 | exit_command
+    ///                 | cd_command
+    ///                 | pwd_command
+    ///                 | find_command;
     /// ```
     fn parse_complete_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
         let command = delimited(
@@ -272,6 +375,8 @@ impl<'a> WinnowFerrixParser<'a> {
             alt((
                 Self::parse_touch_command,
                 Self::parse_move_command,
+                Self::parse_copy_command,
+                Self::parse_link_command,
                 Self::parse_mkdir_command,
                 Self::parse_remove_command,
                 Self::parse_head_command,
@@ -279,9 +384,12 @@ impl<'a> WinnowFerrixParser<'a> {
                 Self::parse_sort_command,
                 Self::parse_cat_command,
                 Self::parse_exit_command,
+                Self::parse_cd_command,
+                Self::parse_pwd_command,
+                Self::parse_find_command,
                 fail.context(cx().msg("Unknown command").lbl("valid command")),
             )),
-            Self::newline,
+            Self::command_terminator,
         )
         .parse_next(input)?;
 
@@ -351,6 +459,72 @@ impl<'a> WinnowFerrixParser<'a> {
         Ok(CompleteCommand::Move { from, to })
     }
 
+    /// Parse a copy command from the input
+    ///
+    /// # Grammar
+    /// ```md
+    /// copy_command := ("copy" | "cp") path_buffer path_buffer;
+    /// ```
+    fn parse_copy_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
+        Self::wss.parse_next(input)?;
+
+        alt(("copy", "cp")).parse_next(input)?;
+
+        let from = Self::parse_path_buffer(input).map_err(|e| {
+            e.add_context(
+                input,
+                &input.checkpoint(),
+                cx().msg("Expected a 'from' path buffer"),
+            )
+        })?;
+        let to = Self::parse_path_buffer(input).map_err(|e| {
+            e.add_context(
+                input,
+                &input.checkpoint(),
+                cx().msg("Expected a 'to' path buffer"),
+            )
+        })?;
+
+        Ok(CompleteCommand::Copy { from, to })
+    }
+
+    /// Parse a link command from the input
+    ///
+    /// # Grammar
+    /// ```md
+    /// link_command := ("link" | "ln") (("-s" | "--symbolic"))? path_buffer path_buffer;
+    /// ```
+    fn parse_link_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
+        Self::wss.parse_next(input)?;
+
+        alt(("link", "ln")).parse_next(input)?;
+
+        let symbolic = opt(preceded(Self::wsp, alt(("--symbolic", "-s"))))
+            .map(|flag| flag.is_some())
+            .parse_next(input)?;
+
+        let target = Self::parse_path_buffer(input).map_err(|e| {
+            e.add_context(
+                input,
+                &input.checkpoint(),
+                cx().msg("Expected a 'target' path buffer"),
+            )
+        })?;
+        let link_name = Self::parse_path_buffer(input).map_err(|e| {
+            e.add_context(
+                input,
+                &input.checkpoint(),
+                cx().msg("Expected a 'link name' path buffer"),
+            )
+        })?;
+
+        Ok(CompleteCommand::Link {
+            target,
+            link_name,
+            symbolic,
+        })
+    }
+
     /// Parse a mkdir command from the input
     ///
     /// # Grammar
@@ -418,8 +592,11 @@ impl<'a> WinnowFerrixParser<'a> {
     /// Parse a head command from the input
     /// # Grammar
     /// ```md
-    /// head_command := "head" path_buffer integer integer line_space*;
+    /// head_command := "head" path_buffer (integer integer?)? line_space*;
     /// ```
+    ///
+    /// A single integer is the end, read from the start of the file like `head -n`, and without
+    /// any the first [`DEFAULT_HEAD_END`] integers are read
     fn parse_head_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
         Self::wss.parse_next(input)?;
 
@@ -433,21 +610,31 @@ impl<'a> WinnowFerrixParser<'a> {
             )
         })?;
 
-        let start = Self::parse_unsigned_integer(input).map_err(|e| {
-            e.add_context(
-                input,
-                &input.checkpoint(),
-                cx().msg("Expected a start integer for head command"),
-            )
-        })?;
+        let first = opt(Self::parse_unsigned_integer)
+            .parse_next(input)
+            .map_err(|e| {
+                e.add_context(
+                    input,
+                    &input.checkpoint(),
+                    cx().msg("Expected a start integer for head command"),
+                )
+            })?;
 
-        let end = Self::parse_unsigned_integer(input).map_err(|e| {
-            e.add_context(
-                input,
-                &input.checkpoint(),
-                cx().msg("Expected an end integer for head command"),
-            )
-        })?;
+        let second = opt(Self::parse_unsigned_integer)
+            .parse_next(input)
+            .map_err(|e| {
+                e.add_context(
+                    input,
+                    &input.checkpoint(),
+                    cx().msg("Expected an end integer for head command"),
+                )
+            })?;
+
+        let (start, end) = match (first, second) {
+            (Some(start), Some(end)) => (start, end),
+            (Some(end), None) => (0, end),
+            _ => (0, DEFAULT_HEAD_END),
+        };
 
         repeat(0.., Self::line_space)
             .map(|_: ()| ())
@@ -520,7 +707,7 @@ impl<'a> WinnowFerrixParser<'a> {
     ///
     /// # Grammar
     /// ```md
-    /// cat_command := "cat" path_buffer path_buffer+ ( ">" path_buffer)? line_space*;
+    /// cat_command := "cat" path_buffer path_buffer+ ((">>" | ">") path_buffer)? line_space*;
     /// ```
     fn parse_cat_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
         Self::wss.parse_next(input)?;
@@ -534,36 +721,120 @@ impl<'a> WinnowFerrixParser<'a> {
             })
             .parse_next(input)?;
 
-        let output_file = opt(delimited(
-            ">",
+        // `>>` has to be tried first, otherwise `>` would match its first character
+        let output = opt((
+            alt((">>".value(true), ">".value(false))),
             Self::parse_path_buffer,
-            repeat(0.., Self::line_space).map(|_: ()| ()).take(),
+            repeat(0.., Self::line_space).map(|_: ()| ()),
         ))
         .parse_next(input)?;
+        let (output_file, append) = match output {
+            Some((append, output_file, _)) => (Some(output_file), append),
+            None => (None, false),
+        };
 
-        Ok(CompleteCommand::Cat { files, output_file })
+        Ok(CompleteCommand::Cat {
+            files,
+            output_file,
+            append,
+        })
     }
 
     /// Parse an exit command from the input
     ///
     /// # Grammar
     /// ```md
-    /// exit_command := "exit" integer;
+    /// exit_command := "exit" integer line_space*;
     /// ```
     fn parse_exit_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
         Self::wss.parse_next(input)?;
 
         "exit".parse_next(input)?;
 
-        let code = Self::parse_unsigned_integer(input).map_err(|e| {
+        let code = Self::parse_unsigned_integer
+            .verify_map(|code| i32::try_from(code).ok())
+            .parse_next(input)
+            .map_err(|e| {
+                e.add_context(
+                    input,
+                    &input.checkpoint(),
+                    cx().msg("Expected an exit code for exit command"),
+                )
+            })?;
+
+        Ok(CompleteCommand::Exit { code })
+    }
+
+    /// Parse a cd command from the input
+    ///
+    /// # Grammar
+    /// ```md
+    /// cd_command := "cd" path_buffer? line_space*;
+    /// ```
+    fn parse_cd_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
+        Self::wss.parse_next(input)?;
+
+        "cd".parse_next(input)?;
+
+        // The path has to be apart from the command so `cdx` isn't `cd x`
+        let path = opt(preceded(Self::wsp, Self::parse_path_buffer)).parse_next(input)?;
+
+        repeat(0.., Self::line_space)
+            .map(|_: ()| ())
+            .parse_next(input)?;
+
+        Ok(CompleteCommand::ChangeDir { path })
+    }
+
+    /// Parse a pwd command from the input
+    ///
+    /// # Grammar
+    /// ```md
+    /// pwd_command := "pwd";
+    /// ```
+    fn parse_pwd_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
+        Self::wss.parse_next(input)?;
+
+        "pwd".parse_next(input)?;
+
+        repeat(0.., Self::line_space)
+            .map(|_: ()| ())
+            .parse_next(input)?;
+
+        Ok(CompleteCommand::Pwd)
+    }
+
+    /// Parse a find command from the input
+    ///
+    /// # Grammar
+    /// ```md
+    /// find_command := "find" path_buffer path_buffer;
+    /// ```
+    fn parse_find_command(input: &mut Input<'_>) -> ParserResult<CompleteCommand> {
+        Self::wss.parse_next(input)?;
+
+        "find".parse_next(input)?;
+
+        let dir = Self::parse_path_buffer(input).map_err(|e| {
+            e.add_context(
+                input,
+                &input.checkpoint(),
+                cx().msg("Expected a directory for find command"),
+            )
+        })?;
+        let name = Self::parse_path_buffer(input).map_err(|e| {
             e.add_context(
                 input,
                 &input.checkpoint(),
-                cx().msg("Expected an exit code for exit command"),
+                cx().msg("Expected a name for find command")
+                    .hlp("The name of the entries to find"),
             )
         })?;
 
-        Ok(CompleteCommand::Exit { code })
+        Ok(CompleteCommand::Find {
+            dir,
+            name: name.to_string_lossy().into_owned(),
+        })
     }
 
     /// Parse a path buffer from the input
@@ -571,14 +842,12 @@ impl<'a> WinnowFerrixParser<'a> {
     /// # Grammar
     ///
     /// ```md
-    /// path_buffer := wsp? string line_space;
+    /// path_buffer := wsp? (quoted_path | unquoted_path) line_space;
     /// ```
     fn parse_path_buffer(input: &mut Input<'_>) -> ParserResult<PathBuf> {
         delimited(
             repeat(0.., Self::wsp).map(|_: ()| ()).take(),
-            take_while(1.., |c: char| {
-                c.is_ascii_alphanumeric() || c == MAIN_SEPARATOR || c == '.'
-            }),
+            alt((Self::parse_quoted_path, Self::parse_unquoted_path)),
             repeat(0.., Self::line_space).map(|_: ()| ()).take(),
         )
         .map(|s: &str| PathBuf::from(s))
@@ -592,31 +861,103 @@ impl<'a> WinnowFerrixParser<'a> {
         })
     }
 
+    /// Parse a path between single or double quotes, which may contain spaces and any other
+    /// printable character but the closing quote, returning it without the quotes
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// quoted_path := "\"" ^"\""* "\"" | "'" ^"'"* "'";
+    /// ```
+    fn parse_quoted_path<'i>(input: &mut Input<'i>) -> ParserResult<&'i str> {
+        let opening = input.location();
+        let quote = one_of(['"', '\'']).parse_next(input)?;
+
+        let path = take_while(0.., |c: char| c != quote && (c == ' ' || !c.is_control()))
+            .parse_next(input)?;
+
+        if opt(quote).parse_next(input)?.is_none() {
+            return Err(ErrMode::Cut(FerrixParserError {
+                message: Some("Unterminated quoted path".into()),
+                span: Some((opening..opening + 1).into()),
+                label: Some("closed".into()),
+                help: Some(format!("Close the path with a matching {quote}")),
+                severity: Some(Severity::Error),
+            }));
+        }
+
+        Ok(path)
+    }
+
+    /// Parse a path without quotes, which may start with `~` standing for the home directory and
+    /// expanded by the system. The home directory of another user, `~user`, is rejected
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// unquoted_path := "~"? (alphanumeric | separator | ".")*;
+    /// ```
+    fn parse_unquoted_path<'i>(input: &mut Input<'i>) -> ParserResult<&'i str> {
+        let start = input.location();
+        let path = (
+            opt(HOME_PATH_PREFIX),
+            take_while(0.., |c: char| {
+                c.is_ascii_alphanumeric() || c == MAIN_SEPARATOR || c == '.'
+            }),
+        )
+            .take()
+            .verify(|path: &str| !path.is_empty())
+            .parse_next(input)?;
+
+        let user = path
+            .strip_prefix(HOME_PATH_PREFIX)
+            .map(|rest| rest.split(MAIN_SEPARATOR).next().unwrap_or_default());
+        if let Some(user) = user.filter(|user| !user.is_empty()) {
+            return Err(ErrMode::Cut(FerrixParserError {
+                message: Some(format!("Home directory of user {user} is not supported")),
+                span: Some((start..start + 1 + user.len()).into()),
+                label: Some("expanded".into()),
+                help: Some(format!(
+                    "Only the own home directory is expanded, use {HOME_PATH_PREFIX} or \
+                    {HOME_PATH_PREFIX}/path instead of {HOME_PATH_PREFIX}{user}"
+                )),
+                severity: Some(Severity::Error),
+            }));
+        }
+
+        Ok(path)
+    }
+
     /// Parse an unsigned integer from the input
     ///
     /// # Grammar
     ///
     /// ```md
-    /// integer := line_space* digit1 (node_space | line_space)*;
+    /// integer := line_space* (("0x" | "0X") radix_digits | ("0b" | "0B") radix_digits | digit1)
+    ///     (node_space | line_space)*;
     /// ```
     fn parse_unsigned_integer(input: &mut Input<'_>) -> ParserResult<u32> {
         delimited(
             repeat(0.., Self::wsp).map(|_: ()| ()).take(),
             trace(
                 "parse_unsigned_integer",
-                (
-                    digit1,
-                    repeat(
-                        0..,
-                        alt(("_", take_while(1.., AsChar::is_dec_digit).take())),
-                    ),
-                )
-                    .try_map(|(l, r): (&str, Vec<&str>)| {
-                        u32::from_str_radix(
-                            &format!("{l}{}", str::replace(&r.join(""), "_", "")),
-                            10,
-                        )
-                    }),
+                alt((
+                    Self::parse_radix_integer(("0x", "0X"), 16),
+                    Self::parse_radix_integer(("0b", "0B"), 2),
+                    (
+                        digit1,
+                        repeat(
+                            0..,
+                            alt(("_", take_while(1.., AsChar::is_dec_digit).take())),
+                        ),
+                    )
+                        .try_map(|(l, r): (&str, Vec<&str>)| {
+                            u32::from_str_radix(
+                                &format!("{l}{}", str::replace(&r.join(""), "_", "")),
+                                10,
+                            )
+                        }),
+                )),
             ),
             repeat(0.., Self::line_space).map(|_: ()| ()).take(),
         )
@@ -630,6 +971,44 @@ impl<'a> WinnowFerrixParser<'a> {
         })
     }
 
+    /// Parse an integer in `radix` after one of its `prefixes`, `_` may group the digits.
+    ///
+    /// Every alphanumeric character is taken so a digit out of `radix` is reported as an invalid
+    /// integer spanning the whole literal instead of ending it early
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// radix_digits := (alphanumeric | "_")*;
+    /// ```
+    fn parse_radix_integer<'i>(
+        prefixes: (&'static str, &'static str),
+        radix: u32,
+    ) -> impl Parser<Input<'i>, u32, FerrixParserError> {
+        let is_radix_digit = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        move |input: &mut Input<'i>| {
+            let token =
+                peek((alt(prefixes), take_while(0.., is_radix_digit)).span()).parse_next(input)?;
+
+            preceded(
+                alt(prefixes),
+                cut_err(
+                    take_while(0.., is_radix_digit).try_map(|digits: &str| {
+                        u32::from_str_radix(&digits.replace('_', ""), radix)
+                    }),
+                ),
+            )
+            .parse_next(input)
+            .map_err(|e| {
+                e.map(|e| FerrixParserError {
+                    span: Some(token.clone().into()),
+                    ..e
+                })
+            })
+        }
+    }
+
     /// Parse a line space from the input
     ///
     /// # Grammar
@@ -660,6 +1039,24 @@ impl<'a> WinnowFerrixParser<'a> {
         .parse_next(input)
     }
 
+    /// Parse the end of a command, either the end of the line or a `;` to chain another command
+    /// on the same line. The last command may also end with the input
+    ///
+    /// # Grammar
+    ///
+    /// ```md
+    /// command_terminator := newline | ";" line_space* | eof;
+    /// ```
+    fn command_terminator(input: &mut Input<'_>) -> ParserResult<()> {
+        alt((
+            Self::newline,
+            (";", repeat(0.., Self::line_space).map(|_: ()| ())).void(),
+            eof.void(),
+        ))
+        .context(cx().lbl("newline or ;"))
+        .parse_next(input)
+    }
+
     /// Parse a newline character from the input
     fn newline(input: &mut Input<'_>) -> ParserResult<()> {
         alt(NEWLINES)
@@ -743,6 +1140,12 @@ mod tests {
             "123   ",
             "   123   ",
             "123   # comment",
+            "0",
+            "0xFF",
+            "0X1_0",
+            "  0xdead_beef  ",
+            "0b1010",
+            "0B1111_0000",
         ];
         let outputs = [
             123,
@@ -754,6 +1157,12 @@ mod tests {
             123,
             123,
             123,
+            0,
+            0xFF,
+            0x10,
+            0xdead_beef,
+            0b1010,
+            0b1111_0000,
         ];
 
         // Arrange
@@ -765,6 +1174,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_invalid_radix_integer() {
+        // Arrange
+        let inputs = ["0b102", "0xG", "0x", "0x1_0000_0000"];
+
+        for input in inputs {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_unsigned_integer, input);
+
+            // Assert
+            let err = result.unwrap_err();
+            assert_eq!(
+                err.diagnostics[0].label.as_deref(),
+                Some("not invalid integer"),
+                "{input}"
+            );
+            assert_eq!(err.diagnostics[0].span, (0..input.len()).into(), "{input}");
+        }
+    }
+
     #[test]
     fn test_parse_path_buffer() {
         // Arrange
@@ -779,11 +1208,21 @@ mod tests {
 
         #[cfg(target_family = "unix")]
         {
-            inputs.extend(vec!["./test.txt", "/test.txt", "/tmp/test.txt"]);
+            inputs.extend(vec![
+                "./test.txt",
+                "/test.txt",
+                "/tmp/test.txt",
+                "\"/tmp/my file.txt\"",
+                "  'a.txt'  ",
+                "'say \"hi\" (1)'",
+            ]);
             outputs.extend(vec![
                 PathBuf::from("./test.txt"),
                 PathBuf::from("/test.txt"),
                 PathBuf::from("/tmp/test.txt"),
+                PathBuf::from("/tmp/my file.txt"),
+                PathBuf::from("a.txt"),
+                PathBuf::from("say \"hi\" (1)"),
             ]);
         }
 
@@ -912,6 +1351,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_copy_command() {
+        // Arrange
+        let inputs = [
+            "cp test.txt test2.txt",
+            "cp test.txt test2.txt   ",
+            "   cp test.txt test2.txt",
+            "   copy test.txt test2.txt   ",
+        ];
+        let output = CompleteCommand::Copy {
+            from: PathBuf::from("test.txt"),
+            to: PathBuf::from("test2.txt"),
+        };
+
+        for input in inputs {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_copy_command, input);
+
+            // Assert
+            assert_eq!(result.unwrap(), output);
+        }
+    }
+
     #[test]
     fn test_mkdir_command() {
         // Arrange
@@ -1110,6 +1572,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_head_command_counts() {
+        // Arrange
+        let inputs = [
+            "head test.txt 20",
+            "head test.txt 20   ",
+            "   head test.txt",
+            "head test.txt   ",
+            "head test.txt 5 20",
+        ];
+        let outputs = [
+            (0, 20),
+            (0, 20),
+            (0, DEFAULT_HEAD_END),
+            (0, DEFAULT_HEAD_END),
+            (5, 20),
+        ];
+
+        for (input, (start, end)) in inputs.iter().zip(outputs) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_head_command, input);
+
+            // Assert
+            assert_eq!(
+                result.unwrap(),
+                CompleteCommand::Head {
+                    file: PathBuf::from("test.txt"),
+                    start,
+                    end,
+                }
+            );
+        }
+    }
+
     #[test]
     fn test_list_command() {
         // Arrange
@@ -1227,40 +1723,60 @@ mod tests {
             "cat test.txt test2.txt > output.txt   ",
             "   cat test.txt test2.txt > output.txt",
             "   cat test.txt test2.txt > output.txt   ",
+            "cat a.txt b.txt >> out.txt",
+            "cat a.txt b.txt>>out.txt",
         ];
 
         let outputs = [
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: None,
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: None,
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: None,
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: None,
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: Some(PathBuf::from("output.txt")),
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: Some(PathBuf::from("output.txt")),
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: Some(PathBuf::from("output.txt")),
+                append: false,
             },
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: Some(PathBuf::from("output.txt")),
+                append: false,
+            },
+            CompleteCommand::Cat {
+                files: vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")],
+                output_file: Some(PathBuf::from("out.txt")),
+                append: true,
+            },
+            CompleteCommand::Cat {
+                files: vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")],
+                output_file: Some(PathBuf::from("out.txt")),
+                append: true,
             },
         ];
 
@@ -1293,12 +1809,131 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_exit_code_out_of_range() {
+        // Arrange
+        let input = "exit 4294967295";
+
+        // Act
+        let result = try_parse(WinnowFerrixParser::parse_exit_command, input);
+
+        // Assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cd_command() {
+        // Arrange
+        let inputs = ["cd /tmp", "cd ..   ", "   cd 'my dir'", "   cd   ", "cd"];
+        let outputs = [
+            CompleteCommand::ChangeDir {
+                path: Some(PathBuf::from("/tmp")),
+            },
+            CompleteCommand::ChangeDir {
+                path: Some(PathBuf::from("..")),
+            },
+            CompleteCommand::ChangeDir {
+                path: Some(PathBuf::from("my dir")),
+            },
+            CompleteCommand::ChangeDir { path: None },
+            CompleteCommand::ChangeDir { path: None },
+        ];
+
+        // Act
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            let result = try_parse(WinnowFerrixParser::parse_cd_command, input);
+
+            // Assert
+            assert_eq!(result.unwrap(), *output);
+        }
+    }
+
+    #[test]
+    fn test_link_command() {
+        // Arrange
+        let inputs = [
+            "ln a.txt b.txt",
+            "   link a.txt b.txt   ",
+            "ln -s ../a.txt b.txt",
+            "ln --symbolic /a.txt 'my link'",
+        ];
+        let outputs = [
+            ("a.txt", "b.txt", false),
+            ("a.txt", "b.txt", false),
+            ("../a.txt", "b.txt", true),
+            ("/a.txt", "my link", true),
+        ];
+
+        for (input, (target, link_name, symbolic)) in inputs.iter().zip(outputs) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_link_command, input);
+
+            // Assert
+            assert_eq!(
+                result.unwrap(),
+                CompleteCommand::Link {
+                    target: PathBuf::from(target),
+                    link_name: PathBuf::from(link_name),
+                    symbolic,
+                },
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_command() {
+        // Arrange
+        let inputs = [
+            "find / a.txt",
+            "find / a.txt   ",
+            "   find / a.txt",
+            "   find test 'my file'   ",
+        ];
+        let outputs = [
+            ("/", "a.txt"),
+            ("/", "a.txt"),
+            ("/", "a.txt"),
+            ("test", "my file"),
+        ];
+
+        for (input, (dir, name)) in inputs.iter().zip(outputs) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_find_command, input);
+
+            // Assert
+            assert_eq!(
+                result.unwrap(),
+                CompleteCommand::Find {
+                    dir: PathBuf::from(dir),
+                    name: name.to_string(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_pwd_command() {
+        // Arrange
+        let inputs = ["pwd", "pwd   ", "   pwd", "   pwd   "];
+
+        // Act
+        for input in inputs.iter() {
+            let result = try_parse(WinnowFerrixParser::parse_pwd_command, input);
+
+            // Assert
+            assert_eq!(result.unwrap(), CompleteCommand::Pwd);
+        }
+    }
+
     #[test]
     fn test_parse_all_commands() {
         // Arrange
         let input = r#"
             touch test.txt 100
             move test.txt test2.txt
+            cp test2.txt test3.txt
+            ln -s test3.txt test4.txt
             mkdir test
             remove test.txt
             head test.txt 0 100
@@ -1306,6 +1941,9 @@ mod tests {
             sort test.txt
             cat test.txt test2.txt > output.txt
             exit 0
+            cd /tmp
+            pwd
+            find /tmp test.txt
         "#;
 
         let outputs = [
@@ -1317,6 +1955,15 @@ mod tests {
                 from: PathBuf::from("test.txt"),
                 to: PathBuf::from("test2.txt"),
             },
+            CompleteCommand::Copy {
+                from: PathBuf::from("test2.txt"),
+                to: PathBuf::from("test3.txt"),
+            },
+            CompleteCommand::Link {
+                target: PathBuf::from("test3.txt"),
+                link_name: PathBuf::from("test4.txt"),
+                symbolic: true,
+            },
             CompleteCommand::MkDir {
                 dir: PathBuf::from("test"),
                 parents: false,
@@ -1341,8 +1988,17 @@ mod tests {
             CompleteCommand::Cat {
                 files: vec![PathBuf::from("test.txt"), PathBuf::from("test2.txt")],
                 output_file: Some(PathBuf::from("output.txt")),
+                append: false,
             },
             CompleteCommand::Exit { code: 0 },
+            CompleteCommand::ChangeDir {
+                path: Some(PathBuf::from("/tmp")),
+            },
+            CompleteCommand::Pwd,
+            CompleteCommand::Find {
+                dir: PathBuf::from("/tmp"),
+                name: "test.txt".to_string(),
+            },
         ];
 
         // Arrange
@@ -1353,6 +2009,204 @@ mod tests {
         assert_eq!(result, outputs);
     }
 
+    #[test]
+    fn test_quoted_paths() {
+        // Arrange
+        let input = "touch \"my file.txt\" 10\ncat 'a b' \"c d\" > 'e f'\n";
+        let outputs = [
+            CompleteCommand::Touch {
+                file: PathBuf::from("my file.txt"),
+                number_of_integers: 10,
+            },
+            CompleteCommand::Cat {
+                files: vec![PathBuf::from("a b"), PathBuf::from("c d")],
+                output_file: Some(PathBuf::from("e f")),
+                append: false,
+            },
+        ];
+
+        // Act
+        let mut parser = WinnowFerrixParser::new(input);
+        let result = parser.get_commands().unwrap();
+
+        // Assert
+        assert_eq!(result, outputs);
+    }
+
+    #[test]
+    fn test_unterminated_quoted_path() {
+        // Arrange
+        let inputs = ["head \"a 0 10", "cat a 'b c\n", "touch \"a\n\" 10\n"];
+        let outputs = [5, 6, 6];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_script, input);
+
+            // Assert
+            let diagnostics = result.unwrap_err().diagnostics;
+            assert_eq!(
+                diagnostics[0].span,
+                (*output..*output + 1).into(),
+                "{input}"
+            );
+            assert_eq!(diagnostics[0].label.as_deref(), Some("not closed"));
+        }
+    }
+
+    #[test]
+    fn test_home_paths() {
+        // Arrange
+        let inputs = ["ls ~\n", "touch ~/data.txt 10\n", "cd ~/a/..\n"];
+        let outputs = [
+            CompleteCommand::List {
+                dir: Some(PathBuf::from("~")),
+                all: false,
+            },
+            CompleteCommand::Touch {
+                file: PathBuf::from("~/data.txt"),
+                number_of_integers: 10,
+            },
+            CompleteCommand::ChangeDir {
+                path: Some(PathBuf::from("~/a/..")),
+            },
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_script, input);
+
+            // Assert
+            assert_eq!(result.unwrap(), vec![output.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_user_home_path() {
+        // Arrange
+        let inputs = ["ls ~root\n", "touch ~bob/data.txt 10\n"];
+        let outputs = [(3..8, "root"), (6..10, "bob")];
+
+        for (input, (span, user)) in inputs.iter().zip(outputs) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_script, input);
+
+            // Assert
+            let diagnostics = result.unwrap_err().diagnostics;
+            assert_eq!(diagnostics[0].span, span.into(), "{input}");
+            assert_eq!(
+                diagnostics[0].help,
+                Some(format!(
+                    "Only the own home directory is expanded, use ~ or ~/path instead of ~{user}"
+                ))
+            );
+            assert_eq!(diagnostics[0].label.as_deref(), Some("not expanded"));
+        }
+    }
+
+    #[test]
+    fn test_strict_rejects_trailing_garbage() {
+        // Arrange
+        let inputs = ["pwd\n$$$", "touch a 10\n  foo bar\n", "ls\npwd\n123"];
+        let outputs = [4, 13, 7];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let strict = try_parse(WinnowFerrixParser::parse_script, input);
+            let (lenient, _) = WinnowFerrixParser::new(input).get_commands_lenient();
+
+            // Assert
+            let diagnostics = strict.unwrap_err().diagnostics;
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].span, (*output..*output + 1).into());
+            assert!(!lenient.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_command_chaining() {
+        // Arrange
+        let inputs = [
+            "mkdir foo -p ; touch foo/a.txt 10 ; ls foo\n",
+            "pwd;ls\n",
+            "pwd ;",
+            "pwd ;\nls ;  \n",
+            "pwd ; # print it\nls\n",
+        ];
+        let mkdir = CompleteCommand::MkDir {
+            dir: PathBuf::from("foo"),
+            parents: true,
+        };
+        let touch = CompleteCommand::Touch {
+            file: PathBuf::from("foo/a.txt"),
+            number_of_integers: 10,
+        };
+        let ls = |dir: Option<&str>| CompleteCommand::List {
+            dir: dir.map(PathBuf::from),
+            all: false,
+        };
+        let outputs = [
+            vec![mkdir, touch, ls(Some("foo"))],
+            vec![CompleteCommand::Pwd, ls(None)],
+            vec![CompleteCommand::Pwd],
+            vec![CompleteCommand::Pwd, ls(None)],
+            vec![CompleteCommand::Pwd, ls(None)],
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = try_parse(WinnowFerrixParser::parse_script, input);
+
+            // Assert
+            assert_eq!(result.unwrap(), *output, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn test_recover_every_bad_command() {
+        // Arrange
+        let inputs = [
+            "pwd\nfoo bar\nls\n$$$\nexit 2\n",
+            "cd 'open\npwd",
+            "pwd\nls\n",
+        ];
+        let outputs = [
+            (
+                vec![
+                    CompleteCommand::Pwd,
+                    CompleteCommand::List {
+                        dir: None,
+                        all: false,
+                    },
+                    CompleteCommand::Exit { code: 2 },
+                ],
+                vec![(4..11).into(), (15..18).into()],
+            ),
+            (vec![CompleteCommand::Pwd], vec![(3..4).into()]),
+            (
+                vec![
+                    CompleteCommand::Pwd,
+                    CompleteCommand::List {
+                        dir: None,
+                        all: false,
+                    },
+                ],
+                vec![],
+            ),
+        ];
+
+        for (input, (commands, spans)) in inputs.iter().zip(outputs) {
+            // Act
+            let (parsed, err) = WinnowFerrixParser::new(input).get_commands_lenient();
+
+            // Assert
+            assert_eq!(parsed, commands, "{input:?}");
+            let diagnostics = err.map(|err| err.diagnostics).unwrap_or_default();
+            let parsed_spans: Vec<SourceSpan> = diagnostics.iter().map(|d| d.span).collect();
+            assert_eq!(parsed_spans, spans, "{input:?}");
+        }
+    }
+
     #[test]
     fn test_single_line_comment() {
         // Arrange
@@ -0,0 +1,138 @@
+use std::io::{IsTerminal, Stderr, Write};
+
+/// Width in characters of the bar between the brackets
+const BAR_WIDTH: u64 = 40;
+
+/// A progress bar redrawn in place on its own line, as `[####    ] 512/1024`
+pub struct ProgressBar<W: Write> {
+    writer: W,
+}
+
+impl ProgressBar<Stderr> {
+    /// A bar drawn on stderr, `None` unless it is `enabled` and both stdout and stderr are
+    /// terminals, so the output of a command piped elsewhere is never mixed with it
+    pub fn stderr(enabled: bool) -> Option<Self> {
+        let is_terminal = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+        (enabled && is_terminal).then(|| Self::new(std::io::stderr()))
+    }
+}
+
+impl<W: Write> ProgressBar<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Redraw the bar with `done` out of `total` elements
+    pub fn update(&mut self, done: u64, total: u64) {
+        let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+        let bar = format!(
+            "\r[{}{}] {done}/{total}",
+            "#".repeat(filled as usize),
+            " ".repeat((BAR_WIDTH - filled) as usize)
+        );
+
+        // A bar that can't be drawn shouldn't fail the command it reports on
+        let _ = self.writer.write_all(bar.as_bytes());
+        let _ = self.writer.flush();
+    }
+
+    /// Move past the line of the bar so it is kept on screen
+    pub fn finish(mut self) {
+        let _ = self.writer.write_all(b"\n");
+        let _ = self.writer.flush();
+    }
+}
+
+/// Runs `op` with a progress callback drawing a [`ProgressBar::stderr`] bar, a no-op callback
+/// if the bar is off
+pub fn with_stderr_progress<T>(enabled: bool, op: impl FnOnce(&mut dyn FnMut(u64, u64)) -> T) -> T {
+    let mut bar = ProgressBar::stderr(enabled);
+    let result = op(&mut |done, total| {
+        if let Some(bar) = &mut bar {
+            bar.update(done, total);
+        }
+    });
+
+    if let Some(bar) = bar {
+        bar.finish();
+    }
+    result
+}
+
+/// Calls `on_progress` with the elements done and the total every `step` elements, and once more
+/// on [`Progress::finish`] if the last elements were not reported yet
+pub struct Progress<F: FnMut(u64, u64)> {
+    total: u64,
+    step: u64,
+    reported: u64,
+    on_progress: F,
+}
+
+impl<F: FnMut(u64, u64)> Progress<F> {
+    pub fn new(total: u64, step: u64, on_progress: F) -> Self {
+        Self {
+            total,
+            step,
+            reported: 0,
+            on_progress,
+        }
+    }
+
+    pub fn update(&mut self, done: u64) {
+        if done >= self.reported + self.step {
+            self.reported = done - done % self.step;
+            (self.on_progress)(self.reported, self.total);
+        }
+    }
+
+    pub fn finish(mut self) {
+        if self.reported != self.total {
+            (self.on_progress)(self.total, self.total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_fires_every_step() {
+        // Arrange
+        let inputs = [(10_000, 4096), (8192, 4096), (100, 4096), (0, 4096)];
+        let outputs: [&[u64]; 4] = [&[4096, 8192, 10_000], &[4096, 8192], &[100], &[]];
+
+        for ((total, step), output) in inputs.into_iter().zip(outputs) {
+            let mut calls = Vec::new();
+            let mut progress = Progress::new(total, step, |done, _| calls.push(done));
+
+            // Act
+            for done in 1..=total {
+                progress.update(done);
+            }
+            progress.finish();
+
+            // Assert
+            assert_eq!(calls, output, "{total} {step}");
+        }
+    }
+
+    #[test]
+    fn bar_redraws_in_place() {
+        // Arrange
+        let mut output = Vec::new();
+        let mut bar = ProgressBar::new(&mut output);
+
+        // Act
+        bar.update(1, 4);
+        bar.update(4, 4);
+        bar.finish();
+
+        // Assert
+        let bars = [
+            format!("\r[{}{}] 1/4", "#".repeat(10), " ".repeat(30)),
+            format!("\r[{}] 4/4", "#".repeat(40)),
+        ];
+        assert_eq!(String::from_utf8(output).unwrap(), bars.concat() + "\n");
+    }
+}
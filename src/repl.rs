@@ -42,23 +42,20 @@ where
 
             self.input_stream.read_line(&mut buffer).into_diagnostic()?;
 
+            // Report every command that failed to parse but still run the others
             let mut parser = WinnowFerrixParser::new(&buffer);
+            let (commands, err) = parser.get_commands_lenient();
+            if let Some(err) = err {
+                eprintln!("{:?}", miette::Report::new(err));
+            }
 
-            match parser.get_commands() {
-                Ok(commands) => {
-                    for command in commands {
-                        match command {
-                            CompleteCommand::Exit { code } => {
-                                let code = i32::try_from(*code).into_diagnostic()?;
-                                exit(code);
-                            }
-                            _ => {
-                                eprintln!("Command not implemented: {:?}", command);
-                            }
-                        }
+            for command in commands {
+                match command {
+                    CompleteCommand::Exit { code } => exit(code),
+                    _ => {
+                        eprintln!("Command not implemented: {:?}", command);
                     }
                 }
-                Err(err) => eprintln!("{:?}", err),
             }
 
             buffer.clear();
@@ -1,22 +1,48 @@
 use byte_unit::{Byte, Unit, UnitType};
 use clean_path::Clean;
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{IsTerminal, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use tabled::Table;
 
-use clap_repl::reedline::{Prompt, PromptHistorySearchStatus};
+use clap::builder::ValueParser;
+use clap::{CommandFactory, Parser};
+use clap_repl::reedline::{
+    default_emacs_keybindings, Emacs, History, HistoryItem, KeyCode, KeyModifiers, Keybindings,
+    Prompt, PromptEditMode, PromptHistorySearchStatus, ReedlineEvent, SearchDirection, SearchQuery,
+    Signal, ValidationResult, Validator,
+};
 use clap_repl::ClapEditor;
+use thiserror::Error;
 
 use crate::complete_command::{
-    CatCommand, ChangeDirCommand, CompleteCommand, HeadCommand, ListCommand, MakeDirCommand,
-    MoveCommand, RemoveCommand, SortCommand, TouchCommand,
+    CatCommand, ChangeDirCommand, CompleteCommand, CopyCommand, DebugCommand, DiffCommand,
+    FindCommand, HeadCommand, LinkCommand, ListCommand, MakeDirCommand, MakeFsCommand, MoveCommand,
+    NumberFormat, RemoveCommand, SortCommand, StatCommand, TailCommand, TestCommand, TouchCommand,
+    WordCountCommand,
+};
+use crate::glob;
+use crate::parser::WinnowFerrixParser;
+use crate::simple_ext4::mkfs;
+use crate::system::{
+    expand_home, is_stdio, split_mount_path, DiffCommandOutput, DiskFreeCommandOutput,
+    ListCommandOutput, Number, System,
 };
-use crate::system::System;
 
 static DEFAULT_PROMPT_INDICATOR: &str = "$ ";
 static DEFAULT_MULTILINE_INDICATOR: &str = "::: ";
 
+/// Key that, pressed together with Ctrl, starts a reverse search through the history
+pub const DEFAULT_HISTORY_SEARCH_KEY: char = 'r';
+
+/// Number of entries `rm` removes without asking for confirmation
+pub const DEFAULT_REMOVE_CONFIRM_THRESHOLD: usize = 10;
+
 #[derive(Clone)]
 pub enum FerrixPromptSegment {
     /// A basic user-defined prompt (i.e. just text)
@@ -30,6 +56,7 @@ pub enum FerrixPromptSegment {
 pub struct FerrixPrompt {
     segment: FerrixPromptSegment,
     current_working_dir: Arc<RwLock<PathBuf>>,
+    show_indicators: bool,
 }
 
 impl FerrixPrompt {
@@ -37,6 +64,25 @@ impl FerrixPrompt {
         Self {
             segment,
             current_working_dir,
+            show_indicators: true,
+        }
+    }
+
+    /// Creates the prompt for the given input, when the input is not a terminal (e.g. a piped
+    /// script) the prompt is suppressed entirely so the output stays clean
+    pub fn for_input(
+        current_working_dir: Arc<RwLock<PathBuf>>,
+        segment: FerrixPromptSegment,
+        is_terminal: bool,
+    ) -> Self {
+        if is_terminal {
+            return Self::new(current_working_dir, segment);
+        }
+
+        Self {
+            segment: FerrixPromptSegment::Empty,
+            current_working_dir,
+            show_indicators: false,
         }
     }
 }
@@ -67,14 +113,19 @@ impl Prompt for FerrixPrompt {
         Cow::Borrowed("")
     }
 
-    fn render_prompt_indicator(
-        &self,
-        _prompt_mode: clap_repl::reedline::PromptEditMode,
-    ) -> std::borrow::Cow<str> {
+    fn render_prompt_indicator(&self, _prompt_mode: PromptEditMode) -> std::borrow::Cow<str> {
+        if !self.show_indicators {
+            return Cow::Borrowed("");
+        }
+
         DEFAULT_PROMPT_INDICATOR.into()
     }
 
     fn render_prompt_multiline_indicator(&self) -> std::borrow::Cow<str> {
+        if !self.show_indicators {
+            return Cow::Borrowed("");
+        }
+
         Cow::Borrowed(DEFAULT_MULTILINE_INDICATOR)
     }
 
@@ -93,6 +144,689 @@ impl Prompt for FerrixPrompt {
     }
 }
 
+/// Prefix that marks a history expansion such as `!!` or `!3`
+static HISTORY_EXPANSION_PREFIX: &str = "!";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HistoryExpansionError {
+    #[error("no commands in history")]
+    EmptyHistory,
+    #[error("!{0}: event not found")]
+    EventNotFound(String),
+}
+
+/// Expands a leading `!!` (last command) or `!n` (n-th command, starting at 1) of `line` using
+/// the given history, appending any remaining arguments to the expanded command.
+///
+/// Returns `Ok(None)` if the line does not start with a history expansion.
+pub fn expand_history(
+    line: &str,
+    history: &[String],
+) -> Result<Option<String>, HistoryExpansionError> {
+    let line = line.trim();
+    let Some(rest) = line.strip_prefix(HISTORY_EXPANSION_PREFIX) else {
+        return Ok(None);
+    };
+
+    let (designator, args) = match rest.split_once(char::is_whitespace) {
+        Some((designator, args)) => (designator, args.trim_start()),
+        None => (rest, ""),
+    };
+
+    let entry = if designator == HISTORY_EXPANSION_PREFIX {
+        history.last().ok_or(HistoryExpansionError::EmptyHistory)?
+    } else {
+        let index = designator
+            .parse::<usize>()
+            .map_err(|_| HistoryExpansionError::EventNotFound(designator.to_string()))?;
+        if history.is_empty() {
+            return Err(HistoryExpansionError::EmptyHistory);
+        }
+        index
+            .checked_sub(1)
+            .and_then(|index| history.get(index))
+            .ok_or_else(|| HistoryExpansionError::EventNotFound(designator.to_string()))?
+    };
+
+    if args.is_empty() {
+        Ok(Some(entry.clone()))
+    } else {
+        Ok(Some(format!("{entry} {args}")))
+    }
+}
+
+/// Most aliases expanded for a single command line, reaching it means the aliases expand to each
+/// other in a loop
+pub const MAX_ALIAS_DEPTH: usize = 16;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AliasError {
+    #[error("{0}: alias loop detected")]
+    Loop(String),
+    #[error("invalid alias name: {0:?}")]
+    InvalidName(String),
+    #[error("expected an alias definition like `name = command`")]
+    MissingExpansion,
+    #[error("invalid alias command: {0}")]
+    InvalidExpansion(String),
+    #[error("{0}: alias not found")]
+    NotFound(String),
+}
+
+/// Aliases of the session, from their name to the command line they expand to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Define the alias `name`, replacing any alias with the same name
+    pub fn define(&mut self, name: &str, expansion: &str) -> Result<(), AliasError> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '=') {
+            return Err(AliasError::InvalidName(name.to_string()));
+        }
+        if expansion.trim().is_empty() {
+            return Err(AliasError::MissingExpansion);
+        }
+
+        self.aliases
+            .insert(name.to_string(), expansion.trim().to_string());
+        Ok(())
+    }
+
+    /// Define an alias from the arguments of `alias`, split by the shell as in `ll = ls -a` or
+    /// `ll=ls -a`
+    pub fn define_from_args(&mut self, args: &[String]) -> Result<(), AliasError> {
+        let index = args
+            .iter()
+            .position(|arg| arg.contains('='))
+            .ok_or(AliasError::MissingExpansion)?;
+        let (before, after) = args[index].split_once('=').unwrap_or_default();
+
+        let name: Vec<&str> = args[..index]
+            .iter()
+            .map(String::as_str)
+            .chain(Some(before).filter(|before| !before.is_empty()))
+            .collect();
+        let [name] = name[..] else {
+            return Err(AliasError::InvalidName(name.join(" ")));
+        };
+
+        let words = Some(after)
+            .filter(|after| !after.is_empty())
+            .into_iter()
+            .chain(args[index + 1..].iter().map(String::as_str));
+        let expansion =
+            shlex::try_join(words).map_err(|e| AliasError::InvalidExpansion(e.to_string()))?;
+        self.define(name, &expansion)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), AliasError> {
+        self.aliases
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| AliasError::NotFound(name.to_string()))
+    }
+
+    /// Expand the leading word of `line` while it names an alias, keeping the rest of the line
+    /// as arguments. An alias starting with its own name, as in `ls = ls -a`, is expanded once.
+    pub fn expand(&self, line: &str) -> Result<String, AliasError> {
+        let first = line.split_whitespace().next().unwrap_or_default();
+        let mut line = line.trim_start().to_string();
+        for _ in 0..=MAX_ALIAS_DEPTH {
+            let (name, args) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+            let Some(expansion) = self.aliases.get(name) else {
+                return Ok(line);
+            };
+
+            let expanded = if args.is_empty() {
+                expansion.clone()
+            } else {
+                format!("{expansion} {args}")
+            };
+            if expansion.split_whitespace().next() == Some(name) {
+                return Ok(expanded);
+            }
+            line = expanded;
+        }
+
+        Err(AliasError::Loop(first.to_string()))
+    }
+
+    /// Save the aliases to `file`, one `name = command` per line
+    pub fn save(&self, file: &Path) -> std::io::Result<()> {
+        std::fs::write(file, self.to_string())
+    }
+
+    /// Aliases saved by [`Aliases::save`], empty if nothing was saved. Lines that are not a valid
+    /// definition are skipped
+    pub fn load(file: &Path) -> Self {
+        let mut aliases = Self::default();
+        for line in std::fs::read_to_string(file).unwrap_or_default().lines() {
+            if let Some((name, expansion)) = line.split_once('=') {
+                let _ = aliases.define(name.trim(), expansion);
+            }
+        }
+        aliases
+    }
+}
+
+impl std::fmt::Display for Aliases {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, expansion) in &self.aliases {
+            writeln!(f, "{name} = {expansion}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Marks, at the end of a line, that the input continues on the next one
+pub const LINE_CONTINUATION: char = '\\';
+
+/// Starts a here-doc, `<<END` on its own line reads the next lines up to one that is only `END`
+pub const HERE_DOC_PREFIX: &str = "<<";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HereDocError {
+    #[error("missing here-doc delimiter after `{HERE_DOC_PREFIX}`")]
+    MissingDelimiter,
+    #[error("here-doc delimited by `{0}` was never terminated")]
+    Unterminated(String),
+}
+
+/// Returns the script of a here-doc, the lines between its `<<END` first line and the first line
+/// that is only `END`. Any line after the delimiter is ignored.
+///
+/// Returns `Ok(None)` if the input is not a here-doc.
+pub fn collect_here_doc(input: &str) -> Result<Option<String>, HereDocError> {
+    let mut lines = input.lines();
+    let Some(delimiter) = lines
+        .next()
+        .and_then(|line| line.trim().strip_prefix(HERE_DOC_PREFIX))
+    else {
+        return Ok(None);
+    };
+
+    let delimiter = delimiter.trim();
+    if delimiter.is_empty() {
+        return Err(HereDocError::MissingDelimiter);
+    }
+
+    let mut script = String::new();
+    for line in lines {
+        if line.trim() == delimiter {
+            return Ok(Some(script));
+        }
+        script.push_str(line);
+        script.push('\n');
+    }
+
+    Err(HereDocError::Unterminated(delimiter.to_string()))
+}
+
+/// Returns true if the input continues on another line, either because its last line ends with
+/// [`LINE_CONTINUATION`], because it has a bracket left open or because it is a here-doc whose
+/// delimiter was not read yet
+pub fn is_incomplete(input: &str) -> bool {
+    match collect_here_doc(input) {
+        Ok(Some(_)) | Err(HereDocError::MissingDelimiter) => return false,
+        Err(HereDocError::Unterminated(_)) => return true,
+        Ok(None) => {}
+    }
+
+    if input.ends_with(LINE_CONTINUATION) {
+        return true;
+    }
+
+    open_brackets(input) > 0
+}
+
+/// Counts the brackets left open in `input`, skipping the ones quoted or escaped with a backslash
+/// the way [`shlex::split`] reads the line
+fn open_brackets(input: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"') | None, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth
+}
+
+/// Joins the lines of a multi-line input into a single command line, dropping the
+/// [`LINE_CONTINUATION`] markers
+pub fn assemble_lines(input: &str) -> String {
+    input
+        .lines()
+        .map(|line| line.strip_suffix(LINE_CONTINUATION).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Keeps reading lines while the input [`is_incomplete`]
+pub struct FerrixValidator;
+
+impl Validator for FerrixValidator {
+    fn validate(&self, line: &str) -> ValidationResult {
+        if is_incomplete(line) {
+            ValidationResult::Incomplete
+        } else {
+            ValidationResult::Complete
+        }
+    }
+}
+
+fn history_items(history: &dyn History) -> Vec<HistoryItem> {
+    history
+        .search(SearchQuery::everything(SearchDirection::Forward, None))
+        .unwrap_or_default()
+}
+
+fn history_entries(history: &dyn History) -> Vec<String> {
+    history_items(history)
+        .into_iter()
+        .map(|item| item.command_line)
+        .collect()
+}
+
+/// Whether removing `count` entries has to be confirmed first, only past the `threshold` without
+/// `--force` and when someone can answer on a terminal
+pub fn needs_confirmation(count: usize, threshold: usize, force: bool, is_terminal: bool) -> bool {
+    !force && is_terminal && count > threshold
+}
+
+/// Asks a yes or no `question` on the terminal, anything but yes is a no
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    if std::io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Renders the last `count` history items, or all of them, one per line with the index `!n`
+/// expands and their timestamp when the history records it
+pub fn render_history(items: &[HistoryItem], count: Option<usize>) -> String {
+    let skip = items.len() - count.unwrap_or(items.len()).min(items.len());
+
+    let mut rendered = String::new();
+    for (index, item) in items.iter().enumerate().skip(skip) {
+        match item.start_timestamp {
+            Some(timestamp) => rendered.push_str(&format!(
+                "{:>5}  {}  {}\n",
+                index + 1,
+                timestamp,
+                item.command_line
+            )),
+            None => rendered.push_str(&format!("{:>5}  {}\n", index + 1, item.command_line)),
+        }
+    }
+
+    rendered
+}
+
+/// Format the image of a `mkfs` command, describing the new file system. The image is a host
+/// path, a relative one is resolved against `launch_dir`, the directory ferrix was launched from,
+/// and not the working directory of the REPL
+pub fn make_image(cmd: &MakeFsCommand, launch_dir: &Path) -> anyhow::Result<String> {
+    let image = launch_dir.join(&cmd.image);
+    let sb = mkfs::make(&image, cmd.size, cmd.block_size)?;
+    Ok(format!(
+        "Formatted {} with {} block group(s) of {} byte blocks",
+        image.display(),
+        sb.groups,
+        sb.block_size
+    ))
+}
+
+/// Writes each name as raw bytes followed by a NUL byte if `null` is set or a newline otherwise,
+/// so names containing newlines survive being piped into other tools
+pub fn write_names<W, I, N>(writer: &mut W, names: I, null: bool) -> std::io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = N>,
+    N: AsRef<OsStr>,
+{
+    let separator = if null { b'\0' } else { b'\n' };
+
+    for name in names {
+        writer.write_all(name.as_ref().as_bytes())?;
+        writer.write_all(&[separator])?;
+    }
+
+    writer.flush()
+}
+
+/// Resolves `path` against the current working directory `cwd` the same way `cd` does, a path
+/// addressing a specific mount as `@name:/path` is kept as is and a leading `~` is the
+/// [`DEFAULT_CURRENT_WORKING_DIR`], where `cd` without a path goes
+pub fn resolve_working_dir(cwd: &Path, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if split_mount_path(path).is_some() {
+        return path.to_path_buf();
+    }
+
+    if let Some(path) = expand_home(path, Path::new(DEFAULT_CURRENT_WORKING_DIR)) {
+        return path.clean();
+    }
+
+    cwd.join(path).clean()
+}
+
+/// Resolves `path` like [`resolve_working_dir`], keeping the standard streams path as is
+fn resolve_source(cwd: &Path, path: impl AsRef<Path>) -> OsString {
+    let path = path.as_ref();
+    if is_stdio(path) {
+        return path.as_os_str().to_os_string();
+    }
+
+    resolve_working_dir(cwd, path).into_os_string()
+}
+
+/// Returns the cleaned current working directory, the same value shown by the prompt
+pub fn working_dir(cwd: &RwLock<PathBuf>) -> PathBuf {
+    cwd.read()
+        .expect("Failed to read current working directory")
+        .clean()
+}
+
+/// Number of bytes on each line rendered by [`render_bytes`]
+const BYTES_PER_LINE: usize = 16;
+
+/// Renders raw bytes as two digit hex or as decimal integers, [`BYTES_PER_LINE`] to a line
+pub fn render_bytes(bytes: &[u8], hex: bool) -> String {
+    let mut output = String::new();
+    for line in bytes.chunks(BYTES_PER_LINE) {
+        let line: Vec<_> = line
+            .iter()
+            .map(|byte| match hex {
+                true => format!("{byte:02x}"),
+                false => byte.to_string(),
+            })
+            .collect();
+        output.push_str(&line.join(" "));
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Renders an integer read by `head` or `tail` in the given base
+pub fn render_number(number: Number, format: NumberFormat) -> String {
+    match format {
+        NumberFormat::Dec => number.to_string(),
+        NumberFormat::Hex => format!("{number:#x}"),
+        NumberFormat::Bin => format!("{number:#b}"),
+    }
+}
+
+/// Renders the output of `df`, the block usage in bytes or, with `inodes` set, the inode usage
+pub fn render_disk_free(output: &DiskFreeCommandOutput, inodes: bool) -> String {
+    if inodes {
+        let used = output.total_inodes.saturating_sub(output.free_inodes);
+        return format!(
+            "Total inodes: {}\nUsed inodes: {}\nFree inodes: {}\n",
+            output.total_inodes, used, output.free_inodes
+        );
+    }
+
+    let total = output.total_blocks * output.block_size;
+    let free = output.free_blocks * output.block_size;
+    format!(
+        "Total disk size: {}\nUsed disk size: {}\nRemaining disk size: {}\n",
+        Byte::from_u64(total).get_appropriate_unit(UnitType::Binary),
+        Byte::from_u64(total.saturating_sub(free)).get_appropriate_unit(UnitType::Binary),
+        Byte::from_u64(free).get_appropriate_unit(UnitType::Binary)
+    )
+}
+
+/// Renders the output of `ls` as a table followed by the node count and disk usage, the footer is
+/// left out when a single file was listed, matching `ls file`
+pub fn render_list_output(output: ListCommandOutput) -> String {
+    let len = output.nodes.len();
+    let is_single_file = output.is_single_file;
+    let total_size = output.total_disk_space_in_bytes;
+    let remaining_size = output.remaining_disk_space_in_bytes;
+    let mut rendered = format!("{}\n", Table::new(output.nodes));
+
+    if is_single_file {
+        return rendered;
+    }
+
+    rendered.push_str(&format!("Total: {len} nodes\n"));
+    rendered.push_str(&format!(
+        "Total disk size: {}\n",
+        Byte::from_u64(total_size).get_appropriate_unit(UnitType::Binary)
+    ));
+    rendered.push_str(&format!(
+        "Remaining disk size: {}\n",
+        Byte::from_u64(remaining_size).get_appropriate_unit(UnitType::Binary)
+    ));
+    rendered
+}
+
+/// Renders the result of `diff`, a file ending before the differing index is shown as `EOF`
+pub fn render_diff_output(cmd: &DiffCommand, output: &DiffCommandOutput) -> String {
+    let left = Path::new(&cmd.left).display();
+    let right = Path::new(&cmd.right).display();
+
+    match output {
+        DiffCommandOutput::Identical => format!("'{left}' and '{right}' are identical"),
+        DiffCommandOutput::Differ {
+            index,
+            left: left_value,
+            right: right_value,
+        } => {
+            let value = |value: &Option<Number>| match value {
+                Some(value) => value.to_string(),
+                None => "EOF".to_string(),
+            };
+            format!(
+                "'{left}' and '{right}' differ at index {index}: {} != {}",
+                value(left_value),
+                value(right_value)
+            )
+        }
+    }
+}
+
+/// `-` names the standard input, but the REPL reads its commands from there
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("- cannot read the standard input the commands are read from, run it with `ferrix -c`")]
+pub struct StdinIsCommandSource;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum GlobExpansionError {
+    #[error("no matches found: {0}")]
+    NoMatches(String),
+}
+
+/// Marks which of `args` are positional path arguments of their command, looked up in the clap
+/// definition of [`CompleteCommand`]. Flags, option values and the positionals parsed as a
+/// `String`, like the name `find` looks for, are not paths.
+fn path_arguments(args: &[String]) -> Vec<bool> {
+    let mut paths = vec![false; args.len()];
+    let root = CompleteCommand::command();
+    let Some(command) = args.first().and_then(|name| root.find_subcommand(name)) else {
+        return paths;
+    };
+
+    let positionals: Vec<_> = command.get_positionals().collect();
+    let takes_value =
+        |arg: Option<&clap::Arg>| arg.is_some_and(|arg| arg.get_action().takes_values());
+    let mut position = 0;
+    let mut only_positionals = false;
+    let mut i = 1;
+
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+
+        if !only_positionals && arg == "--" {
+            only_positionals = true;
+            continue;
+        }
+        if !only_positionals && arg.len() > 1 && arg.starts_with('-') {
+            // The value of an option is the next argument, unless it is attached to it
+            let skip_value = match arg.strip_prefix("--") {
+                Some(long) => {
+                    !long.contains('=')
+                        && takes_value(command.get_arguments().find(|a| a.get_long() == Some(long)))
+                }
+                None => arg[1..].chars().last().is_some_and(|short| {
+                    takes_value(
+                        command
+                            .get_arguments()
+                            .find(|a| a.get_short() == Some(short)),
+                    )
+                }),
+            };
+            i += usize::from(skip_value);
+            continue;
+        }
+
+        // Only the last positional can take several values
+        let Some(positional) = positionals.get(position).or(positionals.last()) else {
+            continue;
+        };
+        paths[i - 1] = positional.get_value_parser().type_id() != ValueParser::string().type_id();
+        position += 1;
+    }
+
+    paths
+}
+
+/// Expands every path argument containing `*`, `?` or `**` into the paths it matches, resolved
+/// against `cwd`, in place of the pattern. The command name, flags and arguments that are not
+/// paths, like the name `find` looks for, are never expanded.
+///
+/// Every match goes into the same command, so `cat d/*` concatenates all the matches. A glob
+/// matching nothing is an error, like in zsh, instead of being passed through as a literal path.
+pub fn expand_globs<F>(args: Vec<String>, cwd: &Path, mut glob: F) -> anyhow::Result<Vec<String>>
+where
+    F: FnMut(&Path) -> anyhow::Result<Vec<PathBuf>>,
+{
+    let paths = path_arguments(&args);
+    let mut expanded = Vec::with_capacity(args.len());
+
+    for (arg, is_path) in args.into_iter().zip(paths) {
+        if !is_path || !glob::is_glob(&arg) {
+            expanded.push(arg);
+            continue;
+        }
+
+        let matches = glob(&resolve_working_dir(cwd, &arg))?;
+        if matches.is_empty() {
+            return Err(GlobExpansionError::NoMatches(arg).into());
+        }
+
+        expanded.extend(
+            matches
+                .into_iter()
+                .map(|path| path.to_string_lossy().into_owned()),
+        );
+    }
+
+    Ok(expanded)
+}
+
+/// Emacs keybindings of the editor, with Tab opening the completion menu and Ctrl plus
+/// `history_search_key` starting a reverse history search instead of the default Ctrl-R
+pub fn keybindings(history_search_key: char) -> Keybindings {
+    let mut keybindings = default_emacs_keybindings();
+    keybindings.add_binding(
+        KeyModifiers::NONE,
+        KeyCode::Tab,
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu("completion_menu".to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+    );
+    keybindings.remove_binding(
+        KeyModifiers::CONTROL,
+        KeyCode::Char(DEFAULT_HISTORY_SEARCH_KEY),
+    );
+    keybindings.add_binding(
+        KeyModifiers::CONTROL,
+        KeyCode::Char(history_search_key.to_ascii_lowercase()),
+        ReedlineEvent::SearchHistory,
+    );
+
+    keybindings
+}
+
+/// Message printed for a failed command, `Error <action>: <error>` or only the error if `quiet`.
+/// The error is shown with its causes, as in `{:#}`
+pub fn format_error(action: &str, error: &dyn std::fmt::Display, quiet: bool) -> String {
+    if quiet {
+        format!("{error:#}")
+    } else {
+        format!("Error {action}: {error:#}")
+    }
+}
+
+/// Save the working directory to `state_file` so the next session can start from it
+pub fn save_working_dir(state_file: &Path, cwd: &Path) -> std::io::Result<()> {
+    std::fs::write(state_file, cwd.as_os_str().as_bytes())
+}
+
+/// Working directory saved by [`save_working_dir`], `None` if nothing was saved or the saved
+/// path is not absolute
+pub fn load_working_dir(state_file: &Path) -> Option<PathBuf> {
+    let saved = PathBuf::from(OsStr::from_bytes(&std::fs::read(state_file).ok()?));
+    saved.is_absolute().then_some(saved)
+}
+
+/// Settings of the REPL itself, usually taken from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplV2Options {
+    /// Key that, pressed together with Ctrl, starts a reverse search through the history
+    pub history_search_key: char,
+    /// File where the working directory is saved on exit and restored from on startup, the
+    /// working directory is not persisted if unset
+    pub cwd_state_file: Option<PathBuf>,
+    /// File the aliases are loaded from on startup and saved to whenever they change, the
+    /// aliases only last for the session if unset
+    pub alias_file: Option<PathBuf>,
+    /// Print only the message of a failed command, without the `Error <action>:` prefix
+    pub quiet: bool,
+    /// Number of entries `rm` removes without asking for confirmation
+    pub remove_confirm_threshold: usize,
+    /// Run only this command line and exit with its status, leaving the standard input free for
+    /// the `-` file
+    pub command: Option<String>,
+}
+
+impl Default for ReplV2Options {
+    fn default() -> Self {
+        Self {
+            history_search_key: DEFAULT_HISTORY_SEARCH_KEY,
+            cwd_state_file: None,
+            alias_file: None,
+            quiet: false,
+            remove_confirm_threshold: DEFAULT_REMOVE_CONFIRM_THRESHOLD,
+            command: None,
+        }
+    }
+}
+
 pub struct ReplV2 {}
 
 #[cfg(target_family = "unix")]
@@ -102,25 +836,100 @@ pub const DEFAULT_CURRENT_WORKING_DIR: &str = "/";
 pub const DEFAULT_CURRENT_WORKING_DIR: &str = "C:\\";
 
 impl ReplV2 {
-    pub fn run<S>(system: &mut S, segment: FerrixPromptSegment) -> anyhow::Result<()>
+    /// Run commands until the input ends and return the exit status to end the process with,
+    /// the status of the last command for a script read from stdin and 0 otherwise
+    pub fn run<S>(
+        system: &mut S,
+        segment: FerrixPromptSegment,
+        options: ReplV2Options,
+    ) -> anyhow::Result<i32>
     where
         S: System + Send + Sync + 'static,
     {
-        let shared_path = Arc::new(RwLock::new(PathBuf::from(DEFAULT_CURRENT_WORKING_DIR)));
+        // A restored directory that is gone by now falls back to the default one
+        let restored = options
+            .cwd_state_file
+            .as_deref()
+            .and_then(load_working_dir)
+            .filter(|dir| {
+                system
+                    .chdir(&ChangeDirCommand {
+                        path: Some(dir.clone().into_os_string()),
+                    })
+                    .is_ok()
+            });
+        let start_dir = match restored {
+            Some(dir) => dir,
+            None => {
+                system.chdir(&ChangeDirCommand {
+                    path: Some(DEFAULT_CURRENT_WORKING_DIR.into()),
+                })?;
+                PathBuf::from(DEFAULT_CURRENT_WORKING_DIR)
+            }
+        };
+        let shared_path = Arc::new(RwLock::new(start_dir));
+        // Exit status of the last command, a script read from stdin exits with it
+        let status = Cell::new(0);
+        let report = |action: &str, error: &dyn std::fmt::Display| {
+            status.set(1);
+            eprintln!("{}", format_error(action, error, options.quiet));
+        };
+        let save_cwd = || {
+            if let Some(state_file) = &options.cwd_state_file {
+                if let Err(e) = save_working_dir(state_file, &working_dir(&shared_path)) {
+                    report("saving working directory", &e);
+                }
+            }
+        };
 
-        let prompt = FerrixPrompt::new(shared_path.clone(), segment);
-        let rl = ClapEditor::<CompleteCommand>::builder()
-            .with_prompt(Box::new(prompt))
+        let is_terminal = std::io::stdin().is_terminal();
+        // Without a one-shot command the lines come from the standard input, so `-` can't
+        let reads_commands = |file: &OsStr| options.command.is_none() && is_stdio(file);
+        let prompt = FerrixPrompt::for_input(shared_path.clone(), segment, is_terminal);
+        // History expansions are not saved as is, the expanded command is saved instead
+        let mut rl = ClapEditor::<CompleteCommand>::builder()
+            .with_edit_mode(Box::new(Emacs::new(keybindings(
+                options.history_search_key,
+            ))))
+            .with_editor_hook(|editor| {
+                editor
+                    .with_history_exclusion_prefix(Some(HISTORY_EXPANSION_PREFIX.into()))
+                    .with_validator(Box::new(FerrixValidator))
+            })
             .build();
 
-        system.chdir(&ChangeDirCommand {
-            path: Some(DEFAULT_CURRENT_WORKING_DIR.into()),
-        })?;
+        let aliases = RefCell::new(
+            options
+                .alias_file
+                .as_deref()
+                .map(Aliases::load)
+                .unwrap_or_default(),
+        );
+        let update_aliases = |update: &dyn Fn(&mut Aliases) -> Result<(), AliasError>| {
+            let mut aliases = aliases.borrow_mut();
+            if let Err(e) = update(&mut aliases) {
+                report("updating aliases", &e);
+                return;
+            }
+            if let Some(alias_file) = &options.alias_file {
+                if let Err(e) = aliases.save(alias_file) {
+                    report("saving aliases", &e);
+                }
+            }
+        };
 
-        rl.repl(|cmd| match cmd {
+        let handle = |system: &mut S, cmd| match cmd {
+            CompleteCommand::Alias(cmd) if cmd.definition.is_empty() => {
+                print!("{}", aliases.borrow());
+            }
+            CompleteCommand::Alias(cmd) => {
+                update_aliases(&|aliases| aliases.define_from_args(&cmd.definition))
+            }
+            CompleteCommand::Unalias(cmd) => update_aliases(&|aliases| aliases.remove(&cmd.name)),
             CompleteCommand::Exit(cmd) => {
+                save_cwd();
                 if let Err(e) = system.exit(&cmd) {
-                    eprintln!("Error exiting: {:?}", e);
+                    report("exiting", &e);
                 }
             }
             CompleteCommand::ChangeDir(cmd) => {
@@ -129,14 +938,8 @@ impl ReplV2 {
                     .expect("Failed to write current working directory");
 
                 let original_path = guard.clone();
-                let new_path = PathBuf::from(
-                    cmd.path
-                        .unwrap_or(DEFAULT_CURRENT_WORKING_DIR.into())
-                        .clone(),
-                );
-                guard.push(new_path);
-                let cleared_path = guard.clean();
-                guard.push(cleared_path);
+                let new_path = cmd.path.unwrap_or(DEFAULT_CURRENT_WORKING_DIR.into());
+                *guard = resolve_working_dir(&guard, &new_path);
 
                 let cmd = ChangeDirCommand {
                     path: Some(guard.clone().into_os_string().to_os_string()),
@@ -146,192 +949,1407 @@ impl ReplV2 {
                     Ok(_) => {}
                     Err(e) => {
                         *guard = original_path;
-                        eprintln!("Error changing directory: {:?}", e);
+                        report("changing directory", &e);
                     }
                 }
             }
+            CompleteCommand::PrintWorkingDir(_) => {
+                println!("{}", working_dir(&shared_path).display());
+            }
+            // The history belongs to the line editor, so the loop reading the lines runs it
+            CompleteCommand::History(_) => {}
+            CompleteCommand::MakeFs(cmd) => match std::env::current_dir()
+                .map_err(Into::into)
+                .and_then(|launch_dir| make_image(&cmd, &launch_dir))
+            {
+                Ok(summary) => println!("{summary}"),
+                Err(e) => report("making the file system", &e),
+            },
+            CompleteCommand::Handles(cmd) => match system.handles(&cmd) {
+                Ok(handles) => println!("{}", Table::new(handles)),
+                Err(e) => report("listing open file handles", &e),
+            },
+            CompleteCommand::Debug(cmd) => {
+                let cwd = working_dir(&shared_path);
+                let cmd = DebugCommand {
+                    path: cmd
+                        .path
+                        .map(|path| resolve_working_dir(&cwd, path).into_os_string()),
+                    ..cmd
+                };
+                match system.debug(&cmd) {
+                    Ok(output) => println!("{output}"),
+                    Err(e) => report("debugging", &e),
+                }
+            }
+            CompleteCommand::Use(cmd) => match system.use_mount(&cmd) {
+                Ok(_) => {
+                    *shared_path
+                        .write()
+                        .expect("Failed to write current working directory") =
+                        PathBuf::from(DEFAULT_CURRENT_WORKING_DIR);
+                }
+                Err(e) => report("switching mount", &e),
+            },
+            CompleteCommand::Mount(cmd) => {
+                if let Err(e) = system.mount(&cmd) {
+                    report("registering mount", &e);
+                }
+            }
             CompleteCommand::List(cmd) => {
-                let mut dir = shared_path
+                let cwd = shared_path
                     .read()
                     .expect("Failed to read current working directory")
-                    .clone()
-                    .into_os_string()
-                    .to_os_string();
-
-                if cmd.dir.is_some() {
-                    let path = PathBuf::from(cmd.dir.as_ref().unwrap());
-                    let cwd = PathBuf::from(dir);
-                    dir = cwd.join(path).clean().into_os_string().to_os_string();
+                    .clone();
+                let dir = match &cmd.dir {
+                    Some(path) => resolve_working_dir(&cwd, path).into_os_string(),
+                    None => cwd.into_os_string(),
                 };
 
                 let cmd = ListCommand {
                     dir: Some(dir),
                     all: cmd.all,
+                    null: cmd.null,
+                    json: cmd.json,
+                    node_type: cmd.node_type,
                 };
                 match system.list(&cmd) {
-                    Ok(output) => {
-                        let len = output.nodes.len();
-                        let total_size = output.total_disk_space_in_bytes;
-                        let remaining_size = output.remaining_disk_space_in_bytes;
-                        let table = Table::new(output.nodes).to_string();
-                        println!("{table}");
-                        println!("Total: {len} nodes");
-                        println!(
-                            "Total disk size: {}",
-                            Byte::from_u64(total_size.into())
-                                .get_appropriate_unit(UnitType::Binary)
-                        );
-                        println!(
-                            "Remaining disk size: {}",
-                            Byte::from_u64(remaining_size.into())
-                                .get_appropriate_unit(UnitType::Binary)
-                        );
+                    Ok(output) if cmd.json => match serde_json::to_string_pretty(&output) {
+                        Ok(json) => println!("{json}"),
+                        Err(e) => report("listing", &e),
+                    },
+                    Ok(output) if cmd.null => {
+                        let names = output.nodes.iter().map(|node| &node.name);
+                        if let Err(e) = write_names(&mut std::io::stdout(), names, true) {
+                            report("listing", &e);
+                        }
                     }
-                    Err(e) => eprintln!("Error listing: {:?}", e),
+                    Ok(output) => print!("{}", render_list_output(output)),
+                    Err(e) => report("listing", &e),
                 }
             }
             CompleteCommand::Touch(cmd) => {
-                let mut cwd = shared_path
-                    .read()
-                    .expect("Failed to read current working directory")
-                    .clone();
-
-                cwd.push(PathBuf::from(cmd.file));
-                let cwd = cwd.clean();
-
+                let cwd = working_dir(&shared_path);
                 let cmd = TouchCommand {
-                    file: cwd.into_os_string().to_os_string(),
-                    number_of_integers: cmd.number_of_integers,
+                    files: cmd
+                        .files
+                        .iter()
+                        .map(|file| resolve_working_dir(&cwd, file).into_os_string())
+                        .collect(),
+                    ..cmd
                 };
 
                 if let Err(e) = system.touch(&cmd) {
-                    eprintln!("Error touching: {:?}", e);
+                    report("touching", &e);
                 }
             }
             CompleteCommand::MakeDir(cmd) => {
-                let mut cwd = shared_path
+                let cwd = shared_path
                     .read()
                     .expect("Failed to read current working directory")
                     .clone();
 
-                cwd.push(PathBuf::from(cmd.dir));
-                let cwd = cwd.clean();
-
                 let cmd = MakeDirCommand {
-                    dir: cwd.into_os_string().to_os_string(),
+                    dir: resolve_working_dir(&cwd, &cmd.dir).into_os_string(),
                     parents: cmd.parents,
+                    verbose: cmd.verbose,
                 };
-                if let Err(e) = system.make_dir(&cmd) {
-                    eprintln!("Error making directory: {:?}", e);
+                match system.make_dir(&cmd) {
+                    Ok(created) => {
+                        if cmd.verbose {
+                            for path in created {
+                                println!("created directory '{}'", path.display());
+                            }
+                        }
+                    }
+                    Err(e) => report("making directory", &e),
                 }
             }
             CompleteCommand::Head(cmd) => {
-                let mut cwd = shared_path
-                    .read()
-                    .expect("Failed to read current working directory")
-                    .clone();
-
-                cwd.push(PathBuf::from(cmd.file));
-                let cwd = cwd.clean();
-
+                if reads_commands(&cmd.file) {
+                    report("heading", &StdinIsCommandSource);
+                    return;
+                }
+                let cwd = working_dir(&shared_path);
                 let cmd = HeadCommand {
-                    file: cwd.into_os_string().to_os_string(),
+                    file: resolve_source(&cwd, cmd.file),
                     start: cmd.start,
                     end: cmd.end,
+                    bytes: cmd.bytes,
+                    hex: cmd.hex,
+                    format: cmd.format,
+                    raw: cmd.raw,
                 };
+                if cmd.bytes.is_some() {
+                    match system.head_bytes(&cmd) {
+                        Ok(bytes) => print!("{}", render_bytes(&bytes, cmd.hex)),
+                        Err(e) => report("heading", &e),
+                    }
+                    return;
+                }
                 match system.head(&cmd) {
                     Ok(numbers) => {
-                        for number in &numbers {
-                            println!("{}", number);
+                        for number in numbers {
+                            println!("{}", render_number(number, cmd.format));
+                        }
+                    }
+                    Err(e) => report("heading", &e),
+                }
+            }
+            CompleteCommand::Tail(cmd) => {
+                let cwd = working_dir(&shared_path);
+                let cmd = TailCommand {
+                    file: resolve_working_dir(&cwd, cmd.file).into_os_string(),
+                    lines: cmd.lines,
+                    reverse: cmd.reverse,
+                    bytes: cmd.bytes,
+                    hex: cmd.hex,
+                    format: cmd.format,
+                    follow: cmd.follow,
+                };
+                if cmd.follow {
+                    let mut stdout = std::io::stdout();
+                    let result = system.follow(&cmd, &mut |numbers| {
+                        let written = numbers.iter().try_for_each(|number| {
+                            writeln!(stdout, "{}", render_number(*number, cmd.format))
+                        });
+                        written.and_then(|_| stdout.flush()).is_ok()
+                    });
+                    if let Err(e) = result {
+                        report("following", &e);
+                    }
+                    return;
+                }
+                if cmd.bytes.is_some() {
+                    match system.tail_bytes(&cmd) {
+                        Ok(bytes) => print!("{}", render_bytes(&bytes, cmd.hex)),
+                        Err(e) => report("tailing", &e),
+                    }
+                    return;
+                }
+                match system.tail(&cmd) {
+                    Ok(numbers) => {
+                        for number in numbers {
+                            println!("{}", render_number(number, cmd.format));
                         }
                     }
-                    Err(e) => eprintln!("Error heading: {:?}", e),
+                    Err(e) => report("tailing", &e),
                 }
             }
             CompleteCommand::Cat(cmd) => {
+                if cmd.files.iter().any(|file| reads_commands(file)) {
+                    report("catting", &StdinIsCommandSource);
+                    return;
+                }
                 let cwd = shared_path
                     .read()
                     .expect("Failed to read current working directory")
                     .clone();
 
-                let mut files = Vec::new();
-
-                for file in cmd.files {
-                    let file = cwd.join(PathBuf::from(file));
-                    files.push(file.into_os_string().to_os_string());
-                }
+                let files = cmd
+                    .files
+                    .iter()
+                    .map(|file| resolve_source(&cwd, file))
+                    .collect();
 
                 let cmd = CatCommand {
-                    files: files,
-                    output_file: cmd.output_file,
+                    files,
+                    output_file: cmd.output_file.map(|file| resolve_source(&cwd, file)),
+                    append: cmd.append,
+                    interleave: cmd.interleave,
+                    sorted_merge: cmd.sorted_merge,
+                    reverse: cmd.reverse,
+                    progress: cmd.progress,
+                    raw: cmd.raw,
                 };
 
                 if let Err(e) = system.cat(&cmd) {
-                    eprintln!("Error catting: {:?}", e);
+                    report("catting", &e);
                 }
             }
             CompleteCommand::Remove(cmd) => {
-                let mut cwd = shared_path
-                    .read()
-                    .expect("Failed to read current working directory")
-                    .clone();
-
-                cwd.push(PathBuf::from(cmd.file_or_dir));
-                let cwd = cwd.clean();
+                let cwd = working_dir(&shared_path);
+                let files_or_dirs = cmd
+                    .files_or_dirs
+                    .into_iter()
+                    .map(|path| resolve_working_dir(&cwd, path).into_os_string())
+                    .collect();
 
                 let cmd = RemoveCommand {
-                    file_or_dir: cwd.into_os_string().to_os_string(),
+                    files_or_dirs,
                     recursive: cmd.recursive,
+                    verbose: cmd.verbose,
+                    trash: cmd.trash,
+                    force: cmd.force,
                 };
-                if let Err(e) = system.remove(&cmd) {
-                    eprintln!("Error removing: {:?}", e);
+                // Counting walks every directory, skip it when nothing would be asked anyway. All
+                // the matches of a glob are counted together, so `rm -r d/*` asks once
+                if !cmd.force && is_terminal {
+                    let count = system.count_removed(&cmd).unwrap_or_default();
+                    let threshold = options.remove_confirm_threshold;
+                    if needs_confirmation(count, threshold, cmd.force, is_terminal)
+                        && !confirm(&format!("remove {count} files?"))
+                    {
+                        return;
+                    }
                 }
-            }
-            CompleteCommand::Move(cmd) => {
+                let action = if cmd.trash { "trashed" } else { "removed" };
+                match system.remove(&cmd) {
+                    Ok(removed) => {
+                        if cmd.verbose {
+                            for path in removed {
+                                println!("{action} '{}'", path.display());
+                            }
+                        }
+                    }
+                    Err(e) => report("removing", &e),
+                }
+            }
+            CompleteCommand::EmptyTrash(cmd) => match system.empty_trash(&cmd) {
+                Ok(removed) => {
+                    if cmd.verbose {
+                        for path in removed {
+                            println!("removed '{}'", path.display());
+                        }
+                    }
+                }
+                Err(e) => report("emptying trash", &e),
+            },
+            CompleteCommand::Move(cmd) => {
                 let cwd = shared_path
                     .read()
                     .expect("Failed to read current working directory")
                     .clone();
 
-                let to = cwd
-                    .join(PathBuf::from(cmd.from))
-                    .clean()
-                    .into_os_string()
-                    .to_os_string();
-                let from = cwd
-                    .join(PathBuf::from(cmd.to))
-                    .clean()
-                    .into_os_string()
-                    .to_os_string();
+                let from = resolve_working_dir(&cwd, cmd.from).into_os_string();
+                let to = resolve_working_dir(&cwd, cmd.to).into_os_string();
 
-                let cmd = MoveCommand { from, to };
+                let cmd = MoveCommand {
+                    from,
+                    to,
+                    verbose: cmd.verbose,
+                };
 
-                if let Err(e) = system.mv(&cmd) {
-                    eprintln!("Error moving: {:?}", e);
+                match system.mv(&cmd) {
+                    Ok(_) => {
+                        if cmd.verbose {
+                            println!(
+                                "renamed '{}' -> '{}'",
+                                PathBuf::from(&cmd.from).display(),
+                                PathBuf::from(&cmd.to).display()
+                            );
+                        }
+                    }
+                    Err(e) => report("moving", &e),
                 }
             }
-            CompleteCommand::Sort(cmd) => {
+            CompleteCommand::Copy(cmd) => {
                 let cwd = shared_path
                     .read()
                     .expect("Failed to read current working directory")
                     .clone();
 
-                let file = cwd
-                    .join(PathBuf::from(cmd.file))
-                    .clean()
-                    .into_os_string()
-                    .to_os_string();
+                let cmd = CopyCommand {
+                    from: resolve_working_dir(&cwd, cmd.from).into_os_string(),
+                    to: resolve_working_dir(&cwd, cmd.to).into_os_string(),
+                    verbose: cmd.verbose,
+                    preserve: cmd.preserve,
+                };
 
-                let cmd = SortCommand {
+                match system.copy(&cmd) {
+                    Ok(_) => {
+                        if cmd.verbose {
+                            println!(
+                                "'{}' -> '{}'",
+                                PathBuf::from(&cmd.from).display(),
+                                PathBuf::from(&cmd.to).display()
+                            );
+                        }
+                    }
+                    Err(e) => report("copying", &e),
+                }
+            }
+            CompleteCommand::Link(cmd) => {
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                // A relative symbolic link target is followed from the link, not the working
+                // directory, only a leading `~` is expanded
+                let target = if cmd.symbolic {
+                    expand_home(
+                        Path::new(&cmd.target),
+                        Path::new(DEFAULT_CURRENT_WORKING_DIR),
+                    )
+                    .map_or(cmd.target, PathBuf::into_os_string)
+                } else {
+                    resolve_working_dir(&cwd, cmd.target).into_os_string()
+                };
+                let cmd = LinkCommand {
+                    target,
+                    link_name: resolve_working_dir(&cwd, cmd.link_name).into_os_string(),
+                    symbolic: cmd.symbolic,
+                };
+
+                let result = if cmd.symbolic {
+                    system.symlink(&cmd)
+                } else {
+                    system.link(&cmd)
+                };
+                if let Err(e) = result {
+                    report("linking", &e);
+                }
+            }
+            CompleteCommand::Stat(cmd) => {
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                let file = resolve_working_dir(&cwd, cmd.file).into_os_string();
+
+                let cmd = StatCommand {
                     file,
+                    format: cmd.format,
+                    json: cmd.json,
+                    no_follow: cmd.no_follow,
+                };
+                match system.stat(&cmd) {
+                    Ok(output) => println!("{output}"),
+                    Err(e) => report("stating", &e),
+                }
+            }
+            CompleteCommand::Test(cmd) => {
+                let cwd = working_dir(&shared_path);
+                let cmd = TestCommand {
+                    path: resolve_working_dir(&cwd, cmd.path).into_os_string(),
+                    ..cmd
+                };
+                match system.test(&cmd) {
+                    Ok(passed) => status.set(i32::from(!passed)),
+                    Err(e) => report("testing", &e),
+                }
+            }
+            CompleteCommand::Find(cmd) => {
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                let dir = resolve_working_dir(&cwd, cmd.dir).into_os_string();
+
+                let cmd = FindCommand {
+                    dir,
+                    newer: cmd
+                        .newer
+                        .map(|newer| resolve_working_dir(&cwd, newer).into_os_string()),
+                    ..cmd
+                };
+                match system.find(&cmd) {
+                    Ok(found) => {
+                        if let Err(e) = write_names(&mut std::io::stdout(), found, cmd.null) {
+                            report("finding", &e);
+                        }
+                    }
+                    Err(e) => report("finding", &e),
+                }
+            }
+            CompleteCommand::Diff(cmd) => {
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                let cmd = DiffCommand {
+                    left: resolve_working_dir(&cwd, cmd.left).into_os_string(),
+                    right: resolve_working_dir(&cwd, cmd.right).into_os_string(),
+                };
+                match system.diff(&cmd) {
+                    Ok(output) => println!("{}", render_diff_output(&cmd, &output)),
+                    Err(e) => report("diffing", &e),
+                }
+            }
+            CompleteCommand::WordCount(cmd) => {
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                let files = cmd
+                    .files
+                    .into_iter()
+                    .map(|file| resolve_working_dir(&cwd, file).into_os_string())
+                    .collect();
+
+                let cmd = WordCountCommand { files };
+                match system.wc(&cmd) {
+                    Ok(output) => {
+                        let mut rows = output.counts;
+                        rows.push(output.total);
+                        println!("{}", Table::new(rows));
+                    }
+                    Err(e) => report("counting", &e),
+                }
+            }
+            CompleteCommand::DiskFree(cmd) => match system.df(&cmd) {
+                Ok(output) if cmd.json => match serde_json::to_string_pretty(&output) {
+                    Ok(json) => println!("{json}"),
+                    Err(e) => report("reporting disk usage", &e),
+                },
+                Ok(output) => print!("{}", render_disk_free(&output, cmd.inodes)),
+                Err(e) => report("reporting disk usage", &e),
+            },
+            CompleteCommand::Sort(cmd) => {
+                if reads_commands(&cmd.file) {
+                    report("sorting", &StdinIsCommandSource);
+                    return;
+                }
+                let cwd = shared_path
+                    .read()
+                    .expect("Failed to read current working directory")
+                    .clone();
+
+                let cmd = SortCommand {
+                    file: resolve_source(&cwd, cmd.file),
                     inverse_order: cmd.inverse_order,
+                    check: cmd.check,
+                    raw: cmd.raw,
+                    chunk_size: cmd.chunk_size,
+                    workers: cmd.workers,
                 };
                 if let Err(e) = system.sort(&cmd) {
-                    eprintln!("Error sorting: {:?}", e);
+                    report("sorting", &e);
                 }
             }
-        });
+        };
 
-        Ok(())
+        let mut one_shot = options.command.clone().map(Some);
+        loop {
+            let line = match one_shot.as_mut().map(Option::take) {
+                Some(Some(line)) => line,
+                Some(None) => return Ok(status.get()),
+                None => match rl.get_editor().read_line(&prompt) {
+                    Ok(Signal::Success(buffer)) => buffer,
+                    Ok(Signal::CtrlC) => continue,
+                    Ok(Signal::CtrlD) => {
+                        save_cwd();
+                        return Ok(if is_terminal { 0 } else { status.get() });
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+            };
+
+            // A here-doc is parsed as a whole before any of its commands runs
+            match collect_here_doc(&line) {
+                Ok(Some(script)) if script.trim().is_empty() => continue,
+                Ok(Some(script)) => {
+                    match WinnowFerrixParser::new(&script).get_commands() {
+                        Ok(commands) => {
+                            for cmd in commands.iter().cloned() {
+                                status.set(0);
+                                handle(system, cmd.into());
+                            }
+                        }
+                        // The debug rendering of the report shows where the script failed
+                        Err(e) => report("parsing here-doc", &format!("{e:?}")),
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    report("reading here-doc", &e);
+                    continue;
+                }
+            }
+
+            let line = assemble_lines(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let history = history_entries(rl.get_editor().history());
+            let line = match expand_history(&line, &history) {
+                Ok(Some(expanded)) => {
+                    println!("{expanded}");
+                    let item = HistoryItem::from_command_line(&expanded);
+                    if let Err(e) = rl.get_editor().history_mut().save(item) {
+                        report("saving history", &e);
+                    }
+                    expanded
+                }
+                Ok(None) => line,
+                Err(e) => {
+                    report("expanding history", &e);
+                    continue;
+                }
+            };
+            let line = match aliases.borrow().expand(&line) {
+                Ok(line) => line,
+                Err(e) => {
+                    report("expanding alias", &e);
+                    continue;
+                }
+            };
+
+            let Some(args) = shlex::split(&line) else {
+                report("parsing", &"input was not valid and could not be processed");
+                continue;
+            };
+            let cwd = working_dir(&shared_path);
+            let args = match expand_globs(args, &cwd, |pattern| Ok(system.glob(pattern)?)) {
+                Ok(args) => args,
+                Err(e) => {
+                    report("expanding glob", &e);
+                    continue;
+                }
+            };
+            match CompleteCommand::try_parse_from(
+                std::iter::once("").chain(args.iter().map(String::as_str)),
+            ) {
+                Ok(CompleteCommand::History(cmd)) => {
+                    let history = rl.get_editor().history_mut();
+                    if !cmd.clear {
+                        print!("{}", render_history(&history_items(history), cmd.count));
+                    } else if let Err(e) = history.clear() {
+                        report("clearing history", &e);
+                    }
+                }
+                Ok(cmd) => {
+                    status.set(0);
+                    handle(system, cmd);
+                }
+                Err(e) => {
+                    // Help and version requests are not failures
+                    if e.use_stderr() {
+                        status.set(1);
+                    }
+                    let _ = e.print();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple_ext4::flemis_system::FlemisSystem;
+    use crate::system::{NodeInfo, SystemError};
+    use clap_repl::reedline::PromptHistorySearch;
+
+    /// Run `line` as a one-shot command and return its exit status
+    fn run_line(system: &mut FlemisSystem, line: &str) -> i32 {
+        let options = ReplV2Options {
+            command: Some(line.to_string()),
+            ..Default::default()
+        };
+        ReplV2::run(system, FerrixPromptSegment::Empty, options).unwrap()
+    }
+
+    fn history() -> Vec<String> {
+        vec![
+            "ls".to_string(),
+            "touch a -n 10".to_string(),
+            "sort a".to_string(),
+        ]
+    }
+
+    fn list_output(is_single_file: bool) -> ListCommandOutput {
+        ListCommandOutput {
+            nodes: vec![NodeInfo {
+                name: "a".into(),
+                size_in_bytes: 14,
+                human_readable_size: "14 B".to_string(),
+                is_dir: false,
+            }],
+            total_disk_space_in_bytes: 1024,
+            remaining_disk_space_in_bytes: 512,
+            is_single_file,
+        }
+    }
+
+    #[test]
+    fn list_single_file() {
+        // Arrange
+        let output = list_output(true);
+
+        // Act
+        let rendered = render_list_output(output);
+
+        // Assert
+        assert!(rendered.contains("a"));
+        assert!(rendered.contains("14 B"));
+        assert!(!rendered.contains("Total"));
+        assert!(!rendered.contains("disk size"));
+    }
+
+    #[test]
+    fn list_directory_has_footer() {
+        // Arrange
+        let output = list_output(false);
+
+        // Act
+        let rendered = render_list_output(output);
+
+        // Assert
+        assert!(rendered.contains("Total: 1 nodes"));
+        assert!(rendered.contains("Total disk size: 1 KiB"));
+        assert!(rendered.contains("Remaining disk size: 512 B"));
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn expand_globs_into_one_command() {
+        // Arrange
+        let inputs = [
+            "wc *.txt",
+            "ls -a a.txt",
+            "cat /d/* ? -o out",
+            "diff /d/a* /d/b*",
+            "find /d *.txt",
+            "find --type f /d/* *.txt",
+            "stat --format %n?x *.txt",
+            "alias x=ls *",
+        ];
+        let outputs = [
+            args("wc /src/a.txt /src/b.txt"),
+            args("ls -a a.txt"),
+            args("cat /d/a /d/b /x /y -o out"),
+            args("diff /d/a /d/b"),
+            args("find /d *.txt"),
+            args("find --type f /d/a /d/b *.txt"),
+            args("stat --format %n?x /src/a.txt /src/b.txt"),
+            args("alias x=ls *"),
+        ];
+        let glob = |pattern: &Path| {
+            Ok(match pattern.to_str().unwrap() {
+                "/src/*.txt" => vec!["/src/a.txt".into(), "/src/b.txt".into()],
+                "/d/*" => vec!["/d/a".into(), "/d/b".into()],
+                "/d/a*" => vec!["/d/a".into()],
+                "/d/b*" => vec!["/d/b".into()],
+                "/src/?" => vec!["/x".into(), "/y".into()],
+                _ => vec![],
+            })
+        };
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = expand_globs(args(input), Path::new("/src"), glob).unwrap();
+
+            // Assert
+            assert_eq!(result, *output);
+        }
+    }
+
+    #[test]
+    fn expand_globs_without_matches() {
+        // Act
+        let result = expand_globs(args("ls *.bin"), Path::new("/"), |_| Ok(vec![]));
+
+        // Assert
+        assert_eq!(
+            result
+                .unwrap_err()
+                .downcast::<GlobExpansionError>()
+                .unwrap(),
+            GlobExpansionError::NoMatches("*.bin".to_string())
+        );
+    }
+
+    #[test]
+    fn render_diff() {
+        // Arrange
+        let cmd = DiffCommand {
+            left: "/a".into(),
+            right: "/b".into(),
+        };
+        let inputs = [
+            DiffCommandOutput::Identical,
+            DiffCommandOutput::Differ {
+                index: 3,
+                left: Some(1),
+                right: Some(2),
+            },
+            DiffCommandOutput::Differ {
+                index: 5,
+                left: None,
+                right: Some(7),
+            },
+        ];
+        let outputs = [
+            "'/a' and '/b' are identical",
+            "'/a' and '/b' differ at index 3: 1 != 2",
+            "'/a' and '/b' differ at index 5: EOF != 7",
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let rendered = render_diff_output(&cmd, input);
+
+            // Assert
+            assert_eq!(rendered, *output);
+        }
+    }
+
+    #[test]
+    fn pwd_after_change_dir() {
+        // Arrange
+        let inputs = ["/a/b", "c", "../d/./e", "~/f/../g", "~"];
+        let outputs = ["/a/b", "/a/b/c", "/a/b/d/e", "/g", "/"];
+        let shared_path = Arc::new(RwLock::new(PathBuf::from(DEFAULT_CURRENT_WORKING_DIR)));
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            {
+                let mut guard = shared_path.write().unwrap();
+                *guard = resolve_working_dir(&guard, input);
+            }
+
+            // Assert
+            assert_eq!(working_dir(&shared_path), PathBuf::from(output));
+        }
+    }
+
+    #[test]
+    fn expand_last_command() {
+        // Arrange
+        let inputs = ["!!", "  !!  ", "!! -i"];
+        let outputs = ["sort a", "sort a", "sort a -i"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let expanded = expand_history(input, &history());
+
+            // Assert
+            assert_eq!(expanded, Ok(Some(output.to_string())));
+        }
+    }
+
+    #[test]
+    fn expand_nth_command() {
+        // Arrange
+        let inputs = ["!1", "!2", "!3 -i"];
+        let outputs = ["ls", "touch a -n 10", "sort a -i"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let expanded = expand_history(input, &history());
+
+            // Assert
+            assert_eq!(expanded, Ok(Some(output.to_string())));
+        }
+    }
+
+    #[test]
+    fn no_expansion() {
+        // Arrange
+        let inputs = ["ls", "touch !a -n 1", ""];
+
+        for input in inputs {
+            // Act
+            let expanded = expand_history(input, &history());
+
+            // Assert
+            assert_eq!(expanded, Ok(None));
+        }
+    }
+
+    #[test]
+    fn expand_event_not_found() {
+        // Arrange
+        let inputs = ["!0", "!4", "!a", "!"];
+
+        for input in inputs {
+            // Act
+            let expanded = expand_history(input, &history());
+
+            // Assert
+            assert_eq!(
+                expanded,
+                Err(HistoryExpansionError::EventNotFound(
+                    input.trim_start_matches('!').to_string()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn expand_empty_history() {
+        // Arrange
+        let inputs = ["!!", "!1"];
+
+        for input in inputs {
+            // Act
+            let expanded = expand_history(input, &[]);
+
+            // Assert
+            assert_eq!(expanded, Err(HistoryExpansionError::EmptyHistory));
+        }
+    }
+
+    #[test]
+    fn remove_confirmation() {
+        // Arrange
+        let inputs = [
+            (11, false, true),
+            (10, false, true),
+            (11, true, true),
+            (11, false, false),
+            (1000, true, false),
+        ];
+        let outputs = [true, false, false, false, false];
+
+        for ((count, force, is_terminal), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let needed = needs_confirmation(count, 10, force, is_terminal);
+
+            // Assert
+            assert_eq!(needed, output);
+        }
+    }
+
+    #[test]
+    fn render_seeded_history() {
+        // Arrange
+        let mut items: Vec<HistoryItem> = history()
+            .into_iter()
+            .map(HistoryItem::from_command_line)
+            .collect();
+        items[2].start_timestamp = "2025-01-02T03:04:05Z".parse().ok();
+        let inputs = [Some(2), Some(0), None];
+        let outputs = [
+            "    2  touch a -n 10\n    3  2025-01-02 03:04:05 UTC  sort a\n",
+            "",
+            "    1  ls\n    2  touch a -n 10\n    3  2025-01-02 03:04:05 UTC  sort a\n",
+        ];
+
+        for (input, output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let rendered = render_history(&items, input);
+
+            // Assert
+            assert_eq!(rendered, output);
+        }
+    }
+
+    #[test]
+    fn write_names_separators() {
+        // Arrange
+        let names = ["/a", "/with\nnewline"];
+        let inputs = [true, false];
+        let outputs: [&[u8]; 2] = [b"/a\0/with\nnewline\0", b"/a\n/with\nnewline\n"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let mut written = Vec::new();
+            write_names(&mut written, names, *input).unwrap();
+
+            // Assert
+            assert_eq!(written, *output);
+        }
+    }
+
+    #[test]
+    fn make_mountable_image() {
+        // Arrange
+        let launch_dir = tempfile::tempdir().unwrap();
+        let image = Path::new("images").join("new.vdisk");
+        std::fs::create_dir(launch_dir.path().join("images")).unwrap();
+        let size = crate::simple_ext4::block_group_size(128).to_string();
+        let line = [
+            "",
+            "mkfs",
+            image.to_str().unwrap(),
+            "--size",
+            &size,
+            "-b",
+            "128",
+        ];
+        let Ok(CompleteCommand::MakeFs(cmd)) = CompleteCommand::try_parse_from(line) else {
+            panic!("mkfs should parse");
+        };
+
+        // Act
+        let summary = make_image(&cmd, launch_dir.path()).unwrap();
+        let again = make_image(&cmd, launch_dir.path());
+
+        // Assert
+        assert!(
+            summary.contains("1 block group(s) of 128 byte blocks"),
+            "{summary}"
+        );
+        let absolute = launch_dir.path().join(&image);
+        assert!(summary.contains(absolute.to_str().unwrap()), "{summary}");
+        assert!(again.is_err());
+        mkfs::validate(&absolute).unwrap();
+        assert!(crate::simple_ext4::fs::SimpleExt4FS::new(&absolute).is_ok());
+    }
+
+    #[test]
+    fn save_and_load_working_dir() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("cwd");
+        let inputs = ["/a/b", "/", "relative"];
+        let outputs = [Some(PathBuf::from("/a/b")), Some(PathBuf::from("/")), None];
+
+        // Nothing saved yet
+        assert_eq!(load_working_dir(&state_file), None);
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            save_working_dir(&state_file, Path::new(input)).unwrap();
+
+            // Assert
+            assert_eq!(load_working_dir(&state_file), *output);
+        }
+    }
+
+    #[test]
+    fn history_search_indicator() {
+        // Arrange
+        let cwd = Arc::new(RwLock::new(PathBuf::from("/")));
+        let prompt = FerrixPrompt::new(cwd, FerrixPromptSegment::WorkingDirectory);
+        let inputs = [
+            PromptHistorySearchStatus::Passing,
+            PromptHistorySearchStatus::Failing,
+        ];
+        let outputs = ["(reverse-search: ls) ", "(failing reverse-search: ls) "];
+
+        for (input, output) in inputs.into_iter().zip(outputs.iter()) {
+            // Act
+            let indicator = prompt.render_prompt_history_search_indicator(
+                PromptHistorySearch::new(input, "ls".to_string()),
+            );
+
+            // Assert
+            assert_eq!(indicator, *output);
+        }
+    }
+
+    #[test]
+    fn render_block_and_inode_usage() {
+        // Arrange
+        let output = DiskFreeCommandOutput {
+            block_size: 1024,
+            total_blocks: 4096,
+            free_blocks: 1024,
+            total_inodes: 100,
+            free_inodes: 60,
+        };
+        let inputs = [false, true];
+        let outputs = [
+            "Total disk size: 4 MiB\nUsed disk size: 3 MiB\nRemaining disk size: 1 MiB\n",
+            "Total inodes: 100\nUsed inodes: 40\nFree inodes: 60\n",
+        ];
+
+        for (inodes, output_text) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let rendered = render_disk_free(&output, *inodes);
+
+            // Assert
+            assert_eq!(rendered, *output_text);
+        }
+    }
+
+    #[test]
+    fn format_system_errors() {
+        // Arrange
+        let error = anyhow::Error::from(SystemError::FileAlreadyExists);
+        let with_context = anyhow::Error::from(SystemError::NoSuchFileOrDirectory).context("/a");
+        let inputs = [(&error, false), (&error, true), (&with_context, false)];
+        let outputs = [
+            "Error touching: File already exists",
+            "File already exists",
+            "Error touching: /a: No such file or directory",
+        ];
+
+        for ((error, quiet), output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let message = format_error("touching", error, *quiet);
+
+            // Assert
+            assert_eq!(message, *output);
+        }
+    }
+
+    #[test]
+    fn render_numbers() {
+        // Arrange
+        let inputs = [NumberFormat::Dec, NumberFormat::Hex, NumberFormat::Bin];
+        let outputs = [
+            ["0", "42", "65535"],
+            ["0x0", "0x2a", "0xffff"],
+            ["0b0", "0b101010", "0b1111111111111111"],
+        ];
+
+        for (format, output) in inputs.into_iter().zip(outputs.iter()) {
+            // Act
+            let rendered = [0, 42, Number::MAX].map(|number| render_number(number, format));
+
+            // Assert
+            assert_eq!(rendered, *output);
+        }
+    }
+
+    #[test]
+    fn render_raw_bytes() {
+        // Arrange
+        let bytes: Vec<u8> = (0..18).collect();
+        let inputs = [true, false];
+        let outputs = [
+            "00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n10 11\n",
+            "0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15\n16 17\n",
+        ];
+
+        for (hex, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let rendered = render_bytes(&bytes, *hex);
+
+            // Assert
+            assert_eq!(rendered, *output);
+        }
+    }
+
+    fn aliases(definitions: &[(&str, &str)]) -> Aliases {
+        let mut aliases = Aliases::default();
+        for (name, expansion) in definitions {
+            aliases.define(name, expansion).unwrap();
+        }
+        aliases
+    }
+
+    #[test]
+    fn expand_aliases() {
+        // Arrange
+        let aliases = aliases(&[("ll", "ls -a"), ("la", "ll --json"), ("ls", "ls -0")]);
+        let inputs = ["pwd", "ll", "ll /dir", "  la /dir", "ls /dir", "lll"];
+        let outputs = [
+            "pwd",
+            "ls -0 -a",
+            "ls -0 -a /dir",
+            "ls -0 -a --json /dir",
+            "ls -0 /dir",
+            "lll",
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = aliases.expand(input);
+
+            // Assert
+            assert_eq!(result.unwrap(), *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn detect_alias_loops() {
+        // Arrange
+        let aliases = aliases(&[("a", "b -x"), ("b", "a -y"), ("c", "c")]);
+        let inputs = ["a", "b", "c"];
+        let outputs = [
+            Err(AliasError::Loop("a".to_string())),
+            Err(AliasError::Loop("b".to_string())),
+            Ok("c".to_string()),
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = aliases.expand(input);
+
+            // Assert
+            assert_eq!(result, *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn define_aliases_from_args() {
+        // Arrange
+        let inputs: [&[&str]; 6] = [
+            &["ll", "=", "ls", "-a"],
+            &["ll=ls", "-a"],
+            &["ll", "=ls", "-a"],
+            &["c", "=", "cat", "a b", "c"],
+            &["ll", "ls"],
+            &["l", "l", "=", "ls"],
+        ];
+        let outputs = [
+            Ok("ll = ls -a\n"),
+            Ok("ll = ls -a\n"),
+            Ok("ll = ls -a\n"),
+            Ok("c = cat 'a b' c\n"),
+            Err(AliasError::MissingExpansion),
+            Err(AliasError::InvalidName("l l".to_string())),
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            let mut aliases = Aliases::default();
+            let args: Vec<String> = input.iter().map(|arg| arg.to_string()).collect();
+
+            // Act
+            let result = aliases.define_from_args(&args);
+
+            // Assert
+            assert_eq!(
+                result.map(|_| aliases.to_string()),
+                output.clone().map(str::to_string),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn aliases_survive_a_restart() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let alias_file = dir.path().join("aliases");
+        let mut aliases = aliases(&[("ll", "ls -a"), ("c", "cat 'a b' c")]);
+
+        // Act
+        aliases.save(&alias_file).unwrap();
+        let loaded = Aliases::load(&alias_file);
+        aliases.remove("ll").unwrap();
+        let missing = aliases.remove("ll");
+
+        // Assert
+        assert_eq!(loaded.expand("c d").unwrap(), "cat 'a b' c d");
+        assert_eq!(loaded.expand("ll").unwrap(), "ls -a");
+        assert_eq!(missing, Err(AliasError::NotFound("ll".to_string())));
+        assert_eq!(
+            Aliases::load(&dir.path().join("missing")),
+            Aliases::default()
+        );
+    }
+
+    #[test]
+    fn collect_here_doc_script() {
+        // Arrange
+        let inputs = [
+            "touch a -n 10",
+            "<<END\ntouch a 10\nmkdir b\nEND",
+            "  <<EOF  \nls\n  EOF  \nignored",
+            "<<END\nEND",
+            "<<\nls",
+            "<<END\ntouch a 10",
+        ];
+        let outputs = [
+            Ok(None),
+            Ok(Some("touch a 10\nmkdir b\n".to_string())),
+            Ok(Some("ls\n".to_string())),
+            Ok(Some(String::new())),
+            Err(HereDocError::MissingDelimiter),
+            Err(HereDocError::Unterminated("END".to_string())),
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = collect_here_doc(input);
+
+            // Assert
+            assert_eq!(result, *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn here_doc_runs_as_a_batch() {
+        // Arrange
+        let script = collect_here_doc("<<END\ntouch a 10\nmkdir b -p\nEND")
+            .unwrap()
+            .unwrap();
+
+        // Act
+        let commands: Vec<CompleteCommand> = WinnowFerrixParser::new(&script)
+            .get_commands()
+            .unwrap()
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect();
+
+        // Assert
+        assert!(matches!(
+            &commands[..],
+            [
+                CompleteCommand::Touch(TouchCommand {
+                    number_of_integers: 10,
+                    ..
+                }),
+                CompleteCommand::MakeDir(MakeDirCommand { parents: true, .. }),
+            ]
+        ));
+    }
+
+    #[test]
+    fn mkdir_expands_home() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let mut system = FlemisSystem::new(dir.path().to_path_buf()).unwrap();
+        let inputs = ["mkdir ~/x", "mkdir -p ~/y/z"];
+        let outputs = ["x", "y/z"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let status = run_line(&mut system, input);
+
+            // Assert
+            assert_eq!(status, 0, "{input}");
+            assert!(dir.path().join(output).is_dir(), "{input}");
+        }
+        assert!(!dir.path().join("~").exists());
+    }
+
+    #[test]
+    fn mkdir_on_named_mount() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let mut system = FlemisSystem::new(dir.path().to_path_buf()).unwrap();
+        system
+            .add_mount("other", other.path().to_path_buf())
+            .unwrap();
+
+        // Act
+        let status = run_line(&mut system, "mkdir -p @other:/d/e");
+
+        // Assert
+        assert_eq!(status, 0);
+        assert!(other.path().join("d/e").is_dir());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn here_doc_status() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let mut system = FlemisSystem::new(dir.path().to_path_buf()).unwrap();
+        let inputs = ["<<END\nmkdir a\nEND", "<<END\nmkdir b\nnot-a-command\nEND"];
+        let outputs = [0, 1];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let status = run_line(&mut system, input);
+
+            // Assert
+            assert_eq!(status, *output, "{input}");
+        }
+        assert!(dir.path().join("a").is_dir());
+        assert!(!dir.path().join("b").exists());
+    }
+
+    #[test]
+    fn symlink_expands_home() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let mut system = FlemisSystem::new(dir.path().to_path_buf()).unwrap();
+        let inputs = ["ln -s ~/a home", "ln -s a relative"];
+        let outputs = [dir.path().join("a"), PathBuf::from("a")];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let status = run_line(&mut system, input);
+
+            // Assert
+            assert_eq!(status, 0, "{input}");
+            let link = dir.path().join(input.rsplit(' ').next().unwrap());
+            assert_eq!(std::fs::read_link(link).unwrap(), *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn incomplete_input() {
+        // Arrange
+        let inputs = [
+            "touch a",
+            "touch a \\",
+            "write a [1 2",
+            "write a [1 2\n3]",
+            "a (b) c",
+            "<<END\nwrite a [1 2",
+            "<<END\nwrite a [1 2\nEND",
+            "<<",
+            "echo \"(\"",
+            "echo '[' [1",
+            "echo \\( a",
+            "echo \"a\\\"(\" b",
+            "write a [\"]\"",
+        ];
+        let outputs = [
+            false, true, true, false, false, true, false, false, false, true, false, false, true,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = is_incomplete(input);
+
+            // Assert
+            assert_eq!(result, *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn assemble_multiline_input() {
+        // Arrange
+        let inputs = [
+            "touch a",
+            "touch a \\\n-n 10",
+            "write a [1 2\n3\n4]",
+            "a\\\nb",
+        ];
+        let outputs = ["touch a", "touch a  -n 10", "write a [1 2 3 4]", "a b"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let line = assemble_lines(input);
+
+            // Assert
+            assert_eq!(line, *output);
+        }
+    }
+
+    #[test]
+    fn history_search_keybinding() {
+        // Arrange
+        let inputs = ['r', 's', 'S'];
+        let outputs = [('r', 's'), ('s', 'r'), ('s', 'r')];
+
+        for (input, (bound, unbound)) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let keybindings = keybindings(*input);
+
+            // Assert
+            assert_eq!(
+                keybindings.find_binding(KeyModifiers::CONTROL, KeyCode::Char(*bound)),
+                Some(ReedlineEvent::SearchHistory)
+            );
+            assert_ne!(
+                keybindings.find_binding(KeyModifiers::CONTROL, KeyCode::Char(*unbound)),
+                Some(ReedlineEvent::SearchHistory)
+            );
+        }
+    }
+
+    #[test]
+    fn prompt_for_input() {
+        // Arrange
+        let cwd = Arc::new(RwLock::new(PathBuf::from("/a")));
+        let inputs = [true, false];
+        let outputs = [("/a@ferrix", DEFAULT_PROMPT_INDICATOR), ("", "")];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let prompt =
+                FerrixPrompt::for_input(cwd.clone(), FerrixPromptSegment::WorkingDirectory, *input);
+
+            // Assert
+            assert_eq!(prompt.render_prompt_left(), output.0);
+            assert_eq!(
+                prompt.render_prompt_indicator(PromptEditMode::Default),
+                output.1
+            );
+        }
     }
 }
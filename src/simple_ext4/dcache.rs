@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    time::{Duration, Instant},
+};
+
+use fuser::FileAttr;
+
+/// Number of directory entries kept before the least recently used one is evicted
+pub const DCACHE_CAPACITY: usize = 1024;
+/// How long the kernel is told it may keep entries and attributes, the dcache keeps them as long
+pub const ENTRY_TTL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct CachedEntry {
+    ino: u32,
+    attr: FileAttr,
+    cached_at: Instant,
+    last_used: u64,
+}
+
+/// Least recently used cache of directory entry lookups, mapping a `(parent, name)` pair to the
+/// child inode and its attributes.
+///
+/// Entries expire after `ttl`, the same time the kernel is told it may keep them, and have to be
+/// invalidated whenever the parent directory or the child inode changes.
+#[derive(Debug)]
+pub struct DirCache {
+    entries: HashMap<(u32, OsString), CachedEntry>,
+    capacity: usize,
+    ttl: Duration,
+    clock: u64,
+    hits: u64,
+}
+
+impl DirCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            capacity,
+            ttl,
+            clock: 0,
+            hits: 0,
+        }
+    }
+
+    /// Look up the child named `name` of `parent`, dropping the entry if it has expired
+    pub fn get(&mut self, parent: u32, name: &OsStr) -> Option<(u32, FileAttr)> {
+        let key = (parent, name.to_owned());
+        let entry = self.entries.get_mut(&key)?;
+        if entry.cached_at.elapsed() >= self.ttl {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        self.clock += 1;
+        self.hits += 1;
+        entry.last_used = self.clock;
+        Some((entry.ino, entry.attr))
+    }
+
+    /// Cache the child named `name` of `parent`, evicting the least recently used entry when full
+    pub fn insert(&mut self, parent: u32, name: &OsStr, ino: u32, attr: FileAttr) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (parent, name.to_owned());
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            CachedEntry {
+                ino,
+                attr,
+                cached_at: Instant::now(),
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Drop every cached entry of the `parent` directory
+    pub fn invalidate_dir(&mut self, parent: u32) {
+        self.entries.retain(|(dir, _), _| *dir != parent);
+    }
+
+    /// Drop every cached entry pointing to the `ino` inode, whose attributes changed
+    pub fn invalidate_inode(&mut self, ino: u32) {
+        self.entries.retain(|_, entry| entry.ino != ino);
+    }
+
+    /// Number of lookups answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+impl Default for DirCache {
+    fn default() -> Self {
+        Self::new(DCACHE_CAPACITY, ENTRY_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use fuser::FileType;
+
+    use super::*;
+
+    fn attr(ino: u64) -> FileAttr {
+        FileAttr {
+            ino,
+            size: 0,
+            blocks: 0,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        // Arrange
+        let mut cache = DirCache::new(2, Duration::from_secs(60));
+        cache.insert(1, OsStr::new("a"), 2, attr(2));
+        cache.insert(1, OsStr::new("b"), 3, attr(3));
+        cache.get(1, OsStr::new("a"));
+
+        // Act
+        cache.insert(1, OsStr::new("c"), 4, attr(4));
+
+        // Assert
+        assert_eq!(cache.get(1, OsStr::new("a")).map(|(ino, _)| ino), Some(2));
+        assert!(cache.get(1, OsStr::new("b")).is_none());
+        assert_eq!(cache.get(1, OsStr::new("c")).map(|(ino, _)| ino), Some(4));
+    }
+
+    #[test]
+    fn expire_and_invalidate() {
+        // Arrange
+        let mut expired = DirCache::new(4, Duration::ZERO);
+        let mut cache = DirCache::new(4, Duration::from_secs(60));
+        expired.insert(1, OsStr::new("a"), 2, attr(2));
+        cache.insert(1, OsStr::new("a"), 2, attr(2));
+        cache.insert(1, OsStr::new("b"), 3, attr(3));
+        cache.insert(5, OsStr::new("c"), 3, attr(3));
+        cache.insert(5, OsStr::new("d"), 6, attr(6));
+
+        // Act
+        cache.invalidate_dir(1);
+        cache.invalidate_inode(3);
+
+        // Assert
+        assert!(expired.get(1, OsStr::new("a")).is_none());
+        assert!(cache.get(1, OsStr::new("a")).is_none());
+        assert!(cache.get(5, OsStr::new("c")).is_none());
+        assert!(cache.get(5, OsStr::new("d")).is_some());
+        assert_eq!(cache.hits(), 1);
+    }
+}
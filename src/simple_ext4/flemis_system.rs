@@ -1,173 +1,358 @@
-use anyhow::{bail, Result};
 use byte_unit::Byte;
 use clean_path::Clean;
 use fuser::{BackgroundSession, MountOption};
 use memmap::{Mmap, MmapMut, MmapOptions};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use rand::distr::Uniform;
-use rand::Rng;
 use std::{
+    collections::{BTreeMap, HashSet},
     ffi::{OsStr, OsString},
-    io::{BufReader, Cursor, Read, Seek, Write},
-    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    fs::{File, FileTimes, Permissions},
+    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    os::{
+        fd::AsRawFd,
+        unix::{
+            ffi::OsStrExt,
+            fs::{MetadataExt, PermissionsExt},
+        },
+    },
     path::{Path, PathBuf},
     process::exit,
-    sync::{Arc, Mutex},
-    thread, u16, usize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+    usize,
 };
 use tracing::info;
 
 use crate::{
-    ext_arr::ExtArr,
+    complete_command::{DebugTarget, NodeType},
+    ext_arr::{ExtArr, FileBufRW, MmapRO, SyncRW},
+    glob,
+    integers::{self, ConcatMode, Generator},
     mem::FixedSizeMem,
-    sort::ExtSorter,
-    system::{ListCommandOutput, Number, System, SystemError, DEFAULT_MEM_SIZE},
+    progress::with_stderr_progress,
+    sort::{ExtSorter, RayonExtSorter, TempRuns},
+    system::{
+        for_each_file, is_stdio, split_mount_path, DiffCommandOutput, DiskFreeCommandOutput,
+        FileCount, ListCommandOutput, NodeInfo, NodeStat, Number, OpenHandle, Stdio, System,
+        SystemError, SystemResult, WordCountCommandOutput, DEFAULT_MEM_SIZE, MOUNT_PATH_PREFIX,
+        STDIO_PATH,
+    },
     vdisk::{self, VDisk, VDiskSize},
 };
 
+use super::fs::{
+    DEBUG_REPLY_SIZE, FERRIX_IOC_HANDLES, FERRIX_IOC_INODE, FERRIX_IOC_SUPERBLOCK,
+    HANDLES_REPLY_SIZE,
+};
+use super::types::{Inode, Superblock};
+
+/// Directory under the mount point where `rm --trash` moves removed files and directories
+pub const TRASH_DIR: &str = ".trash";
+
+/// Number of integers compared at a time by `diff`
+const DIFF_CHUNK_LEN: usize = 4096;
+
+/// Number of integers read at a time by `sort --check`
+const SORT_CHECK_CHUNK_LEN: usize = 4096;
+
+/// How often `tail --follow` checks the file for appended values
+pub const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by the SIGINT handler of [`InterruptGuard`]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Name of the mount given to [`FlemisSystem::new`]
+pub const DEFAULT_MOUNT: &str = "default";
+
+/// Levels below a directory the recursive commands go down before giving up on deeper entries
+pub const DEFAULT_MAX_WALK_DEPTH: usize = 256;
+
+/// How [`FlemisSystem::walk`] goes down a directory tree
+#[derive(Debug, Clone, Copy)]
+struct WalkOptions {
+    /// Levels below the walked directory to visit, its entries being the first one
+    max_depth: usize,
+    /// Go down the directories symbolic links point to instead of visiting only the links
+    follow_links: bool,
+}
+
+/// An integer file, memory-mapped if possible, or the standard input, read whole up front so it
+/// can be seeked
+enum Source {
+    Mapped(MmapRO),
+    File(BufReader<File>),
+    Stdin(Cursor<Vec<u8>>),
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Source::Mapped(mmap) => mmap.read(buf),
+            Source::File(file) => file.read(buf),
+            Source::Stdin(stdin) => stdin.read(buf),
+        }
+    }
+}
+
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Source::Mapped(mmap) => mmap.seek(pos),
+            Source::File(file) => file.seek(pos),
+            Source::Stdin(stdin) => stdin.seek(pos),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FlemisSystem {
+    /// Mount point of the active mount, the one paths without a mount name refer to
     mount_point: PathBuf,
+    /// Mount point of every mount by name, the active one included
+    mounts: BTreeMap<String, PathBuf>,
+    /// Streams read and written in place of the [`STDIO_PATH`] file
+    stdio: Mutex<Stdio>,
+    /// Levels below a directory the recursive commands go down
+    max_walk_depth: usize,
 }
 
-impl FlemisSystem {
-    pub fn new(mount_point: PathBuf) -> Result<Self> {
-        Ok(Self { mount_point })
-    }
+/// Write a file through `write` into a temporary file next to `path`, renamed over it only once
+/// the whole content was written so a failure leaves the previous content untouched
+fn replace_file<T, F>(path: &Path, write: F) -> SystemResult<T>
+where
+    F: FnOnce(&mut dyn Write) -> SystemResult<T>,
+{
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut prefix = OsString::from(".");
+    prefix.push(path.file_name().unwrap_or_default());
+    let permissions = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(_) => Permissions::from_mode(0o666),
+    };
+
+    // The temporary file is removed when dropped on any error
+    let mut file = tempfile::Builder::new()
+        .prefix(&prefix)
+        .permissions(permissions)
+        .tempfile_in(dir)?;
+    let mut writer = std::io::BufWriter::new(&mut file);
+    let written = write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(written)
+}
 
-    fn convert_path_to_vdisk_path(&self, path: &PathBuf) -> PathBuf {
-        let mut vdisk_path = self.mount_point.clean();
-        let path = PathBuf::from("/").join(path).clean();
-        let path = path.strip_prefix("/").unwrap_or(path.as_path());
-        vdisk_path.push(path);
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
 
-        vdisk_path.clean()
-    }
+/// Catches Ctrl-C while alive instead of letting it end the process, the previous handler is
+/// restored on drop
+struct InterruptGuard {
+    previous: SigAction,
 }
 
-impl System for FlemisSystem {
-    fn touch(&mut self, cmd: &crate::complete_command::TouchCommand) -> Result<()> {
-        let file = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file));
+impl InterruptGuard {
+    fn install() -> SystemResult<Self> {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let action = SigAction::new(
+            SigHandler::Handler(on_interrupt),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        let previous = unsafe { sigaction(Signal::SIGINT, &action) }?;
+        Ok(Self { previous })
+    }
 
-        if file.exists() {
-            bail!(SystemError::FileAlreadyExists);
-        }
+    fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
 
-        let file = std::fs::File::create(file)?;
-        let mut writer = std::io::BufWriter::new(file);
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        // SAFETY: puts back the handler that was installed before this guard
+        let _ = unsafe { sigaction(Signal::SIGINT, &self.previous) };
+    }
+}
 
-        let mut rng = rand::rng();
-        let data: Vec<u16> = (0..cmd.number_of_integers)
-            .map(|_| rng.random_range(0..=u16::MAX))
-            .collect();
+impl FlemisSystem {
+    /// Creates a system for the filesystem that will be mounted at `mount_point`, so it has to be
+    /// called before mounting. A missing mount point is created, an existing one must be an empty
+    /// directory
+    pub fn new(mount_point: PathBuf) -> SystemResult<Self> {
+        Self::prepare_mount_point(&mount_point)?;
+
+        Ok(Self {
+            mounts: BTreeMap::from([(DEFAULT_MOUNT.to_string(), mount_point.clone())]),
+            mount_point,
+            stdio: Mutex::new(Stdio::default()),
+            max_walk_depth: DEFAULT_MAX_WALK_DEPTH,
+        })
+    }
 
-        let encoded: Vec<u8> = bincode::serialize(&data)?;
+    /// Read and write the [`STDIO_PATH`] file from `stdio` instead of the process streams
+    pub fn with_stdio(mut self, stdio: Stdio) -> Self {
+        self.stdio = Mutex::new(stdio);
+        self
+    }
 
-        writer.write_all(&encoded)?;
-        writer.flush()?;
+    /// Limit the recursive commands to `max_walk_depth` levels below the directory they start at
+    pub fn with_max_walk_depth(mut self, max_walk_depth: usize) -> Self {
+        self.max_walk_depth = max_walk_depth;
+        self
+    }
 
-        Ok(())
+    fn walk_options(&self, follow_links: bool) -> WalkOptions {
+        WalkOptions {
+            max_depth: self.max_walk_depth,
+            follow_links,
+        }
     }
 
-    fn mv(&mut self, cmd: &crate::complete_command::MoveCommand) -> Result<()> {
-        let file_to_move = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.from));
+    fn stdio(&self) -> std::sync::MutexGuard<'_, Stdio> {
+        self.stdio
+            .lock()
+            .expect("Failed to lock the standard streams")
+    }
 
-        if !file_to_move.exists() {
-            bail!(SystemError::NoSuchFileOrDirectory);
+    /// Registers another filesystem that will be mounted at `mount_point` under `name`, the mount
+    /// point is prepared the same way as in [`FlemisSystem::new`]
+    pub fn add_mount(&mut self, name: &str, mount_point: PathBuf) -> SystemResult<()> {
+        if self.mounts.contains_key(name) {
+            return Err(SystemError::MountAlreadyExists(name.to_string()));
         }
 
-        let new_file = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.to));
-        std::fs::rename(file_to_move, new_file)?;
+        Self::prepare_mount_point(&mount_point)?;
+        self.mounts.insert(name.to_string(), mount_point);
+
         Ok(())
     }
 
-    fn make_dir(&mut self, cmd: &crate::complete_command::MakeDirCommand) -> Result<()> {
-        let dir = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.dir));
-
-        if dir.exists() {
-            bail!(SystemError::FileAlreadyExists);
+    fn prepare_mount_point(mount_point: &Path) -> SystemResult<()> {
+        if !mount_point.exists() {
+            std::fs::create_dir_all(mount_point)?;
+        } else if !mount_point.is_dir() {
+            return Err(SystemError::MountPointNotDirectory(
+                mount_point.to_path_buf(),
+            ));
+        } else if std::fs::read_dir(mount_point)?.next().is_some() {
+            return Err(SystemError::MountPointNotEmpty(mount_point.to_path_buf()));
         }
 
-        std::fs::create_dir_all(dir)?;
         Ok(())
     }
 
-    fn remove(&mut self, cmd: &crate::complete_command::RemoveCommand) -> Result<()> {
-        let file_or_dir = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file_or_dir));
-
-        if !file_or_dir.exists() {
-            bail!(SystemError::NoSuchFileOrDirectory);
-        }
+    /// Path on the host of a path of the system, `@name:/path` addresses a path of the mount
+    /// named `name` and any other path one of the active mount
+    fn convert_path_to_vdisk_path(&self, path: &Path) -> SystemResult<PathBuf> {
+        let (mount_point, path) = match split_mount_path(path) {
+            Some((name, path)) => (self.named_mount_point(name)?, path),
+            None => (self.mount_point.as_path(), path),
+        };
 
-        if file_or_dir.is_dir() && !cmd.recursive {
-            bail!(SystemError::IsDirectory);
-        }
+        let mut vdisk_path = mount_point.clean();
+        let path = PathBuf::from("/").join(path).clean();
+        let path = path.strip_prefix("/").unwrap_or(path.as_path());
+        vdisk_path.push(path);
 
-        if cmd.recursive {
-            Ok(std::fs::remove_dir_all(file_or_dir)?)
-        } else {
-            Ok(std::fs::remove_file(file_or_dir)?)
-        }
+        Ok(vdisk_path.clean())
     }
 
-    fn head(
-        &self,
-        cmd: &crate::complete_command::HeadCommand,
-    ) -> Result<Vec<crate::system::Number>> {
-        let file = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file));
-
-        if !file.exists() {
-            bail!(SystemError::NoSuchFileOrDirectory);
+    /// Path of the system of a path on the host, paths of a mount other than the active one are
+    /// addressed as `@name:/path`
+    fn convert_vdisk_path_to_path(&self, vdisk_path: &Path) -> PathBuf {
+        let mount_point = self.mount_point.clean();
+        if let Ok(path) = vdisk_path.strip_prefix(&mount_point) {
+            return PathBuf::from("/").join(path);
         }
 
-        let start: usize = cmd.start.try_into()?;
-        let mut end: usize = cmd.end.try_into()?;
-        if start > end {
-            end = start + 10;
+        for (name, mount_point) in &self.mounts {
+            if let Ok(path) = vdisk_path.strip_prefix(mount_point.clean()) {
+                let mut mount_path = OsString::from(format!("{MOUNT_PATH_PREFIX}{name}:"));
+                mount_path.push(PathBuf::from("/").join(path));
+                return PathBuf::from(mount_path);
+            }
         }
 
-        let file = std::fs::File::open(file)?;
-        let mut reader = std::io::BufReader::new(file);
-
-        // First read the vector length from bincode header
-        let vec_len: u64 = bincode::deserialize_from(&mut reader)?;
+        PathBuf::from("/").join(vdisk_path)
+    }
 
-        if end >= vec_len as usize {
-            end = vec_len.try_into()?;
+    fn named_mount_point(&self, name: &str) -> SystemResult<&Path> {
+        match self.mounts.get(name) {
+            Some(mount_point) => Ok(mount_point),
+            None => Err(SystemError::UnknownMount(name.to_string())),
         }
+    }
 
-        // Skip elements before start
-        for _ in 0..start {
-            let _: Number = bincode::deserialize_from(&mut reader)?;
+    /// Open a file of the system for reading, failing if it doesn't exist or is a directory
+    fn open_file(&self, file: &OsStr) -> SystemResult<BufReader<File>> {
+        let path = self.convert_path_to_vdisk_path(Path::new(file))?;
+        if !path.exists() {
+            return Err(SystemError::NoSuchFileOrDirectory);
         }
-
-        // Read only the required elements
-        let elements_to_read = end - start;
-        let mut result = Vec::with_capacity(elements_to_read);
-
-        for _ in 0..elements_to_read {
-            let num: Number = bincode::deserialize_from(&mut reader)?;
-            result.push(num);
+        if path.is_dir() {
+            return Err(SystemError::IsDirectory);
         }
 
-        Ok(result)
+        Ok(BufReader::new(File::open(path)?))
     }
 
-    fn list(
-        &self,
-        cmd: &crate::complete_command::ListCommand,
-    ) -> Result<crate::system::ListCommandOutput> {
-        let path = PathBuf::from(cmd.dir.as_ref().unwrap_or(&OsString::from("/")));
-        let path = self.convert_path_to_vdisk_path(&path);
+    /// Read the length header of an integer file and infer the width in bytes of its elements
+    /// from the remaining file size. Empty files have no width
+    fn read_header(path: &Path) -> SystemResult<(u64, Option<u64>)> {
+        integers::read_header(BufReader::new(File::open(path)?), false)
+    }
 
-        if !path.exists() {
-            bail!(SystemError::NoSuchFileOrDirectory);
+    /// Substitute the `stat` format specifiers with the node status, unknown specifiers are
+    /// kept as is
+    fn format_stat(stat: &NodeStat, format: &str) -> String {
+        let mut output = String::with_capacity(format.len());
+        let mut chars = format.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('s') => output.push_str(&stat.size_in_bytes.to_string()),
+                Some('b') => output.push_str(&stat.blocks.to_string()),
+                Some('a') => output.push_str(&format!("{:o}", stat.mode & 0o7777)),
+                Some('U') => output.push_str(&stat.uid.to_string()),
+                Some('G') => output.push_str(&stat.gid.to_string()),
+                Some('Y') => output.push_str(&stat.modified_at.to_string()),
+                Some('n') => output.push_str(&stat.name),
+                Some('%') => output.push('%'),
+                Some(other) => {
+                    output.push('%');
+                    output.push(other);
+                }
+                None => output.push('%'),
+            }
         }
 
+        output
+    }
+
+    /// List the given file, or every entry of the given directory, keeping only the nodes of
+    /// `node_type` if set
+    fn list_nodes(&self, path: &Path, node_type: Option<NodeType>) -> SystemResult<Vec<NodeInfo>> {
         let mut nodes = Vec::new();
 
         if !path.is_dir() {
             let metadata = path.metadata()?;
-            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let file_name = path.file_name().unwrap().to_os_string();
             let size = metadata.size();
 
             let node_info = crate::system::NodeInfo {
@@ -189,10 +374,7 @@ impl System for FlemisSystem {
                 let size = metadata.size();
 
                 let node_info = crate::system::NodeInfo {
-                    name: file_name
-                        .to_os_string()
-                        .into_string()
-                        .expect("expected to be a string"),
+                    name: file_name,
                     is_dir: metadata.is_dir(),
                     size_in_bytes: size as VDiskSize,
                     human_readable_size: Byte::from_u64(size)
@@ -204,156 +386,2937 @@ impl System for FlemisSystem {
             }
         }
 
-        let stat = nix::sys::statfs::statfs(&self.mount_point)?;
+        nodes.retain(|node| node_type.is_none_or(|node_type| node_type.matches(node.is_dir)));
 
-        let total_disk_space_in_bytes = (stat.blocks() * (stat.block_size() as u64)).try_into()?;
-        let remaining_disk_space_in_bytes =
-            (stat.blocks_available() * (stat.block_size() as u64)).try_into()?;
+        Ok(nodes)
+    }
 
-        Ok(ListCommandOutput {
-            nodes,
-            total_disk_space_in_bytes,
-            remaining_disk_space_in_bytes,
+    /// Visit every entry under `dir`, depth first with the entries of a directory before the
+    /// directory itself, so they can be removed in the order they are visited.
+    ///
+    /// Directories deeper than `options.max_depth` aren't gone down. Every directory is gone
+    /// down only once, tracked by its inode, so a symbolic link back to an ancestor doesn't
+    /// loop forever when following links
+    fn walk(
+        dir: &Path,
+        options: WalkOptions,
+        visit: &mut dyn FnMut(&Path, &std::fs::Metadata) -> SystemResult<()>,
+    ) -> SystemResult<()> {
+        let metadata = dir.metadata()?;
+        let mut visited = HashSet::from([(metadata.dev(), metadata.ino())]);
+
+        Self::walk_dir(dir, options.max_depth, options, &mut visited, visit)
+    }
+
+    fn walk_dir(
+        dir: &Path,
+        depth_left: usize,
+        options: WalkOptions,
+        visited: &mut HashSet<(u64, u64)>,
+        visit: &mut dyn FnMut(&Path, &std::fs::Metadata) -> SystemResult<()>,
+    ) -> SystemResult<()> {
+        if depth_left == 0 {
+            return Ok(());
+        }
+
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let mut metadata = entry.metadata()?;
+
+            // A dangling link is visited as the link itself
+            if options.follow_links && metadata.is_symlink() {
+                metadata = path.metadata().unwrap_or(metadata);
+            }
+
+            if metadata.is_dir() && visited.insert((metadata.dev(), metadata.ino())) {
+                Self::walk_dir(&path, depth_left - 1, options, visited, visit)?;
+            }
+
+            visit(&path, &metadata)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the given vdisk path into the trash directory, creating it if needed. On a name
+    /// collision a numeric suffix is appended, returning the path inside the trash
+    fn move_to_trash(&self, file_or_dir: &Path) -> SystemResult<PathBuf> {
+        let trash_dir = self.mount_point.clean().join(TRASH_DIR);
+
+        if file_or_dir.starts_with(&trash_dir) {
+            return Err(SystemError::AlreadyInTrash);
+        }
+
+        std::fs::create_dir_all(&trash_dir)?;
+
+        let name = file_or_dir.file_name().unwrap_or(OsStr::new(TRASH_DIR));
+        let mut trashed = trash_dir.join(name);
+        let mut suffix = 1;
+        while trashed.symlink_metadata().is_ok() {
+            let mut unique_name = name.to_os_string();
+            unique_name.push(format!(".{suffix}"));
+            trashed = trash_dir.join(unique_name);
+            suffix += 1;
+        }
+
+        std::fs::rename(file_or_dir, &trashed)?;
+
+        Ok(self.convert_vdisk_path_to_path(&trashed))
+    }
+
+    /// Walk `dir` up to `depth` levels down, collecting every entry matching the glob pattern
+    fn glob_dir(
+        &self,
+        dir: &Path,
+        pattern: &Path,
+        depth: Option<usize>,
+        matches: &mut Vec<PathBuf>,
+    ) -> SystemResult<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        let mut options = self.walk_options(false);
+        options.max_depth = depth.map_or(options.max_depth, |d| d.min(options.max_depth));
+
+        Self::walk(dir, options, &mut |path, _| {
+            let virtual_path = self.convert_vdisk_path_to_path(path);
+            if glob::matches(pattern, &virtual_path) {
+                matches.push(virtual_path);
+            }
+            Ok(())
         })
     }
 
-    fn sort(&self, cmd: &crate::complete_command::SortCommand) -> Result<()> {
-        let start = std::time::Instant::now();
-        let path = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file));
+    /// Open a file of the system for reading, or the standard input for [`STDIO_PATH`]
+    fn open_source(&self, file: &OsStr) -> SystemResult<Source> {
+        if is_stdio(file) {
+            let mut input = Vec::new();
+            self.stdio().input.read_to_end(&mut input)?;
+            return Ok(Source::Stdin(Cursor::new(input)));
+        }
 
-        if !path.exists() {
-            bail!(SystemError::NoSuchFileOrDirectory);
+        // Mapping saves a syscall per read, files that can't be mapped, e.g. opened with direct
+        // I/O, are read through a buffer instead
+        let file = self.open_file(file)?;
+        match MmapRO::try_from(file.get_ref().try_clone()?) {
+            Ok(mmap) => Ok(Source::Mapped(mmap)),
+            Err(_) => Ok(Source::File(file)),
         }
+    }
 
-        let file = std::fs::File::open(path.clone())?;
-        let reader = BufReader::new(file);
-        let numbers: Vec<u16> = bincode::deserialize_from(reader)?;
-        let length = numbers.len();
+    /// Open an integer file as an array positioned after its length header, if not `raw`
+    fn open_integers(
+        &self,
+        file: &OsStr,
+        raw: bool,
+    ) -> SystemResult<(u64, ExtArr<Number, Source>)> {
+        let mut reader = self.open_source(file)?;
+        let (length, _) = integers::read_header(&mut reader, raw)?;
 
-        let mut mem = FixedSizeMem::<DEFAULT_MEM_SIZE>::new();
-        let mut arr = ExtArr::<Number, _>::new(Cursor::new(Vec::with_capacity(length * 2)));
+        Ok((length, ExtArr::new(reader)))
+    }
 
-        arr.write(&numbers)?;
-        arr.flush()?;
-        arr.rewind()?;
+    /// Stream the integers of a file returning the index of the first one out of order, they are
+    /// expected to be non-decreasing or, when `descending` is set, non-increasing
+    fn find_disorder(
+        &self,
+        file: &OsStr,
+        descending: bool,
+        raw: bool,
+    ) -> SystemResult<Option<u64>> {
+        let (length, mut arr) = self.open_integers(file, raw)?;
+        let in_order = |prev: Number, next: Number| {
+            if descending {
+                prev >= next
+            } else {
+                prev <= next
+            }
+        };
+
+        let mut chunk: Vec<Number> = vec![0; SORT_CHECK_CHUNK_LEN];
+        let mut last: Option<Number> = None;
+        let mut index = 0;
+
+        while index < length {
+            let len = (length - index).min(SORT_CHECK_CHUNK_LEN as u64) as usize;
+            let values = &mut chunk[..len];
+            arr.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(values))?;
+
+            for (offset, &value) in values.iter().enumerate() {
+                if last.is_some_and(|last| !in_order(last, value)) {
+                    return Ok(Some(index + offset as u64));
+                }
+                last = Some(value);
+            }
+
+            index += len as u64;
+        }
 
-        ExtSorter::sort(&mut arr, mem.as_mut(), |_| {
-            Ok(ExtArr::new(Cursor::new(Vec::with_capacity(
-                DEFAULT_MEM_SIZE,
-            ))))
+        Ok(None)
+    }
+
+    /// Remove a directory and all its content, children first, pushing each removed node
+    fn remove_dir_recursively(&self, dir: &Path, removed: &mut Vec<PathBuf>) -> SystemResult<()> {
+        // Links are removed, never what they point to
+        Self::walk(dir, self.walk_options(false), &mut |path, metadata| {
+            if metadata.is_dir() {
+                std::fs::remove_dir(path)?;
+            } else {
+                std::fs::remove_file(path)?;
+            }
+            removed.push(self.convert_vdisk_path_to_path(path));
+            Ok(())
         })?;
 
-        arr.rewind()?;
+        std::fs::remove_dir(dir)?;
+        removed.push(self.convert_vdisk_path_to_path(dir));
 
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        let mut writer = std::io::BufWriter::new(file);
+        Ok(())
+    }
 
-        let mut values = Vec::with_capacity(length);
-        let casted_values = arr.read_to_end(&mut values)?;
-        let encoded = bincode::serialize(casted_values)?;
+    /// Count the directory and every entry under it, the same ones it is removed with
+    fn count_dir_recursively(&self, dir: &Path) -> SystemResult<usize> {
+        let mut count = 1;
+        Self::walk(dir, self.walk_options(false), &mut |_, _| {
+            count += 1;
+            Ok(())
+        })?;
 
-        writer.write_all(&encoded)?;
-        writer.flush()?;
-        info!("Sort took {:?}", start.elapsed());
+        Ok(count)
+    }
+}
+
+impl System for FlemisSystem {
+    fn touch(&mut self, cmd: &crate::complete_command::TouchCommand) -> SystemResult<()> {
+        for_each_file(&cmd.files, cmd.keep_going, |file| {
+            let file = self.convert_path_to_vdisk_path(file)?;
+
+            if file.exists() {
+                return Err(SystemError::FileAlreadyExists);
+            }
+
+            let writer = std::io::BufWriter::new(File::create(file)?);
+            with_stderr_progress(cmd.progress, |on_progress| {
+                integers::write_random(
+                    writer,
+                    cmd.number_of_integers,
+                    Generator::from(cmd),
+                    cmd.raw,
+                    on_progress,
+                )
+            })
+        })
+    }
 
+    fn mv(&mut self, cmd: &crate::complete_command::MoveCommand) -> SystemResult<()> {
+        let file_to_move = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.from))?;
+
+        if !file_to_move.exists() {
+            return Err(SystemError::NoSuchFileOrDirectory);
+        }
+
+        let new_file = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.to))?;
+        std::fs::rename(file_to_move, new_file)?;
         Ok(())
     }
 
-    fn cat(&self, cmd: &crate::complete_command::CatCommand) -> Result<PathBuf> {
-        let mut files = Vec::with_capacity(cmd.files.len());
+    fn copy(&mut self, cmd: &crate::complete_command::CopyCommand) -> SystemResult<()> {
+        let file_to_copy = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.from))?;
 
-        if files.capacity() < 2 {
-            bail!(SystemError::TooLittleFiles);
+        if !file_to_copy.exists() {
+            return Err(SystemError::NoSuchFileOrDirectory);
         }
 
-        for file in &cmd.files {
-            let path = self.convert_path_to_vdisk_path(&PathBuf::from(file));
-            if !path.exists() {
-                bail!(SystemError::NoSuchFileOrDirectory);
-            }
+        if file_to_copy.is_dir() {
+            return Err(SystemError::IsDirectory);
+        }
 
-            if path.is_dir() {
-                bail!(SystemError::IsDirectory);
-            }
+        let new_file = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.to))?;
+        std::fs::copy(&file_to_copy, &new_file)?;
+
+        // The copy is a new inode, only its permissions come from the source. A move is a rename
+        // so it keeps every attribute already.
+        if cmd.preserve {
+            let metadata = file_to_copy.metadata()?;
+            std::os::unix::fs::chown(&new_file, Some(metadata.uid()), Some(metadata.gid()))?;
+            std::fs::set_permissions(&new_file, metadata.permissions())?;
+            File::options().write(true).open(&new_file)?.set_times(
+                FileTimes::new()
+                    .set_accessed(metadata.accessed()?)
+                    .set_modified(metadata.modified()?),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn link(&mut self, cmd: &crate::complete_command::LinkCommand) -> SystemResult<()> {
+        let target = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.target))?;
 
-            let file = std::fs::File::open(path)?;
-            files.push(file);
+        if !target.exists() {
+            return Err(SystemError::NoSuchFileOrDirectory);
         }
 
-        let first_file = cmd.files.first().expect("expected the first file");
-        let first_file = self.convert_path_to_vdisk_path(&PathBuf::from(first_file));
+        if target.is_dir() {
+            return Err(SystemError::IsDirectory);
+        }
+
+        let link = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.link_name))?;
+        if link.symlink_metadata().is_ok() {
+            return Err(SystemError::FileAlreadyExists);
+        }
+
+        Ok(std::fs::hard_link(target, link)?)
+    }
 
-        let extension = first_file.extension().unwrap_or(OsStr::new("txt"));
+    fn symlink(&mut self, cmd: &crate::complete_command::LinkCommand) -> SystemResult<()> {
+        // A relative target is followed from the directory of the link so it is stored as is,
+        // only paths of the system are translated to the host
+        let target = PathBuf::from(&cmd.target);
+        let target = if target.is_relative() && split_mount_path(&target).is_none() {
+            target
+        } else {
+            self.convert_path_to_vdisk_path(&target)?
+        };
 
-        let new_file_path = self.convert_path_to_vdisk_path(&PathBuf::from(format!(
-            "{}.{}",
-            first_file
-                .file_name()
-                .expect("expected to be a file")
-                .to_str()
-                .unwrap(),
-            extension.to_str().expect("expected to be a string")
-        )));
+        let link = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.link_name))?;
+        if link.symlink_metadata().is_ok() {
+            return Err(SystemError::FileAlreadyExists);
+        }
 
-        let new_file = std::fs::File::create(&new_file_path)?;
-        let mut writer = std::io::BufWriter::new(new_file);
+        Ok(std::os::unix::fs::symlink(target, link)?)
+    }
 
-        let total_length: u64 = 0;
-        bincode::serialize_into(&mut writer, &total_length)?;
+    fn make_dir(
+        &mut self,
+        cmd: &crate::complete_command::MakeDirCommand,
+    ) -> SystemResult<Vec<PathBuf>> {
+        let dir = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.dir))?;
 
-        let mut total_numbers = 0u64;
-        for file_path in &cmd.files {
-            let path = self.convert_path_to_vdisk_path(&PathBuf::from(file_path));
-            if !path.exists() {
-                bail!(SystemError::NoSuchFileOrDirectory);
+        // Like `mkdir -p`, an existing directory is already what was asked for
+        if cmd.parents && dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        if dir.exists() {
+            return Err(SystemError::FileAlreadyExists);
+        }
+
+        // Create one level at a time so only the missing components are reported
+        let missing = dir
+            .ancestors()
+            .take_while(|ancestor| !ancestor.exists())
+            .collect::<Vec<_>>();
+        let mut created = Vec::with_capacity(missing.len());
+
+        for component in missing.into_iter().rev() {
+            std::fs::create_dir(component)?;
+            created.push(self.convert_vdisk_path_to_path(component));
+        }
+
+        Ok(created)
+    }
+
+    fn remove(
+        &mut self,
+        cmd: &crate::complete_command::RemoveCommand,
+    ) -> SystemResult<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+
+        for_each_file(&cmd.files_or_dirs, false, |path| {
+            let file_or_dir = self.convert_path_to_vdisk_path(path)?;
+
+            if !file_or_dir.exists() {
+                return Err(SystemError::NoSuchFileOrDirectory);
             }
 
-            if path.is_dir() {
-                bail!(SystemError::IsDirectory);
+            if file_or_dir.is_dir() && !cmd.recursive {
+                return Err(SystemError::IsDirectory);
+            }
+
+            if cmd.trash {
+                self.move_to_trash(&file_or_dir)?;
+                removed.push(self.convert_vdisk_path_to_path(&file_or_dir));
+            } else if cmd.recursive && file_or_dir.is_dir() {
+                self.remove_dir_recursively(&file_or_dir, &mut removed)?;
+            } else {
+                std::fs::remove_file(&file_or_dir)?;
+                removed.push(self.convert_vdisk_path_to_path(&file_or_dir));
             }
 
-            let file = std::fs::File::open(path)?;
-            let mut reader = std::io::BufReader::new(file);
+            Ok(())
+        })?;
+
+        Ok(removed)
+    }
+
+    fn count_removed(&self, cmd: &crate::complete_command::RemoveCommand) -> SystemResult<usize> {
+        let mut count = 0;
+
+        for_each_file(&cmd.files_or_dirs, false, |path| {
+            let file_or_dir = self.convert_path_to_vdisk_path(path)?;
 
-            // Read length of current file
-            let file_length: u64 = bincode::deserialize_from(&mut reader)?;
-            total_numbers += file_length;
+            if !file_or_dir.exists() {
+                return Err(SystemError::NoSuchFileOrDirectory);
+            }
 
-            // Stream numbers directly from input to output
-            for _ in 0..file_length {
-                let number: Number = bincode::deserialize_from(&mut reader)?;
-                bincode::serialize_into(&mut writer, &number)?;
+            if file_or_dir.is_dir() {
+                if !cmd.recursive {
+                    return Err(SystemError::IsDirectory);
+                }
+                count += self.count_dir_recursively(&file_or_dir)?;
+            } else {
+                count += 1;
             }
+
+            Ok(())
+        })?;
+
+        Ok(count)
+    }
+
+    fn empty_trash(
+        &mut self,
+        _cmd: &crate::complete_command::EmptyTrashCommand,
+    ) -> SystemResult<Vec<PathBuf>> {
+        let trash_dir = self.mount_point.clean().join(TRASH_DIR);
+        let mut removed = Vec::new();
+
+        if !trash_dir.is_dir() {
+            return Ok(removed);
         }
 
-        // Go back and update the total length
-        writer.flush()?;
-        writer.seek(std::io::SeekFrom::Start(0))?;
-        bincode::serialize_into(&mut writer, &total_numbers)?;
-        writer.flush()?;
+        for entry in std::fs::read_dir(&trash_dir)? {
+            let path = entry?.path();
 
-        Ok(new_file_path)
+            if path.is_dir() && !path.is_symlink() {
+                self.remove_dir_recursively(&path, &mut removed)?;
+            } else {
+                std::fs::remove_file(&path)?;
+                removed.push(self.convert_vdisk_path_to_path(&path));
+            }
+        }
+
+        Ok(removed)
     }
 
-    fn exit(&self, cmd: &crate::complete_command::ExitCommand) -> Result<()> {
-        exit(cmd.code)
+    fn head(
+        &self,
+        cmd: &crate::complete_command::HeadCommand,
+    ) -> SystemResult<Vec<crate::system::Number>> {
+        integers::head(self.open_source(&cmd.file)?, cmd.start, cmd.end, cmd.raw)
     }
 
-    fn chdir(&self, cmd: &crate::complete_command::ChangeDirCommand) -> Result<()> {
-        let path = cmd.path.as_ref().map(PathBuf::from);
-        let path = path.unwrap_or_else(|| PathBuf::from("/"));
+    fn tail(&self, cmd: &crate::complete_command::TailCommand) -> SystemResult<Vec<Number>> {
+        let (length, mut arr) = self.open_integers(&cmd.file, false)?;
+        let lines = length.min(cmd.lines.into());
 
-        let path = self.convert_path_to_vdisk_path(&path);
+        // Skip the elements before the last lines
+        let offset = integers::HEADER_SIZE + (length - lines) * size_of::<Number>() as u64;
+        arr.seek(SeekFrom::Start(offset))?;
 
-        if !path.exists() {
-            bail!(SystemError::DirectoryNotFound);
+        let mut result: Vec<Number> = vec![0; lines.try_into()?];
+        arr.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(&mut result))?;
+        if cmd.reverse {
+            result.reverse();
         }
 
-        std::env::set_current_dir(path)?;
+        Ok(result)
+    }
+
+    fn follow(
+        &self,
+        cmd: &crate::complete_command::TailCommand,
+        on_append: &mut dyn FnMut(&[Number]) -> bool,
+    ) -> SystemResult<()> {
+        let interrupt = InterruptGuard::install()?;
+        let mut seen = 0;
+
+        while !interrupt.interrupted() {
+            let (length, mut arr) = self.open_integers(&cmd.file, false)?;
+            if length < seen {
+                info!("{:?} was truncated, following it from the start", cmd.file);
+                seen = 0;
+            }
+
+            // Only the last lines of what was appended are shown, so only those are read
+            let kept = (length - seen).min(cmd.lines.into());
+            if kept > 0 {
+                let offset = integers::HEADER_SIZE + (length - kept) * size_of::<Number>() as u64;
+                arr.seek(SeekFrom::Start(offset))?;
+
+                let mut appended: Vec<Number> = vec![0; kept.try_into()?];
+                arr.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(&mut appended))?;
+
+                if !on_append(&appended) {
+                    return Ok(());
+                }
+            }
+
+            seen = length;
+            thread::sleep(FOLLOW_INTERVAL);
+        }
 
         Ok(())
     }
+
+    fn head_bytes(&self, cmd: &crate::complete_command::HeadCommand) -> SystemResult<Vec<u8>> {
+        let file = self.open_file(&cmd.file)?;
+        integers::read_bytes(file, cmd.bytes.unwrap_or_default(), false)
+    }
+
+    fn tail_bytes(&self, cmd: &crate::complete_command::TailCommand) -> SystemResult<Vec<u8>> {
+        let file = self.open_file(&cmd.file)?;
+        integers::read_bytes(file, cmd.bytes.unwrap_or_default(), true)
+    }
+
+    fn list(
+        &self,
+        cmd: &crate::complete_command::ListCommand,
+    ) -> SystemResult<crate::system::ListCommandOutput> {
+        let path = PathBuf::from(cmd.dir.as_ref().unwrap_or(&OsString::from("/")));
+        let path = self.convert_path_to_vdisk_path(&path)?;
+
+        if !path.exists() {
+            return Err(SystemError::NoSuchFileOrDirectory);
+        }
+
+        let is_single_file = !path.is_dir();
+        let nodes = self.list_nodes(&path, cmd.node_type)?;
+
+        let stat = nix::sys::statfs::statfs(&self.mount_point)?;
+
+        let total_disk_space_in_bytes = stat.blocks() * stat.block_size() as u64;
+        let remaining_disk_space_in_bytes = stat.blocks_available() * stat.block_size() as u64;
+
+        Ok(ListCommandOutput {
+            nodes,
+            total_disk_space_in_bytes,
+            remaining_disk_space_in_bytes,
+            is_single_file,
+        })
+    }
+
+    fn sort(&self, cmd: &crate::complete_command::SortCommand) -> SystemResult<()> {
+        if cmd.check {
+            return match self.find_disorder(&cmd.file, cmd.inverse_order, cmd.raw)? {
+                Some(index) => return Err(SystemError::NotSorted(index)),
+                None => Ok(()),
+            };
+        }
+
+        let start = std::time::Instant::now();
+        let numbers = integers::read_numbers(self.open_source(&cmd.file)?, cmd.raw)?;
+        let length = numbers.len();
+
+        // The runs spill to disk and are removed with the directory, even if the sort fails
+        let runs = TempRuns::new()?;
+        let mut arr = ExtArr::<Number, _>::new(SyncRW::new(FileBufRW::try_from(runs.tempfile()?)?));
+
+        arr.write(&numbers)?;
+        arr.flush()?;
+        arr.rewind()?;
+        drop(numbers);
+
+        let mut mem = FixedSizeMem::<DEFAULT_MEM_SIZE>::new();
+        let tmp_arr = |run| runs.create_shared(run);
+        match (cmd.workers, cmd.chunk_size) {
+            (Some(workers), chunk_size) => {
+                let mut sorter = RayonExtSorter::new(mem.as_mut(), workers);
+                if let Some(chunk_size) = chunk_size {
+                    sorter = sorter.with_chunk_size(chunk_size);
+                }
+                sorter.sort(&mut arr, tmp_arr)?;
+            }
+            (None, Some(chunk_size)) => {
+                ExtSorter::sort_with_chunk_size(&mut arr, mem.as_mut(), chunk_size, tmp_arr)?;
+            }
+            (None, None) => {
+                ExtSorter::sort(&mut arr, mem.as_mut(), tmp_arr)?;
+            }
+        };
+
+        arr.rewind()?;
+
+        let mut values = Vec::with_capacity(length);
+        let casted_values = arr.read_to_end(&mut values)?;
+
+        if is_stdio(&cmd.file) {
+            let output = &mut self.stdio().output;
+            integers::write_numbers(&mut *output, casted_values, cmd.raw)?;
+            output.flush()?;
+        } else {
+            let path = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file))?;
+            replace_file(&path, |writer| {
+                integers::write_numbers(writer, casted_values, cmd.raw)
+            })?;
+        }
+        info!("Sort took {:?}", start.elapsed());
+
+        Ok(())
+    }
+
+    fn cat(&mut self, cmd: &crate::complete_command::CatCommand) -> SystemResult<PathBuf> {
+        let mut files = Vec::with_capacity(cmd.files.len());
+
+        if files.capacity() < 2 {
+            return Err(SystemError::TooLittleFiles);
+        }
+
+        for file in &cmd.files {
+            files.push(self.open_source(file)?);
+        }
+
+        let first_file = cmd.files.first().expect("expected the first file");
+
+        let mode = if cmd.interleave {
+            ConcatMode::Interleave
+        } else if cmd.sorted_merge {
+            ConcatMode::SortedMerge {
+                reverse: cmd.reverse,
+            }
+        } else {
+            ConcatMode::Append
+        };
+
+        let to_stdout = match &cmd.output_file {
+            Some(output_file) => is_stdio(output_file),
+            None => is_stdio(first_file),
+        };
+        if to_stdout {
+            let output = &mut self.stdio().output;
+            with_stderr_progress(cmd.progress, |on_progress| {
+                integers::concat(files, &mut *output, mode, cmd.raw, on_progress)
+            })?;
+            output.flush()?;
+            return Ok(PathBuf::from(STDIO_PATH));
+        }
+
+        let new_file_path = match &cmd.output_file {
+            Some(output_file) => self.convert_path_to_vdisk_path(&PathBuf::from(output_file))?,
+            None => {
+                let first_file = self.convert_path_to_vdisk_path(&PathBuf::from(first_file))?;
+                self.convert_path_to_vdisk_path(&PathBuf::from(integers::concat_file_name(
+                    &first_file,
+                )))?
+            }
+        };
+
+        // Appending rewrites the output with its own integers first, so its header stays right
+        if cmd.append && new_file_path.exists() {
+            files.insert(0, Source::File(BufReader::new(File::open(&new_file_path)?)));
+        }
+
+        replace_file(&new_file_path, |writer| {
+            with_stderr_progress(cmd.progress, |on_progress| {
+                integers::concat(files, writer, mode, cmd.raw, on_progress)
+            })
+        })?;
+
+        Ok(new_file_path)
+    }
+
+    fn stat(&self, cmd: &crate::complete_command::StatCommand) -> SystemResult<String> {
+        let path = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.file))?;
+
+        // A dangling link only exists when it isn't followed
+        let exists = if cmd.no_follow {
+            path.symlink_metadata().is_ok()
+        } else {
+            path.exists()
+        };
+        if !exists {
+            return Err(SystemError::NoSuchFileOrDirectory);
+        }
+
+        let metadata = if cmd.no_follow {
+            path.symlink_metadata()?
+        } else {
+            path.metadata()?
+        };
+        let stat = NodeStat {
+            name: self
+                .convert_vdisk_path_to_path(&path)
+                .to_string_lossy()
+                .to_string(),
+            size_in_bytes: metadata.size(),
+            blocks: metadata.blocks(),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            modified_at: metadata.mtime(),
+            is_dir: metadata.is_dir(),
+        };
+
+        if cmd.json {
+            return Ok(serde_json::to_string_pretty(&stat)?);
+        }
+
+        let kind = if metadata.is_symlink() {
+            "symbolic link"
+        } else if stat.is_dir {
+            "directory"
+        } else {
+            "regular file"
+        };
+        let default_format = format!(
+            "  File: %n\n  Size: %s\tBlocks: %b\t{kind}\nAccess: (%a)\tUid: %U\tGid: %G\nModify: %Y"
+        );
+
+        Ok(Self::format_stat(
+            &stat,
+            cmd.format.as_deref().unwrap_or(&default_format),
+        ))
+    }
+
+    fn test(&self, cmd: &crate::complete_command::TestCommand) -> SystemResult<bool> {
+        let path = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.path))?;
+
+        Ok(match (cmd.file, cmd.dir) {
+            (true, _) => path.is_file(),
+            (_, true) => path.is_dir(),
+            _ => path.exists(),
+        })
+    }
+
+    fn find(&self, cmd: &crate::complete_command::FindCommand) -> SystemResult<Vec<PathBuf>> {
+        let dir = self.convert_path_to_vdisk_path(&PathBuf::from(&cmd.dir))?;
+
+        if !dir.is_dir() {
+            return Err(SystemError::DirectoryNotFound);
+        }
+
+        let newer = match &cmd.newer {
+            Some(file) => {
+                let file = self.convert_path_to_vdisk_path(Path::new(file))?;
+                if file.symlink_metadata().is_err() {
+                    return Err(SystemError::NoSuchFileOrDirectory);
+                }
+                Some(file.metadata()?.modified()?)
+            }
+            None => None,
+        };
+        let now = SystemTime::now();
+
+        // Every predicate set must hold for an entry to be found
+        let matches = |name: &OsStr, metadata: &std::fs::Metadata| {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let age = now.duration_since(modified).unwrap_or_default().as_secs();
+
+            cmd.name.as_deref().is_none_or(|n| name == n)
+                && cmd.node_type.is_none_or(|t| t.matches(metadata.is_dir()))
+                && cmd.size.is_none_or(|size| size.matches(metadata.len()))
+                && cmd.mtime.is_none_or(|mtime| mtime.matches(age))
+                && newer.is_none_or(|newer| modified > newer)
+        };
+
+        let mut found = Vec::new();
+        Self::walk(
+            &dir,
+            self.walk_options(cmd.follow),
+            &mut |path, metadata| {
+                if matches(path.file_name().unwrap_or_default(), metadata) {
+                    found.push(self.convert_vdisk_path_to_path(path));
+                }
+                Ok(())
+            },
+        )?;
+        found.sort();
+
+        Ok(found)
+    }
+
+    fn glob(&self, pattern: &Path) -> SystemResult<Vec<PathBuf>> {
+        let pattern = PathBuf::from("/").join(pattern).clean();
+        let base = glob::base_dir(&pattern);
+        let base_vdisk_path = self.convert_path_to_vdisk_path(&base)?;
+        let mut matches = Vec::new();
+
+        if base == pattern {
+            if base_vdisk_path.symlink_metadata().is_ok() {
+                matches.push(base);
+            }
+            return Ok(matches);
+        }
+
+        self.glob_dir(
+            &base_vdisk_path,
+            &pattern,
+            glob::max_depth(&pattern),
+            &mut matches,
+        )?;
+        matches.sort();
+
+        Ok(matches)
+    }
+
+    fn diff(&self, cmd: &crate::complete_command::DiffCommand) -> SystemResult<DiffCommandOutput> {
+        let (left_len, mut left) = self.open_integers(&cmd.left, false)?;
+        let (right_len, mut right) = self.open_integers(&cmd.right, false)?;
+
+        let common_len = left_len.min(right_len);
+        let mut left_chunk: Vec<Number> = vec![0; DIFF_CHUNK_LEN];
+        let mut right_chunk: Vec<Number> = vec![0; DIFF_CHUNK_LEN];
+        let mut index = 0;
+
+        while index < common_len {
+            let len = (common_len - index).min(DIFF_CHUNK_LEN as u64) as usize;
+            let left_values = &mut left_chunk[..len];
+            let right_values = &mut right_chunk[..len];
+            left.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(left_values))?;
+            right.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(right_values))?;
+
+            let mismatch = left_values
+                .iter()
+                .zip(right_values.iter())
+                .position(|(l, r)| l != r);
+            if let Some(offset) = mismatch {
+                return Ok(DiffCommandOutput::Differ {
+                    index: index + offset as u64,
+                    left: Some(left_values[offset]),
+                    right: Some(right_values[offset]),
+                });
+            }
+
+            index += len as u64;
+        }
+
+        if left_len == right_len {
+            return Ok(DiffCommandOutput::Identical);
+        }
+
+        // Both are positioned at the end of the common prefix, only the longer one has a value
+        let mut next: [Number; 1] = [0];
+        if left_len > right_len {
+            left.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(&mut next))?;
+            return Ok(DiffCommandOutput::Differ {
+                index: common_len,
+                left: Some(next[0]),
+                right: None,
+            });
+        }
+
+        right.read_exact(&mut bytemuck::cast_slice_mut::<Number, u8>(&mut next))?;
+        Ok(DiffCommandOutput::Differ {
+            index: common_len,
+            left: None,
+            right: Some(next[0]),
+        })
+    }
+
+    fn wc(
+        &self,
+        cmd: &crate::complete_command::WordCountCommand,
+    ) -> SystemResult<WordCountCommandOutput> {
+        let mut counts = Vec::with_capacity(cmd.files.len());
+        let mut total = FileCount {
+            name: "total".to_string(),
+            integers: 0,
+            size_in_bytes: 0,
+        };
+
+        for file in &cmd.files {
+            let path = self.convert_path_to_vdisk_path(&PathBuf::from(file))?;
+            if !path.exists() {
+                return Err(SystemError::NoSuchFileOrDirectory);
+            }
+
+            if path.is_dir() {
+                return Err(SystemError::IsDirectory);
+            }
+
+            let (integers, _) = Self::read_header(&path)?;
+            let size_in_bytes = path.metadata()?.size();
+
+            total.integers += integers;
+            total.size_in_bytes += size_in_bytes;
+            counts.push(FileCount {
+                name: self
+                    .convert_vdisk_path_to_path(&path)
+                    .to_string_lossy()
+                    .to_string(),
+                integers,
+                size_in_bytes,
+            });
+        }
+
+        Ok(WordCountCommandOutput { counts, total })
+    }
+
+    fn df(
+        &self,
+        _cmd: &crate::complete_command::DiskFreeCommand,
+    ) -> SystemResult<DiskFreeCommandOutput> {
+        let stat = nix::sys::statfs::statfs(&self.mount_point)?;
+
+        Ok(DiskFreeCommandOutput {
+            block_size: stat.block_size() as u64,
+            total_blocks: stat.blocks(),
+            free_blocks: stat.blocks_available(),
+            total_inodes: stat.files(),
+            free_inodes: stat.files_free(),
+        })
+    }
+
+    fn exit(&self, cmd: &crate::complete_command::ExitCommand) -> SystemResult<()> {
+        exit(cmd.code)
+    }
+
+    fn chdir(&self, cmd: &crate::complete_command::ChangeDirCommand) -> SystemResult<()> {
+        let path = cmd.path.as_ref().map(PathBuf::from);
+        let path = path.unwrap_or_else(|| PathBuf::from("/"));
+
+        let path = self.convert_path_to_vdisk_path(&path)?;
+
+        // The working directory lives in the REPL, the process one is left alone so host paths
+        // keep resolving against the directory ferrix was launched from
+        if !path.is_dir() {
+            return Err(SystemError::DirectoryNotFound);
+        }
+
+        Ok(())
+    }
+
+    fn use_mount(&mut self, cmd: &crate::complete_command::UseCommand) -> SystemResult<()> {
+        self.mount_point = self.named_mount_point(&cmd.name)?.to_path_buf();
+
+        Ok(())
+    }
+
+    fn mount(&mut self, cmd: &crate::complete_command::MountCommand) -> SystemResult<()> {
+        self.add_mount(&cmd.name, std::path::absolute(&cmd.mount_point)?)
+    }
+
+    fn handles(
+        &self,
+        _cmd: &crate::complete_command::HandlesCommand,
+    ) -> SystemResult<Vec<OpenHandle>> {
+        let root = File::open(&self.mount_point)?;
+        let mut reply = vec![0u8; HANDLES_REPLY_SIZE as usize];
+
+        // SAFETY: the command encodes a read of HANDLES_REPLY_SIZE bytes, the size of `reply`
+        let result = unsafe {
+            libc::ioctl(
+                root.as_raw_fd(),
+                FERRIX_IOC_HANDLES as _,
+                reply.as_mut_ptr(),
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(bincode::deserialize(&reply)?)
+    }
+
+    fn debug(&self, cmd: &crate::complete_command::DebugCommand) -> SystemResult<String> {
+        let (file, request) = match (cmd.target, &cmd.path) {
+            (DebugTarget::Inode, Some(path)) => (
+                self.convert_path_to_vdisk_path(&PathBuf::from(path))?,
+                FERRIX_IOC_INODE,
+            ),
+            (DebugTarget::Inode, None) => return Err(SystemError::NoSuchFileOrDirectory),
+            (DebugTarget::Superblock, _) => (self.mount_point.clone(), FERRIX_IOC_SUPERBLOCK),
+        };
+        let file = File::open(file)?;
+        let mut reply = vec![0u8; DEBUG_REPLY_SIZE as usize];
+
+        // SAFETY: the command encodes a read of DEBUG_REPLY_SIZE bytes, the size of `reply`
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, reply.as_mut_ptr()) };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(match cmd.target {
+            DebugTarget::Inode => format!("{:#?}", bincode::deserialize::<Inode>(&reply)?),
+            DebugTarget::Superblock => {
+                format!("{:#?}", bincode::deserialize::<Superblock>(&reply)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::complete_command::{
+        Bound, CatCommand, ChangeDirCommand, CopyCommand, DiffCommand, DiskFreeCommand,
+        Distribution, EmptyTrashCommand, FindCommand, HeadCommand, LinkCommand, ListCommand,
+        MakeDirCommand, MountCommand, NumberFormat, RemoveCommand, SortCommand, StatCommand,
+        TailCommand, TestCommand, TouchCommand, UseCommand, WordCountCommand, DEFAULT_STD_DEV,
+    };
+    use std::num::NonZero;
+
+    fn make_system() -> (tempfile::TempDir, FlemisSystem) {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        let system = FlemisSystem::new(dir.path().to_path_buf()).expect("failed to create system");
+        (dir, system)
+    }
+
+    fn touch(system: &mut FlemisSystem, file: &str, number_of_integers: u32) {
+        system
+            .touch(&TouchCommand {
+                files: vec![file.into()],
+                number_of_integers,
+                progress: false,
+                keep_going: false,
+                raw: false,
+                dist: Distribution::Uniform,
+                std_dev: DEFAULT_STD_DEV,
+            })
+            .expect("failed to touch file");
+    }
+
+    fn mkdir(system: &mut FlemisSystem, dir: &str) {
+        system
+            .make_dir(&MakeDirCommand {
+                dir: dir.into(),
+                parents: true,
+                verbose: false,
+            })
+            .expect("failed to make dir");
+    }
+
+    #[test]
+    fn new_creates_missing_mount_point() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = dir.path().join("a/b");
+
+        // Act
+        let result = FlemisSystem::new(mount_point.clone());
+
+        // Assert
+        assert!(result.is_ok());
+        assert!(mount_point.is_dir());
+    }
+
+    #[test]
+    fn touch_multiple_files() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/b", 1);
+        let inputs = [false, true];
+        let outputs = [
+            (SystemError::FileAlreadyExists, [true, true, false]),
+            (
+                SystemError::FilesNotCreated(vec!["/b: File already exists".to_string()]),
+                [true, true, true],
+            ),
+        ];
+
+        for (keep_going, (error, created)) in inputs.iter().zip(outputs.iter()) {
+            for file in ["a", "c"] {
+                let _ = std::fs::remove_file(dir.path().join(file));
+            }
+
+            // Act
+            let result = system.touch(&TouchCommand {
+                files: vec!["/a".into(), "/b".into(), "/c".into()],
+                number_of_integers: 5,
+                progress: false,
+                keep_going: *keep_going,
+                raw: false,
+                dist: Distribution::Uniform,
+                std_dev: DEFAULT_STD_DEV,
+            });
+
+            // Assert
+            assert_eq!(result.unwrap_err().to_string(), error.to_string());
+            for (file, created) in ["a", "b", "c"].iter().zip(created.iter()) {
+                assert_eq!(dir.path().join(file).exists(), *created, "{file}");
+            }
+        }
+    }
+
+    #[test]
+    fn copy_keeps_source() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/a", 3);
+        let inputs = [("/a", "/d/b"), ("/missing", "/c"), ("/d", "/e")];
+        let outputs = [
+            None,
+            Some(SystemError::NoSuchFileOrDirectory),
+            Some(SystemError::IsDirectory),
+        ];
+
+        for ((from, to), output) in inputs.into_iter().zip(outputs.iter()) {
+            // Act
+            let result = system.copy(&CopyCommand {
+                from: from.into(),
+                to: to.into(),
+                verbose: false,
+                preserve: false,
+            });
+
+            // Assert
+            assert_eq!(
+                result.err().map(|e| e.to_string()),
+                output.as_ref().map(ToString::to_string)
+            );
+        }
+        assert_eq!(
+            std::fs::read(dir.path().join("a")).unwrap(),
+            std::fs::read(dir.path().join("d/b")).unwrap()
+        );
+        assert!(!dir.path().join("c").exists());
+        assert!(!dir.path().join("e").exists());
+    }
+
+    #[test]
+    fn copy_preserves_attributes() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        let source = dir.path().join("a");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        std::fs::set_permissions(&source, Permissions::from_mode(0o600)).unwrap();
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+        let inputs = [("/b", false), ("/c", true)];
+        let outputs = [false, true];
+
+        for ((to, preserve), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            system
+                .copy(&CopyCommand {
+                    from: "/a".into(),
+                    to: to.into(),
+                    verbose: false,
+                    preserve,
+                })
+                .unwrap();
+
+            // Assert
+            let metadata = std::fs::metadata(dir.path().join(&to[1..])).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+            assert_eq!(metadata.modified().unwrap() == modified, output, "{to}");
+            assert_eq!(metadata.uid(), source.metadata().unwrap().uid());
+        }
+    }
+
+    #[test]
+    fn link_across_dirs() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/a", 3);
+        let inputs = [
+            ("/a", "/d/hard", false),
+            ("../a", "/d/relative", true),
+            ("/a", "/d/absolute", true),
+            ("/missing", "/b", false),
+            ("/d", "/c", false),
+            ("/a", "/d/hard", true),
+        ];
+        let outputs = [
+            None,
+            None,
+            None,
+            Some(SystemError::NoSuchFileOrDirectory),
+            Some(SystemError::IsDirectory),
+            Some(SystemError::FileAlreadyExists),
+        ];
+
+        for ((target, link_name, symbolic), output) in inputs.into_iter().zip(outputs.iter()) {
+            // Act
+            let cmd = LinkCommand {
+                target: target.into(),
+                link_name: link_name.into(),
+                symbolic,
+            };
+            let result = if symbolic {
+                system.symlink(&cmd)
+            } else {
+                system.link(&cmd)
+            };
+
+            // Assert
+            assert_eq!(
+                result.err().map(|e| e.to_string()),
+                output.as_ref().map(ToString::to_string),
+                "{link_name}"
+            );
+        }
+        let source = std::fs::metadata(dir.path().join("a")).unwrap();
+        let hard = std::fs::symlink_metadata(dir.path().join("d/hard")).unwrap();
+        assert_eq!((hard.ino(), hard.nlink()), (source.ino(), 2));
+        for name in ["d/relative", "d/absolute"] {
+            let link = dir.path().join(name);
+            assert!(link.is_symlink(), "{name}");
+            assert_eq!(
+                std::fs::metadata(link).unwrap().ino(),
+                source.ino(),
+                "{name}"
+            );
+        }
+        assert_eq!(
+            std::fs::read_link(dir.path().join("d/relative")).unwrap(),
+            Path::new("../a")
+        );
+    }
+
+    #[test]
+    fn new_rejects_bad_mount_point() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("file"), b"").unwrap();
+        std::fs::create_dir(dir.path().join("full")).unwrap();
+        std::fs::write(dir.path().join("full/a"), b"").unwrap();
+        let inputs = [dir.path().join("file"), dir.path().join("full")];
+        let outputs = [
+            SystemError::MountPointNotDirectory(inputs[0].clone()),
+            SystemError::MountPointNotEmpty(inputs[1].clone()),
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = FlemisSystem::new(input.clone());
+
+            // Assert
+            assert_eq!(result.unwrap_err().to_string(), output.to_string());
+        }
+    }
+
+    #[test]
+    fn use_switches_active_mount() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        let other = tempfile::tempdir().unwrap();
+        system
+            .add_mount("other", other.path().to_path_buf())
+            .unwrap();
+        let inputs = ["other", DEFAULT_MOUNT];
+        let outputs = [other.path(), dir.path()];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            system
+                .use_mount(&UseCommand {
+                    name: input.to_string(),
+                })
+                .unwrap();
+            touch(&mut system, &format!("/{input}"), 1);
+
+            // Assert
+            assert!(output.join(input).is_file());
+        }
+        assert!(!dir.path().join("other").exists());
+        assert!(!other.path().join(DEFAULT_MOUNT).exists());
+    }
+
+    #[test]
+    fn mount_paths_address_a_mount() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        let other = tempfile::tempdir().unwrap();
+        system
+            .add_mount("other", other.path().to_path_buf())
+            .unwrap();
+        write_integers(other.path(), "a", &[1, 2, 3]);
+
+        // Act
+        let numbers = system
+            .head(&HeadCommand {
+                file: "@other:/a".into(),
+                start: 0,
+                end: 3,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: false,
+            })
+            .unwrap();
+        let found = system
+            .find(&FindCommand {
+                dir: "@other:/".into(),
+                name: Some("a".into()),
+                null: false,
+                node_type: None,
+                size: None,
+                mtime: None,
+                newer: None,
+                follow: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(numbers, [1, 2, 3]);
+        assert_eq!(found, [PathBuf::from("@other:/a")]);
+        assert!(!dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn mount_then_use() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        let other = tempfile::tempdir().unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        touch(&mut system, "/a", 1);
+
+        // Act
+        system
+            .mount(&MountCommand {
+                name: "other".into(),
+                mount_point: other.path().into(),
+            })
+            .unwrap();
+        system
+            .use_mount(&UseCommand {
+                name: "other".into(),
+            })
+            .unwrap();
+        touch(&mut system, "/b", 1);
+        let changed = system.chdir(&ChangeDirCommand {
+            path: Some("/".into()),
+        });
+
+        // Assert
+        assert!(changed.is_ok());
+        assert!(other.path().join("b").exists());
+        assert!(!dir.path().join("b").exists());
+        assert_eq!(std::env::current_dir().unwrap(), cwd);
+    }
+
+    #[test]
+    fn unknown_and_duplicate_mounts() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        let other = tempfile::tempdir().unwrap();
+
+        // Act
+        let unknown = system.use_mount(&UseCommand {
+            name: "other".into(),
+        });
+        let duplicate = system.add_mount(DEFAULT_MOUNT, other.path().to_path_buf());
+
+        // Assert
+        assert!(matches!(
+            unknown.unwrap_err(),
+            SystemError::UnknownMount(name) if name == "other"
+        ));
+        assert!(matches!(
+            duplicate.unwrap_err(),
+            SystemError::MountAlreadyExists(name) if name == DEFAULT_MOUNT
+        ));
+    }
+
+    #[test]
+    fn make_dir_reports_missing_components() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/a");
+        let inputs = ["/a/b/c", "/a/d", "/e"];
+        let outputs = [
+            vec![PathBuf::from("/a/b"), PathBuf::from("/a/b/c")],
+            vec![PathBuf::from("/a/d")],
+            vec![PathBuf::from("/e")],
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let created = system
+                .make_dir(&MakeDirCommand {
+                    dir: input.into(),
+                    parents: true,
+                    verbose: true,
+                })
+                .unwrap();
+
+            // Assert
+            assert_eq!(&created, output, "{input}");
+            assert!(dir.path().join(&input[1..]).is_dir());
+        }
+    }
+
+    #[test]
+    fn make_dir_existing() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/f", 1);
+        let inputs = [("/d", true), ("/d", false), ("/f", true), ("/f", false)];
+        let outputs = [Some(0), None, None, None];
+
+        for ((input, parents), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let result = system.make_dir(&MakeDirCommand {
+                dir: input.into(),
+                parents,
+                verbose: false,
+            });
+
+            // Assert
+            match output {
+                Some(created) => assert_eq!(result.unwrap().len(), created),
+                None => assert!(matches!(result, Err(SystemError::FileAlreadyExists))),
+            }
+        }
+    }
+
+    #[test]
+    fn remove_file_reports_path() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 1);
+
+        // Act
+        let removed = system
+            .remove(&RemoveCommand {
+                files_or_dirs: vec!["/a".into()],
+                recursive: false,
+                verbose: true,
+                trash: false,
+                force: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(removed, vec![PathBuf::from("/a")]);
+    }
+
+    #[test]
+    fn count_removed_matches_remove() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d/e");
+        touch(&mut system, "/d/a", 1);
+        touch(&mut system, "/d/e/b", 1);
+        let inputs = [("/d/a", false), ("/d/e", true), ("/d", true)];
+
+        for (input, recursive) in inputs {
+            let cmd = RemoveCommand {
+                files_or_dirs: vec![input.into()],
+                recursive,
+                verbose: false,
+                trash: false,
+                force: false,
+            };
+
+            // Act
+            let count = system.count_removed(&cmd).unwrap();
+            let removed = system.remove(&cmd).unwrap();
+
+            // Assert
+            assert_eq!(count, removed.len(), "{input}");
+        }
+    }
+
+    #[test]
+    fn count_removed_across_glob_matches() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d/e");
+        touch(&mut system, "/d/a", 1);
+        touch(&mut system, "/d/b", 1);
+        touch(&mut system, "/d/e/c", 1);
+        let matches = system.glob(Path::new("/d/*")).unwrap();
+        let cmd = RemoveCommand {
+            files_or_dirs: matches.into_iter().map(PathBuf::into_os_string).collect(),
+            recursive: true,
+            verbose: false,
+            trash: false,
+            force: false,
+        };
+
+        // Act
+        let count = system.count_removed(&cmd).unwrap();
+        let removed = system.remove(&cmd).unwrap();
+
+        // Assert
+        assert_eq!(count, 4);
+        assert_eq!(removed.len(), count);
+        assert_eq!(std::fs::read_dir(dir.path().join("d")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn remove_recursive_reports_every_path() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d/e");
+        touch(&mut system, "/d/a", 1);
+        touch(&mut system, "/d/e/b", 1);
+
+        // Act
+        let mut removed = system
+            .remove(&RemoveCommand {
+                files_or_dirs: vec!["/d".into()],
+                recursive: true,
+                verbose: true,
+                trash: false,
+                force: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(removed.pop(), Some(PathBuf::from("/d")));
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![
+                PathBuf::from("/d/a"),
+                PathBuf::from("/d/e"),
+                PathBuf::from("/d/e/b"),
+            ]
+        );
+        assert!(!dir.path().join("d").exists());
+    }
+
+    fn trash(system: &mut FlemisSystem, file_or_dir: &str) -> SystemResult<Vec<PathBuf>> {
+        system.remove(&RemoveCommand {
+            files_or_dirs: vec![file_or_dir.into()],
+            recursive: true,
+            verbose: false,
+            trash: true,
+            force: false,
+        })
+    }
+
+    #[test]
+    fn remove_to_trash() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        let content = std::fs::read(dir.path().join("a")).unwrap();
+
+        // Act
+        let removed = trash(&mut system, "/a").unwrap();
+
+        // Assert
+        assert_eq!(removed, vec![PathBuf::from("/a")]);
+        assert!(!dir.path().join("a").exists());
+        assert_eq!(
+            std::fs::read(dir.path().join(TRASH_DIR).join("a")).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn remove_to_trash_collision() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        let inputs = ["/a", "/d/a", "/a"];
+        let outputs = ["a", "a.1", "a.2"];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            if input.starts_with("/d") {
+                mkdir(&mut system, "/d");
+            }
+            touch(&mut system, input, 1);
+
+            // Act
+            trash(&mut system, input).unwrap();
+
+            // Assert
+            assert!(dir.path().join(TRASH_DIR).join(output).exists());
+        }
+    }
+
+    #[test]
+    fn remove_from_trash_is_rejected() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 1);
+        trash(&mut system, "/a").unwrap();
+
+        // Act
+        let result = trash(&mut system, "/.trash/a");
+
+        // Assert
+        assert!(matches!(result.unwrap_err(), SystemError::AlreadyInTrash));
+    }
+
+    #[test]
+    fn empty_trash() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/d/a", 1);
+        touch(&mut system, "/b", 1);
+        trash(&mut system, "/d").unwrap();
+        trash(&mut system, "/b").unwrap();
+
+        // Act
+        let mut removed = system
+            .empty_trash(&EmptyTrashCommand { verbose: false })
+            .unwrap();
+
+        // Assert
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![
+                PathBuf::from("/.trash/b"),
+                PathBuf::from("/.trash/d"),
+                PathBuf::from("/.trash/d/a"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_dir(dir.path().join(TRASH_DIR))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    /// Reference implementation of head deserializing the file one element at a time
+    fn head_deserialize(path: &Path, start: usize, end: usize) -> Vec<Number> {
+        let mut reader = BufReader::new(std::fs::File::open(path).unwrap());
+        let vec_len: u64 = bincode::deserialize_from(&mut reader).unwrap();
+        let data: Vec<Number> = (0..vec_len)
+            .map(|_| bincode::deserialize_from(&mut reader).unwrap())
+            .collect();
+        let end = if start > end { start + 10 } else { end }.min(data.len());
+
+        data.get(start..end).unwrap_or_default().to_vec()
+    }
+
+    fn check_sorted(system: &FlemisSystem, file: &str, inverse_order: bool) -> SystemResult<()> {
+        system.sort(&SortCommand {
+            file: file.into(),
+            inverse_order,
+            check: true,
+            raw: false,
+            chunk_size: None,
+            workers: None,
+        })
+    }
+
+    #[test]
+    fn sort_spills_runs_with_workers() {
+        // Arrange
+        let (dir, system) = make_system();
+        let numbers: Vec<Number> = (0..1000u32)
+            .map(|i| ((i * 7919) % 1000) as Number)
+            .collect();
+        let mut expected = numbers.clone();
+        expected.sort_unstable();
+        let inputs = [
+            (None, None),
+            (None, Some(64)),
+            (Some(3), None),
+            (Some(3), Some(64)),
+        ];
+
+        for (workers, chunk_size) in inputs {
+            write_integers(dir.path(), "a", &numbers);
+
+            // Act
+            system
+                .sort(&SortCommand {
+                    file: "/a".into(),
+                    inverse_order: false,
+                    check: false,
+                    raw: false,
+                    chunk_size: chunk_size.and_then(NonZero::new),
+                    workers: workers.and_then(NonZero::new),
+                })
+                .unwrap();
+
+            // Assert
+            let sorted: Vec<Number> =
+                bincode::deserialize(&std::fs::read(dir.path().join("a")).unwrap()).unwrap();
+            assert_eq!(sorted, expected, "{workers:?} {chunk_size:?}");
+        }
+    }
+
+    #[test]
+    fn sort_check() {
+        // Arrange
+        let (dir, system) = make_system();
+        write_integers(dir.path(), "sorted", &[1, 2, 2, 5]);
+        write_integers(dir.path(), "unsorted", &[1, 3, 2, 5]);
+        write_integers(dir.path(), "descending", &[5, 2, 2, 1]);
+        write_integers(dir.path(), "empty", &[]);
+        let inputs = [
+            ("/sorted", false),
+            ("/unsorted", false),
+            ("/descending", false),
+            ("/descending", true),
+            ("/sorted", true),
+            ("/empty", false),
+            ("/empty", true),
+        ];
+        let outputs = [
+            None,
+            Some(SystemError::NotSorted(2)),
+            Some(SystemError::NotSorted(1)),
+            None,
+            Some(SystemError::NotSorted(1)),
+            None,
+            None,
+        ];
+
+        for ((file, inverse_order), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let result = check_sorted(&system, file, inverse_order);
+
+            // Assert
+            assert_eq!(
+                result.err().map(|e| e.to_string()),
+                output.map(|e| e.to_string()),
+                "{file} {inverse_order}"
+            );
+        }
+
+        // The checked file is left untouched
+        let content = std::fs::read(dir.path().join("unsorted")).unwrap();
+        assert_eq!(
+            content,
+            bincode::serialize(&vec![1 as Number, 3, 2, 5]).unwrap()
+        );
+    }
+
+    #[test]
+    fn tail() {
+        // Arrange
+        let (dir, system) = make_system();
+        write_integers(dir.path(), "a", &[1, 2, 3, 4, 5]);
+        let inputs = [(2, false), (2, true), (10, false), (0, true)];
+        let outputs: [&[Number]; 4] = [&[4, 5], &[5, 4], &[1, 2, 3, 4, 5], &[]];
+
+        for ((lines, reverse), output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let result = system
+                .tail(&TailCommand {
+                    file: "/a".into(),
+                    lines,
+                    reverse,
+                    bytes: None,
+                    hex: false,
+                    format: NumberFormat::Dec,
+                    follow: false,
+                })
+                .unwrap();
+
+            // Assert
+            assert_eq!(result, output, "{lines} {reverse}");
+        }
+    }
+
+    #[test]
+    fn head_and_tail_bytes() {
+        // Arrange
+        let (dir, system) = make_system();
+        write_integers(dir.path(), "a", &[0x0201, 0x0403]);
+
+        // Act
+        let head = system
+            .head_bytes(&HeadCommand {
+                file: "/a".into(),
+                start: 0,
+                end: 10,
+                bytes: Some(9),
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: false,
+            })
+            .unwrap();
+        let tail = system
+            .tail_bytes(&TailCommand {
+                file: "/a".into(),
+                lines: 10,
+                reverse: false,
+                bytes: Some(3),
+                hex: true,
+                format: NumberFormat::Dec,
+                follow: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(head, [2, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(tail, [2, 3, 4]);
+    }
+
+    #[test]
+    fn head_matches_deserialize() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 1000);
+        let inputs = [
+            (0, 10),
+            (0, 1000),
+            (990, 2000),
+            (500, 501),
+            (20, 5),
+            (999, 1000),
+        ];
+
+        for (start, end) in inputs {
+            // Act
+            let result = system
+                .head(&HeadCommand {
+                    file: "/a".into(),
+                    start,
+                    end,
+                    bytes: None,
+                    hex: false,
+                    format: NumberFormat::Dec,
+                    raw: false,
+                })
+                .unwrap();
+
+            // Assert
+            let expected = head_deserialize(&dir.path().join("a"), start as usize, end as usize);
+            assert_eq!(result, expected, "{start}..{end}");
+        }
+    }
+
+    #[test]
+    fn head_past_end_is_empty() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 10);
+
+        // Act
+        let result = system
+            .head(&HeadCommand {
+                file: "/a".into(),
+                start: 20,
+                end: 30,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn cat_same_width() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        touch(&mut system, "/b", 2);
+
+        // Act
+        let output = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        let read = |path: &Path| -> Vec<Number> {
+            bincode::deserialize(&std::fs::read(path).unwrap()).unwrap()
+        };
+        let mut expected = read(&dir.path().join("a"));
+        expected.extend(read(&dir.path().join("b")));
+        assert_eq!(read(&output), expected);
+    }
+
+    #[test]
+    fn cat_mixed_widths() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        let wide: Vec<u32> = vec![1, 2, 3];
+        std::fs::write(dir.path().join("b"), bincode::serialize(&wide).unwrap()).unwrap();
+
+        // Act
+        let err = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap_err();
+
+        // Assert
+        assert!(matches!(err, SystemError::MixedElementWidths));
+    }
+
+    #[test]
+    fn cat_append() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        write_integers(dir.path(), "a", &[3]);
+        write_integers(dir.path(), "b", &[4, 5]);
+        write_integers(dir.path(), "out", &[1, 2]);
+        let inputs = ["/out", "/new"];
+        let outputs: [&[Number]; 2] = [&[1, 2, 3, 4, 5], &[3, 4, 5]];
+
+        for (input, output) in inputs.into_iter().zip(outputs) {
+            // Act
+            let path = system
+                .cat(&CatCommand {
+                    files: vec!["/a".into(), "/b".into()],
+                    output_file: Some(input.into()),
+                    append: true,
+                    interleave: false,
+                    sorted_merge: false,
+                    reverse: false,
+                    progress: false,
+                    raw: false,
+                })
+                .unwrap();
+
+            // Assert
+            let written: Vec<Number> = bincode::deserialize(&std::fs::read(path).unwrap()).unwrap();
+            assert_eq!(written, output, "{input}");
+        }
+    }
+
+    #[test]
+    fn cat_interleave() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        write_integers(dir.path(), "a", &[1, 4, 7]);
+        write_integers(dir.path(), "b", &[2, 3]);
+        write_integers(dir.path(), "c", &[]);
+
+        // Act
+        let output = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into(), "/c".into()],
+                output_file: None,
+                interleave: true,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        let read: Vec<Number> = bincode::deserialize(&std::fs::read(output).unwrap()).unwrap();
+        assert_eq!(read, vec![1, 2, 4, 3, 7]);
+    }
+
+    #[test]
+    fn cat_sorted_merge() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        write_integers(dir.path(), "a", &[1, 4, 7]);
+        write_integers(dir.path(), "b", &[]);
+        write_integers(dir.path(), "c", &[2, 3, 4, 9]);
+
+        // Act
+        let output = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into(), "/c".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: true,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        let read: Vec<Number> = bincode::deserialize(&std::fs::read(output).unwrap()).unwrap();
+        assert_eq!(read, vec![1, 2, 3, 4, 4, 7, 9]);
+    }
+
+    #[test]
+    fn cat_sorted_merge_reverse() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        write_integers(dir.path(), "a", &[7, 4, 1]);
+        write_integers(dir.path(), "b", &[9, 4, 3, 2]);
+
+        // Act
+        let output = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: true,
+                reverse: true,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        let read: Vec<Number> = bincode::deserialize(&std::fs::read(output).unwrap()).unwrap();
+        assert_eq!(read, vec![9, 7, 4, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn cat_sorted_merge_wide_elements() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        let wide: Vec<u32> = vec![2];
+        std::fs::write(dir.path().join("b"), bincode::serialize(&wide).unwrap()).unwrap();
+        std::fs::write(dir.path().join("c"), bincode::serialize(&wide).unwrap()).unwrap();
+
+        // Act
+        let err = system
+            .cat(&CatCommand {
+                files: vec!["/b".into(), "/c".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: true,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap_err();
+
+        // Assert
+        assert!(matches!(err, SystemError::NotIntegerElements(4)));
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        system
+            .touch(&TouchCommand {
+                files: vec!["/a".into(), "/b".into()],
+                number_of_integers: 100,
+                progress: false,
+                keep_going: false,
+                raw: true,
+                dist: Distribution::Uniform,
+                std_dev: DEFAULT_STD_DEV,
+            })
+            .unwrap();
+
+        // Act
+        system
+            .sort(&SortCommand {
+                file: "/a".into(),
+                inverse_order: false,
+                check: false,
+                raw: true,
+                chunk_size: None,
+                workers: None,
+            })
+            .unwrap();
+        let head = system
+            .head(&HeadCommand {
+                file: "/a".into(),
+                start: 0,
+                end: 100,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: true,
+            })
+            .unwrap();
+        let output = system
+            .cat(&CatCommand {
+                files: vec!["/a".into(), "/b".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: true,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        let size = |file: &Path| file.metadata().unwrap().len();
+        assert_eq!(size(&dir.path().join("a")), 200);
+        assert!(head.is_sorted());
+        assert_eq!(head.len(), 100);
+        assert_eq!(size(&output), 400);
+        let sorted = std::fs::read(dir.path().join("a")).unwrap();
+        assert_eq!(&std::fs::read(output).unwrap()[..200], sorted);
+    }
+
+    #[test]
+    fn raw_partial_element() {
+        // Arrange
+        let (dir, system) = make_system();
+        std::fs::write(dir.path().join("a"), [1, 2, 3]).unwrap();
+
+        // Act
+        let err = system
+            .sort(&SortCommand {
+                file: "/a".into(),
+                inverse_order: false,
+                check: true,
+                raw: true,
+                chunk_size: None,
+                workers: None,
+            })
+            .unwrap_err();
+
+        // Assert
+        assert!(matches!(err, SystemError::PartialElement(3)));
+    }
+
+    /// Standard output shared with the test after the system takes ownership of it
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn with_stdin(system: FlemisSystem, input: Vec<u8>) -> (FlemisSystem, Captured) {
+        let captured = Captured::default();
+        let stdio = Stdio::new(Box::new(Cursor::new(input)), Box::new(captured.clone()));
+        (system.with_stdio(stdio), captured)
+    }
+
+    #[test]
+    fn sort_stdin() {
+        // Arrange
+        let numbers: Vec<Number> = vec![3, 1, 2];
+        let inputs = [true, false];
+
+        for raw in inputs {
+            let mut input = Vec::new();
+            integers::write_numbers(&mut input, &numbers, raw).unwrap();
+            let (_dir, system) = make_system();
+            let (system, captured) = with_stdin(system, input);
+
+            // Act
+            system
+                .sort(&SortCommand {
+                    file: STDIO_PATH.into(),
+                    inverse_order: false,
+                    check: false,
+                    raw,
+                    chunk_size: None,
+                    workers: None,
+                })
+                .unwrap();
+
+            // Assert
+            let output = captured.0.lock().unwrap().clone();
+            let sorted = integers::read_numbers(Cursor::new(output), raw).unwrap();
+            assert_eq!(sorted, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn follow_appended_values() {
+        // Arrange
+        let (dir, system) = make_system();
+        write_integers(dir.path(), "a", &[1, 2, 3, 4, 5]);
+        let inputs: [Vec<Number>; 3] = [(1..=8).collect(), (1..=20).collect(), Vec::new()];
+        let outputs: [&[Number]; 3] = [&[3, 4, 5], &[6, 7, 8], &[18, 19, 20]];
+        let mut appends = inputs.iter();
+        let mut calls = Vec::new();
+
+        // Act
+        system
+            .follow(
+                &TailCommand {
+                    file: "/a".into(),
+                    lines: 3,
+                    reverse: false,
+                    bytes: None,
+                    hex: false,
+                    format: NumberFormat::Dec,
+                    follow: true,
+                },
+                &mut |numbers| {
+                    calls.push(numbers.to_vec());
+                    match appends.next() {
+                        Some(data) if !data.is_empty() => {
+                            write_integers(dir.path(), "a", data);
+                            true
+                        }
+                        _ => false,
+                    }
+                },
+            )
+            .unwrap();
+
+        // Assert
+        assert_eq!(calls, outputs);
+    }
+
+    #[test]
+    fn follow_stops_on_interrupt() {
+        // Arrange
+        let (dir, system) = make_system();
+        write_integers(dir.path(), "a", &[1, 2, 3]);
+        let mut calls = 0;
+
+        // Act
+        let result = system.follow(
+            &TailCommand {
+                file: "/a".into(),
+                lines: 3,
+                reverse: false,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                follow: true,
+            },
+            &mut |_| {
+                calls += 1;
+                nix::sys::signal::raise(Signal::SIGINT).is_ok()
+            },
+        );
+
+        // Assert
+        assert!(result.is_ok());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn replace_file_keeps_destination_on_error() {
+        // Arrange
+        let (dir, _system) = make_system();
+        let path = dir.path().join("out");
+        std::fs::write(&path, b"original").unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+
+        // Act
+        let failed = replace_file(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err::<(), _>(std::io::Error::other("interrupted").into())
+        });
+        let replaced = replace_file(&path, |writer| Ok(writer.write_all(b"replaced")?));
+
+        // Assert
+        assert!(matches!(failed, Err(SystemError::Io(_))));
+        assert!(replaced.is_ok());
+        assert_eq!(std::fs::read(&path).unwrap(), b"replaced");
+        assert_eq!(path.metadata().unwrap().mode() & 0o777, 0o640);
+        let entries: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["out"]);
+    }
+
+    #[test]
+    fn head_stdin() {
+        // Arrange
+        let mut input = Vec::new();
+        integers::write_numbers(&mut input, &[5, 6, 7], false).unwrap();
+        let (_dir, system) = make_system();
+        let (system, _) = with_stdin(system, input);
+
+        // Act
+        let head = system
+            .head(&HeadCommand {
+                file: STDIO_PATH.into(),
+                start: 0,
+                end: 2,
+                bytes: None,
+                hex: false,
+                format: NumberFormat::Dec,
+                raw: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(head, vec![5, 6]);
+    }
+
+    #[test]
+    fn cat_stdin_to_stdout() {
+        // Arrange
+        let mut input = Vec::new();
+        integers::write_numbers(&mut input, &[5, 6, 7], false).unwrap();
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/b", 2);
+        let (mut system, captured) = with_stdin(system, input);
+
+        // Act
+        let output = system
+            .cat(&CatCommand {
+                files: vec![STDIO_PATH.into(), "/b".into()],
+                output_file: None,
+                interleave: false,
+                sorted_merge: false,
+                reverse: false,
+                progress: false,
+                raw: false,
+                append: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(output, PathBuf::from(STDIO_PATH));
+        let b = integers::read_numbers(File::open(dir.path().join("b")).unwrap(), false).unwrap();
+        let output = captured.0.lock().unwrap().clone();
+        let numbers = integers::read_numbers(Cursor::new(output), false).unwrap();
+        assert_eq!(numbers[..3], [5, 6, 7]);
+        assert_eq!(numbers[3..], b[..]);
+    }
+
+    #[test]
+    fn stat_format() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        let metadata = dir.path().join("a").metadata().unwrap();
+        let inputs = ["%n %s", "%a %U:%G", "%Y %% %q"];
+        let outputs = [
+            "/a 14".to_string(),
+            format!(
+                "{:o} {}:{}",
+                metadata.mode() & 0o7777,
+                metadata.uid(),
+                metadata.gid()
+            ),
+            format!("{} % %q", metadata.mtime()),
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let stat = system
+                .stat(&StatCommand {
+                    file: "/a".into(),
+                    format: Some(input.to_string()),
+                    json: false,
+                    no_follow: false,
+                })
+                .unwrap();
+
+            // Assert
+            assert_eq!(&stat, output);
+        }
+    }
+
+    #[test]
+    fn stat_default_format() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+
+        // Act
+        let stat = system
+            .stat(&StatCommand {
+                file: "/d".into(),
+                format: None,
+                json: false,
+                no_follow: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert!(stat.starts_with("  File: /d\n"));
+        assert!(stat.contains("directory"));
+    }
+
+    #[test]
+    fn stat_symlink() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        std::os::unix::fs::symlink("a", dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.path().join("dangling")).unwrap();
+        let inputs = [false, true];
+        let outputs = [("/link 14", "regular file"), ("/link 1", "symbolic link")];
+
+        for (no_follow, (output, kind)) in inputs.into_iter().zip(outputs) {
+            // Act
+            let stat = system
+                .stat(&StatCommand {
+                    file: "/link".into(),
+                    format: Some("%n %s".into()),
+                    json: false,
+                    no_follow,
+                })
+                .unwrap();
+            let default = system
+                .stat(&StatCommand {
+                    file: "/link".into(),
+                    format: None,
+                    json: false,
+                    no_follow,
+                })
+                .unwrap();
+            let dangling = system.stat(&StatCommand {
+                file: "/dangling".into(),
+                format: None,
+                json: false,
+                no_follow,
+            });
+
+            // Assert
+            assert_eq!(stat, output);
+            assert!(default.contains(kind), "{default}");
+            assert_eq!(dangling.is_ok(), no_follow);
+        }
+    }
+
+    #[test]
+    fn test_predicates() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/a", 1);
+        let inputs = [(false, false), (true, false), (false, true)];
+        let outputs = [
+            [true, true, false],
+            [true, false, false],
+            [false, true, false],
+        ];
+
+        for ((file, dir), output) in inputs.into_iter().zip(outputs.iter()) {
+            // Act
+            let passed = ["/a", "/d", "/missing"].map(|path| {
+                system
+                    .test(&TestCommand {
+                        path: path.into(),
+                        exists: false,
+                        file,
+                        dir,
+                    })
+                    .unwrap()
+            });
+
+            // Assert
+            assert_eq!(passed, *output);
+        }
+    }
+
+    #[test]
+    fn find_by_name() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d/a");
+        touch(&mut system, "/a", 1);
+        touch(&mut system, "/d/b", 1);
+        touch(&mut system, "/d/a/a", 1);
+
+        // Act
+        let found = system
+            .find(&FindCommand {
+                dir: "/".into(),
+                name: Some("a".to_string()),
+                null: false,
+                node_type: None,
+                size: None,
+                mtime: None,
+                newer: None,
+                follow: false,
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("/a"),
+                PathBuf::from("/d/a"),
+                PathBuf::from("/d/a/a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_by_size_and_mtime() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/d/small", 1);
+        touch(&mut system, "/d/big", 1000);
+        touch(&mut system, "/old", 1000);
+        touch(&mut system, "/reference", 1);
+        let hours_ago =
+            |hours: u64| SystemTime::now() - std::time::Duration::from_secs(hours * 3600);
+        for (file, modified) in [("old", hours_ago(72)), ("reference", hours_ago(24))] {
+            File::options()
+                .write(true)
+                .open(dir.path().join(file))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let inputs = [
+            (Some(Bound::MoreThan(1024)), None, None),
+            (Some(Bound::LessThan(1024)), None, None),
+            (None, Some(Bound::MoreThan(2 * 86_400)), None),
+            (
+                Some(Bound::MoreThan(1024)),
+                Some(Bound::LessThan(3600)),
+                None,
+            ),
+            (None, None, Some("/reference")),
+        ];
+        let outputs: [&[&str]; 5] = [
+            &["/d/big", "/old"],
+            &["/reference", "/d/small"],
+            &["/old"],
+            &["/d/big"],
+            &["/d", "/d/big", "/d/small"],
+        ];
+
+        for ((size, mtime, newer), output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let found = system
+                .find(&FindCommand {
+                    dir: "/".into(),
+                    name: None,
+                    null: false,
+                    node_type: Some(NodeType::File).filter(|_| newer.is_none()),
+                    size: *size,
+                    mtime: *mtime,
+                    newer: newer.map(Into::into),
+                    follow: false,
+                })
+                .unwrap();
+
+            // Assert
+            let mut expected: Vec<PathBuf> = output.iter().map(PathBuf::from).collect();
+            expected.sort();
+            assert_eq!(found, expected, "{size:?} {mtime:?} {newer:?}");
+        }
+    }
+
+    #[test]
+    fn find_by_type() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d/a");
+        touch(&mut system, "/a", 1);
+        touch(&mut system, "/d/a/a", 1);
+        let inputs = [Some(NodeType::File), Some(NodeType::Directory)];
+        let outputs = [vec!["/a", "/d/a/a"], vec!["/d/a"]];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let found = system
+                .find(&FindCommand {
+                    dir: "/".into(),
+                    name: Some("a".to_string()),
+                    null: false,
+                    node_type: *input,
+                    size: None,
+                    mtime: None,
+                    newer: None,
+                    follow: false,
+                })
+                .unwrap();
+
+            // Assert
+            let expected: Vec<PathBuf> = output.iter().map(PathBuf::from).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn find_through_symlink_cycle() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d/e");
+        mkdir(&mut system, "/f");
+        touch(&mut system, "/f/x", 1);
+        std::os::unix::fs::symlink("..", dir.path().join("d/e/up")).unwrap();
+        std::os::unix::fs::symlink("../f", dir.path().join("d/out")).unwrap();
+        let inputs = [
+            (false, DEFAULT_MAX_WALK_DEPTH),
+            (true, DEFAULT_MAX_WALK_DEPTH),
+            (true, 1),
+        ];
+        let outputs = [
+            vec!["/d/e", "/d/e/up", "/d/out"],
+            vec!["/d/e", "/d/e/up", "/d/out", "/d/out/x"],
+            vec!["/d/e", "/d/out"],
+        ];
+
+        for ((follow, max_depth), output) in inputs.into_iter().zip(outputs.iter()) {
+            system.max_walk_depth = max_depth;
+
+            // Act
+            let found = system
+                .find(&FindCommand {
+                    dir: "/d".into(),
+                    name: None,
+                    null: false,
+                    node_type: None,
+                    size: None,
+                    mtime: None,
+                    newer: None,
+                    follow,
+                })
+                .unwrap();
+
+            // Assert
+            let expected: Vec<PathBuf> = output.iter().map(PathBuf::from).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn list_by_type() {
+        // Arrange
+        let (dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        mkdir(&mut system, "/e");
+        touch(&mut system, "/a", 1);
+        touch(&mut system, "/.b", 1);
+        let inputs = [None, Some(NodeType::File), Some(NodeType::Directory)];
+        let outputs = [vec![".b", "a", "d", "e"], vec![".b", "a"], vec!["d", "e"]];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let nodes = system.list_nodes(dir.path(), *input).unwrap();
+
+            // Assert
+            let mut names: Vec<_> = nodes.into_iter().map(|node| node.name).collect();
+            names.sort();
+            assert_eq!(names, *output);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn list_non_utf8_names() {
+        // Arrange
+        let (dir, system) = make_system();
+        let name = OsStr::from_bytes(b"a\xff");
+        write_integers(dir.path(), "b", &[1]);
+        std::fs::write(dir.path().join(name), b"").unwrap();
+
+        // Act
+        let nodes = system.list_nodes(dir.path(), Some(NodeType::File)).unwrap();
+
+        // Assert
+        let node = nodes.iter().find(|node| node.name != "b").unwrap();
+        assert_eq!(node.name, name);
+        assert!(tabled::Table::new(&nodes).to_string().contains("a\u{FFFD}"));
+        assert!(serde_json::to_string(&nodes).unwrap().contains("a\u{FFFD}"));
+    }
+
+    #[test]
+    fn glob_known_tree() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/src/d/e");
+        mkdir(&mut system, "/src/.hidden");
+        for file in [
+            "/src/a.txt",
+            "/src/b.txt",
+            "/src/ab.bin",
+            "/src/d/c.txt",
+            "/src/d/e/f.txt",
+            "/src/.hidden/g.txt",
+        ] {
+            touch(&mut system, file, 1);
+        }
+        let inputs = [
+            "/src/*.txt",
+            "src/?.txt",
+            "/src/**/*.txt",
+            "/src/d/*",
+            "/src/a.txt",
+        ];
+        let outputs: [&[&str]; 5] = [
+            &["/src/a.txt", "/src/b.txt"],
+            &["/src/a.txt", "/src/b.txt"],
+            &["/src/a.txt", "/src/b.txt", "/src/d/c.txt", "/src/d/e/f.txt"],
+            &["/src/d/c.txt", "/src/d/e"],
+            &["/src/a.txt"],
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let matches = system.glob(Path::new(input)).unwrap();
+
+            // Assert
+            let expected: Vec<PathBuf> = output.iter().map(PathBuf::from).collect();
+            assert_eq!(matches, expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn glob_without_matches() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 1);
+        let inputs = ["/*.txt", "/missing/**/*", "/b"];
+
+        for input in inputs {
+            // Act
+            let matches = system.glob(Path::new(input)).unwrap();
+
+            // Assert
+            assert!(matches.is_empty(), "{input}");
+        }
+    }
+
+    fn write_integers(dir: &Path, file: &str, data: &[Number]) {
+        std::fs::write(dir.join(file), bincode::serialize(data).unwrap()).unwrap();
+    }
+
+    fn diff(system: &FlemisSystem, left: &str, right: &str) -> DiffCommandOutput {
+        system
+            .diff(&DiffCommand {
+                left: left.into(),
+                right: right.into(),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn diff_equal_files() {
+        // Arrange
+        let (dir, system) = make_system();
+        let data: Vec<Number> = (0..10_000).map(|i| (i * 7) as Number).collect();
+        write_integers(dir.path(), "a", &data);
+        write_integers(dir.path(), "b", &data);
+        write_integers(dir.path(), "empty", &[]);
+
+        let inputs = [("/a", "/b"), ("/empty", "/empty")];
+
+        for (left, right) in inputs {
+            // Act
+            let result = diff(&system, left, right);
+
+            // Assert
+            assert_eq!(result, DiffCommandOutput::Identical);
+        }
+    }
+
+    #[test]
+    fn diff_different_length() {
+        // Arrange
+        let (dir, system) = make_system();
+        let data: Vec<Number> = (0..5000).map(|i| i as Number).collect();
+        write_integers(dir.path(), "a", &data);
+        write_integers(dir.path(), "b", &data[..4500]);
+        write_integers(dir.path(), "empty", &[]);
+        let inputs = [("/a", "/b"), ("/b", "/a"), ("/empty", "/a")];
+        let outputs = [
+            DiffCommandOutput::Differ {
+                index: 4500,
+                left: Some(4500),
+                right: None,
+            },
+            DiffCommandOutput::Differ {
+                index: 4500,
+                left: None,
+                right: Some(4500),
+            },
+            DiffCommandOutput::Differ {
+                index: 0,
+                left: None,
+                right: Some(0),
+            },
+        ];
+
+        for ((left, right), output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = diff(&system, left, right);
+
+            // Assert
+            assert_eq!(result, *output);
+        }
+    }
+
+    #[test]
+    fn diff_differing_value() {
+        // Arrange
+        let (dir, system) = make_system();
+        let data: Vec<Number> = (0..5000).map(|i| i as Number).collect();
+        let mut changed = data.clone();
+        changed[4100] = 1;
+        changed[4200] = 2;
+        write_integers(dir.path(), "a", &data);
+        write_integers(dir.path(), "b", &changed);
+
+        // Act
+        let result = diff(&system, "/a", "/b");
+
+        // Assert
+        assert_eq!(
+            result,
+            DiffCommandOutput::Differ {
+                index: 4100,
+                left: Some(4100),
+                right: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn wc_single_file() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+
+        // Act
+        let output = system
+            .wc(&WordCountCommand {
+                files: vec!["/a".into()],
+            })
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            output.counts,
+            vec![FileCount {
+                name: "/a".to_string(),
+                integers: 3,
+                size_in_bytes: 14,
+            }]
+        );
+        assert_eq!(output.total.integers, 3);
+        assert_eq!(output.total.size_in_bytes, 14);
+    }
+
+    #[test]
+    fn wc_multiple_files() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        mkdir(&mut system, "/d");
+        touch(&mut system, "/a", 3);
+        touch(&mut system, "/d/b", 5);
+        touch(&mut system, "/c", 0);
+
+        // Act
+        let output = system
+            .wc(&WordCountCommand {
+                files: vec!["/a".into(), "/d/b".into(), "/c".into()],
+            })
+            .unwrap();
+
+        // Assert
+        let integers: Vec<_> = output.counts.iter().map(|c| c.integers).collect();
+        let names: Vec<_> = output.counts.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(integers, vec![3, 5, 0]);
+        assert_eq!(names, vec!["/a", "/d/b", "/c"]);
+        assert_eq!(output.total.integers, 8);
+        assert_eq!(output.total.size_in_bytes, 14 + 18 + 8);
+    }
+
+    #[test]
+    fn wc_missing_file() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+
+        // Act
+        let err = system
+            .wc(&WordCountCommand {
+                files: vec!["/a".into(), "/missing".into()],
+            })
+            .unwrap_err();
+
+        // Assert
+        assert!(matches!(err, SystemError::NoSuchFileOrDirectory));
+    }
+
+    #[test]
+    fn list_json() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+        mkdir(&mut system, "/d");
+
+        // Act
+        let output = system
+            .list(&ListCommand {
+                dir: Some("/".into()),
+                all: false,
+                null: false,
+                json: true,
+                node_type: None,
+            })
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string_pretty(&output).unwrap()).unwrap();
+
+        // Assert
+        let node = |name: &str| {
+            json["nodes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|node| node["name"] == name)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(node("a")["size_in_bytes"], 14);
+        assert_eq!(node("a")["is_dir"], false);
+        assert_eq!(node("d")["is_dir"], true);
+        let total = json["total_disk_space_in_bytes"].as_u64().unwrap();
+        let remaining = json["remaining_disk_space_in_bytes"].as_u64().unwrap();
+        assert!(remaining <= total && total > 0);
+    }
+
+    #[test]
+    fn stat_json() {
+        // Arrange
+        let (_dir, mut system) = make_system();
+        touch(&mut system, "/a", 3);
+
+        // Act
+        let output = system
+            .stat(&StatCommand {
+                file: "/a".into(),
+                format: None,
+                json: true,
+                no_follow: false,
+            })
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        // Assert
+        assert_eq!(json["name"], "/a");
+        assert_eq!(json["size_in_bytes"], 14);
+        assert_eq!(json["is_dir"], false);
+        assert!(json["mode"].is_u64());
+        assert!(json["modified_at"].is_i64());
+    }
+
+    #[test]
+    fn df_json() {
+        // Arrange
+        let (_dir, system) = make_system();
+
+        // Act
+        let output = system
+            .df(&DiskFreeCommand {
+                json: true,
+                inodes: false,
+            })
+            .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string_pretty(&output).unwrap()).unwrap();
+
+        // Assert
+        for field in [
+            "block_size",
+            "total_blocks",
+            "free_blocks",
+            "total_inodes",
+            "free_inodes",
+        ] {
+            assert!(json[field].is_u64(), "missing field {field}");
+        }
+    }
 }
@@ -1,15 +1,21 @@
-use crate::{simple_ext4::mkfs::make, vdisk::VDisk};
+use crate::{simple_ext4::mkfs::make, system::OpenHandle, vdisk::VDisk};
 
 use super::{
+    dcache::{DirCache, ENTRY_TTL},
     fs_in_fs::check_access,
-    types::{Directory, Group, Inode, Superblock},
-    DIRECT_POINTERS, INODE_SIZE, ROOT_INODE, SUPERBLOCK_SIZE,
+    layout::Layout,
+    types::{
+        Directory, Group, Inode, LegacyDirectory, Superblock, DIRECTORY_HEADER_SIZE,
+        INODE_FLAG_APPEND_ONLY, INODE_FLAG_IMMUTABLE,
+    },
+    DIRECT_POINTERS, FERRIX_VERSION, INODE_SIZE, MIN_SUPPORTED_VERSION, ROOT_INODE,
 };
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use fs::OpenOptions;
 use fuser::{
-    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData,
-    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request,
+    consts, FileAttr, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyOpen, ReplyWrite, Request,
+    TimeOrNow,
 };
 use io::{Cursor, SeekFrom};
 use memmap::MmapMut;
@@ -17,26 +23,142 @@ use nix::{
     errno::Errno,
     sys::stat::{Mode, SFlag},
 };
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     ffi::{OsStr, OsString},
     fs,
     io::{self, prelude::*},
     mem,
+    os::unix::ffi::OsStrExt,
     path::Path,
 };
 use std::{
     path::PathBuf,
-    time::{Duration, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tracing::debug;
+use tracing::{debug, warn};
 
 pub type FSResult<T> = Result<T, nix::Error>;
 
+/// ioctl command that runs a scrub on the mounted filesystem, replying with the bincode encoded
+/// `ScrubReport`
+pub const FERRIX_IOC_SCRUB: u32 = 0x6600;
+
+/// Size of the buffer the kernel hands to a `FERRIX_IOC_HANDLES` ioctl for the reply
+pub const HANDLES_REPLY_SIZE: u32 = 4096;
+/// ioctl command that lists the open file handles, replying with the bincode encoded
+/// `Vec<OpenHandle>`. Encoded as a read of [`HANDLES_REPLY_SIZE`] bytes, so the kernel passes a
+/// reply buffer to a restricted ioctl
+pub const FERRIX_IOC_HANDLES: u32 = (2 << 30) | (HANDLES_REPLY_SIZE << 16) | (0x66 << 8) | 0x01;
+
+/// Size of the buffer the kernel hands to a `FERRIX_IOC_INODE` or `FERRIX_IOC_SUPERBLOCK` ioctl
+/// for the reply
+pub const DEBUG_REPLY_SIZE: u32 = 512;
+/// ioctl command that replies with the bincode encoded `Inode` of the file it is made on
+pub const FERRIX_IOC_INODE: u32 = (2 << 30) | (DEBUG_REPLY_SIZE << 16) | (0x66 << 8) | 0x02;
+/// ioctl command that replies with the bincode encoded `Superblock` of the mounted filesystem
+pub const FERRIX_IOC_SUPERBLOCK: u32 = (2 << 30) | (DEBUG_REPLY_SIZE << 16) | (0x66 << 8) | 0x03;
+
+/// Times an operation failing with `EINTR` or `EAGAIN` is retried before giving up
+const TRANSIENT_RETRIES: u32 = 5;
+/// Delay before the first retry of a transient failure, doubled after every attempt
+const TRANSIENT_BACKOFF: Duration = Duration::from_millis(1);
+
+/// `chattr`/`lsattr` ioctl commands reading and writing the inode flags
+const FS_IOC_GETFLAGS: u32 = libc::FS_IOC_GETFLAGS as u32;
+const FS_IOC_SETFLAGS: u32 = libc::FS_IOC_SETFLAGS as u32;
+
+/// Inode flags that can be changed with `set_flags`
+const SETTABLE_INODE_FLAGS: u32 = INODE_FLAG_IMMUTABLE | INODE_FLAG_APPEND_ONLY;
+
+/// Drift found by a scrub between the superblock free counters and the group bitmaps, positive
+/// when the superblock reported more free entries than the bitmaps
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubReport {
+    pub free_blocks_drift: i64,
+    pub free_inodes_drift: i64,
+}
+
+impl ScrubReport {
+    pub fn has_drift(&self) -> bool {
+        self.free_blocks_drift != 0 || self.free_inodes_drift != 0
+    }
+}
+
+/// Attributes changed by [`SimpleExt4FS::set_attr`], the ones left as `None` are kept
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SetAttr {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub size: Option<u64>,
+    pub atime: Option<TimeOrNow>,
+    pub mtime: Option<TimeOrNow>,
+    pub ctime: Option<SystemTime>,
+}
+
+/// Whether `flags` open a file for reading and for writing
+fn access_mode(flags: i32) -> FSResult<(bool, bool)> {
+    match flags & libc::O_ACCMODE {
+        libc::O_RDONLY => Ok((true, false)),
+        libc::O_WRONLY => Ok((false, true)),
+        libc::O_RDWR => Ok((true, true)),
+        // Exactly one access mode flag must be specified
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Run `op` again while it fails with `EINTR` or `EAGAIN`, backing off between attempts, at most
+/// [`TRANSIENT_RETRIES`] times
+fn retry_transient<T>(mut op: impl FnMut() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    let mut backoff = TRANSIENT_BACKOFF;
+    let mut retries = 0;
+
+    loop {
+        match op() {
+            Err(e) if retries < TRANSIENT_RETRIES && is_transient(&e) => {
+                warn!("retrying after transient failure: {e}");
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(e: &anyhow::Error) -> bool {
+    let kind = if let Some(e) = e.downcast_ref::<io::Error>() {
+        e.kind()
+    } else if let Some(bincode::ErrorKind::Io(e)) = e.downcast_ref::<bincode::Error>().map(|e| &**e)
+    {
+        e.kind()
+    } else {
+        return false;
+    };
+
+    matches!(kind, io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock)
+}
+
+/// State of an open file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileHandle {
+    pub ino: u64,
+    pub read: bool,
+    pub write: bool,
+    /// The flags the handle was opened with, e.g. `O_APPEND` or `O_TRUNC`
+    pub flags: i32,
+}
+
 #[derive(Debug, Default)]
 pub struct SimpleExt4FS {
     pub sb: Option<Superblock>,
     pub mmap: Option<MmapMut>,
     pub groups: Option<Vec<Group>>,
+    file_handles: HashMap<u64, FileHandle>,
+    next_file_handle: u64,
+    dcache: DirCache,
 }
 
 impl SimpleExt4FS {
@@ -49,6 +171,21 @@ impl SimpleExt4FS {
         let mut cursor = Cursor::new(&mmap);
 
         let sb = Superblock::deserialize_from(&mut cursor)?;
+        if sb.version > FERRIX_VERSION {
+            bail!(
+                "Unsupported ferrix format version {}, the newest supported version is {}",
+                sb.version,
+                FERRIX_VERSION
+            );
+        }
+        if sb.version < MIN_SUPPORTED_VERSION {
+            bail!(
+                "Unsupported ferrix format version {}, the oldest supported version is {}, \
+                 recreate the image with mkfs",
+                sb.version,
+                MIN_SUPPORTED_VERSION
+            );
+        }
 
         let groups = Group::deserialize_from(&mut cursor, sb.block_size, sb.groups as usize)?;
 
@@ -56,1061 +193,2847 @@ impl SimpleExt4FS {
             sb: Some(sb),
             groups: Some(groups),
             mmap: Some(mmap),
+            ..Default::default()
         };
 
+        // A crash between updating the bitmaps and the superblock leaves the counters stale, the
+        // bitmaps are the source of truth
+        fs.scrub();
+        if fs.superblock().version < FERRIX_VERSION {
+            fs.migrate()?;
+        }
         fs.create_root()?;
 
         Ok(fs)
     }
 
-    pub fn create_root(&mut self) -> anyhow::Result<()> {
-        let group = self.groups_mut().get_mut(0).unwrap();
-        if group.has_inode(ROOT_INODE as _) {
-            return Ok(());
+    /// Rewrite what changed layout since the format version of the image, then record the
+    /// current version
+    /// - 5: every directory is rewritten from a single blob of its entries, keeping its times
+    fn migrate(&mut self) -> anyhow::Result<()> {
+        let version = self.superblock().version;
+        warn!(
+            "migrating ferrix format version {} to {}",
+            version, FERRIX_VERSION
+        );
+
+        if version < 5 {
+            let inodes = self.superblock().inode_count;
+            for index in ROOT_INODE..=inodes {
+                let Ok(inode) = self.find_inode(index) else {
+                    continue;
+                };
+                if !inode.is_dir() {
+                    continue;
+                }
+
+                let data = self.read_dir_data(&inode)?;
+                self.save_dir(LegacyDirectory::deserialize_from(data.as_slice())?, index)?;
+                let mut migrated = self.find_inode(index)?;
+                migrated.modified_at = inode.modified_at;
+                migrated.changed_at = inode.changed_at;
+                self.save_inode(migrated, index)?;
+            }
         }
 
-        let mut inode = Inode::new(self.superblock().block_size);
-        inode.mode = SFlag::S_IFDIR.bits() | 0o777;
-        inode.hard_links = 2;
+        self.superblock_mut().version = FERRIX_VERSION;
+        self.sync()
+    }
 
-        let dir = Directory::default();
+    /// Record in the superblock that the filesystem was just mounted
+    fn mark_mounted(&mut self) {
+        let sb = self.superblock_mut();
+        sb.update_last_mounted_at();
+        sb.update_modified_at();
+    }
 
-        let index = self
-            .allocate_inode()
-            .ok_or_else(|| anyhow!("No space left for inodes"))?;
-        assert_eq!(index, ROOT_INODE);
+    /// Write the superblock and the group bitmaps to the disk and flush it, retrying the
+    /// transient failures
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        let sb = self
+            .sb
+            .as_mut()
+            .ok_or_else(|| anyhow!("no superblock loaded"))?;
+        let groups = self
+            .groups
+            .as_deref()
+            .ok_or_else(|| anyhow!("no groups loaded"))?;
+        let mmap = self
+            .mmap
+            .as_mut()
+            .ok_or_else(|| anyhow!("disk is not mapped"))?;
+
+        retry_transient(|| sb.serialize_into(Cursor::new(mmap.as_mut())))?;
+        retry_transient(|| Group::serialize_into(Cursor::new(mmap.as_mut()), groups))?;
 
-        inode.add_block(
-            self.allocate_data_block()
-                .ok_or_else(|| anyhow!("No space left for data"))?,
-            0,
-        )?;
-        self.save_inode(inode, index)?;
-        self.save_dir(dir, index)
+        debug!("flushing mmap");
+        retry_transient(|| Ok(mmap.flush()?))
     }
 
-    fn save_inode(&mut self, mut inode: Inode, index: u32) -> anyhow::Result<()> {
-        let offset = self.inode_seek_position(index);
-        let buf = self.mmap_mut().as_mut();
-        let mut cursor = Cursor::new(buf);
-        debug!("save_inode: offset={}", offset);
-        cursor.seek(SeekFrom::Start(offset))?;
+    /// Recompute the free counts from the group bitmaps and correct the superblock counters,
+    /// reporting any drift found
+    pub fn scrub(&mut self) -> ScrubReport {
+        let free_blocks: usize = self.groups().iter().map(Group::free_data_blocks).sum();
+        let free_inodes: usize = self.groups().iter().map(Group::free_inodes).sum();
 
-        Ok(inode.serialize_into(&mut cursor)?)
+        let sb = self.superblock_mut();
+        let report = ScrubReport {
+            free_blocks_drift: sb.free_blocks as i64 - free_blocks as i64,
+            free_inodes_drift: sb.free_inodes as i64 - free_inodes as i64,
+        };
+
+        if report.has_drift() {
+            warn!("scrub: found drift {:?}", report);
+            sb.free_blocks = free_blocks as u32;
+            sb.free_inodes = free_inodes as u32;
+            sb.update_modified_at();
+        }
+
+        report
     }
 
-    fn save_dir(&mut self, mut dir: Directory, index: u32) -> anyhow::Result<()> {
-        debug!("save_dir: index={}, dir={:?}", index, dir);
-        let mut inode = self.find_inode(index)?;
-        debug!("save_dir: inode={:?}", inode);
-        inode.update_modified_at();
-        self.save_inode(inode, index)?;
+    /// Open the inode with the given open flags, returning the id of the allocated handle
+    pub fn open_handle(&mut self, ino: u64, flags: i32) -> FSResult<u64> {
+        let (read, write) = access_mode(flags)?;
+
+        self.find_inode(ino as u32)?;
+        Ok(self.allocate_file_handle(FileHandle {
+            ino,
+            read,
+            write,
+            flags,
+        }))
+    }
 
-        let offset = self.data_block_seek_position(index);
-        let buf = self.mmap_mut().as_mut();
-        let mut cursor = Cursor::new(buf);
-        cursor.seek(SeekFrom::Start(offset))?;
+    /// Release a handle previously returned by `open_handle`
+    pub fn release_handle(&mut self, fh: u64) -> FSResult<FileHandle> {
+        self.file_handles.remove(&fh).ok_or(Errno::EBADF)
+    }
 
-        Ok(dir.serialize_into(&mut cursor)?)
+    pub fn file_handle(&self, fh: u64) -> Option<&FileHandle> {
+        self.file_handles.get(&fh)
     }
 
-    fn find_inode(&self, index: u32) -> FSResult<Inode> {
-        debug!("find_inode: index={}", index);
-        let (group_index, _bitmap_index) = self.inode_offsets(index);
-        if !self
-            .groups()
-            .get(group_index as usize)
-            .unwrap()
-            .has_inode(index as usize)
-        {
-            return Err(Errno::ENOENT);
+    /// List the open file handles ordered by id, to diagnose handles that are never released
+    pub fn open_handles(&self) -> Vec<OpenHandle> {
+        let mut open_counts: HashMap<u64, usize> = HashMap::new();
+        for handle in self.file_handles.values() {
+            *open_counts.entry(handle.ino).or_default() += 1;
         }
-        debug!("find_inode: group_index={}", group_index);
 
-        let offset = self.inode_seek_position(index);
-        debug!("find_inode: offset={}", offset);
-        let buf = self.mmap();
-        let mut cursor = Cursor::new(buf);
-        cursor
-            .seek(SeekFrom::Start(offset))
-            .inspect_err(|e| debug!("seek failed {}", e))
-            .unwrap();
+        let mut handles: Vec<_> = self
+            .file_handles
+            .iter()
+            .map(|(&fh, handle)| OpenHandle {
+                fh,
+                ino: handle.ino,
+                flags: handle.flags,
+                read: handle.read,
+                write: handle.write,
+                open_count: open_counts[&handle.ino],
+            })
+            .collect();
+        handles.sort_by_key(|handle| handle.fh);
+        handles
+    }
 
-        let inode = Inode::deserialize_from(cursor).map_err(|_e| Errno::EIO)?;
-        debug!("find_inode: inode={:?}", inode);
-        Ok(inode)
+    fn allocate_file_handle(&mut self, handle: FileHandle) -> u64 {
+        self.next_file_handle += 1;
+        self.file_handles.insert(self.next_file_handle, handle);
+        self.next_file_handle
     }
 
-    fn find_inode_from_path<P>(&self, path: P) -> FSResult<(Inode, u32)>
-    where
-        P: AsRef<Path>,
-    {
-        match path.as_ref().parent() {
-            None => Ok((self.find_inode(ROOT_INODE)?, ROOT_INODE)),
-            Some(parent) => {
-                let (parent, _) = self.find_dir(parent)?;
-                let index = parent.entry(
-                    path.as_ref()
-                        .file_name()
-                        .ok_or(Errno::EINVAL)?
-                        .to_os_string(),
-                )?;
-                Ok((self.find_inode(index)?, index))
+    /// Create a regular file named `name` in the `parent` directory owned by `uid` and `gid`.
+    ///
+    /// If the name already exists it fails with `EEXIST` when `O_EXCL` is set, otherwise the
+    /// existing file is reused and truncated when `O_TRUNC` is set. Flags without exactly one
+    /// access mode fail with `EINVAL` before anything is created.
+    pub fn create_file(
+        &mut self,
+        parent: u32,
+        name: &OsStr,
+        mode: u32,
+        flags: i32,
+        uid: u32,
+        gid: u32,
+    ) -> FSResult<(Inode, u32)> {
+        access_mode(flags)?;
+        let parent_dir = self.find_dir_from_inode(parent)?;
+
+        if let Ok(index) = parent_dir.entry(name) {
+            if flags & libc::O_EXCL != 0 {
+                return Err(Errno::EEXIST);
+            }
+
+            let mut inode = self.find_inode(index)?;
+            if inode.is_dir() {
+                return Err(Errno::EISDIR);
             }
+
+            if flags & libc::O_TRUNC != 0 {
+                if inode.is_immutable() || inode.is_append_only() {
+                    return Err(Errno::EPERM);
+                }
+                self.release_blocks(&inode).map_err(|_| Errno::EIO)?;
+                inode.truncate();
+                inode.indirect_block = 0;
+                inode.double_indirect_block = 0;
+                self.save_inode(inode, index).map_err(|_| Errno::EIO)?;
+            }
+
+            return Ok((self.find_inode(index)?, index));
         }
+
+        let index = self.allocate_inode().ok_or(Errno::ENOSPC)?;
+
+        let mut inode = Inode::new(self.superblock().block_size);
+        inode.mode = mode;
+        inode.user_id = uid;
+        inode.group_id = gid;
+
+        self.save_inode(inode, index).map_err(|_| Errno::EIO)?;
+        self.add_dir_entry(parent_dir, parent, name, index)
+            .map_err(|_| Errno::EIO)?;
+
+        Ok((self.find_inode(index)?, index))
     }
 
-    fn find_dir<P>(&self, path: P) -> FSResult<(Directory, u32)>
-    where
-        P: AsRef<Path>,
-    {
-        let mut current = self.find_dir_from_inode(ROOT_INODE)?;
-        let mut index = ROOT_INODE;
-        for c in path.as_ref().components().skip(1) {
-            index = current.entry(c)?;
-            current = self.find_dir_from_inode(index)?;
-        }
+    /// Create a directory named `name` in the `parent` directory owned by `uid` and `gid`
+    pub fn create_dir(
+        &mut self,
+        parent: u32,
+        name: &OsStr,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> FSResult<(Inode, u32)> {
+        let parent_dir = self.find_dir_from_inode(parent)?;
+        let index = self.allocate_inode().ok_or(Errno::ENOSPC)?;
+        debug!("create_dir: index={}", index);
 
-        Ok((current, index))
+        let mut inode = Inode::new(self.superblock().block_size);
+        inode.mode = SFlag::S_IFDIR.bits() | mode;
+        inode.hard_links = 2;
+        inode.user_id = uid;
+        inode.group_id = gid;
+
+        let data_block_index = self.allocate_data_block(index).ok_or(Errno::ENOSPC)?;
+        inode
+            .add_block(data_block_index, 0)
+            .map_err(|_| Errno::EIO)?;
+
+        self.save_inode(inode, index).map_err(|_| Errno::EIO)?;
+        self.save_dir(Directory::new(parent), index)
+            .map_err(|_| Errno::EIO)?;
+        self.add_hard_links(parent, 1)?;
+        self.add_dir_entry(parent_dir, parent, name, index)
+            .map_err(|_| Errno::EIO)?;
+
+        Ok((self.find_inode(index)?, index))
     }
 
-    fn find_dir_from_inode(&self, index: u32) -> FSResult<Directory> {
-        debug!("find_dir_from_inode: index={}", index);
-        let inode = self.find_inode(index)?;
-        if !inode.is_dir() {
-            return Err(Errno::ENOTDIR);
+    /// Create a symbolic link named `name` in the `parent` directory owned by `uid` and `gid`,
+    /// storing the bytes of `target` as they are in its single data block.
+    ///
+    /// Fails with `EEXIST` when the name is taken and with `ENAMETOOLONG` when the target does
+    /// not fit in a block.
+    pub fn create_symlink(
+        &mut self,
+        parent: u32,
+        name: &OsStr,
+        target: &Path,
+        uid: u32,
+        gid: u32,
+    ) -> FSResult<(Inode, u32)> {
+        let parent_dir = self.find_dir_from_inode(parent)?;
+        if parent_dir.entry(name).is_ok() {
+            return Err(Errno::EEXIST);
         }
 
-        // TODO: support more blocks
-        let block = inode.direct_blocks[0];
-        let (group_index, _) = self.data_block_offsets(index);
-        if !self
-            .groups()
-            .get(group_index as usize)
-            .unwrap()
-            .has_data_block(block as usize)
-        {
-            return Err(Errno::ENOENT.into());
+        let target = target.as_os_str().as_bytes();
+        if target.len() > self.superblock().block_size as usize {
+            return Err(Errno::ENAMETOOLONG);
         }
 
-        let mut cursor = Cursor::new(self.mmap().as_ref());
-        cursor
-            .seek(SeekFrom::Start(self.data_block_seek_position(block)))
+        let index = self.allocate_inode().ok_or(Errno::ENOSPC)?;
+        let mut inode = Inode::new(self.superblock().block_size);
+        inode.mode = SFlag::S_IFLNK.bits() | 0o777;
+        inode.user_id = uid;
+        inode.group_id = gid;
+
+        let data_block_index = self.allocate_data_block(index).ok_or(Errno::ENOSPC)?;
+        inode
+            .add_block(data_block_index, 0)
+            .map_err(|_| Errno::EIO)?;
+        self.write_data(target, 0, data_block_index)
+            .map_err(|_| Errno::EIO)?;
+        inode.set_size(target.len() as u64);
+
+        self.save_inode(inode, index).map_err(|_| Errno::EIO)?;
+        self.add_dir_entry(parent_dir, parent, name, index)
             .map_err(|_| Errno::EIO)?;
 
-        Directory::deserialize_from(cursor).map_err(|_| Errno::EIO.into())
+        Ok((self.find_inode(index)?, index))
     }
 
-    fn find_data_block(
-        &mut self,
-        inode: &mut Inode,
-        offset: u64,
-        read: bool,
-    ) -> FSResult<(u32, u32)> {
-        let blk_size = self.superblock().block_size as u64;
-        let index = offset / blk_size;
+    /// Read the target stored by `create_symlink`, failing with `EINVAL` when the inode is not a
+    /// symbolic link
+    pub fn read_symlink(&self, ino: u32) -> FSResult<Vec<u8>> {
+        let inode = self.find_inode(ino)?;
+        if !inode.is_symlink() {
+            return Err(Errno::EINVAL);
+        }
 
-        let pointers_per_block = blk_size / mem::size_of::<u32>() as u64;
+        let mut target = vec![0u8; inode.size as usize];
+        self.read_data(&mut target, 0, inode.direct_blocks[0])
+            .map_err(|_| Errno::EIO)?;
 
-        let block = if index < DIRECT_POINTERS {
-            inode.find_direct_block(index as usize)
-        } else if index < (pointers_per_block + DIRECT_POINTERS) {
-            self.find_indirect(
-                inode.indirect_block,
-                index - DIRECT_POINTERS,
-                offset,
-                pointers_per_block,
-            )
-            .map_err(|_| Errno::EIO)?
-        } else if index
-            < (pointers_per_block * pointers_per_block + pointers_per_block + DIRECT_POINTERS)
-        {
-            self.find_indirect(
-                inode.double_indirect_block,
-                index - DIRECT_POINTERS,
-                offset,
-                pointers_per_block,
-            )
-            .map_err(|_| Errno::EIO)?
-        } else {
-            return Err(Errno::ENOSPC.into());
-        };
+        Ok(target)
+    }
 
-        if block != 0 {
-            return Ok((block, ((index + 1) * blk_size - offset) as u32));
+    /// Write `data` at `offset` of the file open as `fh`, returning how many bytes were written.
+    ///
+    /// Fails with `EPERM` on an immutable file or on an append-only one when `offset` is not the
+    /// end of the file.
+    pub fn write_file(&mut self, ino: u64, fh: u64, offset: i64, data: &[u8]) -> FSResult<u32> {
+        if !self.file_handle(fh).is_some_and(|handle| handle.write) {
+            return Err(Errno::EBADF);
         }
 
-        if read {
-            return Err(Errno::EINVAL.into());
+        let mut inode = self.find_inode(ino as u32)?;
+        if inode.is_immutable() || (inode.is_append_only() && offset as u64 != inode.size) {
+            return Err(Errno::EPERM);
         }
 
-        let mut block = self.allocate_data_block().ok_or_else(|| Errno::ENOSPC)?;
-        if index < DIRECT_POINTERS {
-            inode
-                .add_block(block, index as usize)
-                .map_err(|_| Errno::ENOSPC)?;
-        } else if index < (pointers_per_block + DIRECT_POINTERS) {
-            if inode.indirect_block == 0 {
-                inode.indirect_block = block;
-                self.write_data(&vec![0u8; blk_size as usize], 0, block)
-                    .map_err(|_| Errno::EIO)?;
-                block = self.allocate_data_block().ok_or_else(|| Errno::ENOSPC)?;
-            }
+        let mut total_wrote = 0;
+        let overwrite = inode.size > offset as u64;
+        let mut current_offset = offset as u64;
+        let blk_size = self.superblock().block_size;
 
-            self.save_indirect(
-                inode.indirect_block,
-                block,
-                index - DIRECT_POINTERS,
-                pointers_per_block,
-            )
-            .map_err(|_| Errno::EIO)?;
-        } else if index
-            < (pointers_per_block * pointers_per_block + pointers_per_block + DIRECT_POINTERS)
-        {
-            if inode.double_indirect_block == 0 {
-                inode.double_indirect_block = block;
-                self.write_data(&vec![0u8; blk_size as usize], 0, block)
-                    .map_err(|_| Errno::EIO)?;
-                block = self.allocate_data_block().ok_or_else(|| Errno::ENOSPC)?;
-            }
+        while total_wrote != data.len() {
+            let direct_block_index = current_offset / blk_size as u64;
+            let (block_index, space_left) =
+                self.find_data_block(ino as u32, &mut inode, current_offset, false)?;
 
-            let indirect_offset = (index - DIRECT_POINTERS) / pointers_per_block - 1;
-            let indirect_block = match self
-                .find_indirect(
-                    inode.double_indirect_block,
-                    indirect_offset,
-                    0,
-                    pointers_per_block,
+            let max_write_len = data.len().min(space_left as usize);
+            let offset_in_block = if total_wrote != 0 {
+                0
+            } else {
+                current_offset - direct_block_index * blk_size as u64
+            };
+
+            let wrote = self
+                .write_data(
+                    &data[total_wrote..data.len().min(max_write_len + total_wrote)],
+                    offset_in_block,
+                    block_index,
                 )
-                .map_err(|_| Errno::EIO)?
-            {
-                0 => {
-                    let indirect_block = block;
-                    self.save_indirect(
-                        inode.double_indirect_block,
-                        block,
-                        indirect_offset,
-                        pointers_per_block,
-                    )
-                    .map_err(|_| Errno::EIO)?;
-                    self.write_data(&vec![0u8; blk_size as usize], 0, block)
-                        .map_err(|_| Errno::EIO)?;
-                    block = self.allocate_data_block().ok_or_else(|| Errno::ENOSPC)?;
-                    indirect_block
-                }
-                indirect_block => indirect_block,
-            };
+                .map_err(|_| Errno::EIO)?;
 
-            self.save_indirect(
-                indirect_block,
-                block,
-                (index - DIRECT_POINTERS) & (pointers_per_block - 1),
-                pointers_per_block,
-            )
-            .map_err(|_| Errno::EIO)?;
+            total_wrote += wrote;
+            current_offset += wrote as u64;
+        }
+
+        inode.update_modified_at();
+        if overwrite {
+            inode.adjust_size(total_wrote as u64);
         } else {
-            return Err(Errno::ENOSPC.into());
+            inode.increment_size(total_wrote as u64);
         }
 
-        Ok((block, blk_size as u32))
+        self.save_inode(inode, ino as u32).map_err(|_| Errno::EIO)?;
+
+        debug!("wrote {} bytes", total_wrote);
+        Ok(total_wrote as u32)
     }
 
-    fn find_indirect(
-        &self,
-        pointer: u32,
-        index: u64,
-        offset: u64,
-        pointers_per_block: u64,
-    ) -> anyhow::Result<u32> {
-        if pointer == 0 {
-            return Ok(pointer);
+    /// Read up to `size` bytes of the `ino` file starting at `offset`, stopping at the end of the
+    /// file.
+    ///
+    /// Blocks that follow each other on the disk are read with a single copy out of the mmap
+    /// instead of one per block, so a sequential read of a file written in one go is one copy.
+    pub fn read_file(&mut self, ino: u64, fh: u64, offset: i64, size: u32) -> FSResult<Vec<u8>> {
+        if !self.file_handle(fh).is_some_and(|handle| handle.read) {
+            return Err(Errno::EBADF);
         }
 
-        let off = if index < pointers_per_block {
-            index & (pointers_per_block - 1)
-        } else {
-            index / pointers_per_block - 1
-        };
+        let mut inode = self.find_inode(ino as u32)?;
+        let blk_size = self.superblock().block_size as u64;
+        let should_read = (size as u64).min(inode.size.saturating_sub(offset as u64)) as usize;
 
-        let block = self.read_u32(off, pointer)?;
+        let mut data = vec![0u8; should_read];
+        let mut total_read = 0;
+        let mut current_offset = offset as u64;
 
-        if block == 0 || index < pointers_per_block {
-            return Ok(block);
-        }
+        while total_read != should_read {
+            let (block_index, space_left) =
+                self.find_data_block(ino as u32, &mut inode, current_offset, true)?;
+
+            // Grow the run while the next block of the file is the next one on the disk
+            let mut run_len = should_read.min(total_read + space_left as usize) - total_read;
+            let mut last_block = block_index;
+            while total_read + run_len != should_read {
+                let (next_block, _) = self.find_data_block(
+                    ino as u32,
+                    &mut inode,
+                    current_offset + run_len as u64,
+                    true,
+                )?;
+                if self.data_block_seek_position(next_block)
+                    != self.data_block_seek_position(last_block) + blk_size
+                {
+                    break;
+                }
 
-        self.find_indirect(
-            block,
-            index & (pointers_per_block - 1),
-            offset,
-            pointers_per_block,
-        )
-    }
+                last_block = next_block;
+                run_len = should_read.min(total_read + run_len + blk_size as usize) - total_read;
+            }
 
-    fn save_indirect(
-        &mut self,
-        pointer: u32,
-        block: u32,
-        index: u64,
-        pointers_per_block: u64,
-    ) -> anyhow::Result<()> {
-        assert_ne!(pointer, 0);
-        let offset = index & (pointers_per_block - 1);
+            let read = self
+                .read_data(
+                    &mut data[total_read..total_read + run_len],
+                    current_offset % blk_size,
+                    block_index,
+                )
+                .map_err(|_| Errno::EIO)?;
 
-        if index < pointers_per_block {
-            self.write_data(&block.to_le_bytes(), offset * 4, pointer)
-                .map(|_| ())
-        } else {
-            let indirect_offset = index / pointers_per_block - 1;
-            let new_pointer = self.read_u32(indirect_offset, pointer)?;
-            self.save_indirect(new_pointer, block, offset, pointers_per_block)
+            total_read += read;
+            current_offset += read as u64;
         }
-    }
 
-    // (group_block_index, bitmap_index)
-    fn inode_offsets(&self, index: u32) -> (u64, u64) {
-        let inodes_per_group = self.superblock().data_blocks_per_group as u64;
-        let inode_bg = (index as u64 - 1) / inodes_per_group;
-        let bitmap_index = (index as u64 - 1) & (inodes_per_group - 1);
-        (inode_bg, bitmap_index)
-    }
+        inode.update_accessed_at();
+        self.save_inode(inode, ino as u32).map_err(|_| Errno::EIO)?;
 
-    fn inode_seek_position(&self, index: u32) -> u64 {
-        let (group_index, bitmap_index) = self.inode_offsets(index);
-        let block_size = self.superblock().block_size;
-        group_index * super::block_group_size(block_size)
-            + 2 * block_size as u64
-            + bitmap_index * INODE_SIZE
-            + SUPERBLOCK_SIZE
+        Ok(data)
     }
 
-    fn data_block_offsets(&self, index: u32) -> (u64, u64) {
-        let data_blocks_per_group = self.superblock().data_blocks_per_group as u64;
-        let group_index = (index as u64 - 1) / data_blocks_per_group;
-        let block_index = (index as u64 - 1) & (data_blocks_per_group - 1);
+    /// Add the entry `name` to the `new_parent` directory for the existing `ino` inode, a hard
+    /// link sharing its data.
+    ///
+    /// Fails with `EPERM` when the inode is a directory, immutable or append-only, with `EEXIST`
+    /// when the name is taken and with `EMLINK` when the inode has no more room for links.
+    pub fn create_link(
+        &mut self,
+        ino: u32,
+        new_parent: u32,
+        new_name: &OsStr,
+    ) -> FSResult<(Inode, u32)> {
+        let inode = self.find_inode(ino)?;
+        if inode.is_dir() || inode.is_immutable() || inode.is_append_only() {
+            return Err(Errno::EPERM);
+        }
+        if inode.hard_links == u16::MAX {
+            return Err(Errno::EMLINK);
+        }
 
-        (group_index, block_index)
-    }
+        let new_parent_dir = self.find_dir_from_inode(new_parent)?;
+        if new_parent_dir.entry(new_name).is_ok() {
+            return Err(Errno::EEXIST);
+        }
 
-    fn data_block_seek_position(&self, index: u32) -> u64 {
-        let (group_index, block_index) = self.data_block_offsets(index);
+        self.add_hard_links(ino, 1)?;
+        self.add_dir_entry(new_parent_dir, new_parent, new_name, ino)
+            .map_err(|_| Errno::EIO)?;
 
-        let block_size = self.superblock().block_size;
-        group_index * super::block_group_size(block_size)
-            + 2 * block_size as u64
-            + self.superblock().data_blocks_per_group as u64 * INODE_SIZE
-            + SUPERBLOCK_SIZE
-            + block_size as u64 * block_index
+        Ok((self.find_inode(ino)?, ino))
     }
 
-    fn allocate_inode(&mut self) -> Option<u32> {
-        // TODO: handle when group has run out of space
-        let group_index = self.groups().iter().position(|g| g.free_inodes() > 0)?;
-        self.superblock_mut().free_inodes -= 1;
-        let group = self.groups_mut().get_mut(group_index).unwrap();
+    /// Remove the file named `name` from the `parent` directory, releasing its blocks once no
+    /// other hard link is left.
+    ///
+    /// Fails with `EPERM` when the file is immutable or append-only.
+    pub fn remove_file(&mut self, parent: u32, name: &OsStr) -> FSResult<()> {
+        let mut parent_dir = self.find_dir_from_inode(parent)?;
+        let index = parent_dir.entry(name)?;
+        let inode = self.find_inode(index)?;
+        if inode.is_immutable() || inode.is_append_only() {
+            return Err(Errno::EPERM);
+        }
 
-        let index = group.allocate_inode()?;
-        Some(index as u32 + group_index as u32 * self.superblock().data_blocks_per_group)
+        parent_dir.entries.remove(name);
+        self.save_dir(parent_dir, parent).map_err(|_| Errno::EIO)?;
+        self.drop_link(&inode, index)
     }
 
-    fn allocate_data_block(&mut self) -> Option<u32> {
-        // TODO: handle when group has run out of space
-        let group_index = self
-            .groups()
-            .iter()
-            .position(|g| g.free_data_blocks() > 0)?;
-
-        self.superblock_mut().free_blocks -= 1;
-        let group = self.groups_mut().get_mut(group_index).unwrap();
+    /// Drop a hard link of the file `inode` of the `index` inode whose entry was removed,
+    /// releasing its blocks and the inode along with the last one
+    fn drop_link(&mut self, inode: &Inode, index: u32) -> FSResult<()> {
+        if inode.hard_links > 1 {
+            return self.add_hard_links(index, -1);
+        }
 
-        let index = group.allocate_data_block()?;
-        Some(index as u32 + group_index as u32 * self.superblock().data_blocks_per_group)
+        self.release_blocks(inode).map_err(|_| Errno::EIO)?;
+        self.release_inode(index);
+        Ok(())
     }
 
-    fn release_data_blocks(&mut self, blocks: &[u32]) {
-        for block in blocks {
-            let (group_index, block_index) = self.data_block_offsets(*block);
-            // TODO: release multiple blocks from the same group in a single call
-            self.groups_mut()
-                .get_mut(group_index as usize)
-                .unwrap()
-                .release_data_block(1 + block_index as usize);
+    /// Remove the empty directory named `name` from the `parent` directory and release its block.
+    ///
+    /// Fails with `ENOTDIR` when the entry is not a directory, with `ENOTEMPTY` when it has
+    /// entries and with `EPERM` when it is immutable or append-only.
+    pub fn remove_dir(&mut self, parent: u32, name: &OsStr) -> FSResult<()> {
+        let mut parent_dir = self.find_dir_from_inode(parent)?;
+        let index = parent_dir.entry(name)?;
+        let inode = self.find_inode(index)?;
+        if !inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        if !self.find_dir_from_inode(index)?.entries.is_empty() {
+            return Err(Errno::ENOTEMPTY);
+        }
+        if inode.is_immutable() || inode.is_append_only() {
+            return Err(Errno::EPERM);
         }
-        self.superblock_mut().free_blocks += blocks.len() as u32;
-    }
 
-    fn release_inode(&mut self, index: u32) {
-        let (group_index, _) = self.inode_offsets(index);
-        self.groups_mut()
-            .get_mut(group_index as usize)
-            .unwrap()
-            .release_inode(index as usize);
-        self.superblock_mut().free_inodes += 1;
-    }
+        parent_dir.entries.remove(name);
+        self.release_blocks(&inode).map_err(|_| Errno::EIO)?;
+        self.release_inode(index);
+        self.add_hard_links(parent, -1)?;
+        self.save_dir(parent_dir, parent).map_err(|_| Errno::EIO)?;
 
-    fn release_indirect_block(&mut self, block: u32) -> anyhow::Result<()> {
-        let blocks = self.read_indirect_block(block)?;
-        self.release_data_blocks(&blocks);
         Ok(())
     }
 
-    fn release_double_indirect_block(&mut self, block: u32) -> anyhow::Result<()> {
-        let pointers_per_block = self.superblock().block_size as usize / 4;
-        let indirect_blocks = self.read_indirect_block(block)?;
-        let mut blocks = Vec::with_capacity(indirect_blocks.len() * pointers_per_block);
-        for b in indirect_blocks.iter().filter(|x| **x != 0) {
-            blocks.append(&mut self.read_indirect_block(*b)?);
+    /// Change the attributes of the `ino` inode given in `attr` and return the new ones. The
+    /// file type bits of the mode are kept, a shrunk file releases the blocks past its end and a
+    /// grown one reads as zeros up to its new size.
+    ///
+    /// Fails with `EPERM` on an immutable inode or when resizing an append-only one, and with
+    /// `EISDIR` when resizing a directory.
+    pub fn set_attr(&mut self, ino: u32, attr: SetAttr) -> FSResult<FileAttr> {
+        let mut inode = self.find_inode(ino)?;
+        if inode.is_immutable() || (attr.size.is_some() && inode.is_append_only()) {
+            return Err(Errno::EPERM);
         }
 
-        self.release_data_blocks(&indirect_blocks);
-        self.release_data_blocks(&blocks);
+        if let Some(mode) = attr.mode {
+            inode.mode = (inode.mode & libc::S_IFMT) | (mode & !libc::S_IFMT);
+        }
+        if let Some(uid) = attr.uid {
+            inode.user_id = uid;
+        }
+        if let Some(gid) = attr.gid {
+            inode.group_id = gid;
+        }
+        if let Some(size) = attr.size {
+            if inode.is_dir() {
+                return Err(Errno::EISDIR);
+            }
+            self.resize(ino, &mut inode, size)?;
+        }
 
-        Ok(())
-    }
+        let now = SystemTime::now();
+        let time = |time| match time {
+            TimeOrNow::SpecificTime(time) => time,
+            TimeOrNow::Now => now,
+        };
+        if let Some(atime) = attr.atime {
+            inode.accessed_at = time(atime);
+        }
+        if let Some(mtime) = attr.mtime {
+            inode.modified_at = time(mtime);
+        }
+        inode.changed_at = attr.ctime.unwrap_or(now);
 
-    fn write_data(&mut self, data: &[u8], offset: u64, block_index: u32) -> anyhow::Result<usize> {
-        let block_offset = self.data_block_seek_position(block_index);
+        let file_attr = inode.to_attr(ino);
+        self.save_inode(inode, ino).map_err(|_| Errno::EIO)?;
 
-        let buf = self.mmap_mut().as_mut();
-        let mut cursor = Cursor::new(buf);
-        cursor.seek(SeekFrom::Start(block_offset + offset))?;
-        Ok(cursor.write(data)?)
+        Ok(file_attr)
     }
 
-    fn read_data(&self, data: &mut [u8], offset: u64, block_index: u32) -> anyhow::Result<usize> {
-        let block_offset = self.data_block_seek_position(block_index);
-        let buf = self.mmap().as_ref();
-        let mut cursor = Cursor::new(buf);
-        cursor.seek(SeekFrom::Start(block_offset + offset))?;
+    /// Move the entry `name` of the `parent` directory to `new_name` in `new_parent`, replacing
+    /// the entry already there, if any. A moved directory gets `new_parent` as its `..`.
+    ///
+    /// Fails with `EINVAL` when moving a directory into itself or one of its descendants, with
+    /// `EPERM` when the entry is immutable or append-only and with `ENOTEMPTY` when replacing a
+    /// directory that has entries.
+    pub fn rename(
+        &mut self,
+        parent: u32,
+        name: &OsStr,
+        new_parent: u32,
+        new_name: &OsStr,
+    ) -> FSResult<()> {
+        let index = self.find_dir_from_inode(parent)?.entry(name)?;
+        let inode = self.find_inode(index)?;
+        if inode.is_immutable() || inode.is_append_only() {
+            return Err(Errno::EPERM);
+        }
+        if inode.is_dir() && self.is_ancestor(index, new_parent)? {
+            return Err(Errno::EINVAL);
+        }
 
-        cursor.read_exact(data)?;
+        if let Ok(existing) = self.find_dir_from_inode(new_parent)?.entry(new_name) {
+            if existing == index {
+                return Ok(());
+            }
+            self.remove_replaced(new_parent, new_name, existing, inode.is_dir())?;
+        }
 
-        Ok(data.len())
-    }
+        let mut parent_dir = self.find_dir_from_inode(parent)?;
+        parent_dir.entries.remove(name);
+        if parent == new_parent {
+            parent_dir.entries.insert(new_name.to_owned(), index);
+            return self.save_dir(parent_dir, parent).map_err(|_| Errno::EIO);
+        }
 
-    fn read_u32(&self, offset: u64, block_index: u32) -> anyhow::Result<u32> {
-        let mut data = [0u8; 4];
-        self.read_data(&mut data, offset * 4, block_index)?;
-        Ok(u32::from_le_bytes(data))
+        let new_parent_dir = self.find_dir_from_inode(new_parent)?;
+        if inode.is_dir() {
+            let mut dir = self.find_dir_from_inode(index)?;
+            dir.parent = new_parent;
+            self.save_dir(dir, index).map_err(|_| Errno::EIO)?;
+            self.add_hard_links(parent, -1)?;
+            self.add_hard_links(new_parent, 1)?;
+        }
+
+        self.save_dir(parent_dir, parent).map_err(|_| Errno::EIO)?;
+        self.add_dir_entry(new_parent_dir, new_parent, new_name, index)
+            .map_err(|_| Errno::EIO)
     }
 
-    fn read_indirect_block(&mut self, block: u32) -> anyhow::Result<Vec<u32>> {
-        let pointers_per_block = self.superblock().block_size as usize / 4;
-        let mut vec = Vec::with_capacity(pointers_per_block);
-        for i in 0..pointers_per_block {
-            let b = self.read_u32(i as u64, block)?;
-            if b != 0 {
-                vec.push(b);
+    /// Remove the entry `existing` named `name` of `parent` that a rename is replacing, a file can
+    /// only replace a file and a directory only an empty directory
+    fn remove_replaced(
+        &mut self,
+        parent: u32,
+        name: &OsStr,
+        existing: u32,
+        is_dir: bool,
+    ) -> FSResult<()> {
+        let inode = self.find_inode(existing)?;
+        match (is_dir, inode.is_dir()) {
+            (false, true) => return Err(Errno::EISDIR),
+            (true, false) => return Err(Errno::ENOTDIR),
+            (true, true) if !self.find_dir_from_inode(existing)?.entries.is_empty() => {
+                return Err(Errno::ENOTEMPTY)
             }
+            _ => {}
+        }
+        if inode.is_immutable() || inode.is_append_only() {
+            return Err(Errno::EPERM);
         }
 
-        Ok(vec)
-    }
+        let mut parent_dir = self.find_dir_from_inode(parent)?;
+        parent_dir.entries.remove(name);
+        if is_dir {
+            self.release_blocks(&inode).map_err(|_| Errno::EIO)?;
+            self.release_inode(existing);
+            self.add_hard_links(parent, -1)?;
+        } else {
+            self.drop_link(&inode, existing)?;
+        }
 
-    fn groups(&self) -> &[Group] {
-        self.groups
-            .as_ref()
-            .expect("expected to get reference to group")
+        self.save_dir(parent_dir, parent).map_err(|_| Errno::EIO)
     }
 
-    fn groups_mut(&mut self) -> &mut [Group] {
-        self.groups.as_mut().unwrap()
+    /// Whether the directory `ancestor` is `dir` itself or one of the directories above it
+    fn is_ancestor(&self, ancestor: u32, mut dir: u32) -> FSResult<bool> {
+        loop {
+            if dir == ancestor {
+                return Ok(true);
+            }
+            if dir == ROOT_INODE {
+                return Ok(false);
+            }
+            dir = self.find_dir_from_inode(dir)?.parent;
+        }
     }
 
-    fn superblock(&self) -> &Superblock {
-        self.sb.as_ref().unwrap()
+    /// Add `delta` to the hard links of the inode, a directory has one more for every
+    /// subdirectory `..`
+    fn add_hard_links(&mut self, index: u32, delta: i16) -> FSResult<()> {
+        let mut inode = self.find_inode(index)?;
+        inode.hard_links = inode.hard_links.saturating_add_signed(delta);
+        inode.changed_at = SystemTime::now();
+        self.save_inode(inode, index).map_err(|_| Errno::EIO)
     }
 
-    fn superblock_mut(&mut self) -> &mut Superblock {
-        self.sb.as_mut().unwrap()
+    /// Flags of the inode, see `INODE_FLAG_IMMUTABLE` and `INODE_FLAG_APPEND_ONLY`
+    pub fn flags(&self, ino: u64) -> FSResult<u32> {
+        Ok(self.find_inode(ino as u32)?.flags)
     }
 
-    fn mmap(&self) -> &MmapMut {
-        self.mmap.as_ref().unwrap()
-    }
+    /// Replace the flags of the inode on behalf of `uid`, only its owner or root can do it.
+    ///
+    /// Fails with `EOPNOTSUPP` when asked to set a flag the filesystem doesn't know about.
+    pub fn set_flags(&mut self, ino: u64, flags: u32, uid: u32) -> FSResult<()> {
+        let mut inode = self.find_inode(ino as u32)?;
+        if uid != 0 && uid != inode.user_id {
+            return Err(Errno::EPERM);
+        }
+        if flags & !SETTABLE_INODE_FLAGS != 0 {
+            return Err(Errno::EOPNOTSUPP);
+        }
 
-    fn mmap_mut(&mut self) -> &mut MmapMut {
-        self.mmap.as_mut().unwrap()
+        inode.flags = flags;
+        inode.changed_at = SystemTime::now();
+        self.save_inode(inode, ino as u32).map_err(|_| Errno::EIO)
     }
-}
 
-impl Filesystem for SimpleExt4FS {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        debug!("lookup: parent={}, name={:?}", parent, name);
-        match self.find_dir_from_inode(parent as u32) {
-            Ok(dir) => match dir.entry(name) {
-                Ok(index) => match self.find_inode(index) {
-                    Ok(inode) => {
-                        reply.entry(&Duration::from_secs(1), &inode.to_attr(index), 0);
-                    }
-                    Err(e) => reply.error(e as i32),
-                },
-                Err(e) => reply.error(e as i32),
-            },
-            Err(e) => reply.error(e as i32),
+    pub fn create_root(&mut self) -> anyhow::Result<()> {
+        let group = self.groups_mut().get_mut(0).unwrap();
+        if group.has_inode(ROOT_INODE as _) {
+            return Ok(());
         }
-    }
-
-    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
-        let sb = self.superblock();
-        reply.statfs(
-            sb.block_count.into(),
-            sb.free_blocks.into(),
-            sb.free_blocks.into(),
-            sb.inode_count.into(),
-            sb.free_inodes.into(),
-            sb.block_size,
-            255,
-            sb.block_size,
-        );
-    }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, fh: Option<u64>, reply: ReplyAttr) {
-        debug!("getattr: ino={}, fh={:?}", ino, fh);
-        match self.find_inode(ino as u32) {
-            Ok(inode) => {
-                reply.attr(&Duration::from_secs(1), &inode.to_attr(ino as u32));
-            }
-            Err(e) => reply.error(e as i32),
-        }
+        let mut inode = Inode::new(self.superblock().block_size);
+        inode.mode = SFlag::S_IFDIR.bits() | 0o777;
+        inode.hard_links = 2;
+
+        let dir = Directory::new(ROOT_INODE);
+
+        let index = self
+            .allocate_inode()
+            .ok_or_else(|| anyhow!("No space left for inodes"))?;
+        assert_eq!(index, ROOT_INODE);
+
+        inode.add_block(
+            self.allocate_data_block(index)
+                .ok_or_else(|| anyhow!("No space left for data"))?,
+            0,
+        )?;
+        self.save_inode(inode, index)?;
+        self.save_dir(dir, index)
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
-        debug!("readdir: ino={}, fh={}, offset={}", ino, fh, offset);
-        match self.find_dir_from_inode(ino as u32) {
-            Ok(dir) => {
-                let mut entries: Vec<(OsString, u64, FileType)> = vec![
-                    (OsString::from("."), ino, FileType::Directory),
-                    (OsString::from(".."), 1, FileType::Directory),
-                ];
-
-                for (name, index) in dir.entries {
-                    if let Ok(inode) = self.find_inode(index) {
-                        let file_type = if inode.is_dir() {
-                            FileType::Directory
-                        } else {
-                            FileType::RegularFile
-                        };
-                        entries.push((name, index as u64, file_type));
-                    }
-                }
+    fn save_inode(&mut self, mut inode: Inode, index: u32) -> anyhow::Result<()> {
+        self.dcache.invalidate_inode(index);
+        let offset = self.inode_seek_position(index);
+        let buf = self.mmap_mut().as_mut();
+        let mut cursor = Cursor::new(buf);
+        debug!("save_inode: offset={}", offset);
+        cursor.seek(SeekFrom::Start(offset))?;
 
-                for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
-                    if reply.add(entry.1, (i + 1) as i64, entry.2, entry.0) {
-                        break;
-                    }
-                }
-                reply.ok();
-            }
-            Err(e) => reply.error(e as i32),
+        Ok(inode.serialize_into(&mut cursor)?)
+    }
+
+    fn save_dir(&mut self, mut dir: Directory, index: u32) -> anyhow::Result<()> {
+        debug!("save_dir: index={}, dir={:?}", index, dir);
+        self.dcache.invalidate_dir(index);
+        let mut inode = self.find_inode(index)?;
+        debug!("save_dir: inode={:?}", inode);
+        inode.update_modified_at();
+
+        let mut data = Vec::new();
+        dir.serialize_into(&mut data)?;
+
+        // The directory takes as many blocks as it needs, allocated as it grows and released as
+        // it shrinks
+        let blk_size = self.superblock().block_size as usize;
+        for (i, chunk) in data.chunks(blk_size).enumerate() {
+            let (block, _) =
+                self.find_data_block(index, &mut inode, (i * blk_size) as u64, false)?;
+            self.write_data(chunk, 0, block)?;
         }
+        self.release_blocks_from(&mut inode, data.len().div_ceil(blk_size) as u64)?;
+
+        self.save_inode(inode, index)
     }
 
-    fn create(
+    /// Add the entry `name` for the inode `entry` to the directory `dir` of the inode `index`.
+    ///
+    /// The entry is written after the others in the last block of the directory along with the
+    /// header in its first block, the whole directory is only saved again when the entry doesn't
+    /// fit in the last block or can't be appended, see [`Directory::append`]
+    fn add_dir_entry(
         &mut self,
-        _req: &Request,
-        parent: u64,
+        mut dir: Directory,
+        index: u32,
         name: &OsStr,
-        mode: u32,
-        umask: u32,
-        flags: i32,
-        reply: ReplyCreate,
-    ) {
-        debug!(
-            "create: parent={}, name={:?}, mode={:#o}, umask={:#o}, flags={:#x}",
-            parent, name, mode, umask, flags
-        );
-        let index = match self.allocate_inode() {
-            Some(index) => index,
-            None => {
-                reply.error(libc::ENOSPC);
-                return;
-            }
+        entry: u32,
+    ) -> anyhow::Result<()> {
+        let Some((bytes, header)) = dir.append(name, entry)? else {
+            return self.save_dir(dir, index);
         };
 
-        let mut inode = Inode::new(self.superblock().block_size);
-        inode.mode = mode;
-        inode.user_id = self.superblock().uid;
-        inode.group_id = self.superblock().gid;
-
-        match self.find_dir_from_inode(parent as u32) {
-            Ok(mut parent_dir) => {
-                parent_dir.entries.insert(name.to_owned(), index);
-                if let Err(_) = self.save_inode(inode, index) {
-                    reply.error(libc::EIO);
-                    return;
-                }
-                if let Err(_) = self.save_dir(parent_dir, parent as u32) {
-                    reply.error(libc::EIO);
-                    return;
-                }
-                match self.find_inode(index) {
-                    Ok(created_inode) => {
-                        reply.created(
-                            &Duration::from_secs(1),
-                            &created_inode.to_attr(index),
-                            0,
-                            0,
-                            0,
-                        );
-                    }
-                    Err(e) => reply.error(e as i32),
-                }
-            }
-            Err(e) => reply.error(e as i32),
+        let blk_size = self.superblock().block_size as u64;
+        let end = DIRECTORY_HEADER_SIZE + header.len as u64;
+        let start = end - bytes.len() as u64;
+        if start.is_multiple_of(blk_size) || end > start.next_multiple_of(blk_size) {
+            return self.save_dir(dir, index);
         }
-    }
 
-    fn write(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        data: &[u8],
-        write_flags: u32,
-        flags: i32,
-        lock_owner: Option<u64>,
-        reply: ReplyWrite,
-    ) {
         debug!(
-            "write: ino={}, fh={}, offset={}, data.len={}, write_flags={:#x}, flags={:#x}, lock_owner={:?}",
-            ino, fh, offset, data.len(), write_flags, flags, lock_owner
+            "add_dir_entry: index={}, name={:?}, start={}",
+            index, name, start
         );
-        let mut inode = match self.find_inode(ino as u32) {
-            Ok(inode) => inode,
-            Err(e) => {
-                reply.error(e as i32);
-                return;
+        self.dcache.invalidate_dir(index);
+        let mut inode = self.find_inode(index)?;
+        inode.update_modified_at();
+
+        let (block, _) = self.find_data_block(index, &mut inode, start, true)?;
+        self.write_data(&bytes, start % blk_size, block)?;
+        let (first, _) = self.find_data_block(index, &mut inode, 0, true)?;
+        self.write_data(&bincode::serialize(&header)?, 0, first)?;
+
+        self.save_inode(inode, index)
+    }
+
+    fn find_inode(&self, index: u32) -> FSResult<Inode> {
+        debug!("find_inode: index={}", index);
+        let (group_index, bitmap_index) = self.inode_offsets(index);
+        if !self
+            .groups()
+            .get(group_index as usize)
+            .unwrap()
+            .has_inode(1 + bitmap_index as usize)
+        {
+            return Err(Errno::ENOENT);
+        }
+        debug!("find_inode: group_index={}", group_index);
+
+        let offset = self.inode_seek_position(index);
+        debug!("find_inode: offset={}", offset);
+        self.check_bounds(offset, INODE_SIZE)?;
+        let buf = self.mmap();
+        let mut cursor = Cursor::new(buf);
+        cursor
+            .seek(SeekFrom::Start(offset))
+            .inspect_err(|e| debug!("seek failed {}", e))
+            .unwrap();
+
+        let inode = Inode::deserialize_from(cursor).map_err(|_e| Errno::EIO)?;
+        debug!("find_inode: inode={:?}", inode);
+        Ok(inode)
+    }
+
+    fn find_inode_from_path<P>(&self, path: P) -> FSResult<(Inode, u32)>
+    where
+        P: AsRef<Path>,
+    {
+        match path.as_ref().parent() {
+            None => Ok((self.find_inode(ROOT_INODE)?, ROOT_INODE)),
+            Some(parent) => {
+                let (parent, _) = self.find_dir(parent)?;
+                let index = parent.entry(
+                    path.as_ref()
+                        .file_name()
+                        .ok_or(Errno::EINVAL)?
+                        .to_os_string(),
+                )?;
+                Ok((self.find_inode(index)?, index))
             }
-        };
+        }
+    }
 
-        let mut total_wrote = 0;
-        let overwrite = inode.size > offset as u64;
-        let mut current_offset = offset as u64;
-        let blk_size = self.superblock().block_size;
+    fn find_dir<P>(&self, path: P) -> FSResult<(Directory, u32)>
+    where
+        P: AsRef<Path>,
+    {
+        let mut current = self.find_dir_from_inode(ROOT_INODE)?;
+        let mut index = ROOT_INODE;
+        for c in path.as_ref().components().skip(1) {
+            index = current.entry(c)?;
+            current = self.find_dir_from_inode(index)?;
+        }
 
-        while total_wrote != data.len() {
-            let direct_block_index = current_offset / blk_size as u64;
-            let (block_index, space_left) =
-                match self.find_data_block(&mut inode, current_offset, false) {
-                    Ok(result) => result,
-                    Err(e) => {
-                        reply.error(e as i32);
-                        return;
-                    }
-                };
+        Ok((current, index))
+    }
 
-            let max_write_len = data.len().min(space_left as usize);
-            let offset_in_block = if total_wrote != 0 {
-                0
-            } else {
-                current_offset - direct_block_index * blk_size as u64
-            };
+    /// Find the child named `name` of the `parent` directory, answering from the dcache when
+    /// it was looked up less than [`ENTRY_TTL`] ago
+    fn lookup_entry(&mut self, parent: u32, name: &OsStr) -> FSResult<(u32, FileAttr)> {
+        if let Some(entry) = self.dcache.get(parent, name) {
+            return Ok(entry);
+        }
 
-            let wrote = match self.write_data(
-                &data[total_wrote..data.len().min(max_write_len + total_wrote)],
-                offset_in_block,
-                block_index,
-            ) {
-                Ok(wrote) => wrote,
-                Err(_) => {
-                    reply.error(libc::EIO);
-                    return;
-                }
-            };
+        let index = self.find_dir_from_inode(parent)?.entry(name)?;
+        let attr = self.find_inode(index)?.to_attr(index);
+        self.dcache.insert(parent, name, index, attr);
 
-            total_wrote += wrote;
-            current_offset += wrote as u64;
+        Ok((index, attr))
+    }
+
+    /// Entries of the directory at `index`, `.` and `..` first, with the attributes of their
+    /// inodes so `readdir` and `readdirplus` read every child inode only once
+    fn dir_entries(&self, index: u32) -> FSResult<Vec<(OsString, FileAttr)>> {
+        let dir = self.find_dir_from_inode(index)?;
+        let links = [
+            (OsString::from("."), index),
+            (OsString::from(".."), dir.parent),
+        ];
+
+        let mut entries = Vec::with_capacity(links.len() + dir.entries.len());
+        for (name, index) in links.into_iter().chain(dir.entries) {
+            if let Ok(inode) = self.find_inode(index) {
+                entries.push((name, inode.to_attr(index)));
+            }
         }
 
-        inode.update_modified_at();
-        if overwrite {
-            inode.adjust_size(total_wrote as u64);
-        } else {
-            inode.increment_size(total_wrote as u64);
+        Ok(entries)
+    }
+
+    fn find_dir_from_inode(&self, index: u32) -> FSResult<Directory> {
+        debug!("find_dir_from_inode: index={}", index);
+        let inode = self.find_inode(index)?;
+        if !inode.is_dir() {
+            return Err(Errno::ENOTDIR);
         }
 
-        if let Err(_) = self.save_inode(inode, ino as u32) {
-            reply.error(libc::EIO);
-            return;
+        let data = self.read_dir_data(&inode)?;
+        Directory::deserialize_from(data.as_slice()).map_err(|_| Errno::EIO)
+    }
+
+    /// Read every data block of the directory `inode`, its serialized entries span them one
+    /// after another
+    fn read_dir_data(&self, inode: &Inode) -> FSResult<Vec<u8>> {
+        let blk_size = self.superblock().block_size as usize;
+        let blocks = self.data_blocks(inode).map_err(|_| Errno::EIO)?;
+        let mut data = vec![0u8; blocks.len() * blk_size];
+        for (block, chunk) in blocks.into_iter().zip(data.chunks_mut(blk_size)) {
+            let (group_index, block_index) = self.data_block_offsets(block);
+            if !self
+                .groups()
+                .get(group_index as usize)
+                .unwrap()
+                .has_data_block(1 + block_index as usize)
+            {
+                return Err(Errno::ENOENT);
+            }
+
+            self.read_data(chunk, 0, block).map_err(|_| Errno::EIO)?;
         }
 
-        debug!("wrote {} bytes", total_wrote);
+        Ok(data)
+    }
+
+    /// Every data block of the inode in the order of the file, without the indirect blocks
+    /// pointing to them
+    fn data_blocks(&self, inode: &Inode) -> anyhow::Result<Vec<u32>> {
+        let mut blocks = inode.direct_blocks();
+        if inode.indirect_block != 0 {
+            blocks.append(&mut self.read_indirect_block(inode.indirect_block)?);
+        }
+        if inode.double_indirect_block != 0 {
+            for indirect_block in self.read_indirect_block(inode.double_indirect_block)? {
+                blocks.append(&mut self.read_indirect_block(indirect_block)?);
+            }
+        }
 
-        reply.written(total_wrote as u32);
+        Ok(blocks)
     }
 
-    fn read(
+    /// Find the data block of the `ino` inode holding `offset`, allocating it unless `read`
+    fn find_data_block(
         &mut self,
-        _req: &Request,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        size: u32,
-        flags: i32,
-        lock_owner: Option<u64>,
-        reply: ReplyData,
-    ) {
-        debug!(
-            "read: ino={}, fh={}, offset={}, size={}, flags={:#x}, lock_owner={:?}",
-            ino, fh, offset, size, flags, lock_owner
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        read: bool,
+    ) -> FSResult<(u32, u32)> {
+        let blk_size = self.superblock().block_size as u64;
+        let index = offset / blk_size;
+
+        let pointers_per_block = blk_size / mem::size_of::<u32>() as u64;
+
+        let block = if index < DIRECT_POINTERS {
+            inode.find_direct_block(index as usize)
+        } else if index < (pointers_per_block + DIRECT_POINTERS) {
+            self.find_indirect(
+                inode.indirect_block,
+                index - DIRECT_POINTERS,
+                offset,
+                pointers_per_block,
+            )
+            .map_err(|_| Errno::EIO)?
+        } else if index
+            < (pointers_per_block * pointers_per_block + pointers_per_block + DIRECT_POINTERS)
+        {
+            self.find_indirect(
+                inode.double_indirect_block,
+                index - DIRECT_POINTERS,
+                offset,
+                pointers_per_block,
+            )
+            .map_err(|_| Errno::EIO)?
+        } else {
+            return Err(Errno::ENOSPC.into());
+        };
+
+        if block != 0 {
+            return Ok((block, ((index + 1) * blk_size - offset) as u32));
+        }
+
+        if read {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let mut block = self.allocate_data_block(ino).ok_or(Errno::ENOSPC)?;
+        if index < DIRECT_POINTERS {
+            inode
+                .add_block(block, index as usize)
+                .map_err(|_| Errno::ENOSPC)?;
+        } else if index < (pointers_per_block + DIRECT_POINTERS) {
+            if inode.indirect_block == 0 {
+                inode.indirect_block = block;
+                self.write_data(&vec![0u8; blk_size as usize], 0, block)
+                    .map_err(|_| Errno::EIO)?;
+                block = self.allocate_data_block(ino).ok_or(Errno::ENOSPC)?;
+            }
+
+            self.save_indirect(
+                inode.indirect_block,
+                block,
+                index - DIRECT_POINTERS,
+                pointers_per_block,
+            )
+            .map_err(|_| Errno::EIO)?;
+        } else if index
+            < (pointers_per_block * pointers_per_block + pointers_per_block + DIRECT_POINTERS)
+        {
+            if inode.double_indirect_block == 0 {
+                inode.double_indirect_block = block;
+                self.write_data(&vec![0u8; blk_size as usize], 0, block)
+                    .map_err(|_| Errno::EIO)?;
+                block = self.allocate_data_block(ino).ok_or(Errno::ENOSPC)?;
+            }
+
+            let indirect_offset = (index - DIRECT_POINTERS) / pointers_per_block - 1;
+            let indirect_block = match self
+                .find_indirect(
+                    inode.double_indirect_block,
+                    indirect_offset,
+                    0,
+                    pointers_per_block,
+                )
+                .map_err(|_| Errno::EIO)?
+            {
+                0 => {
+                    let indirect_block = block;
+                    self.save_indirect(
+                        inode.double_indirect_block,
+                        block,
+                        indirect_offset,
+                        pointers_per_block,
+                    )
+                    .map_err(|_| Errno::EIO)?;
+                    self.write_data(&vec![0u8; blk_size as usize], 0, block)
+                        .map_err(|_| Errno::EIO)?;
+                    block = self.allocate_data_block(ino).ok_or(Errno::ENOSPC)?;
+                    indirect_block
+                }
+                indirect_block => indirect_block,
+            };
+
+            self.save_indirect(
+                indirect_block,
+                block,
+                (index - DIRECT_POINTERS) & (pointers_per_block - 1),
+                pointers_per_block,
+            )
+            .map_err(|_| Errno::EIO)?;
+        } else {
+            return Err(Errno::ENOSPC.into());
+        }
+
+        Ok((block, blk_size as u32))
+    }
+
+    /// Grow or shrink the `ino` file to `size` bytes, zeroing the bytes a grown file gains since
+    /// a shrunk one leaves its old data in the last block
+    fn resize(&mut self, ino: u32, inode: &mut Inode, size: u64) -> FSResult<()> {
+        let blk_size = self.superblock().block_size as u64;
+        if size < inode.size {
+            self.release_blocks_from(inode, size.div_ceil(blk_size))
+                .map_err(|_| Errno::EIO)?;
+        }
+
+        let mut offset = inode.size;
+        while offset < size {
+            let (block_index, _) = self.find_data_block(ino, inode, offset, false)?;
+            let len = (blk_size - offset % blk_size).min(size - offset);
+            self.write_data(&vec![0u8; len as usize], offset % blk_size, block_index)
+                .map_err(|_| Errno::EIO)?;
+            offset += len;
+        }
+
+        inode.set_size(size);
+        inode.update_modified_at();
+
+        Ok(())
+    }
+
+    fn find_indirect(
+        &self,
+        pointer: u32,
+        index: u64,
+        offset: u64,
+        pointers_per_block: u64,
+    ) -> anyhow::Result<u32> {
+        if pointer == 0 {
+            return Ok(pointer);
+        }
+
+        let off = if index < pointers_per_block {
+            index & (pointers_per_block - 1)
+        } else {
+            index / pointers_per_block - 1
+        };
+
+        let block = self.read_u32(off, pointer)?;
+
+        if block == 0 || index < pointers_per_block {
+            return Ok(block);
+        }
+
+        self.find_indirect(
+            block,
+            index & (pointers_per_block - 1),
+            offset,
+            pointers_per_block,
+        )
+    }
+
+    fn save_indirect(
+        &mut self,
+        pointer: u32,
+        block: u32,
+        index: u64,
+        pointers_per_block: u64,
+    ) -> anyhow::Result<()> {
+        assert_ne!(pointer, 0);
+        let offset = index & (pointers_per_block - 1);
+
+        if index < pointers_per_block {
+            self.write_data(&block.to_le_bytes(), offset * 4, pointer)
+                .map(|_| ())
+        } else {
+            let indirect_offset = index / pointers_per_block - 1;
+            let new_pointer = self.read_u32(indirect_offset, pointer)?;
+            self.save_indirect(new_pointer, block, offset, pointers_per_block)
+        }
+    }
+
+    // (group_block_index, bitmap_index)
+    fn inode_offsets(&self, index: u32) -> (u64, u64) {
+        let inodes_per_group = self.superblock().inodes_per_group() as u64;
+        let inode_bg = (index as u64 - 1) / inodes_per_group;
+        let bitmap_index = (index as u64 - 1) % inodes_per_group;
+        (inode_bg, bitmap_index)
+    }
+
+    fn inode_seek_position(&self, index: u32) -> u64 {
+        self.layout().inode_offset(index)
+    }
+
+    fn data_block_offsets(&self, index: u32) -> (u64, u64) {
+        let data_blocks_per_group = self.superblock().data_blocks_per_group as u64;
+        let group_index = (index as u64 - 1) / data_blocks_per_group;
+        let block_index = (index as u64 - 1) & (data_blocks_per_group - 1);
+
+        (group_index, block_index)
+    }
+
+    fn data_block_seek_position(&self, index: u32) -> u64 {
+        self.layout().data_block_offset(index)
+    }
+
+    /// Absolute position of `len` bytes at `offset` inside the data block, failing with `EIO`
+    /// when a corrupted block pointer would reach outside of the disk
+    fn checked_data_position(&self, block_index: u32, offset: u64, len: usize) -> FSResult<u64> {
+        if block_index == 0 {
+            warn!("data block pointer is null");
+            return Err(Errno::EIO);
+        }
+
+        let position = self.data_block_seek_position(block_index) + offset;
+        self.check_bounds(position, len as u64)?;
+        Ok(position)
+    }
+
+    /// Fail with `EIO` unless the `len` bytes starting at `position` are inside the disk
+    fn check_bounds(&self, position: u64, len: u64) -> FSResult<()> {
+        let disk_len = self.mmap().len() as u64;
+        match position.checked_add(len) {
+            Some(end) if end <= disk_len => Ok(()),
+            _ => {
+                warn!(
+                    "position {} with length {} is past the disk of {} bytes",
+                    position, len, disk_len
+                );
+                Err(Errno::EIO)
+            }
+        }
+    }
+
+    fn allocate_inode(&mut self) -> Option<u32> {
+        // TODO: handle when group has run out of space
+        let group_index = self.groups().iter().position(|g| g.free_inodes() > 0)?;
+        self.superblock_mut().free_inodes -= 1;
+        let group = self.groups_mut().get_mut(group_index).unwrap();
+
+        let index = group.allocate_inode()?;
+        Some(index as u32 + group_index as u32 * self.superblock().inodes_per_group())
+    }
+
+    /// Allocate a data block in the group of the `ino` inode so its blocks stay close together,
+    /// or in the next group with space
+    fn allocate_data_block(&mut self, ino: u32) -> Option<u32> {
+        let groups = self.groups().len();
+        let goal = self.inode_offsets(ino).0 as usize;
+        let group_index = (goal..groups)
+            .chain(0..goal)
+            .find(|&i| self.groups()[i].free_data_blocks() > 0)?;
+
+        self.superblock_mut().free_blocks -= 1;
+        let group = self.groups_mut().get_mut(group_index).unwrap();
+
+        let index = group.allocate_data_block()?;
+        Some(index as u32 + group_index as u32 * self.superblock().data_blocks_per_group)
+    }
+
+    fn release_data_blocks(&mut self, blocks: &[u32]) {
+        for block in blocks {
+            let (group_index, block_index) = self.data_block_offsets(*block);
+            // TODO: release multiple blocks from the same group in a single call
+            self.groups_mut()
+                .get_mut(group_index as usize)
+                .unwrap()
+                .release_data_block(1 + block_index as usize);
+        }
+        self.superblock_mut().free_blocks += blocks.len() as u32;
+    }
+
+    fn release_inode(&mut self, index: u32) {
+        let (group_index, bitmap_index) = self.inode_offsets(index);
+        self.groups_mut()
+            .get_mut(group_index as usize)
+            .unwrap()
+            .release_inode(1 + bitmap_index as usize);
+        self.superblock_mut().free_inodes += 1;
+    }
+
+    /// Release every data block referenced by the inode
+    fn release_blocks(&mut self, inode: &Inode) -> anyhow::Result<()> {
+        self.release_data_blocks(&inode.direct_blocks());
+        if inode.indirect_block != 0 {
+            self.release_indirect_block(inode.indirect_block)?;
+        }
+        if inode.double_indirect_block != 0 {
+            self.release_double_indirect_block(inode.double_indirect_block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Release the data blocks of the inode past the first `keep` ones, along with the indirect
+    /// blocks left without any pointer
+    fn release_blocks_from(&mut self, inode: &mut Inode, keep: u64) -> anyhow::Result<()> {
+        let pointers_per_block = self.superblock().block_size as u64 / mem::size_of::<u32>() as u64;
+
+        for index in keep.min(DIRECT_POINTERS)..DIRECT_POINTERS {
+            let block = mem::take(&mut inode.direct_blocks[index as usize]);
+            if block != 0 {
+                self.release_data_blocks(&[block]);
+            }
+        }
+
+        let keep = keep.saturating_sub(DIRECT_POINTERS);
+        if inode.indirect_block != 0 {
+            self.release_pointers_from(inode.indirect_block, keep.min(pointers_per_block))?;
+            if keep == 0 {
+                self.release_data_blocks(&[mem::take(&mut inode.indirect_block)]);
+            }
+        }
+
+        // Each pointer of the double indirect block covers the blocks of one indirect block
+        let keep = keep.saturating_sub(pointers_per_block);
+        if inode.double_indirect_block != 0 {
+            for slot in 0..pointers_per_block {
+                let indirect_block = self.read_u32(slot, inode.double_indirect_block)?;
+                if indirect_block == 0 {
+                    continue;
+                }
+
+                let kept = keep
+                    .saturating_sub(slot * pointers_per_block)
+                    .min(pointers_per_block);
+                self.release_pointers_from(indirect_block, kept)?;
+                if kept == 0 {
+                    self.release_data_blocks(&[indirect_block]);
+                    self.write_data(&0u32.to_le_bytes(), slot * 4, inode.double_indirect_block)?;
+                }
+            }
+
+            if keep == 0 {
+                self.release_data_blocks(&[mem::take(&mut inode.double_indirect_block)]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Release the blocks pointed to by the `pointer` block from its `first` pointer on, clearing
+    /// those pointers
+    fn release_pointers_from(&mut self, pointer: u32, first: u64) -> anyhow::Result<()> {
+        let pointers_per_block = self.superblock().block_size as u64 / mem::size_of::<u32>() as u64;
+        for slot in first..pointers_per_block {
+            let block = self.read_u32(slot, pointer)?;
+            if block != 0 {
+                self.release_data_blocks(&[block]);
+                self.write_data(&0u32.to_le_bytes(), slot * 4, pointer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn release_indirect_block(&mut self, block: u32) -> anyhow::Result<()> {
+        let blocks = self.read_indirect_block(block)?;
+        self.release_data_blocks(&blocks);
+        Ok(())
+    }
+
+    fn release_double_indirect_block(&mut self, block: u32) -> anyhow::Result<()> {
+        let pointers_per_block = self.superblock().block_size as usize / 4;
+        let indirect_blocks = self.read_indirect_block(block)?;
+        let mut blocks = Vec::with_capacity(indirect_blocks.len() * pointers_per_block);
+        for b in indirect_blocks.iter().filter(|x| **x != 0) {
+            blocks.append(&mut self.read_indirect_block(*b)?);
+        }
+
+        self.release_data_blocks(&indirect_blocks);
+        self.release_data_blocks(&blocks);
+
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8], offset: u64, block_index: u32) -> anyhow::Result<usize> {
+        let position = self.checked_data_position(block_index, offset, data.len())?;
+
+        let buf = self.mmap_mut().as_mut();
+        let mut cursor = Cursor::new(buf);
+        cursor.seek(SeekFrom::Start(position))?;
+        Ok(cursor.write(data)?)
+    }
+
+    fn read_data(&self, data: &mut [u8], offset: u64, block_index: u32) -> anyhow::Result<usize> {
+        let position = self.checked_data_position(block_index, offset, data.len())?;
+        let buf = self.mmap().as_ref();
+        let mut cursor = Cursor::new(buf);
+        cursor.seek(SeekFrom::Start(position))?;
+
+        cursor.read_exact(data)?;
+
+        Ok(data.len())
+    }
+
+    fn read_u32(&self, offset: u64, block_index: u32) -> anyhow::Result<u32> {
+        let mut data = [0u8; 4];
+        self.read_data(&mut data, offset * 4, block_index)?;
+        Ok(u32::from_le_bytes(data))
+    }
+
+    fn read_indirect_block(&self, block: u32) -> anyhow::Result<Vec<u32>> {
+        let pointers_per_block = self.superblock().block_size as usize / 4;
+        let mut vec = Vec::with_capacity(pointers_per_block);
+        for i in 0..pointers_per_block {
+            let b = self.read_u32(i as u64, block)?;
+            if b != 0 {
+                vec.push(b);
+            }
+        }
+
+        Ok(vec)
+    }
+
+    fn groups(&self) -> &[Group] {
+        self.groups
+            .as_ref()
+            .expect("expected to get reference to group")
+    }
+
+    fn groups_mut(&mut self) -> &mut [Group] {
+        self.groups.as_mut().unwrap()
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::new(self.superblock().block_size)
+    }
+
+    fn superblock(&self) -> &Superblock {
+        self.sb.as_ref().unwrap()
+    }
+
+    /// Reply of a `FERRIX_IOC_INODE` ioctl on `ino` or of a `FERRIX_IOC_SUPERBLOCK` one, the
+    /// bincode encoded structure as it is on the disk
+    pub fn debug_reply(&self, ino: u64, cmd: u32) -> FSResult<Vec<u8>> {
+        let data = match cmd {
+            FERRIX_IOC_INODE => bincode::serialize(&self.find_inode(ino as u32)?),
+            FERRIX_IOC_SUPERBLOCK => bincode::serialize(self.superblock()),
+            _ => return Err(Errno::ENOTTY),
+        };
+
+        let data = data.map_err(|_| Errno::EIO)?;
+        if data.len() > DEBUG_REPLY_SIZE as usize {
+            return Err(Errno::ERANGE);
+        }
+
+        Ok(data)
+    }
+
+    fn superblock_mut(&mut self) -> &mut Superblock {
+        self.sb.as_mut().unwrap()
+    }
+
+    fn mmap(&self) -> &MmapMut {
+        self.mmap.as_ref().unwrap()
+    }
+
+    fn mmap_mut(&mut self) -> &mut MmapMut {
+        self.mmap.as_mut().unwrap()
+    }
+}
+
+impl Filesystem for SimpleExt4FS {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        debug!("lookup: parent={}, name={:?}", parent, name);
+        match self.lookup_entry(parent as u32, name) {
+            Ok((_, attr)) => reply.entry(&ENTRY_TTL, &attr, 0),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
+        let sb = self.superblock();
+        reply.statfs(
+            sb.block_count.into(),
+            sb.free_blocks.into(),
+            sb.free_blocks.into(),
+            sb.inode_count.into(),
+            sb.free_inodes.into(),
+            sb.block_size,
+            255,
+            sb.block_size,
+        );
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, fh: Option<u64>, reply: ReplyAttr) {
+        debug!("getattr: ino={}, fh={:?}", ino, fh);
+        match self.find_inode(ino as u32) {
+            Ok(inode) => {
+                reply.attr(&ENTRY_TTL, &inode.to_attr(ino as u32));
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        debug!("readdir: ino={}, fh={}, offset={}", ino, fh, offset);
+        match self.dir_entries(ino as u32) {
+            Ok(entries) => {
+                for (i, (name, attr)) in entries.into_iter().enumerate().skip(offset as usize) {
+                    if reply.add(attr.ino, (i + 1) as i64, attr.kind, name) {
+                        break;
+                    }
+                }
+                reply.ok();
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn readdirplus(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectoryPlus,
+    ) {
+        debug!("readdirplus: ino={}, fh={}, offset={}", ino, fh, offset);
+        match self.dir_entries(ino as u32) {
+            Ok(entries) => {
+                for (i, (name, attr)) in entries.into_iter().enumerate().skip(offset as usize) {
+                    if reply.add(attr.ino, (i + 1) as i64, name, &ENTRY_TTL, &attr, 0) {
+                        break;
+                    }
+                }
+                reply.ok();
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn create(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        debug!(
+            "create: parent={}, name={:?}, mode={:#o}, umask={:#o}, flags={:#x}",
+            parent, name, mode, umask, flags
+        );
+        let created = self
+            .create_file(parent as u32, name, mode, flags, req.uid(), req.gid())
+            .and_then(|(created_inode, index)| {
+                let fh = self.open_handle(index as u64, flags)?;
+                Ok((created_inode, index, fh))
+            });
+        match created {
+            Ok((created_inode, index, fh)) => {
+                reply.created(&ENTRY_TTL, &created_inode.to_attr(index), 0, fh, 0);
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        write_flags: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        debug!(
+            "write: ino={}, fh={}, offset={}, data.len={}, write_flags={:#x}, flags={:#x}, lock_owner={:?}",
+            ino, fh, offset, data.len(), write_flags, flags, lock_owner
+        );
+        match self.write_file(ino, fh, offset, data) {
+            Ok(wrote) => reply.written(wrote),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        debug!(
+            "read: ino={}, fh={}, offset={}, size={}, flags={:#x}, lock_owner={:?}",
+            ino, fh, offset, size, flags, lock_owner
+        );
+        match self.read_file(ino, fh, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        debug!("open: ino={}, flags={:#x}", ino, flags);
+        match self.open_handle(ino, flags) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        debug!("release: ino={}, fh={}", ino, fh);
+        match self.release_handle(fh) {
+            Ok(_) => reply.ok(),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn opendir(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        debug!("opendir: ino={}, flags={:#x}", ino, flags);
+        if let Err(e) = self.find_dir_from_inode(ino as u32) {
+            reply.error(e as i32);
+            return;
+        }
+
+        match self.open_handle(ino, flags) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        debug!("releasedir: ino={}, fh={}", ino, fh);
+        match self.release_handle(fh) {
+            Ok(_) => reply.ok(),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        match self.find_inode(ino as u32) {
+            Ok(attr) => {
+                if check_access(
+                    attr.user_id,
+                    attr.group_id,
+                    attr.mode.try_into().unwrap(),
+                    req.uid(),
+                    req.gid(),
+                    mask,
+                ) {
+                    reply.ok();
+                } else {
+                    reply.error(libc::EACCES);
+                }
+            }
+            Err(error_code) => reply.error(error_code as i32),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        debug!(
+            "mkdir: parent={}, name={:?}, mode={:#o}, umask={:#o}",
+            parent, name, mode, umask
+        );
+        match self.create_dir(parent as u32, name, mode, req.uid(), req.gid()) {
+            Ok((created_inode, index)) => {
+                reply.entry(&ENTRY_TTL, &created_inode.to_attr(index), 0);
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        debug!(
+            "symlink: parent={}, link_name={:?}, target={:?}",
+            parent, link_name, target
+        );
+        match self.create_symlink(parent as u32, link_name, target, req.uid(), req.gid()) {
+            Ok((created_inode, index)) => {
+                reply.entry(&ENTRY_TTL, &created_inode.to_attr(index), 0);
+            }
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn link(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        debug!(
+            "link: ino={}, newparent={}, newname={:?}",
+            ino, newparent, newname
+        );
+        match self.create_link(ino as u32, newparent as u32, newname) {
+            Ok((inode, index)) => reply.entry(&ENTRY_TTL, &inode.to_attr(index), 0),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        debug!("readlink: ino={}", ino);
+        match self.read_symlink(ino as u32) {
+            Ok(target) => reply.data(&target),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("unlink: parent={}, name={:?}", parent, name);
+        match self.remove_file(parent as u32, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        debug!(
+            "setattr: ino={}, mode={:?}, uid={:?}, gid={:?}, size={:?}",
+            ino, mode, uid, gid, size
+        );
+        let attr = SetAttr {
+            mode,
+            uid,
+            gid,
+            size,
+            atime,
+            mtime,
+            ctime,
+        };
+
+        match self.set_attr(ino as u32, attr) {
+            Ok(attr) => reply.attr(&ENTRY_TTL, &attr),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("rmdir: parent={}, name={:?}", parent, name);
+        match self.remove_dir(parent as u32, name) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        debug!(
+            "rename: parent={}, name={:?}, newparent={}, newname={:?}",
+            parent, name, newparent, newname
+        );
+        // Neither RENAME_NOREPLACE nor RENAME_EXCHANGE are supported
+        if flags != 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+
+        match self.rename(parent as u32, name, newparent as u32, newname) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(e as i32),
+        }
+    }
+
+    fn ioctl(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        debug!(
+            "ioctl: ino={}, fh={}, flags={:#x}, cmd={:#x}, in_data.len={}, out_size={}",
+            ino,
+            fh,
+            flags,
+            cmd,
+            in_data.len(),
+            out_size
+        );
+        match cmd {
+            FERRIX_IOC_SCRUB => {
+                let report = self.scrub();
+                match bincode::serialize(&report) {
+                    Ok(data) => reply.ioctl(0, &data),
+                    Err(_) => reply.error(libc::EIO),
+                }
+            }
+            FERRIX_IOC_HANDLES => match bincode::serialize(&self.open_handles()) {
+                Ok(data) if data.len() > out_size as usize => reply.error(libc::ERANGE),
+                Ok(data) => reply.ioctl(0, &data),
+                Err(_) => reply.error(libc::EIO),
+            },
+            FERRIX_IOC_INODE | FERRIX_IOC_SUPERBLOCK => match self.debug_reply(ino, cmd) {
+                Ok(data) => reply.ioctl(0, &data),
+                Err(e) => reply.error(e as i32),
+            },
+            FS_IOC_GETFLAGS => match self.flags(ino) {
+                Ok(flags) => reply.ioctl(0, &flags.to_ne_bytes()),
+                Err(e) => reply.error(e as i32),
+            },
+            FS_IOC_SETFLAGS => {
+                let Some(flags) = in_data.get(..4) else {
+                    reply.error(libc::EINVAL);
+                    return;
+                };
+                let flags = u32::from_ne_bytes(flags.try_into().unwrap());
+                match self.set_flags(ino, flags, req.uid()) {
+                    Ok(()) => reply.ioctl(0, &[]),
+                    Err(e) => reply.error(e as i32),
+                }
+            }
+            _ => reply.error(libc::ENOTTY),
+        }
+    }
+
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
+        debug!("init: kernel_config={:?}", config);
+        self.mark_mounted();
+
+        // Older kernels without READDIRPLUS keep sending readdir and a lookup per entry, and
+        // the debug ioctls are sent to the mount root so they need ioctls on directories
+        let capabilities = consts::FUSE_DO_READDIRPLUS | consts::FUSE_HAS_IOCTL_DIR;
+        if let Err(unsupported) = config.add_capabilities(capabilities) {
+            debug!("init: unsupported capabilities={:#x}", unsupported);
+        }
+
+        Ok(())
+    }
+
+    fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        debug!("fsync: ino={}, fh={}, datasync={}", ino, fh, datasync);
+        match self.sync() {
+            Ok(()) => reply.ok(),
+            Err(e) => {
+                warn!("fsync failed: {e:?}");
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn destroy(&mut self) {
+        debug!("destroy called");
+        if let Err(e) = self.sync() {
+            warn!("sync on destroy failed: {e:?}");
+        }
+
+        self.mmap = None;
+        debug!("destroyed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        simple_ext4::mkfs,
+        simple_ext4::{types::Superblock, INODE_SIZE, ROOT_INODE, SUPERBLOCK_SIZE},
+    };
+    use bitvec::vec::BitVec;
+    use fuser::{
+        FileAttr, FileType, Filesystem, Reply, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+        ReplyWrite, Request,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+    use std::{ffi::OsString, path::PathBuf};
+
+    const BLOCK_SIZE: u32 = 128;
+
+    #[test]
+    fn inode_offsets() {
+        let mut fs = SimpleExt4FS::default();
+        fs.sb = Some(Superblock::new(1024, 3, 0, 0));
+        fs.superblock_mut().data_blocks_per_group = 1024 * 8;
+
+        let (group_index, offset) = fs.inode_offsets(1);
+        assert_eq!(group_index, 0);
+        assert_eq!(offset, 0);
+
+        let (group_index, offset) = fs.inode_offsets(1024 * 8);
+        assert_eq!(group_index, 0);
+        assert_eq!(offset, 8191);
+
+        let (group_index, offset) = fs.inode_offsets(1024 * 8 - 1);
+        assert_eq!(group_index, 0);
+        assert_eq!(offset, 8190);
+
+        let (group_index, offset) = fs.inode_offsets(2 * 1024 * 8 - 1);
+        assert_eq!(group_index, 1);
+        assert_eq!(offset, 8190);
+    }
+
+    #[test]
+    fn inode_seek_position() {
+        let mut fs = SimpleExt4FS::default();
+        fs.sb = Some(Superblock::new(1024, 3, 0, 0));
+        fs.superblock_mut().data_blocks_per_group = 1024 * 8;
+
+        let offset = fs.inode_seek_position(1);
+        assert_eq!(3072, offset);
+
+        let offset = fs.inode_seek_position(2);
+        assert_eq!(3072 + INODE_SIZE, offset);
+
+        let offset = fs.inode_seek_position(8192);
+        assert_eq!(3072 + 8191 * INODE_SIZE, offset); // superblock + data bitmap + inode bitmap + 8191 inodes
+
+        let offset = fs.inode_seek_position(8193);
+        assert_eq!(3072 + 8192 * INODE_SIZE + 1024 * 1024 * 8 + 2048, offset); // superblock + data bitmap + inode bitmap + inode table + data blocks + data bitmap + inode bitmap
+    }
+
+    // #[test]
+    // fn new_fs() -> anyhow::Result<()> {
+    //     let tmp_file = make_fs("new_fs")?;
+    //     let fs = SimpleExt4FS::new(&tmp_file)?;
+    //     let inode = fs.find_inode(ROOT_INODE)?;
+    //
+    //     assert_eq!(inode.mode, SFlag::S_IFDIR.bits() | 0o777);
+    //     assert_eq!(inode.hard_links, 2);
+    //
+    //     assert!(fs.groups().first().unwrap().has_inode(ROOT_INODE as _));
+    //     assert!(fs.groups().first().unwrap().has_data_block(ROOT_INODE as _));
+    //
+    //     assert_eq!(fs.superblock().groups, fs.groups().len() as u32);
+    //     assert_eq!(fs.superblock().free_inodes, BLOCK_SIZE * 8 - 1);
+    //     assert_eq!(fs.superblock().free_blocks, BLOCK_SIZE * 8 - 1);
+    //
+    //     Ok(std::fs::remove_file(&tmp_file)?)
+    // }
+
+    // #[test]
+    // fn init_destroy() -> anyhow::Result<()> {
+    //     let tmp_file = make_fs("init_destroy")?;
+    //     let fs = SimpleExt4FS::new(&tmp_file)?;
+    //     let tmp_dir = tempfile::tempdir()?.path().join("init_destroy");
+    //     fs::create_dir_all(&tmp_dir)?;
+    //
+    //     assert_eq!(fs.superblock().last_mounted_at, None);
+    //
+    //     let fs = SimpleExt4FS::new(&tmp_file)?;
+    //
+    //     assert_ne!(fs.superblock().last_mounted_at, None);
+    //     assert_eq!(fs.superblock().free_inodes, BLOCK_SIZE * 8 - 1);
+    //     assert_eq!(fs.superblock().free_blocks, BLOCK_SIZE * 8 - 1);
+    //
+    //     Ok(std::fs::remove_file(&tmp_file)?)
+    // }
+
+    #[test]
+    fn data_block_seek_position() {
+        let mut fs = SimpleExt4FS::default();
+        let block_size = 1024;
+        fs.sb = Some(Superblock::new(block_size, 3, 0, 0));
+        fs.superblock_mut().data_blocks_per_group = block_size as u32 * 8;
+
+        let prefix = SUPERBLOCK_SIZE + 2 * block_size as u64 + block_size as u64 * INODE_SIZE * 8;
+        let offset = fs.data_block_seek_position(1);
+        assert_eq!(prefix, offset);
+
+        let offset = fs.data_block_seek_position(2);
+        assert_eq!(prefix + block_size as u64, offset);
+
+        let offset = fs.data_block_seek_position(8192);
+        assert_eq!(prefix + 8191 * block_size as u64, offset);
+
+        let offset = fs.data_block_seek_position(8193);
+        assert_eq!(
+            2 * prefix - SUPERBLOCK_SIZE + (block_size * block_size) as u64 * 8,
+            offset
+        );
+    }
+
+    #[test]
+    fn save_dir() -> anyhow::Result<()> {
+        let tmp_file = make_fs("save_dir")?;
+        let fs = SimpleExt4FS::new(&tmp_file)?;
+        let dir = fs.find_dir_from_inode(ROOT_INODE)?;
+
+        assert_eq!(dir.entries.len(), 0);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn dir_spans_blocks() -> anyhow::Result<()> {
+        let tmp_file = make_fs("dir_spans_blocks")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let free_blocks = fs.superblock().free_blocks;
+        let names: Vec<_> = (0..40)
+            .map(|i| OsString::from(format!("a-file-with-a-long-name-{i:02}")))
+            .collect();
+
+        for name in &names {
+            fs.create_file(ROOT_INODE, name, 0o644, 0, 0, 0)?;
+        }
+        let root = fs.find_inode(ROOT_INODE)?;
+        assert!(fs.data_blocks(&root)?.len() > DIRECT_POINTERS as usize);
+        let dir = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert_eq!(
+            dir.entries.keys().collect::<Vec<_>>(),
+            names.iter().collect::<Vec<_>>()
+        );
+
+        // Emptying the directory shrinks it back to a single block
+        for name in &names {
+            fs.remove_file(ROOT_INODE, name)?;
+        }
+        let root = fs.find_inode(ROOT_INODE)?;
+        assert_eq!(fs.data_blocks(&root)?.len(), 1);
+        assert_eq!(root.indirect_block, 0);
+        assert!(fs.find_dir_from_inode(ROOT_INODE)?.entries.is_empty());
+        assert_eq!(fs.superblock().free_blocks, free_blocks);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn dir_entries_appended_in_order() -> anyhow::Result<()> {
+        let tmp_file = make_fs("dir_entries_appended_in_order")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+
+        fs.create_file(ROOT_INODE, OsStr::new("b"), 0o644, 0, 0, 0)?;
+        fs.create_file(ROOT_INODE, OsStr::new("a"), 0o644, 0, 0, 0)?;
+        let data = fs.read_dir_data(&fs.find_inode(ROOT_INODE)?)?;
+        // Each entry starts with its serialized name
+        let position = |name: &str| {
+            let entry = bincode::serialize(OsStr::new(name)).unwrap();
+            data.windows(entry.len()).position(|w| w == entry)
+        };
+        // Appended after the entries already there instead of in the order of the names
+        assert!(position("b").unwrap() < position("a").unwrap());
+
+        fs.remove_file(ROOT_INODE, OsStr::new("b"))?;
+        fs.create_file(ROOT_INODE, OsStr::new("c"), 0o644, 0, 0, 0)?;
+        let dir = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert_eq!(dir.entries.keys().collect::<Vec<_>>(), ["a", "c"]);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn mount_migrates_directories() -> anyhow::Result<()> {
+        let tmp_file = make_fs("mount_migrates_directories")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        fs.create_file(ROOT_INODE, OsStr::new("a"), 0o644, 0, 0, 0)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("d"), 0o755, 0, 0)?;
+        fs.create_file(dir, OsStr::new("b"), 0o644, 0, 0, 0)?;
+
+        // Write both directories back as the single blob of version 4
+        for index in [ROOT_INODE, dir] {
+            let Directory {
+                entries, parent, ..
+            } = fs.find_dir_from_inode(index)?;
+            let checksum = crate::simple_ext4::calculate_checksum(&(&entries, parent, 0u32));
+            let legacy = bincode::serialize(&(entries, parent, checksum))?;
+            let block = fs.find_inode(index)?.direct_blocks[0];
+            fs.write_data(&legacy, 0, block)?;
+        }
+        fs.superblock_mut().version = 4;
+        fs.sync()?;
+        drop(fs);
+
+        let fs = SimpleExt4FS::new(&tmp_file)?;
+        assert_eq!(fs.superblock().version, FERRIX_VERSION);
+        let root = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert_eq!(root.entries.keys().collect::<Vec<_>>(), ["a", "d"]);
+        let migrated = fs.find_dir_from_inode(dir)?;
+        assert_eq!(migrated.entries.keys().collect::<Vec<_>>(), ["b"]);
+        assert_eq!(migrated.parent, ROOT_INODE);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn mount_newer_version() -> anyhow::Result<()> {
+        let tmp_file = make_fs("mount_newer_version")?;
+        let mut file = OpenOptions::new().read(true).write(true).open(&tmp_file)?;
+        let mut sb = Superblock::deserialize_from(&mut file)?;
+        sb.version = FERRIX_VERSION + 1;
+        file.rewind()?;
+        sb.serialize_into(&mut file)?;
+
+        let err = SimpleExt4FS::new(&tmp_file).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported ferrix format version"));
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn mount_older_version() -> anyhow::Result<()> {
+        let tmp_file = make_fs("mount_older_version")?;
+        let mut file = OpenOptions::new().read(true).write(true).open(&tmp_file)?;
+        let mut sb = Superblock::deserialize_from(&mut file)?;
+        sb.version = MIN_SUPPORTED_VERSION - 1;
+        file.rewind()?;
+        sb.serialize_into(&mut file)?;
+
+        let err = SimpleExt4FS::new(&tmp_file).unwrap_err();
+        assert!(err.to_string().contains("oldest supported version"));
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn mount_current_version() -> anyhow::Result<()> {
+        let tmp_file = make_fs("mount_current_version")?;
+        let fs = SimpleExt4FS::new(&tmp_file)?;
+
+        assert_eq!(fs.superblock().version, FERRIX_VERSION);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn remounts_within_a_second_have_distinct_mount_times() -> anyhow::Result<()> {
+        let tmp_file = make_fs("remounts_within_a_second")?;
+        let mut last_mounted_at = Vec::new();
+
+        for _ in 0..2 {
+            let mut fs = SimpleExt4FS::new(&tmp_file)?;
+            fs.mark_mounted();
+            fs.destroy();
+
+            let fs = SimpleExt4FS::new(&tmp_file)?;
+            last_mounted_at.push(fs.superblock().last_mounted_at.unwrap());
+        }
+
+        assert!(last_mounted_at[0] < last_mounted_at[1]);
+        assert!(last_mounted_at[1] - last_mounted_at[0] < 1_000_000_000);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn scrub_restores_free_counts() -> anyhow::Result<()> {
+        let tmp_file = make_fs("scrub_restores_free_counts")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+
+        assert_eq!(fs.scrub(), ScrubReport::default());
+
+        let free_blocks = fs.superblock().free_blocks;
+        let free_inodes = fs.superblock().free_inodes;
+        fs.superblock_mut().free_blocks += 3;
+        fs.superblock_mut().free_inodes -= 2;
+
+        let report = fs.scrub();
+        assert_eq!(report.free_blocks_drift, 3);
+        assert_eq!(report.free_inodes_drift, -2);
+        assert_eq!(fs.superblock().free_blocks, free_blocks);
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+
+        assert!(!fs.scrub().has_drift());
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn mount_reconciles_free_counts() -> anyhow::Result<()> {
+        let tmp_file = make_fs("mount_reconciles_free_counts")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let free_blocks = fs.superblock().free_blocks;
+        let free_inodes = fs.superblock().free_inodes;
+
+        fs.superblock_mut().free_blocks -= 7;
+        fs.superblock_mut().free_inodes += 5;
+        fs.sync()?;
+        drop(fs);
+
+        let fs = SimpleExt4FS::new(&tmp_file)?;
+        assert_eq!(fs.superblock().free_blocks, free_blocks);
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn file_handle_allocation() -> anyhow::Result<()> {
+        let tmp_file = make_fs("file_handle_allocation")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let ino = ROOT_INODE as u64;
+
+        let first = fs.open_handle(ino, libc::O_RDONLY)?;
+        let second = fs.open_handle(ino, libc::O_RDWR | libc::O_APPEND)?;
+        assert!(second > first);
+
+        assert_eq!(
+            fs.file_handle(second),
+            Some(&FileHandle {
+                ino,
+                read: true,
+                write: true,
+                flags: libc::O_RDWR | libc::O_APPEND,
+            })
+        );
+        assert!(!fs.file_handle(first).unwrap().write);
+
+        assert_eq!(fs.open_handle(ino, libc::O_ACCMODE), Err(Errno::EINVAL));
+        assert_eq!(fs.open_handle(42, libc::O_RDONLY), Err(Errno::ENOENT));
+
+        let created = fs.create_file(ROOT_INODE, OsStr::new("a"), 0o644, libc::O_ACCMODE, 0, 0);
+        assert_eq!(created.err(), Some(Errno::EINVAL));
+        assert_eq!(
+            fs.lookup_entry(ROOT_INODE, OsStr::new("a")).err(),
+            Some(Errno::ENOENT)
+        );
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn file_handle_release() -> anyhow::Result<()> {
+        let tmp_file = make_fs("file_handle_release")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+
+        let fh = fs.open_handle(ROOT_INODE as u64, libc::O_WRONLY)?;
+        let handle = fs.release_handle(fh)?;
+        assert!(handle.write);
+        assert_eq!(fs.file_handle(fh), None);
+        assert_eq!(fs.release_handle(fh), Err(Errno::EBADF));
+
+        // Handle ids are never reused
+        assert!(fs.open_handle(ROOT_INODE as u64, libc::O_RDONLY)? > fh);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn list_open_handles() -> anyhow::Result<()> {
+        let tmp_file = make_fs("list_open_handles")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, a) = fs.create_file(ROOT_INODE, OsStr::new("a"), 0o644, 0, 0, 0)?;
+        let (_, b) = fs.create_file(ROOT_INODE, OsStr::new("b"), 0o644, 0, 0, 0)?;
+
+        let first = fs.open_handle(a as u64, libc::O_RDONLY)?;
+        let second = fs.open_handle(a as u64, libc::O_RDWR)?;
+        let released = fs.open_handle(b as u64, libc::O_WRONLY)?;
+        let third = fs.open_handle(b as u64, libc::O_WRONLY | libc::O_APPEND)?;
+        fs.release_handle(released)?;
+
+        let handles: Vec<_> = fs
+            .open_handles()
+            .iter()
+            .map(|handle| {
+                (
+                    handle.fh,
+                    handle.ino,
+                    handle.read,
+                    handle.write,
+                    handle.open_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            handles,
+            [
+                (first, a as u64, true, false, 2),
+                (second, a as u64, true, true, 2),
+                (third, b as u64, false, true, 1),
+            ]
+        );
+        assert_eq!(fs.open_handles()[2].flags, libc::O_WRONLY | libc::O_APPEND);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn created_nodes_are_owned_by_the_caller() -> anyhow::Result<()> {
+        let tmp_file = make_fs("created_nodes_are_owned_by_the_caller")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+
+        let (file, file_index) =
+            fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 1000, 100)?;
+        assert_eq!((file.user_id, file.group_id), (1000, 100));
+        assert_eq!(file.to_attr(file_index).uid, 1000);
+
+        let (dir, dir_index) = fs.create_dir(ROOT_INODE, OsStr::new("dir"), 0o755, 1001, 101)?;
+        assert_eq!((dir.user_id, dir.group_id), (1001, 101));
+        assert!(dir.is_dir());
+
+        let (nested, _) = fs.create_file(dir_index, OsStr::new("nested"), 0o644, 0, 1002, 102)?;
+        assert_eq!((nested.user_id, nested.group_id), (1002, 102));
+
+        let root = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert_eq!(root.entry("file")?, file_index);
+        assert_eq!(root.entry("dir")?, dir_index);
+        assert_eq!(fs.find_dir("/dir")?.0.entries.len(), 1);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn symlink_round_trip() -> anyhow::Result<()> {
+        let tmp_file = make_fs("symlink_round_trip")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, file) = fs.create_file(ROOT_INODE, OsStr::new("f"), 0o644, 0, 0, 0)?;
+        let target = Path::new("../some/target");
+
+        let (inode, link) = fs.create_symlink(ROOT_INODE, OsStr::new("link"), target, 1000, 100)?;
+        let attr = inode.to_attr(link);
+        assert_eq!(
+            (attr.kind, attr.size, attr.uid, attr.gid),
+            (
+                FileType::Symlink,
+                target.as_os_str().len() as u64,
+                1000,
+                100
+            )
+        );
+        assert_eq!(fs.read_symlink(link)?, target.as_os_str().as_bytes());
+        assert_eq!(fs.lookup_entry(ROOT_INODE, OsStr::new("link"))?.0, link);
+
+        let too_long = "t".repeat(BLOCK_SIZE as usize + 1);
+        let inputs = [("link", target), ("other", Path::new(&too_long))];
+        let outputs = [Errno::EEXIST, Errno::ENAMETOOLONG];
+        for ((name, target), output) in inputs.into_iter().zip(outputs) {
+            let result = fs.create_symlink(ROOT_INODE, OsStr::new(name), target, 0, 0);
+            assert_eq!(result.err(), Some(output), "{name}");
+        }
+        assert_eq!(fs.read_symlink(file).err(), Some(Errno::EINVAL));
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn hard_link_shares_data() -> anyhow::Result<()> {
+        let tmp_file = make_fs("hard_link_shares_data")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("d"), 0o755, 0, 0)?;
+        let (_, file) = fs.create_file(ROOT_INODE, OsStr::new("f"), 0o644, 0, 0, 0)?;
+        let fh = fs.open_handle(file as u64, libc::O_RDWR)?;
+        fs.write_file(file as u64, fh, 0, b"shared")?;
+        let free_inodes = fs.superblock().free_inodes;
+        let free_blocks = fs.superblock().free_blocks;
+
+        let (inode, link) = fs.create_link(file, dir, OsStr::new("hard"))?;
+        assert_eq!((link, inode.hard_links), (file, 2));
+        assert_eq!(fs.lookup_entry(dir, OsStr::new("hard"))?.0, file);
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+
+        let inputs = [(file, ROOT_INODE, "f"), (dir, ROOT_INODE, "d2")];
+        let outputs = [Errno::EEXIST, Errno::EPERM];
+        for ((ino, parent, name), output) in inputs.into_iter().zip(outputs) {
+            let result = fs.create_link(ino, parent, OsStr::new(name));
+            assert_eq!(result.err(), Some(output), "{name}");
+        }
+
+        // The data outlives the first name and is released along with the last one
+        fs.remove_file(ROOT_INODE, OsStr::new("f"))?;
+        assert_eq!(fs.find_inode(file)?.hard_links, 1);
+        assert_eq!(fs.read_file(file as u64, fh, 0, 6)?, b"shared");
+        fs.remove_file(dir, OsStr::new("hard"))?;
+        assert_eq!(fs.superblock().free_inodes, free_inodes + 1);
+        assert_eq!(fs.superblock().free_blocks, free_blocks + 1);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn dir_entries_carry_attrs() -> anyhow::Result<()> {
+        let tmp_file = make_fs("dir_entries_carry_attrs")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("d"), 0o755, 0, 0)?;
+        let (_, file) = fs.create_file(dir, OsStr::new("f"), 0o640, 0, 0, 0)?;
+
+        let entries = fs.dir_entries(dir)?;
+
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(name, attr)| (name.to_str().unwrap(), attr.ino, attr.kind))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (".", dir as u64, FileType::Directory),
+                ("..", ROOT_INODE as u64, FileType::Directory),
+                ("f", file as u64, FileType::RegularFile),
+            ]
+        );
+        assert!(fs.dir_entries(file).is_err());
+
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
+
+    #[test]
+    fn repeated_lookups_hit_the_dcache() -> anyhow::Result<()> {
+        let tmp_file = make_fs("repeated_lookups_hit_the_dcache")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("d"), 0o755, 0, 0)?;
+        let (_, file) = fs.create_file(dir, OsStr::new("f"), 0o640, 0, 0, 0)?;
+
+        let (first, _) = fs.lookup_entry(dir, OsStr::new("f"))?;
+        let (second, attr) = fs.lookup_entry(dir, OsStr::new("f"))?;
+        assert_eq!((first, second, attr.ino), (file, file, file as u64));
+        assert_eq!(fs.dcache.hits(), 1);
+
+        fs.create_file(dir, OsStr::new("g"), 0o640, 0, 0, 0)?;
+        fs.lookup_entry(dir, OsStr::new("f"))?;
+        assert_eq!(fs.dcache.hits(), 1);
+
+        fs.remove_file(dir, OsStr::new("f"))?;
+        assert_eq!(
+            fs.lookup_entry(dir, OsStr::new("f")).unwrap_err(),
+            Errno::ENOENT
         );
-        let mut inode = match self.find_inode(ino as u32) {
-            Ok(inode) => inode,
-            Err(e) => {
-                reply.error(e as i32);
-                return;
-            }
-        };
 
-        let mut data = vec![0u8; size as usize];
-        let mut total_read = 0;
-        let mut current_offset = offset as u64;
-        let blk_size = self.superblock().block_size;
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
-        let should_read = (size as usize).min(inode.size as usize);
-        while total_read != should_read {
-            let direct_block_index = current_offset / blk_size as u64;
-            let (block_index, space_left) =
-                match self.find_data_block(&mut inode, current_offset, true) {
-                    Ok(result) => result,
-                    Err(e) => {
-                        reply.error(e as i32);
-                        return;
-                    }
-                };
+    #[test]
+    fn rename_dir_in_place() -> anyhow::Result<()> {
+        let tmp_file = make_fs("rename_dir_in_place")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("a"), 0o755, 0, 0)?;
+        fs.create_file(dir, OsStr::new("file"), 0o644, 0, 0, 0)?;
 
-            let max_read_len = data.len().min(space_left as usize);
-            let max_read_len = data.len().min(max_read_len + total_read);
-            let offset_in_block = if total_read != 0 {
-                0
-            } else {
-                current_offset - direct_block_index * blk_size as u64
-            };
+        fs.rename(ROOT_INODE, OsStr::new("a"), ROOT_INODE, OsStr::new("b"))?;
 
-            let read = match self.read_data(
-                &mut data[total_read..max_read_len],
-                offset_in_block,
-                block_index,
-            ) {
-                Ok(read) => read,
-                Err(_) => {
-                    reply.error(libc::EIO);
-                    return;
-                }
-            };
+        let root = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert!(root.entry("a").is_err());
+        assert_eq!(root.entry("b")?, dir);
+        assert_eq!(fs.find_dir("/b")?.0.entries.len(), 1);
+        assert_eq!(fs.find_dir_from_inode(dir)?.parent, ROOT_INODE);
+        assert_eq!(fs.find_inode(ROOT_INODE)?.hard_links, 3);
 
-            total_read += read;
-            current_offset += read as u64;
-        }
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
-        inode.update_accessed_at();
-        if let Err(_) = self.save_inode(inode, ino as u32) {
-            reply.error(libc::EIO);
-            return;
-        }
+    #[test]
+    fn rename_dir_across_parents() -> anyhow::Result<()> {
+        let tmp_file = make_fs("rename_dir_across_parents")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, from) = fs.create_dir(ROOT_INODE, OsStr::new("from"), 0o755, 0, 0)?;
+        let (_, to) = fs.create_dir(ROOT_INODE, OsStr::new("to"), 0o755, 0, 0)?;
+        let (_, dir) = fs.create_dir(from, OsStr::new("dir"), 0o755, 0, 0)?;
+        fs.create_file(dir, OsStr::new("file"), 0o644, 0, 0, 0)?;
+
+        fs.rename(from, OsStr::new("dir"), to, OsStr::new("moved"))?;
+
+        assert!(fs.find_dir("/from/dir").is_err());
+        assert_eq!(fs.find_dir("/to/moved")?.1, dir);
+        assert!(fs.find_dir("/to/moved")?.0.entry("file").is_ok());
+        assert_eq!(fs.find_dir_from_inode(dir)?.parent, to);
+        assert_eq!(fs.find_inode(from)?.hard_links, 2);
+        assert_eq!(fs.find_inode(to)?.hard_links, 3);
 
-        reply.data(&data[..total_read]);
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
-    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
-        match self.find_inode(ino as u32) {
-            Ok(attr) => {
-                if check_access(
-                    attr.user_id,
-                    attr.group_id,
-                    attr.mode.try_into().unwrap(),
-                    req.uid(),
-                    req.gid(),
-                    mask,
-                ) {
-                    reply.ok();
-                } else {
-                    reply.error(libc::EACCES);
-                }
-            }
-            Err(error_code) => reply.error(error_code as i32),
+    #[test]
+    fn rename_dir_into_descendant() -> anyhow::Result<()> {
+        let tmp_file = make_fs("rename_dir_into_descendant")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, dir) = fs.create_dir(ROOT_INODE, OsStr::new("dir"), 0o755, 0, 0)?;
+        let (_, child) = fs.create_dir(dir, OsStr::new("child"), 0o755, 0, 0)?;
+        let inputs = [dir, child];
+
+        for input in inputs {
+            let result = fs.rename(ROOT_INODE, OsStr::new("dir"), input, OsStr::new("moved"));
+
+            assert_eq!(result, Err(Errno::EINVAL));
+            assert_eq!(fs.find_dir_from_inode(ROOT_INODE)?.entry("dir")?, dir);
         }
+
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
-    fn mkdir(
-        &mut self,
-        _req: &Request,
-        parent: u64,
-        name: &OsStr,
-        mode: u32,
-        umask: u32,
-        reply: ReplyEntry,
-    ) {
-        debug!(
-            "mkdir: parent={}, name={:?}, mode={:#o}, umask={:#o}",
-            parent, name, mode, umask
-        );
-        let index = match self.allocate_inode() {
-            Some(index) => index,
-            None => {
-                reply.error(libc::ENOSPC);
-                return;
-            }
-        };
-        debug!("mkdir: index={}", index);
-
-        match self.find_dir_from_inode(parent as u32) {
-            Ok(mut parent_dir) => {
-                parent_dir.entries.insert(name.to_owned(), index);
-
-                let mut inode = Inode::new(self.superblock().block_size);
-                inode.mode = SFlag::S_IFDIR.bits() | mode;
-                inode.hard_links = 2;
-                inode.user_id = self.superblock().uid;
-                inode.group_id = self.superblock().gid;
-
-                let data_block_index = match self.allocate_data_block() {
-                    Some(index) => index,
-                    None => {
-                        reply.error(libc::ENOSPC);
-                        return;
-                    }
-                };
+    #[test]
+    fn remove_empty_dir_only() -> anyhow::Result<()> {
+        let tmp_file = make_fs("remove_empty_dir_only")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let free_inodes = fs.superblock().free_inodes;
+        let free_blocks = fs.superblock().free_blocks;
+        let (_, full) = fs.create_dir(ROOT_INODE, OsStr::new("full"), 0o755, 0, 0)?;
+        fs.create_dir(ROOT_INODE, OsStr::new("empty"), 0o755, 0, 0)?;
+        fs.create_file(full, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        let inputs = [(ROOT_INODE, "full"), (full, "file"), (ROOT_INODE, "empty")];
+        let outputs = [Err(Errno::ENOTEMPTY), Err(Errno::ENOTDIR), Ok(())];
+
+        for ((parent, name), output) in inputs.into_iter().zip(outputs) {
+            assert_eq!(fs.remove_dir(parent, OsStr::new(name)), output, "{name}");
+        }
 
-                let dir = Directory::default();
+        let root = fs.find_dir_from_inode(ROOT_INODE)?;
+        assert!(root.entry("empty").is_err());
+        assert_eq!(root.entry("full")?, full);
+        assert_eq!(fs.find_inode(ROOT_INODE)?.hard_links, 3);
 
-                if let Err(_) = inode.add_block(data_block_index, 0) {
-                    reply.error(libc::EIO);
-                    return;
-                }
+        fs.remove_file(full, OsStr::new("file"))?;
+        fs.remove_dir(ROOT_INODE, OsStr::new("full"))?;
+        assert!(fs.find_dir_from_inode(ROOT_INODE)?.entries.is_empty());
+        assert_eq!(fs.find_inode(ROOT_INODE)?.hard_links, 2);
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+        assert_eq!(fs.superblock().free_blocks, free_blocks);
 
-                if let Err(_) = self.save_inode(inode, index) {
-                    reply.error(libc::EIO);
-                    return;
-                }
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
-                if let Err(_) = self.save_dir(dir, data_block_index) {
-                    reply.error(libc::EIO);
-                    return;
-                }
+    #[test]
+    fn create_existing_file() -> anyhow::Result<()> {
+        let tmp_file = make_fs("create_existing_file")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let name = OsStr::new("file");
+
+        let (mut inode, index) = fs.create_file(ROOT_INODE, name, 0o644, 0, 0, 0)?;
+        fs.find_data_block(index, &mut inode, 0, false)?;
+        inode.increment_size(BLOCK_SIZE as u64);
+        fs.save_inode(inode, index)?;
+        let free_inodes = fs.superblock().free_inodes;
+        let free_blocks = fs.superblock().free_blocks;
+
+        // Reusing the existing file keeps its content
+        let (inode, reused) = fs.create_file(ROOT_INODE, name, 0o644, 0, 0, 0)?;
+        assert_eq!(reused, index);
+        assert_eq!(inode.size, BLOCK_SIZE as u64);
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+        assert_eq!(fs.superblock().free_blocks, free_blocks);
+
+        // Truncating releases the blocks of the existing file
+        let (inode, truncated) = fs.create_file(ROOT_INODE, name, 0o644, libc::O_TRUNC, 0, 0)?;
+        assert_eq!(truncated, index);
+        assert_eq!(inode.size, 0);
+        assert!(inode.direct_blocks().is_empty());
+        assert_eq!(fs.superblock().free_inodes, free_inodes);
+        assert_eq!(fs.superblock().free_blocks, free_blocks + 1);
 
-                if let Err(e) = self.save_dir(parent_dir, parent as u32) {
-                    println!("here3 {:?}", e);
-                    reply.error(libc::EIO);
-                    return;
-                }
-                println!("here4");
+        assert_eq!(
+            fs.create_file(ROOT_INODE, name, 0o644, libc::O_EXCL, 0, 0)
+                .err(),
+            Some(Errno::EEXIST)
+        );
+        assert_eq!(fs.find_dir_from_inode(ROOT_INODE)?.entries.len(), 1);
 
-                match self.find_inode(index) {
-                    Ok(created_inode) => {
-                        reply.entry(&Duration::from_secs(1), &created_inode.to_attr(index), 0);
-                    }
-                    Err(e) => reply.error(e as i32),
-                }
-            }
-            Err(e) => reply.error(e as i32),
-        }
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
-    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        debug!("unlink: parent={}, name={:?}", parent, name);
-        match self.find_dir_from_inode(parent as u32) {
-            Ok(mut parent_dir) => match parent_dir.entries.remove(name) {
-                Some(index) => match self.find_inode(index) {
-                    Ok(inode) => {
-                        self.release_data_blocks(&inode.direct_blocks());
-                        if inode.indirect_block != 0 {
-                            if let Err(_) = self.release_indirect_block(inode.indirect_block) {
-                                reply.error(libc::EIO);
-                                return;
-                            }
-                        }
-                        if inode.double_indirect_block != 0 {
-                            if let Err(_) =
-                                self.release_double_indirect_block(inode.double_indirect_block)
-                            {
-                                reply.error(libc::EIO);
-                                return;
-                            }
-                        }
-                        if let Err(_) = self.save_dir(parent_dir, parent as u32) {
-                            reply.error(libc::EIO);
-                            return;
-                        }
-                        self.release_inode(index);
-                        reply.ok();
-                    }
-                    Err(e) => reply.error(e as i32),
-                },
-                None => reply.error(libc::ENOENT),
-            },
-            Err(e) => reply.error(e as i32),
+    #[test]
+    fn retry_transient_failures() {
+        // Arrange
+        let inputs = [
+            (io::ErrorKind::Interrupted, 2),
+            (io::ErrorKind::WouldBlock, TRANSIENT_RETRIES),
+            (io::ErrorKind::Interrupted, TRANSIENT_RETRIES + 1),
+            (io::ErrorKind::PermissionDenied, 1),
+        ];
+        let outputs = [
+            (true, 3),
+            (true, TRANSIENT_RETRIES + 1),
+            (false, TRANSIENT_RETRIES + 1),
+            (false, 1),
+        ];
+
+        for ((kind, failures), (ok, attempts)) in inputs.into_iter().zip(outputs) {
+            let mut made = 0;
+
+            // Act
+            let result = retry_transient(|| {
+                made += 1;
+                if made <= failures {
+                    Err(io::Error::from(kind).into())
+                } else {
+                    Ok(())
+                }
+            });
+
+            // Assert
+            assert_eq!(result.is_ok(), ok, "{kind:?} {failures}");
+            assert_eq!(made, attempts, "{kind:?} {failures}");
         }
     }
 
-    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
-        debug!("init: kernel_config={:?}", config);
-        let sb = self.superblock_mut();
-        sb.update_last_mounted_at();
-        sb.update_modified_at();
+    #[test]
+    fn retry_transient_bincode_failures() {
+        let mut made = 0;
+        let result = retry_transient(|| {
+            made += 1;
+            if made == 1 {
+                let e: bincode::Error = Box::new(bincode::ErrorKind::Io(io::Error::from(
+                    io::ErrorKind::Interrupted,
+                )));
+                Err(e.into())
+            } else {
+                Ok(())
+            }
+        });
 
-        Ok(())
+        assert!(result.is_ok());
+        assert_eq!(made, 2);
     }
 
-    fn destroy(&mut self) {
-        debug!("destroy called");
-        let mut mmap = mem::replace(&mut self.mmap, None).unwrap();
-        let buf = mmap.as_mut();
-        let mut cursor = Cursor::new(buf);
+    #[test]
+    fn sync_persists_free_counts() -> anyhow::Result<()> {
+        let tmp_file = make_fs("sync_persists_free_counts")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
 
-        if let Err(e) = self.superblock_mut().serialize_into(&mut cursor) {
-            println!("inside superblock {e:?}");
-            return;
-        }
+        let free_inodes = fs.superblock().free_inodes;
+        fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        fs.sync()?;
 
-        if let Err(e) = Group::serialize_into(&mut cursor, self.groups()) {
-            println!("inside group {e:?}");
-            return;
-        }
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        assert_eq!(fs.superblock().free_inodes, free_inodes - 1);
+        assert!(!fs.scrub().has_drift());
 
-        debug!("flushing mmap");
-        if let Err(e) = mmap.flush() {
-            println!("inside flush {e:?}");
-            return;
-        }
-        debug!("destroyed");
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{
-        simple_ext4::mkfs,
-        simple_ext4::{types::Superblock, INODE_SIZE, ROOT_INODE},
-    };
-    use fuser::{
-        FileAttr, Filesystem, Reply, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyWrite,
-        Request,
-    };
-    use std::time::{Duration, UNIX_EPOCH};
-    use std::{ffi::OsString, path::PathBuf};
+    #[test]
+    fn block_pointer_past_the_disk() -> anyhow::Result<()> {
+        let tmp_file = make_fs("block_pointer_past_the_disk")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
 
-    const BLOCK_SIZE: u32 = 128;
+        let (mut inode, index) = fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        let past_the_disk = 16 * fs.superblock().data_blocks_per_group;
+        inode.direct_blocks[0] = past_the_disk;
+        inode.block_count = 1;
+        inode.size = BLOCK_SIZE as u64;
+        fs.save_inode(inode, index)?;
 
-    #[test]
-    fn inode_offsets() {
-        let mut fs = SimpleExt4FS::default();
-        fs.sb = Some(Superblock::new(1024, 3, 0, 0));
-        fs.superblock_mut().data_blocks_per_group = 1024 * 8;
+        let mut data = [0u8; 4];
+        let err = fs.read_data(&mut data, 0, past_the_disk).unwrap_err();
+        assert_eq!(err.downcast_ref::<Errno>(), Some(&Errno::EIO));
 
-        let (group_index, offset) = fs.inode_offsets(1);
-        assert_eq!(group_index, 0);
-        assert_eq!(offset, 0);
+        let fh = fs.open_handle(index as u64, libc::O_WRONLY)?;
+        assert_eq!(fs.write_file(index as u64, fh, 0, b"data"), Err(Errno::EIO));
 
-        let (group_index, offset) = fs.inode_offsets(1024 * 8);
-        assert_eq!(group_index, 0);
-        assert_eq!(offset, 8191);
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
-        let (group_index, offset) = fs.inode_offsets(1024 * 8 - 1);
-        assert_eq!(group_index, 0);
-        assert_eq!(offset, 8190);
+    #[test]
+    fn data_blocks_land_in_the_inode_group() -> anyhow::Result<()> {
+        let mut tmp_file = tempfile::tempdir()?.path().to_path_buf();
+        fs::create_dir_all(&tmp_file)?;
+        tmp_file.push("data_blocks_land_in_the_inode_group.img");
+        let block_group_size = crate::simple_ext4::block_group_size(BLOCK_SIZE);
+        mkfs::make(&tmp_file, 2 * block_group_size, BLOCK_SIZE)?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+
+        // Fill the inodes of the first group so the file inode lands in the second one
+        let first = &fs.groups()[0];
+        let full_inodes = BitVec::repeat(true, first.inode_bitmap.len());
+        fs.groups_mut()[0] = Group::new(first.data_bitmap.clone(), full_inodes);
+        let free_blocks: Vec<usize> = fs.groups().iter().map(Group::free_data_blocks).collect();
+
+        let (_, index) = fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        assert_eq!(fs.inode_offsets(index).0, 1);
+        let ino = index as u64;
+        let fh = fs.open_handle(ino, libc::O_WRONLY)?;
+        fs.write_file(ino, fh, 0, &vec![7u8; 3 * BLOCK_SIZE as usize])?;
+
+        let inode = fs.find_inode(index)?;
+        for block in inode.direct_blocks() {
+            assert_eq!(fs.data_block_offsets(block).0, 1);
+        }
+        assert_eq!(fs.groups()[0].free_data_blocks(), free_blocks[0]);
+        assert_eq!(fs.groups()[1].free_data_blocks(), free_blocks[1] - 3);
 
-        let (group_index, offset) = fs.inode_offsets(2 * 1024 * 8 - 1);
-        assert_eq!(group_index, 1);
-        assert_eq!(offset, 8190);
+        // A full group falls back to the next group with space
+        let second = &fs.groups()[1];
+        let full_blocks = BitVec::repeat(true, second.data_bitmap.len());
+        fs.groups_mut()[1] = Group::new(full_blocks, second.inode_bitmap.clone());
+        let block = fs.allocate_data_block(index).unwrap();
+        assert_eq!(fs.data_block_offsets(block).0, 0);
+
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
     #[test]
-    fn inode_seek_position() {
-        let mut fs = SimpleExt4FS::default();
-        fs.sb = Some(Superblock::new(1024, 3, 0, 0));
-        fs.superblock_mut().data_blocks_per_group = 1024 * 8;
+    fn immutable_file_rejects_writes_and_unlink() -> anyhow::Result<()> {
+        let tmp_file = make_fs("immutable_file_rejects_writes_and_unlink")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let name = OsStr::new("file");
 
-        let offset = fs.inode_seek_position(1);
-        assert_eq!(3072, offset);
+        let (_, index) = fs.create_file(ROOT_INODE, name, 0o644, 0, 1000, 100)?;
+        let ino = index as u64;
+        let fh = fs.open_handle(ino, libc::O_WRONLY)?;
+        assert_eq!(fs.write_file(ino, fh, 0, b"hello")?, 5);
 
-        let offset = fs.inode_seek_position(2);
-        assert_eq!(3072 + INODE_SIZE, offset);
+        // Only the owner or root can change the flags
+        assert_eq!(
+            fs.set_flags(ino, INODE_FLAG_IMMUTABLE, 1001),
+            Err(Errno::EPERM)
+        );
+        fs.set_flags(ino, INODE_FLAG_IMMUTABLE, 1000)?;
+        assert_eq!(fs.flags(ino)?, INODE_FLAG_IMMUTABLE);
+        assert_eq!(
+            fs.find_inode(index)?.to_attr(index).flags,
+            INODE_FLAG_IMMUTABLE
+        );
 
-        let offset = fs.inode_seek_position(8192);
-        assert_eq!(3072 + 8191 * INODE_SIZE, offset); // superblock + data bitmap + inode bitmap + 8191 inodes
+        assert_eq!(fs.write_file(ino, fh, 0, b"world"), Err(Errno::EPERM));
+        assert_eq!(fs.write_file(ino, fh, 5, b"world"), Err(Errno::EPERM));
+        assert_eq!(
+            fs.create_file(ROOT_INODE, name, 0o644, libc::O_TRUNC, 0, 0)
+                .err(),
+            Some(Errno::EPERM)
+        );
+        assert_eq!(fs.remove_file(ROOT_INODE, name), Err(Errno::EPERM));
+        assert_eq!(fs.find_inode(index)?.size, 5);
 
-        let offset = fs.inode_seek_position(8193);
-        assert_eq!(3072 + 8192 * INODE_SIZE + 1024 * 1024 * 8 + 2048, offset); // superblock + data bitmap + inode bitmap + inode table + data blocks + data bitmap + inode bitmap
+        // Clearing the flag allows removing it again
+        fs.set_flags(ino, 0, 0)?;
+        fs.remove_file(ROOT_INODE, name)?;
+        assert!(fs.find_dir_from_inode(ROOT_INODE)?.entries.is_empty());
+
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
-    // #[test]
-    // fn new_fs() -> anyhow::Result<()> {
-    //     let tmp_file = make_fs("new_fs")?;
-    //     let fs = SimpleExt4FS::new(&tmp_file)?;
-    //     let inode = fs.find_inode(ROOT_INODE)?;
-    //
-    //     assert_eq!(inode.mode, SFlag::S_IFDIR.bits() | 0o777);
-    //     assert_eq!(inode.hard_links, 2);
-    //
-    //     assert!(fs.groups().first().unwrap().has_inode(ROOT_INODE as _));
-    //     assert!(fs.groups().first().unwrap().has_data_block(ROOT_INODE as _));
-    //
-    //     assert_eq!(fs.superblock().groups, fs.groups().len() as u32);
-    //     assert_eq!(fs.superblock().free_inodes, BLOCK_SIZE * 8 - 1);
-    //     assert_eq!(fs.superblock().free_blocks, BLOCK_SIZE * 8 - 1);
-    //
-    //     Ok(std::fs::remove_file(&tmp_file)?)
-    // }
+    #[test]
+    fn append_only_file_writes_at_eof() -> anyhow::Result<()> {
+        let tmp_file = make_fs("append_only_file_writes_at_eof")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let name = OsStr::new("file");
+
+        let (_, index) = fs.create_file(ROOT_INODE, name, 0o644, 0, 0, 0)?;
+        let ino = index as u64;
+        let fh = fs.open_handle(ino, libc::O_WRONLY | libc::O_APPEND)?;
+        fs.write_file(ino, fh, 0, b"hello")?;
+        fs.set_flags(ino, INODE_FLAG_APPEND_ONLY, 0)?;
+
+        assert_eq!(fs.write_file(ino, fh, 0, b"world"), Err(Errno::EPERM));
+        assert_eq!(fs.write_file(ino, fh, 5, b"world")?, 5);
+        assert_eq!(fs.find_inode(index)?.size, 10);
+        assert_eq!(fs.remove_file(ROOT_INODE, name), Err(Errno::EPERM));
+        assert_eq!(fs.set_flags(ino, 0x1, 0), Err(Errno::EOPNOTSUPP));
 
-    // #[test]
-    // fn init_destroy() -> anyhow::Result<()> {
-    //     let tmp_file = make_fs("init_destroy")?;
-    //     let fs = SimpleExt4FS::new(&tmp_file)?;
-    //     let tmp_dir = tempfile::tempdir()?.path().join("init_destroy");
-    //     fs::create_dir_all(&tmp_dir)?;
-    //
-    //     assert_eq!(fs.superblock().last_mounted_at, None);
-    //
-    //     let fs = SimpleExt4FS::new(&tmp_file)?;
-    //
-    //     assert_ne!(fs.superblock().last_mounted_at, None);
-    //     assert_eq!(fs.superblock().free_inodes, BLOCK_SIZE * 8 - 1);
-    //     assert_eq!(fs.superblock().free_blocks, BLOCK_SIZE * 8 - 1);
-    //
-    //     Ok(std::fs::remove_file(&tmp_file)?)
-    // }
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
     #[test]
-    fn data_block_seek_position() {
-        let mut fs = SimpleExt4FS::default();
-        let block_size = 1024;
-        fs.sb = Some(Superblock::new(block_size, 3, 0, 0));
-        fs.superblock_mut().data_blocks_per_group = block_size as u32 * 8;
-
-        let prefix = SUPERBLOCK_SIZE + 2 * block_size as u64 + block_size as u64 * INODE_SIZE * 8;
-        let offset = fs.data_block_seek_position(1);
-        assert_eq!(prefix, offset);
+    fn read_across_blocks() -> anyhow::Result<()> {
+        let tmp_file = make_fs("read_across_blocks")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let block = BLOCK_SIZE as usize;
+        let content: Vec<u8> = (0..5 * block + 17).map(|i| (i % 251) as u8).collect();
+
+        // One file written in one go, so its blocks follow each other on the disk, and two
+        // written a block at a time in turns, so theirs don't
+        let mut files = Vec::new();
+        for name in ["contiguous", "left", "right"] {
+            let (_, index) = fs.create_file(ROOT_INODE, OsStr::new(name), 0o644, 0, 0, 0)?;
+            let ino = index as u64;
+            files.push((ino, fs.open_handle(ino, libc::O_RDWR)?));
+        }
+        fs.write_file(files[0].0, files[0].1, 0, &content)?;
+        for (i, chunk) in content.chunks(block).enumerate() {
+            for &(ino, fh) in &files[1..] {
+                fs.write_file(ino, fh, (i * block) as i64, chunk)?;
+            }
+        }
 
-        let offset = fs.data_block_seek_position(2);
-        assert_eq!(prefix + block_size as u64, offset);
+        let inputs = [
+            (0, content.len()),
+            (3, 2 * block),
+            (block + 5, 10 * block),
+            (content.len(), block),
+        ];
+        for (ino, fh) in files {
+            for (offset, size) in inputs {
+                let data = fs.read_file(ino, fh, offset as i64, size as u32)?;
+                let end = content.len().min(offset + size);
+                assert_eq!(data, content[offset..end], "{ino} {offset} {size}");
+            }
+        }
 
-        let offset = fs.data_block_seek_position(8192);
-        assert_eq!(prefix + 8191 * block_size as u64, offset);
+        Ok(std::fs::remove_file(&tmp_file)?)
+    }
 
-        let offset = fs.data_block_seek_position(8193);
+    #[test]
+    fn set_attr_resizes_file() -> anyhow::Result<()> {
+        let tmp_file = make_fs("set_attr_resizes_file")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let block = BLOCK_SIZE as usize;
+        let content: Vec<u8> = (0..(DIRECT_POINTERS as usize + 3) * block)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let free_blocks = fs.superblock().free_blocks;
+        let (_, index) = fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        let ino = index as u64;
+        let fh = fs.open_handle(ino, libc::O_RDWR)?;
+        fs.write_file(ino, fh, 0, &content)?;
+
+        // Shrinking into the second block releases the others and the indirect block
+        let mtime = UNIX_EPOCH + Duration::from_secs(60);
+        let attr = fs.set_attr(
+            index,
+            SetAttr {
+                mode: Some(0o600),
+                uid: Some(1000),
+                gid: Some(100),
+                size: Some(block as u64 + 5),
+                mtime: Some(TimeOrNow::SpecificTime(mtime)),
+                ..SetAttr::default()
+            },
+        )?;
         assert_eq!(
-            2 * prefix - SUPERBLOCK_SIZE + (block_size * block_size) as u64 * 8,
-            offset
+            (attr.perm, attr.uid, attr.gid, attr.size, attr.mtime),
+            (0o600, 1000, 100, block as u64 + 5, mtime)
         );
+        assert_eq!(fs.superblock().free_blocks, free_blocks - 2);
+
+        // Growing again reads as zeros past the old end
+        let attr = fs.set_attr(
+            index,
+            SetAttr {
+                size: Some(3 * block as u64),
+                ..SetAttr::default()
+            },
+        )?;
+        assert_eq!(attr.size, 3 * block as u64);
+        assert_eq!(fs.superblock().free_blocks, free_blocks - 3);
+        let data = fs.read_file(ino, fh, 0, 3 * block as u32)?;
+        assert_eq!(data[..block + 5], content[..block + 5]);
+        assert!(data[block + 5..].iter().all(|&byte| byte == 0));
+
+        Ok(std::fs::remove_file(&tmp_file)?)
     }
 
     #[test]
-    fn save_dir() -> anyhow::Result<()> {
-        let tmp_file = make_fs("save_dir")?;
-        let fs = SimpleExt4FS::new(&tmp_file)?;
-        let dir = fs.find_dir_from_inode(ROOT_INODE)?;
-
-        assert_eq!(dir.entries.len(), 0);
+    fn debug_inode_and_superblock() -> anyhow::Result<()> {
+        let tmp_file = make_fs("debug_inode_and_superblock")?;
+        let mut fs = SimpleExt4FS::new(&tmp_file)?;
+        let (_, index) = fs.create_file(ROOT_INODE, OsStr::new("file"), 0o644, 0, 0, 0)?;
+        let ino = index as u64;
+        let fh = fs.open_handle(ino, libc::O_WRONLY)?;
+        fs.write_file(ino, fh, 0, &vec![7u8; 2 * BLOCK_SIZE as usize + 1])?;
+
+        let inode: Inode = bincode::deserialize(&fs.debug_reply(ino, FERRIX_IOC_INODE)?)?;
+        let expected = fs.find_inode(index)?;
+        assert_eq!(inode.size, 2 * BLOCK_SIZE as u64 + 1);
+        assert_eq!(inode.mode, expected.mode);
+        assert_eq!(inode.direct_blocks, expected.direct_blocks);
+        assert_eq!(inode.direct_blocks().len(), 3);
+        assert_eq!(inode.modified_at, expected.modified_at);
+        assert_eq!(inode.checksum, expected.checksum);
+
+        let superblock: Superblock =
+            bincode::deserialize(&fs.debug_reply(ROOT_INODE as u64, FERRIX_IOC_SUPERBLOCK)?)?;
+        assert_eq!(superblock.block_size, BLOCK_SIZE);
+        assert_eq!(superblock.free_blocks, fs.superblock().free_blocks);
+        assert_eq!(fs.debug_reply(ino, FERRIX_IOC_HANDLES), Err(Errno::ENOTTY));
 
         Ok(std::fs::remove_file(&tmp_file)?)
     }
@@ -22,6 +22,7 @@ use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{env, fs, io};
+use tracing::warn;
 
 const BLOCK_SIZE: u64 = 512;
 const MAX_NAME_LENGTH: u32 = 255;
@@ -238,24 +239,23 @@ pub struct FSInFS {
     next_file_handle: AtomicU64,
     direct_io: bool,
     suid_support: bool,
-    size: u64,
+    block_size: u64,
 }
 
 impl FSInFS {
-    pub fn new(
-        data_dir: String,
-        direct_io: bool,
-        #[allow(unused_variables)] suid_support: bool,
-        size: u64,
-    ) -> FSInFS {
-        {
-            FSInFS {
-                data_dir,
-                next_file_handle: AtomicU64::new(1),
-                direct_io,
-                suid_support: false,
-                size,
-            }
+    /// Creates a filesystem storing its inodes and contents under `data_dir`.
+    ///
+    /// - `direct_io`: open files with `FOPEN_DIRECT_IO`, bypassing the kernel page cache
+    /// - `suid_support`: keep the setuid and setgid bits of the mode of created nodes, otherwise
+    ///   they are stripped
+    /// - `block_size`: block size in bytes reported by `statfs`
+    pub fn new(data_dir: String, direct_io: bool, suid_support: bool, block_size: u64) -> FSInFS {
+        FSInFS {
+            data_dir,
+            next_file_handle: AtomicU64::new(1),
+            direct_io,
+            suid_support,
+            block_size,
         }
     }
 
@@ -369,11 +369,15 @@ impl FSInFS {
             let inode_path = Path::new(&self.data_dir)
                 .join("inodes")
                 .join(inode.inode.to_string());
-            fs::remove_file(inode_path).unwrap();
+            if let Err(e) = fs::remove_file(&inode_path) {
+                warn!("failed to remove {}: {e}", inode_path.display());
+            }
             let content_path = Path::new(&self.data_dir)
                 .join("contents")
                 .join(inode.inode.to_string());
-            fs::remove_file(content_path).unwrap();
+            if let Err(e) = fs::remove_file(&content_path) {
+                warn!("failed to remove {}: {e}", content_path.display());
+            }
 
             return true;
         }
@@ -381,6 +385,17 @@ impl FSInFS {
         return false;
     }
 
+    // Drop one open handle of the inode. An unlinked inode is only removed once its last handle
+    // is released
+    fn release_handle(&self, inode: Inode) {
+        if let Ok(mut attrs) = self.get_inode(inode) {
+            attrs.open_file_handles -= 1;
+            if !self.gc_inode(&attrs) {
+                self.write_inode(&attrs);
+            }
+        }
+    }
+
     fn truncate(
         &self,
         inode: Inode,
@@ -1421,9 +1436,7 @@ impl Filesystem for FSInFS {
         _flush: bool,
         reply: ReplyEmpty,
     ) {
-        if let Ok(mut attrs) = self.get_inode(inode) {
-            attrs.open_file_handles -= 1;
-        }
+        self.release_handle(inode);
         reply.ok();
     }
 
@@ -1512,9 +1525,7 @@ impl Filesystem for FSInFS {
         _flags: i32,
         reply: ReplyEmpty,
     ) {
-        if let Ok(mut attrs) = self.get_inode(inode) {
-            attrs.open_file_handles -= 1;
-        }
+        self.release_handle(inode);
         reply.ok();
     }
 
@@ -1526,9 +1537,9 @@ impl Filesystem for FSInFS {
             10_000,
             1,
             10_000,
-            BLOCK_SIZE as u32,
+            self.block_size as u32,
             MAX_NAME_LENGTH,
-            BLOCK_SIZE as u32,
+            self.block_size as u32,
         );
     }
 
@@ -1899,3 +1910,60 @@ fn fuse_allow_other_enabled() -> io::Result<bool> {
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A filesystem in `dir` holding a regular file with `hardlinks` links and one open handle
+    fn fs_with_open_file(dir: &Path, inode: Inode, hardlinks: u32) -> FSInFS {
+        fs::create_dir_all(dir.join("inodes")).unwrap();
+        fs::create_dir_all(dir.join("contents")).unwrap();
+        let fs = FSInFS::new(dir.to_string_lossy().into_owned(), false, false, 512);
+        fs.write_inode(&InodeAttributes {
+            inode,
+            open_file_handles: 1,
+            size: 0,
+            last_accessed: time_now(),
+            last_modified: time_now(),
+            last_metadata_changed: time_now(),
+            kind: FileKind::File,
+            mode: 0o644,
+            hardlinks,
+            uid: 0,
+            gid: 0,
+            xattrs: Default::default(),
+        });
+        File::create(fs.content_path(inode)).unwrap();
+        fs
+    }
+
+    #[test]
+    fn release_drops_open_handle() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let fs = fs_with_open_file(dir.path(), 2, 1);
+
+        fs.release_handle(2);
+
+        assert_eq!(fs.get_inode(2).unwrap().open_file_handles, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn unlinked_inode_removed_on_last_release() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let fs = fs_with_open_file(dir.path(), 2, 0);
+        let mut attrs = fs.get_inode(2).unwrap();
+        attrs.open_file_handles += 1;
+        fs.write_inode(&attrs);
+
+        fs.release_handle(2);
+        assert_eq!(fs.get_inode(2).unwrap().open_file_handles, 1);
+        assert!(fs.content_path(2).exists());
+
+        fs.release_handle(2);
+        assert_eq!(fs.get_inode(2).err(), Some(libc::ENOENT));
+        assert!(!fs.content_path(2).exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! On-disk layout of the filesystem.
+//!
+//! The disk starts with the superblock followed by the block groups, one after another:
+//!
+//! ```text
+//! | superblock | group 0 | group 1 | ... | group n |
+//! ```
+//!
+//! Every group is laid out as:
+//!
+//! ```text
+//! | data bitmap | inode bitmap | inode table | data region |
+//! ```
+//!
+//! Each bitmap takes a single block, so a group tracks one inode and one data block per bitmap
+//! bit. The inode table has an `INODE_SIZE` slot for every inode of the group and the data region
+//! a block for every data block. Inodes and data blocks are numbered from 1 across all groups.
+
+use super::{INODE_SIZE, SUPERBLOCK_SIZE};
+
+/// Offsets of every region of a disk formatted with a given block size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    block_size: u64,
+}
+
+impl Layout {
+    pub fn new(block_size: u32) -> Self {
+        Self {
+            block_size: block_size as u64,
+        }
+    }
+
+    /// Number of inodes, and of data blocks, in a group, one for every bit of its bitmaps
+    pub fn entries_per_group(&self) -> u64 {
+        self.block_size * 8
+    }
+
+    pub fn inode_table_size(&self) -> u64 {
+        self.entries_per_group() * INODE_SIZE
+    }
+
+    pub fn data_region_size(&self) -> u64 {
+        self.entries_per_group() * self.block_size
+    }
+
+    /// Size of a whole group, bitmaps included
+    pub fn group_size(&self) -> u64 {
+        2 * self.block_size + self.inode_table_size() + self.data_region_size()
+    }
+
+    pub fn group_start(&self, group: u64) -> u64 {
+        SUPERBLOCK_SIZE + group * self.group_size()
+    }
+
+    pub fn data_bitmap_offset(&self, group: u64) -> u64 {
+        self.group_start(group)
+    }
+
+    pub fn inode_bitmap_offset(&self, group: u64) -> u64 {
+        self.data_bitmap_offset(group) + self.block_size
+    }
+
+    pub fn inode_table_offset(&self, group: u64) -> u64 {
+        self.inode_bitmap_offset(group) + self.block_size
+    }
+
+    pub fn data_region_offset(&self, group: u64) -> u64 {
+        self.inode_table_offset(group) + self.inode_table_size()
+    }
+
+    /// Position of the inode with the given 1-based index
+    pub fn inode_offset(&self, index: u32) -> u64 {
+        let (group, slot) = self.group_and_slot(index);
+        self.inode_table_offset(group) + slot * INODE_SIZE
+    }
+
+    /// Position of the data block with the given 1-based index
+    pub fn data_block_offset(&self, index: u32) -> u64 {
+        let (group, slot) = self.group_and_slot(index);
+        self.data_region_offset(group) + slot * self.block_size
+    }
+
+    fn group_and_slot(&self, index: u32) -> (u64, u64) {
+        let index = index as u64 - 1;
+        (
+            index / self.entries_per_group(),
+            index % self.entries_per_group(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_regions() {
+        // Arrange
+        let layout = Layout::new(1024);
+        let group_size = 2 * 1024 + 8192 * INODE_SIZE + 8192 * 1024;
+        let inputs = [0, 1, 2];
+
+        for group in inputs {
+            // Act
+            let start = layout.group_start(group);
+
+            // Assert
+            assert_eq!(start, SUPERBLOCK_SIZE + group * group_size);
+            assert_eq!(layout.data_bitmap_offset(group), start);
+            assert_eq!(layout.inode_bitmap_offset(group), start + 1024);
+            assert_eq!(layout.inode_table_offset(group), start + 2048);
+            assert_eq!(
+                layout.data_region_offset(group),
+                start + 2048 + 8192 * INODE_SIZE
+            );
+        }
+        assert_eq!(layout.group_size(), group_size);
+    }
+
+    #[test]
+    fn inode_offset() {
+        // Arrange
+        let layout = Layout::new(1024);
+        let inputs = [1, 2, 8192, 8193];
+        let outputs = [
+            3072,
+            3072 + INODE_SIZE,
+            3072 + 8191 * INODE_SIZE,
+            // superblock + data bitmap + inode bitmap + inode table + data blocks + data bitmap
+            // + inode bitmap
+            3072 + 8192 * INODE_SIZE + 1024 * 1024 * 8 + 2048,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let offset = layout.inode_offset(*input);
+
+            // Assert
+            assert_eq!(offset, *output);
+        }
+    }
+
+    #[test]
+    fn data_block_offset() {
+        // Arrange
+        let layout = Layout::new(1024);
+        let prefix = SUPERBLOCK_SIZE + 2 * 1024 + 1024 * INODE_SIZE * 8;
+        let inputs = [1, 2, 8192, 8193];
+        let outputs = [
+            prefix,
+            prefix + 1024,
+            prefix + 8191 * 1024,
+            2 * prefix - SUPERBLOCK_SIZE + 1024 * 1024 * 8,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let offset = layout.data_block_offset(*input);
+
+            // Assert
+            assert_eq!(offset, *output);
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use anyhow::{anyhow, bail};
 use std::{
-    fs::OpenOptions,
-    io::{BufWriter, Write},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Write},
     path::Path,
 };
 
-use super::{block_group_size, types::Superblock, SUPERBLOCK_SIZE};
+use super::{block_group_size, types::Superblock, FERRIX_MAGIC, SUPERBLOCK_SIZE};
 
 pub fn make<P>(path: P, file_size: u64, blk_size: u32) -> anyhow::Result<Superblock>
 where
     P: AsRef<Path>,
 {
+    if !blk_size.is_power_of_two() {
+        bail!("block size {blk_size} is not a power of two");
+    }
+
     let bg_size = block_group_size(blk_size);
     if file_size < (bg_size - 2 * blk_size as u64) {
         bail!("file size too small");
@@ -31,3 +35,86 @@ where
 
     Ok(sb)
 }
+
+/// Check that the file at `path` was formatted by [`make`], returning its superblock
+pub fn validate<P>(path: P) -> anyhow::Result<Superblock>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file =
+        File::open(path).map_err(|e| anyhow!("{}: {e}, run mkfs to create it", path.display()))?;
+
+    let sb = Superblock::deserialize_from(BufReader::new(file))
+        .map_err(|e| anyhow!("{} was not formatted by mkfs: {e}", path.display()))?;
+    if sb.magic != FERRIX_MAGIC {
+        bail!("{} was not formatted by mkfs: bad magic", path.display());
+    }
+
+    Ok(sb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple_ext4::DEFAULT_BLOCK_SIZE;
+
+    #[test]
+    fn validate_formatted_file() -> anyhow::Result<()> {
+        // Arrange
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ferrix.vdisk");
+        let sb = make(
+            &path,
+            block_group_size(DEFAULT_BLOCK_SIZE),
+            DEFAULT_BLOCK_SIZE,
+        )?;
+
+        // Act
+        let validated = validate(&path)?;
+
+        // Assert
+        assert_eq!(validated.groups, sb.groups);
+        assert_eq!(validated.block_size, sb.block_size);
+        Ok(())
+    }
+
+    #[test]
+    fn make_invalid_sizes() -> anyhow::Result<()> {
+        // Arrange
+        let dir = tempfile::tempdir()?;
+        let inputs = [
+            (block_group_size(DEFAULT_BLOCK_SIZE), 1000),
+            (DEFAULT_BLOCK_SIZE as u64, DEFAULT_BLOCK_SIZE),
+        ];
+
+        for (file_size, blk_size) in inputs {
+            // Act
+            let result = make(dir.path().join("ferrix.vdisk"), file_size, blk_size);
+
+            // Assert
+            assert!(result.is_err(), "{file_size} {blk_size}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate_unformatted_file() -> anyhow::Result<()> {
+        // Arrange
+        let dir = tempfile::tempdir()?;
+        let inputs = [
+            dir.path().join("missing.vdisk"),
+            dir.path().join("raw.vdisk"),
+        ];
+        File::create(&inputs[1])?.set_len(SUPERBLOCK_SIZE)?;
+
+        for input in inputs {
+            // Act
+            let result = validate(&input);
+
+            // Assert
+            assert!(result.is_err(), "{}", input.display());
+        }
+        Ok(())
+    }
+}
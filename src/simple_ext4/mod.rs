@@ -1,13 +1,27 @@
+pub mod dcache;
 pub mod fs;
 pub mod flemis_system;
 pub mod types;
+pub mod layout;
 pub mod fs_in_fs;
 pub mod mkfs;
 use std::time::{self, SystemTime};
 
 const FERRIX_MAGIC: u32 = 0x64627a;
+/// On-disk format version, bump it on every layout change
+/// - 2: superblock timestamps are stored in nanoseconds instead of seconds
+/// - 3: inodes carry a `flags` field
+/// - 4: directories record their `parent` for `..`
+/// - 5: directories are a header followed by their entries in the order they were added
+const FERRIX_VERSION: u16 = 5;
+/// Oldest on-disk format version that can still be mounted, the versions after it are migrated
+/// on mount and older images have to be recreated
+/// - 2: the superblock timestamps of version 1 are in seconds
+/// - 3: the inodes of version 2 have no `flags` and don't deserialize with the current layout
+/// - 4: the directories of version 3 don't record their `parent` for `..`
+const MIN_SUPPORTED_VERSION: u16 = 4;
 const ROOT_INODE: u32 = 1;
-const INODE_SIZE: u64 = 138;
+const INODE_SIZE: u64 = 142;
 pub const SUPERBLOCK_SIZE: u64 = 1024;
 pub const DIRECT_POINTERS: u64 = 12;
 pub const DEFAULT_BLOCK_SIZE: u32 = 4096;
@@ -22,26 +36,16 @@ where
     hasher.finalize()
 }
 
+/// Nanoseconds since the Unix epoch, as stored in the superblock timestamps
 #[inline]
 pub fn now() -> u64 {
     SystemTime::now()
         .duration_since(time::UNIX_EPOCH)
         .unwrap()
-        .as_secs()
+        .as_nanos() as u64
 }
 
+/// Size of a block group, see [`layout::Layout`] for what it is made of
 pub fn block_group_size(blk_size: u32) -> u64 {
-    let size = blk_size + // data bitmap
-        blk_size + // inode bitmap
-        inode_table_size(blk_size) +
-        data_table_size(blk_size);
-    size as u64
-}
-
-pub fn inode_table_size(blk_size: u32) -> u32 {
-    blk_size * 8 * INODE_SIZE as u32
-}
-
-pub fn data_table_size(blk_size: u32) -> u32 {
-    blk_size * blk_size * 8
+    layout::Layout::new(blk_size).group_size()
 }
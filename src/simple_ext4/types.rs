@@ -1,11 +1,13 @@
-use super::{fs::FSResult, DIRECT_POINTERS, FERRIX_MAGIC, SUPERBLOCK_SIZE};
+use super::{
+    fs::FSResult, layout::Layout, DIRECT_POINTERS, FERRIX_MAGIC, FERRIX_VERSION, INODE_SIZE,
+};
 use anyhow::anyhow;
 use bitvec::{order::Lsb0, vec::BitVec};
 use fuser::{FileAttr, FileType};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     io::{prelude::*, SeekFrom},
     path::Path,
     time::SystemTime,
@@ -16,6 +18,7 @@ use tracing::debug;
 pub struct Superblock {
     pub magic: u32,
     pub block_size: u32,
+    /// Timestamps are in nanoseconds since the Unix epoch, see [`super::now`]
     pub created_at: u64,
     pub modified_at: Option<u64>,
     pub last_mounted_at: Option<u64>,
@@ -27,12 +30,19 @@ pub struct Superblock {
     pub data_blocks_per_group: u32,
     pub uid: u32,
     pub gid: u32,
+    pub version: u16,
     pub checksum: u32,
 }
 
 impl Superblock {
     pub fn new(block_size: u32, groups: u32, uid: u32, gid: u32) -> Self {
-        let total_blocks = block_size * 8 * groups;
+        // Every group holds as many data blocks as its data region fits and as many inodes as
+        // its inode table does
+        let layout = Layout::new(block_size);
+        let blocks_per_group = (layout.data_region_size() / block_size as u64) as u32;
+        let inodes_per_group = (layout.inode_table_size() / INODE_SIZE) as u32;
+        let total_blocks = blocks_per_group * groups;
+        let total_inodes = inodes_per_group * groups;
         Self {
             block_size,
             groups,
@@ -43,14 +53,20 @@ impl Superblock {
             modified_at: None,
             last_mounted_at: None,
             free_blocks: total_blocks,
-            free_inodes: total_blocks,
+            free_inodes: total_inodes,
             block_count: total_blocks,
-            inode_count: total_blocks,
-            data_blocks_per_group: block_size * 8,
+            inode_count: total_inodes,
+            data_blocks_per_group: blocks_per_group,
+            version: FERRIX_VERSION,
             checksum: 0,
         }
     }
 
+    /// Number of inodes in every group, as many as its inode table holds
+    pub fn inodes_per_group(&self) -> u32 {
+        self.inode_count / self.groups
+    }
+
     pub fn update_last_mounted_at(&mut self) {
         self.last_mounted_at = Some(super::now());
     }
@@ -115,9 +131,9 @@ impl Group {
     {
         assert!(!groups.is_empty());
         let blk_size = groups.first().unwrap().data_bitmap.len() / 8;
+        let layout = Layout::new(blk_size as u32);
         for (i, g) in groups.iter().enumerate() {
-            let offset = super::block_group_size(blk_size as u32) * i as u64 + SUPERBLOCK_SIZE;
-            w.seek(SeekFrom::Start(offset))?;
+            w.seek(SeekFrom::Start(layout.data_bitmap_offset(i as u64)))?;
             w.write_all(g.data_bitmap.as_raw_slice())?;
             w.write_all(g.inode_bitmap.as_raw_slice())
                 .inspect_err(|e| println!("expected to be here {e:?}"))?;
@@ -136,9 +152,9 @@ impl Group {
             buf.set_len(blk_size as usize);
         }
 
+        let layout = Layout::new(blk_size);
         for i in 0..count {
-            let offset = super::block_group_size(blk_size) * i as u64 + SUPERBLOCK_SIZE;
-            r.seek(SeekFrom::Start(offset))?;
+            r.seek(SeekFrom::Start(layout.data_bitmap_offset(i as u64)))?;
             r.read_exact(&mut buf)?;
             let data_bitmap = BitVec::<u8, Lsb0>::from_slice(&buf);
             r.read_exact(&mut buf)?;
@@ -233,6 +249,11 @@ impl Group {
     }
 }
 
+/// Inode flag that rejects writes, truncation and unlinking, same value as Linux `FS_IMMUTABLE_FL`
+pub const INODE_FLAG_IMMUTABLE: u32 = 0x10;
+/// Inode flag that only allows writes at the end of the file, same value as Linux `FS_APPEND_FL`
+pub const INODE_FLAG_APPEND_ONLY: u32 = 0x20;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Inode {
     pub mode: libc::mode_t,
@@ -248,6 +269,7 @@ pub struct Inode {
     pub direct_blocks: [u32; DIRECT_POINTERS as usize],
     pub indirect_block: u32,
     pub double_indirect_block: u32,
+    pub flags: u32,
     pub checksum: u32,
     pub block_size: u32,
 }
@@ -270,6 +292,7 @@ impl Inode {
             block_size,
             indirect_block: 0,
             double_indirect_block: 0,
+            flags: 0,
             checksum: 0,
         }
     }
@@ -303,6 +326,18 @@ impl Inode {
         (self.mode & libc::S_IFDIR) != 0
     }
 
+    pub fn is_symlink(&self) -> bool {
+        (self.mode & libc::S_IFMT) == libc::S_IFLNK
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        (self.flags & INODE_FLAG_IMMUTABLE) != 0
+    }
+
+    pub fn is_append_only(&self) -> bool {
+        (self.flags & INODE_FLAG_APPEND_ONLY) != 0
+    }
+
     pub fn update_modified_at(&mut self) {
         let now = SystemTime::now();
         self.changed_at = now;
@@ -316,6 +351,8 @@ impl Inode {
     pub fn to_attr(&self, index: u32) -> FileAttr {
         let kind = if self.is_dir() {
             FileType::Directory
+        } else if self.is_symlink() {
+            FileType::Symlink
         } else {
             FileType::RegularFile
         };
@@ -335,7 +372,7 @@ impl Inode {
             gid: self.group_id,
             rdev: 0,
             blksize: self.block_size,
-            flags: 0,
+            flags: self.flags,
         }
     }
 
@@ -373,6 +410,11 @@ impl Inode {
         self.block_count = self.size as u32 / 512 + 1;
     }
 
+    pub fn set_size(&mut self, len: u64) {
+        self.size = len;
+        self.block_count = self.size as u32 / 512 + 1;
+    }
+
     pub fn increment_size(&mut self, len: u64) {
         self.size += len;
         self.block_count = self.size as u32 / 512 + 1;
@@ -393,31 +435,135 @@ impl Inode {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// Size in bytes of the [`DirectoryHeader`] at the start of a serialized directory
+pub const DIRECTORY_HEADER_SIZE: u64 = 12;
+
+/// Start of a serialized directory, followed by `len` bytes of entries.
+///
+/// Each entry is a `(name, inode)` pair in the order they were added, so a new entry is written
+/// after the others and only the header changes before them. A later entry with the same name
+/// replaces an earlier one.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryHeader {
+    /// Inode of the directory `..` refers to, the root is its own parent
+    pub parent: u32,
+    /// Size in bytes of the entries after the header
+    pub len: u32,
+    /// CRC32 of the parent and the entries, resumed from the previous one on every append
+    pub checksum: u32,
+}
+
+impl DirectoryHeader {
+    fn new(parent: u32) -> Self {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&parent.to_le_bytes());
+        Self {
+            parent,
+            len: 0,
+            checksum: hasher.finalize(),
+        }
+    }
+
+    fn append(&mut self, entry: &[u8]) -> anyhow::Result<()> {
+        let mut hasher = crc32fast::Hasher::new_with_initial(self.checksum);
+        hasher.update(entry);
+        self.len = self
+            .len
+            .checked_add(entry.len().try_into()?)
+            .ok_or_else(|| anyhow!("Directory is too large"))?;
+        self.checksum = hasher.finalize();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Directory {
     pub entries: BTreeMap<OsString, u32>,
-    checksum: u32,
+    /// Inode of the directory `..` refers to, the root is its own parent
+    pub parent: u32,
+    /// Header and number of entries as last serialized or deserialized, `None` for a directory
+    /// never written
+    written: Option<(DirectoryHeader, usize)>,
 }
 
 impl Directory {
-    pub fn serialize_into<W>(&mut self, w: W) -> anyhow::Result<()>
+    pub fn new(parent: u32) -> Self {
+        Self {
+            parent,
+            ..Default::default()
+        }
+    }
+
+    /// Serialize the header and every entry, returning the header written
+    pub fn serialize_into<W>(&mut self, mut w: W) -> anyhow::Result<DirectoryHeader>
     where
         W: Write,
     {
-        self.checksum();
-        bincode::serialize_into(w, self).map_err(|e| e.into())
+        let mut header = DirectoryHeader::new(self.parent);
+        let mut entries = Vec::new();
+        for entry in &self.entries {
+            let start = entries.len();
+            bincode::serialize_into(&mut entries, &entry)?;
+            header.append(&entries[start..])?;
+        }
+
+        bincode::serialize_into(&mut w, &header)?;
+        w.write_all(&entries)?;
+        self.written = Some((header, self.entries.len()));
+        Ok(header)
     }
 
-    pub fn deserialize_from<R>(r: R) -> anyhow::Result<Self>
+    pub fn deserialize_from<R>(mut r: R) -> anyhow::Result<Self>
     where
         R: Read,
     {
-        let mut sb: Self = bincode::deserialize_from(r)?;
-        if !sb.verify_checksum() {
+        let header: DirectoryHeader = bincode::deserialize_from(&mut r)?;
+        let mut data = vec![0u8; header.len as usize];
+        r.read_exact(&mut data)?;
+
+        let mut expected = DirectoryHeader::new(header.parent);
+        expected.append(&data)?;
+        if expected != header {
             return Err(anyhow!("Directory checksum verification failed"));
         }
 
-        Ok(sb)
+        let mut entries = BTreeMap::new();
+        let mut data = data.as_slice();
+        while !data.is_empty() {
+            let (name, index): (OsString, u32) = bincode::deserialize_from(&mut data)?;
+            entries.insert(name, index);
+        }
+
+        let written = Some((header, entries.len()));
+        Ok(Self {
+            entries,
+            parent: header.parent,
+            written,
+        })
+    }
+
+    /// Add the entry `name` and return its serialized bytes with the updated header, to be
+    /// written after the others instead of serializing the whole directory again.
+    ///
+    /// Returns `None` when that can't describe the directory, as it was never written, changed
+    /// since or `name` replaces an entry, then the directory has to be serialized again
+    pub fn append(
+        &mut self,
+        name: &OsStr,
+        index: u32,
+    ) -> anyhow::Result<Option<(Vec<u8>, DirectoryHeader)>> {
+        let replaced = self.entries.insert(name.to_owned(), index).is_some();
+        let Some((header, len)) = &mut self.written else {
+            return Ok(None);
+        };
+        if replaced || header.parent != self.parent || *len + 1 != self.entries.len() {
+            return Ok(None);
+        }
+
+        let entry = bincode::serialize(&(name, index))?;
+        header.append(&entry)?;
+        *len += 1;
+        Ok(Some((entry, *header)))
     }
 
     pub fn entry<P>(&self, path: P) -> FSResult<u32>
@@ -429,25 +575,40 @@ impl Directory {
             .copied()
             .ok_or(nix::errno::Errno::ENOENT)
     }
+}
 
-    fn checksum(&mut self) {
-        self.checksum = 0;
-        self.checksum = super::calculate_checksum(&self);
-    }
+/// Directory as serialized up to format version 4, a single checksummed blob of all its entries.
+/// Only read to migrate it to the current [`Directory`] layout
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyDirectory {
+    entries: BTreeMap<OsString, u32>,
+    parent: u32,
+    checksum: u32,
+}
 
-    fn verify_checksum(&mut self) -> bool {
-        let checksum = self.checksum;
-        self.checksum = 0;
-        let ok = checksum == super::calculate_checksum(&self);
-        self.checksum = checksum;
+impl LegacyDirectory {
+    pub fn deserialize_from<R>(r: R) -> anyhow::Result<Directory>
+    where
+        R: Read,
+    {
+        let mut dir: Self = bincode::deserialize_from(r)?;
+        let checksum = std::mem::take(&mut dir.checksum);
+        if checksum != super::calculate_checksum(&dir) {
+            return Err(anyhow!("Directory checksum verification failed"));
+        }
 
-        ok
+        Ok(Directory {
+            entries: dir.entries,
+            parent: dir.parent,
+            written: None,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::simple_ext4::SUPERBLOCK_SIZE;
     use anyhow::*;
     use std::io::Cursor;
     use std::time::{self, SystemTime};
@@ -455,7 +616,10 @@ mod tests {
     #[test]
     fn superblock_new() {
         let sb = Superblock::new(1024, 3, 0, 0);
-        assert_eq!(sb.free_inodes, 8192 * 3);
+        let inode_table_size = Layout::new(1024).inode_table_size();
+        assert_eq!(sb.inode_count as u64, inode_table_size / INODE_SIZE * 3);
+        assert_eq!(sb.free_inodes, sb.inode_count);
+        assert_eq!(sb.inodes_per_group() as u64, inode_table_size / INODE_SIZE);
         assert_eq!(sb.free_blocks, 8192 * 3);
         assert_eq!(sb.data_blocks_per_group, 1024 * 8);
     }
@@ -629,23 +793,18 @@ mod tests {
 
     #[test]
     fn directory_serialization() -> anyhow::Result<()> {
-        let mut entries = BTreeMap::new();
-        entries.insert(OsString::from("foo.txt"), 1);
-        entries.insert(OsString::from("bar.txt"), 2);
-        let mut dir = Directory {
-            entries,
-            checksum: 0,
-        };
+        let mut dir = Directory::new(1);
+        dir.entries.insert(OsString::from("foo.txt"), 1);
+        dir.entries.insert(OsString::from("bar.txt"), 2);
 
-        let size = bincode::serialized_size(&dir)?;
-        let buf = vec![0u8; size as _];
-        let mut cursor = Cursor::new(buf);
-        dir.serialize_into(&mut cursor)?;
-        cursor.set_position(0);
-        let deserialized = Directory::deserialize_from(cursor)?;
+        let mut buf = Vec::new();
+        let header = dir.serialize_into(&mut buf)?;
+        let deserialized = Directory::deserialize_from(buf.as_slice())?;
 
+        assert_eq!(buf.len() as u64, DIRECTORY_HEADER_SIZE + header.len as u64);
         assert_eq!(deserialized.entries.len(), 2);
-        assert_ne!(deserialized.checksum, 0);
+        assert_eq!(deserialized.parent, 1);
+        assert_ne!(header.checksum, 0);
         for (i, (path, inode)) in deserialized.entries.iter().enumerate() {
             if i == 0 {
                 assert_eq!(path, &OsString::from("bar.txt"));
@@ -656,6 +815,32 @@ mod tests {
             }
         }
 
+        buf[DIRECTORY_HEADER_SIZE as usize] ^= 1;
+        assert!(Directory::deserialize_from(buf.as_slice()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn directory_append() -> anyhow::Result<()> {
+        let mut dir = Directory::new(1);
+        dir.entries.insert(OsString::from("foo.txt"), 1);
+        let mut buf = Vec::new();
+        dir.serialize_into(&mut buf)?;
+
+        let (entry, header) = dir.append(OsStr::new("bar.txt"), 2)?.unwrap();
+        buf.extend_from_slice(&entry);
+        bincode::serialize_into(&mut buf[..DIRECTORY_HEADER_SIZE as usize], &header)?;
+        let appended = Directory::deserialize_from(buf.as_slice())?;
+
+        let mut full = Vec::new();
+        assert_eq!(dir.serialize_into(&mut full)?.len, header.len);
+        assert_eq!(appended.entries, dir.entries);
+        assert!(dir.append(OsStr::new("bar.txt"), 3)?.is_none());
+        assert!(Directory::new(1)
+            .append(OsStr::new("foo.txt"), 1)?
+            .is_none());
+
         Ok(())
     }
 
@@ -666,7 +851,8 @@ mod tests {
         entries.insert(OsString::from("bar.txt"), 2);
         let dir = Directory {
             entries,
-            checksum: 0,
+            parent: 1,
+            ..Default::default()
         };
 
         assert_eq!(dir.entry("foo.txt")?, 1);
@@ -1,10 +1,13 @@
 use std::{
     collections::BinaryHeap,
+    fs::File,
     io::{Read, Seek, Write},
     num::NonZero,
+    ops::Range,
+    path::Path,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc, Mutex,
+        mpsc, Arc, Mutex,
     },
 };
 
@@ -15,7 +18,7 @@ use rayon::{
     slice::ParallelSliceMut,
 };
 
-use crate::ext_arr::ExtArr;
+use crate::ext_arr::{ExtArr, FileBufRW, Reserve, SyncRW};
 
 struct ExtItem<T, R> {
     item: T,
@@ -42,18 +45,267 @@ impl<T: PartialEq, R> PartialEq for ExtItem<T, R> {
 
 impl<T: Eq, R> Eq for ExtItem<T, R> {}
 
+/// An element ordered only by its key, so the element itself doesn't need to be `Ord`
+struct KeyedItem<K, T>(K, T);
+
+impl<K: Ord, T> Ord for KeyedItem<K, T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<K: Ord, T> PartialOrd for KeyedItem<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> PartialEq for KeyedItem<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq(&other.0)
+    }
+}
+
+impl<K: Ord, T> Eq for KeyedItem<K, T> {}
+
+/// A source of a merge that reads ahead a batch of elements into its own window of the merge
+/// buffer, instead of reading one element at a time
+struct ReadAhead<'b, T, R> {
+    source: &'b mut ExtArr<T, R>,
+    window: Range<usize>,
+    pos: usize,
+    len: usize,
+}
+
+impl<'b, T: AnyBitPattern + NoUninit, R: Read> ReadAhead<'b, T, R> {
+    /// Split `buf` in a window per source, each one a whole number of elements.
+    ///
+    /// If `buf` can't fit an element per source every source shares the first element of `buf`,
+    /// which is safe as a window of a single element is always consumed right after being read.
+    fn split<I>(buf: &[u8], sources: I) -> Vec<Self>
+    where
+        I: ExactSizeIterator<Item = &'b mut ExtArr<T, R>>,
+    {
+        let size = std::mem::size_of::<T>();
+        let share = (buf.len() / sources.len().max(1) / size).max(1) * size;
+        let shared = share * sources.len() > buf.len();
+
+        sources
+            .enumerate()
+            .map(|(i, source)| {
+                let start = if shared { 0 } else { i * share };
+                ReadAhead {
+                    source,
+                    window: start..start + share,
+                    pos: 0,
+                    len: 0,
+                }
+            })
+            .collect()
+    }
+
+    /// Take the next element of the source, refilling the window from it when it is exhausted
+    fn next(&mut self, buf: &mut [u8]) -> std::io::Result<Option<T>> {
+        if self.pos == self.len {
+            let mut window = &mut buf[self.window.clone()];
+            self.len = self.source.read(&mut window)?.len();
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+
+        let size = std::mem::size_of::<T>();
+        let start = self.window.start + self.pos * size;
+        self.pos += 1;
+        Ok(Some(bytemuck::pod_read_unaligned(
+            &buf[start..start + size],
+        )))
+    }
+}
+
+/// Statistics of an external sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortResult {
+    /// The number of sorted runs written to temporary external arrays
+    pub runs: usize,
+    /// The number of passes over the data, the run generation plus the merge if needed
+    pub passes: usize,
+}
+
+/// Number of merged elements handed at once to the background writer of a concurrent merge
+const MERGE_BATCH_LEN: usize = 4096;
+/// Number of batches that can wait for the background writer before the merge blocks
+const MERGE_PIPELINE_DEPTH: usize = 2;
+
+/// Validate that every worker gets a buffer of at least one element
+fn check_buffer_size<T>(buf: &[u8], workers: usize) -> std::io::Result<()> {
+    let min_size = workers * std::mem::size_of::<T>();
+    if buf.len() < min_size {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "sort buffer of {} bytes is too small, at least {} bytes are needed for {} worker(s)",
+                buf.len(),
+                min_size,
+                workers
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate that a chunk of `chunk_size` elements fits in the `buf_len` bytes given to a worker,
+/// returning the chunk length in bytes
+fn check_chunk_size<T>(buf_len: usize, chunk_size: NonZero<usize>) -> std::io::Result<usize> {
+    let chunk_len = chunk_size.get().saturating_mul(std::mem::size_of::<T>());
+    if chunk_len > buf_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "sort chunk of {} element(s) needs {} bytes but only {} bytes are available",
+                chunk_size, chunk_len, buf_len
+            ),
+        ));
+    }
+
+    Ok(chunk_len)
+}
+
+/// Directory of the temporary files a sort spills its runs to, removed with every file in it
+/// when dropped, whether the sort finished, failed or panicked.
+///
+/// Its [`TempRuns::create`] is meant as the `f` of the sorters, e.g.
+/// `ExtSorter::sort(&mut arr, buf, |run| runs.create(run))`.
+pub struct TempRuns {
+    dir: tempfile::TempDir,
+}
+
+impl TempRuns {
+    /// Create the directory in the system temporary directory
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            dir: tempfile::Builder::new().prefix("ferrix-sort").tempdir()?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Create the file of the `run`th run, failing if it already exists
+    pub fn create<T>(&self, run: usize) -> std::io::Result<ExtArr<T, File>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(self.dir.path().join(format!("run-{run}")))?;
+
+        Ok(ExtArr::new(file))
+    }
+
+    /// Like [`TempRuns::create`] but buffered and shareable between threads, as needed by
+    /// [`RayonExtSorter`]
+    pub fn create_shared<T>(&self, run: usize) -> std::io::Result<ExtArr<T, SyncRW<FileBufRW>>> {
+        let file = self.create::<T>(run)?.into_inner();
+        Ok(ExtArr::new(SyncRW::new(FileBufRW::try_from(file)?)))
+    }
+
+    /// Create an unnamed file in the directory, e.g. for the array being sorted
+    pub fn tempfile(&self) -> std::io::Result<File> {
+        tempfile::tempfile_in(self.dir.path())
+    }
+}
+
 pub struct ExtSorter;
 
 impl ExtSorter {
-    pub fn sort<T, RW, F>(ext_arr: &mut ExtArr<T, RW>, buf: &mut [u8], f: F) -> std::io::Result<()>
+    /// Sort the external array using `buf` as memory and `f` to create the temporary arrays.
+    ///
+    /// An array that fits in `buf` is sorted in place without any temporary array. Otherwise the
+    /// array is checked for order while reading it, an already sorted array is left as is
+    /// without spilling any run or merging them. See [`ExtSorter::sort_chunks_unless_sorted`].
+    pub fn sort<T, RW, F>(
+        ext_arr: &mut ExtArr<T, RW>,
+        buf: &mut [u8],
+        f: F,
+    ) -> std::io::Result<SortResult>
     where
         T: Ord + bytemuck::Pod,
-        RW: Read + Write + Seek,
+        RW: Read + Write + Seek + Reserve,
+        F: Fn(usize) -> std::io::Result<ExtArr<T, RW>>,
+    {
+        check_buffer_size::<T>(buf, 1)?;
+        let chunk_size = NonZero::new(buf.len() / std::mem::size_of::<T>())
+            .expect("buffer fits at least one element");
+        Self::sort_with_chunk_size(ext_arr, buf, chunk_size, f)
+    }
+
+    /// Like [`ExtSorter::sort`] but each run is sorted in a chunk of `chunk_size` elements instead
+    /// of the whole `buf`, trading more runs to merge for cheaper sorts of each run.
+    ///
+    /// The merge still reads ahead using the whole `buf`, so the chunk has to fit in it.
+    pub fn sort_with_chunk_size<T, RW, F>(
+        ext_arr: &mut ExtArr<T, RW>,
+        buf: &mut [u8],
+        chunk_size: NonZero<usize>,
+        f: F,
+    ) -> std::io::Result<SortResult>
+    where
+        T: Ord + bytemuck::Pod,
+        RW: Read + Write + Seek + Reserve,
         F: Fn(usize) -> std::io::Result<ExtArr<T, RW>>,
     {
-        let mut tmp_arrs = Self::sort_chunks(buf, ext_arr, &f)?;
+        check_buffer_size::<T>(buf, 1)?;
+        let chunk_len = check_chunk_size::<T>(buf.len(), chunk_size)?;
+        if Self::sort_single_chunk(&mut buf[..chunk_len], ext_arr)? {
+            return Ok(SortResult { runs: 0, passes: 1 });
+        }
+
+        let Some(mut tmp_arrs) =
+            Self::sort_chunks_unless_sorted(&mut buf[..chunk_len], ext_arr, &f)?
+        else {
+            return Ok(SortResult { runs: 0, passes: 1 });
+        };
+        let runs = tmp_arrs.len();
+
+        // The merge writes back every element read, its room is set aside before writing
+        let elements = ext_arr.stream_position()? / size_of::<T>() as u64;
         ext_arr.rewind()?;
-        Self::merge_chunks(buf, ext_arr, tmp_arrs.iter_mut())
+        ext_arr.reserve(elements)?;
+        Self::merge_chunks(buf, ext_arr, tmp_arrs.iter_mut())?;
+        Ok(SortResult { runs, passes: 2 })
+    }
+
+    /// Like [`ExtSorter::sort`] but the merged output is written by a background thread through
+    /// a clone of the [`SyncRW`] writer, while this thread keeps popping the heap.
+    ///
+    /// The merged elements are handed over in batches of [`MERGE_BATCH_LEN`] allocated outside of
+    /// `buf`, so it trades some extra memory for overlapping the final write with the merge.
+    pub fn sort_with_concurrent_writer<T, RW, F>(
+        ext_arr: &mut ExtArr<T, SyncRW<RW>>,
+        buf: &mut [u8],
+        f: F,
+    ) -> std::io::Result<SortResult>
+    where
+        T: Ord + bytemuck::Pod + Send,
+        RW: Read + Write + Seek + Send,
+        F: Fn(usize) -> std::io::Result<ExtArr<T, SyncRW<RW>>>,
+    {
+        check_buffer_size::<T>(buf, 1)?;
+        if Self::sort_single_chunk(buf, ext_arr)? {
+            return Ok(SortResult { runs: 0, passes: 1 });
+        }
+
+        let Some(mut tmp_arrs) = Self::sort_chunks_unless_sorted(buf, ext_arr, &f)? else {
+            return Ok(SortResult { runs: 0, passes: 1 });
+        };
+        let runs = tmp_arrs.len();
+
+        ext_arr.rewind()?;
+        Self::merge_chunks_concurrent(buf, ext_arr.clone(), tmp_arrs.iter_mut())?;
+        Ok(SortResult { runs, passes: 2 })
     }
 
     pub fn parallel_sort<T, RW, F>(
@@ -68,6 +320,7 @@ impl ExtSorter {
         F: Fn(usize) -> std::io::Result<ExtArr<T, RW>> + Send + Sync + 'static,
     {
         let workers = workers.get();
+        check_buffer_size::<T>(buf, workers)?;
         let chunk_size = buf.len() / workers;
         let mut handles = Vec::with_capacity(workers);
         let f = Arc::new(f);
@@ -81,7 +334,7 @@ impl ExtSorter {
             let handle = std::thread::spawn(move || {
                 let mut buf = buf.lock().unwrap(); // Lock buf to access it safely in the thread
                 let chunk = &mut buf[i * chunk_size..(i + 1) * chunk_size]; // Create a slice for each chunk
-                Self::sort_chunks(chunk, &mut ext_arr, f.as_ref())
+                Self::sort_chunks(chunk, &mut ext_arr, f.as_ref(), 0)
             });
 
             handles.push(handle);
@@ -107,17 +360,98 @@ impl ExtSorter {
         Ok(())
     }
 
+    /// Sort the array in place if it fits in a single chunk of `buf`, returning whether it did.
+    ///
+    /// The array has to fit in a single read, otherwise it is rewound for a regular sort.
+    /// An empty array is left for the regular sort too.
+    fn sort_single_chunk<T, RW>(
+        mut buf: &mut [u8],
+        ext_arr: &mut ExtArr<T, RW>,
+    ) -> std::io::Result<bool>
+    where
+        T: Ord + bytemuck::Pod,
+        RW: Read + Write + Seek,
+    {
+        let read = ext_arr.read(&mut buf)?;
+        let mut probe = vec![0u8; std::mem::size_of::<T>()];
+        if read.is_empty() || !ext_arr.read(&mut probe)?.is_empty() {
+            ext_arr.rewind()?;
+            return Ok(false);
+        }
+
+        read.sort_unstable();
+        ext_arr.rewind()?;
+        ext_arr.write(read)?;
+        ext_arr.flush()?;
+
+        Ok(true)
+    }
+
+    /// Read the reader while it stays sorted, returning `None` without spilling anything if it
+    /// is sorted to the end.
+    ///
+    /// Otherwise the sorted prefix read so far is copied into a single run, as it needs no
+    /// sorting, and the rest of the reader is sorted into a run per chunk.
+    fn sort_chunks_unless_sorted<T, R, F>(
+        mut buf: &mut [u8],
+        reader: &mut ExtArr<T, R>,
+        f: &F,
+    ) -> std::io::Result<Option<Vec<ExtArr<T, R>>>>
+    where
+        T: Ord + bytemuck::Pod,
+        R: Read + Write + Seek,
+        F: Fn(usize) -> std::io::Result<ExtArr<T, R>>,
+    {
+        let mut prefix_len: usize = 0;
+        let mut last: Option<T> = None;
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read.is_empty() {
+                return Ok(None);
+            }
+
+            // Check if the chunk keeps the sequence monotonic
+            if !(last.is_none_or(|last| last <= read[0]) && read.is_sorted()) {
+                break;
+            }
+            last = read.last().copied();
+            prefix_len += std::mem::size_of_val(read);
+        }
+
+        let mut tmp_arrs = Vec::new();
+        reader.rewind()?;
+        if prefix_len > 0 {
+            let mut run = f(0)?.with_checksum();
+            let size = std::mem::size_of::<T>();
+            let mut left = prefix_len;
+            while left > 0 {
+                let len = left.min(buf.len() - buf.len() % size);
+                run.write(reader.read_exact(&mut &mut buf[..len])?)?;
+                left -= len;
+            }
+            run.flush()?;
+            run.rewind()?;
+            tmp_arrs.push(run);
+        }
+
+        let first_id = tmp_arrs.len();
+        tmp_arrs.extend(Self::sort_chunks(buf, reader, f, first_id)?);
+        Ok(Some(tmp_arrs))
+    }
+
+    /// Sort each chunk of the reader into a temporary array, numbering them from `first_id`
     fn sort_chunks<T, R, F>(
         mut buf: &mut [u8],
         reader: &mut ExtArr<T, R>,
         f: &F,
+        first_id: usize,
     ) -> std::io::Result<Vec<ExtArr<T, R>>>
     where
         T: Ord + bytemuck::Pod,
         R: Read + Write + Seek,
         F: Fn(usize) -> std::io::Result<ExtArr<T, R>>,
     {
-        let mut chunk_id: usize = 0;
+        let mut chunk_id = first_id;
         let mut tmp_arrs = Vec::new();
         loop {
             let read = reader.read(&mut buf)?;
@@ -129,7 +463,7 @@ impl ExtSorter {
             read.sort_unstable();
 
             // Write number order to a tmp external array
-            let mut tmp_ext_arr = f(chunk_id)?;
+            let mut tmp_ext_arr = f(chunk_id)?.with_checksum();
             tmp_ext_arr.write(read)?;
             tmp_ext_arr.flush()?;
             tmp_ext_arr.rewind()?;
@@ -140,7 +474,12 @@ impl ExtSorter {
         Ok(tmp_arrs)
     }
 
-    fn merge_chunks<'b, T, W, I, R>(
+    /// Merge the already sorted `chunk_readers` into `writer` by repeatedly popping the smallest
+    /// head element off a heap.
+    ///
+    /// `buf` is split evenly between the readers to read ahead batches of elements, it only has
+    /// to fit a single element but reads one element at a time when it can't fit one per reader
+    pub fn merge_chunks<'b, T, W, I, R>(
         buf: &mut [u8],
         writer: &mut ExtArr<T, W>,
         chunk_readers: I,
@@ -152,22 +491,47 @@ impl ExtSorter {
         W: Write,
         R: Read + 'b,
     {
-        let sources = chunk_readers.into_iter();
-        let mut heap = BinaryHeap::with_capacity(sources.len());
-        let (mut num_buffer, _) = buf.split_at_mut(std::mem::size_of::<T>());
+        Self::merge_chunks_by_key(buf, writer, chunk_readers, |item| item)
+    }
 
-        for source in sources {
-            let item = source.read(&mut num_buffer)?[0];
+    /// Like [`ExtSorter::merge_chunks`] but the chunks are sorted by `key`, e.g. merging chunks
+    /// sorted in descending order with [`std::cmp::Reverse`] as the key
+    pub fn merge_chunks_by_key<'b, T, K, W, I, R, F>(
+        buf: &mut [u8],
+        writer: &mut ExtArr<T, W>,
+        chunk_readers: I,
+        key: F,
+    ) -> std::io::Result<()>
+    where
+        T: AnyBitPattern + NoUninit,
+        K: Ord,
+        I: IntoIterator<Item = &'b mut ExtArr<T, R>>,
+        <I as IntoIterator>::IntoIter: ExactSizeIterator,
+        W: Write,
+        R: Read + 'b,
+        F: Fn(T) -> K,
+    {
+        let mut sources = ReadAhead::split(buf, chunk_readers.into_iter());
+        let mut heap = BinaryHeap::with_capacity(sources.len());
 
-            heap.push(ExtItem { item, source });
+        for (source, read_ahead) in sources.iter_mut().enumerate() {
+            if let Some(item) = read_ahead.next(buf)? {
+                heap.push(ExtItem {
+                    item: KeyedItem(key(item), item),
+                    source,
+                });
+            }
         }
 
-        while let Some(ExtItem { item, source }) = heap.pop() {
+        while let Some(ExtItem {
+            item: KeyedItem(_, item),
+            source,
+        }) = heap.pop()
+        {
             writer.write(&[item])?;
-            let read = source.read(&mut num_buffer)?;
-            if !read.is_empty() {
+            if let Some(item) = sources[source].next(buf)? {
                 heap.push(ExtItem {
-                    item: read[0],
+                    item: KeyedItem(key(item), item),
                     source,
                 });
             }
@@ -175,11 +539,74 @@ impl ExtSorter {
         writer.flush()?;
         Ok(())
     }
+
+    fn merge_chunks_concurrent<'b, T, W, I, R>(
+        buf: &mut [u8],
+        writer: ExtArr<T, SyncRW<W>>,
+        chunk_readers: I,
+    ) -> std::io::Result<()>
+    where
+        T: Ord + AnyBitPattern + NoUninit + Send,
+        I: IntoIterator<Item = &'b mut ExtArr<T, R>>,
+        <I as IntoIterator>::IntoIter: ExactSizeIterator,
+        W: Write + Send,
+        R: Read + 'b,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<T>>(MERGE_PIPELINE_DEPTH);
+
+        std::thread::scope(|s| {
+            let drain = s.spawn(move || {
+                let mut writer = writer;
+                for batch in receiver {
+                    writer.write(&batch)?;
+                }
+                writer.flush()
+            });
+
+            let merged = (|| {
+                let mut sources = ReadAhead::split(buf, chunk_readers.into_iter());
+                let mut heap = BinaryHeap::with_capacity(sources.len());
+                let stopped = |_| std::io::Error::other("Writer has stopped");
+
+                for (source, read_ahead) in sources.iter_mut().enumerate() {
+                    if let Some(item) = read_ahead.next(buf)? {
+                        heap.push(ExtItem { item, source });
+                    }
+                }
+
+                let mut batch = Vec::with_capacity(MERGE_BATCH_LEN);
+                while let Some(ExtItem { item, source }) = heap.pop() {
+                    batch.push(item);
+                    if batch.len() == MERGE_BATCH_LEN {
+                        let full =
+                            std::mem::replace(&mut batch, Vec::with_capacity(MERGE_BATCH_LEN));
+                        sender.send(full).map_err(stopped)?;
+                    }
+
+                    if let Some(item) = sources[source].next(buf)? {
+                        heap.push(ExtItem { item, source });
+                    }
+                }
+                sender.send(batch).map_err(stopped)
+            })();
+            // Closing the channel lets the writer finish
+            drop(sender);
+
+            let drained = drain
+                .join()
+                .map_err(|_| std::io::Error::other("Writer has panicked"))?;
+
+            // A write error stops the writer, which is the cause of any send error
+            drained.and(merged)
+        })
+    }
 }
 
 pub struct RayonExtSorter<'a> {
     buf: &'a mut [u8],
     workers: usize,
+    /// Number of elements sorted at once by each worker, the whole worker share of `buf` if unset
+    chunk_size: Option<NonZero<usize>>,
 }
 
 impl<'a> RayonExtSorter<'a> {
@@ -187,6 +614,24 @@ impl<'a> RayonExtSorter<'a> {
         Self {
             buf,
             workers: workers.get(),
+            chunk_size: None,
+        }
+    }
+
+    /// Sort runs of `chunk_size` elements, which has to fit in the share of each worker
+    pub fn with_chunk_size(mut self, chunk_size: NonZero<usize>) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Validate the buffer and return how many bytes of its share each worker sorts at once,
+    /// always a whole number of elements
+    fn chunk_len<T>(&self) -> std::io::Result<usize> {
+        check_buffer_size::<T>(self.buf, self.workers)?;
+        let share = self.buf.len() / self.workers;
+        match self.chunk_size {
+            Some(chunk_size) => check_chunk_size::<T>(share, chunk_size),
+            None => Ok(share - share % std::mem::size_of::<T>()),
         }
     }
 
@@ -196,7 +641,8 @@ impl<'a> RayonExtSorter<'a> {
         RW: Read + Write + Seek + Send + Sync + Clone,
         F: Fn(usize) -> std::io::Result<ExtArr<T, RW>> + Sync,
     {
-        let mut tmp_arrs = self.sort_chunks(ext_arr, f)?;
+        let chunk_len = self.chunk_len::<T>()?;
+        let mut tmp_arrs = self.sort_chunks(ext_arr, chunk_len, f)?;
         ext_arr.rewind()?;
 
         self.merge_chunks(ext_arr, &mut tmp_arrs)?;
@@ -213,7 +659,8 @@ impl<'a> RayonExtSorter<'a> {
         RW: Read + Write + Seek + Send + Sync + Clone,
         F: Fn(usize) -> std::io::Result<ExtArr<T, RW>> + Sync,
     {
-        let mut tmp_arrs = self.sort_chunks(ext_arr, f)?;
+        let chunk_len = self.chunk_len::<T>()?;
+        let mut tmp_arrs = self.sort_chunks(ext_arr, chunk_len, f)?;
         ext_arr.rewind()?;
 
         self.merge_chunks_linear(ext_arr, &mut tmp_arrs)?;
@@ -223,6 +670,7 @@ impl<'a> RayonExtSorter<'a> {
     fn sort_chunks<T, R, F>(
         &mut self,
         reader: &mut ExtArr<T, R>,
+        chunk_len: usize,
         f: F,
     ) -> std::io::Result<Vec<ExtArr<T, R>>>
     where
@@ -232,15 +680,16 @@ impl<'a> RayonExtSorter<'a> {
     {
         let chunk_id = AtomicUsize::new(0);
 
-        let chunk_size = self.buf.len() / self.workers;
-        let tmp_arrs: Vec<_> = self
+        let share = self.buf.len() / self.workers;
+        let tmp_arrs: Vec<Vec<_>> = self
             .buf
-            .par_chunks_mut(chunk_size)
-            .flat_map(|mut chunk| {
+            .par_chunks_exact_mut(share)
+            .map(|chunk| {
+                let mut chunk = &mut chunk[..chunk_len];
                 let mut reader = reader.clone();
                 let mut tmp_arrs = Vec::new();
                 loop {
-                    let read = reader.read(&mut chunk).unwrap();
+                    let read = reader.read(&mut chunk)?;
                     if read.is_empty() {
                         break;
                     }
@@ -248,19 +697,18 @@ impl<'a> RayonExtSorter<'a> {
                     // Sort numbers
                     read.par_sort_unstable();
 
-                    // Write number order to a tmp external array
-                    let mut tmp_ext_arr = f(chunk_id.load(Ordering::Relaxed)).unwrap();
-                    tmp_ext_arr.write(read).unwrap();
-                    tmp_ext_arr.flush().unwrap();
-                    tmp_ext_arr.rewind().unwrap();
+                    // Write number order to a tmp external array, each run with its own id
+                    let mut tmp_ext_arr =
+                        f(chunk_id.fetch_add(1, Ordering::Relaxed))?.with_checksum();
+                    tmp_ext_arr.write(read)?;
+                    tmp_ext_arr.flush()?;
+                    tmp_ext_arr.rewind()?;
                     tmp_arrs.push(tmp_ext_arr);
-
-                    chunk_id.fetch_add(1, Ordering::SeqCst);
                 }
-                tmp_arrs
+                Ok(tmp_arrs)
             })
-            .collect();
-        Ok(tmp_arrs)
+            .collect::<std::io::Result<_>>()?;
+        Ok(tmp_arrs.into_iter().flatten().collect())
     }
 
     fn merge_chunks<'i, T, W, I, R>(
@@ -345,3 +793,325 @@ impl<'a> RayonExtSorter<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek};
+
+    use rand::Rng;
+
+    use super::*;
+
+    fn sort(numbers: &[u16], buf_size: usize) -> (Vec<u16>, SortResult) {
+        let mut buf = vec![0u8; buf_size];
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(Vec::new()));
+        arr.write(numbers).unwrap();
+        arr.flush().unwrap();
+        arr.rewind().unwrap();
+
+        let result = ExtSorter::sort(&mut arr, &mut buf, |_| {
+            Ok(ExtArr::new(Cursor::new(Vec::new())))
+        })
+        .unwrap();
+
+        arr.rewind().unwrap();
+        let mut values = Vec::new();
+        let sorted = arr.read_to_end(&mut values).unwrap().to_vec();
+        (sorted, result)
+    }
+
+    #[test]
+    fn sort_unsorted_input() {
+        // Arrange
+        let numbers = [10, 5, 3, 7, 1, 9, 2, 6, 8, 4];
+
+        // Act
+        let (sorted, result) = sort(&numbers, 8);
+
+        // Assert
+        assert_eq!(sorted, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(result, SortResult { runs: 3, passes: 2 });
+    }
+
+    #[test]
+    fn sort_presorted_input_takes_fast_path() {
+        // Arrange
+        let numbers = [1, 2, 2, 3, 5, 8, 13, 21, 34, 55];
+
+        // Act
+        let (sorted, result) = sort(&numbers, 8);
+
+        // Assert
+        assert_eq!(sorted, numbers.to_vec());
+        assert_eq!(result, SortResult { runs: 0, passes: 1 });
+    }
+
+    #[test]
+    fn sort_sorted_prefix_spills_single_run() {
+        // Arrange
+        let numbers = [1, 2, 3, 4, 5, 6, 7, 8, 0, 9];
+
+        // Act
+        let (sorted, result) = sort(&numbers, 8);
+
+        // Assert
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(result, SortResult { runs: 2, passes: 2 });
+    }
+
+    #[test]
+    fn sort_sorted_chunks_out_of_order() {
+        // Arrange
+        let numbers = [5, 6, 7, 8, 1, 2, 3, 4];
+
+        // Act
+        let (sorted, result) = sort(&numbers, 8);
+
+        // Assert
+        assert_eq!(sorted, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(result, SortResult { runs: 2, passes: 2 });
+    }
+
+    #[test]
+    fn sort_single_chunk_in_place() {
+        // Arrange
+        let numbers = [10, 5, 3, 7, 1, 9, 2, 6, 8, 4];
+        let mut buf = vec![0u8; 64];
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(Vec::new()));
+        arr.write(&numbers).unwrap();
+        arr.flush().unwrap();
+        arr.rewind().unwrap();
+        let tmp_arrs = AtomicUsize::new(0);
+
+        // Act
+        let result = ExtSorter::sort(&mut arr, &mut buf, |_| {
+            tmp_arrs.fetch_add(1, Ordering::Relaxed);
+            Ok(ExtArr::new(Cursor::new(Vec::new())))
+        })
+        .unwrap();
+
+        // Assert
+        arr.rewind().unwrap();
+        let mut values = Vec::new();
+        let sorted = arr.read_to_end(&mut values).unwrap().to_vec();
+        assert_eq!(sorted, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(result, SortResult { runs: 0, passes: 1 });
+        assert_eq!(tmp_arrs.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn smaller_chunks_make_more_runs() {
+        // Arrange
+        let numbers = [10, 5, 3, 7, 1, 9, 2, 6, 8, 4];
+        let inputs = [5, 4, 2];
+        let outputs = [2, 3, 5];
+
+        for (chunk_size, runs) in inputs.into_iter().zip(outputs) {
+            let mut buf = vec![0u8; 16];
+            let mut arr = ExtArr::<u16, _>::new(Cursor::new(Vec::new()));
+            arr.write(&numbers).unwrap();
+            arr.flush().unwrap();
+            arr.rewind().unwrap();
+            let tmp_arrs = AtomicUsize::new(0);
+
+            // Act
+            let chunk_size = NonZero::new(chunk_size).unwrap();
+            let result = ExtSorter::sort_with_chunk_size(&mut arr, &mut buf, chunk_size, |_| {
+                tmp_arrs.fetch_add(1, Ordering::Relaxed);
+                Ok(ExtArr::new(Cursor::new(Vec::new())))
+            })
+            .unwrap();
+
+            // Assert
+            arr.rewind().unwrap();
+            let mut values = Vec::new();
+            let sorted = arr.read_to_end(&mut values).unwrap().to_vec();
+            assert_eq!(sorted, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+            assert_eq!(result.runs, runs);
+            assert_eq!(tmp_arrs.load(Ordering::Relaxed), runs);
+        }
+    }
+
+    #[test]
+    fn chunk_larger_than_buffer() {
+        // Arrange
+        let mut buf = [0u8; 8];
+        let mut rayon_buf = [0u8; 8];
+        let chunk_size = NonZero::new(3).unwrap();
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(vec![2, 0, 1, 0]));
+        let mut sorter = RayonExtSorter::new(&mut rayon_buf, NonZero::new(2).unwrap())
+            .with_chunk_size(chunk_size);
+
+        // Act
+        let err =
+            ExtSorter::sort_with_chunk_size(&mut arr, &mut buf, NonZero::new(5).unwrap(), |_| {
+                Ok(ExtArr::new(Cursor::new(Vec::new())))
+            })
+            .unwrap_err();
+        let rayon_err = sorter
+            .sort(&mut arr, |_| Ok(ExtArr::new(Cursor::new(Vec::new()))))
+            .unwrap_err();
+
+        // Assert
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(rayon_err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn concurrent_writer_matches_sequential_sort() {
+        // Arrange
+        let mut rng = rand::rng();
+        let numbers: Vec<u16> = (0..20_000).map(|_| rng.random()).collect();
+        let (expected, expected_result) = sort(&numbers, 1024);
+        let mut buf = vec![0u8; 1024];
+        let mut arr = ExtArr::<u16, _>::new(SyncRW::new(Cursor::new(Vec::new())));
+        arr.write(&numbers).unwrap();
+        arr.flush().unwrap();
+        arr.rewind().unwrap();
+
+        // Act
+        let result = ExtSorter::sort_with_concurrent_writer(&mut arr, &mut buf, |_| {
+            Ok(ExtArr::new(SyncRW::new(Cursor::new(Vec::new()))))
+        })
+        .unwrap();
+
+        // Assert
+        arr.rewind().unwrap();
+        let mut values = Vec::new();
+        let sorted = arr.read_to_end(&mut values).unwrap().to_vec();
+        assert_eq!(sorted, expected);
+        assert_eq!(result, expected_result);
+    }
+
+    #[test]
+    fn merge_with_any_read_ahead() {
+        // Arrange
+        let chunks: [&[u16]; 3] = [&[1, 4, 7, 10], &[2, 5, 8], &[0, 3, 6, 9, 11]];
+        let inputs = [2, 6, 10, 64];
+
+        for buf_size in inputs {
+            let mut buf = vec![0u8; buf_size];
+            let mut sources: Vec<_> = chunks
+                .iter()
+                .map(|chunk| {
+                    ExtArr::<u16, _>::new(Cursor::new(bytemuck::cast_slice(chunk).to_vec()))
+                })
+                .collect();
+            let mut output = ExtArr::<u16, _>::new(Cursor::new(Vec::new()));
+
+            // Act
+            ExtSorter::merge_chunks(&mut buf, &mut output, sources.iter_mut()).unwrap();
+
+            // Assert
+            output.rewind().unwrap();
+            let mut values = Vec::new();
+            let merged = output.read_to_end(&mut values).unwrap().to_vec();
+            assert_eq!(merged, (0..12).collect::<Vec<_>>(), "{buf_size}");
+        }
+    }
+
+    #[test]
+    fn temp_runs_removed_on_drop() {
+        // Arrange
+        let numbers: Vec<u16> = (0..10).rev().collect();
+        let mut buf = [0u8; 8];
+        let mut arr = ExtArr::<u16, _>::new(tempfile::tempfile().unwrap());
+        arr.write(&numbers).unwrap();
+        arr.flush().unwrap();
+        arr.rewind().unwrap();
+        let runs = TempRuns::new().unwrap();
+        let dir = runs.path().to_path_buf();
+
+        // Act
+        let result = ExtSorter::sort(&mut arr, &mut buf, |run| runs.create(run)).unwrap();
+        let spilled = std::fs::read_dir(&dir).unwrap().count();
+        drop(runs);
+
+        // Assert
+        assert_eq!(result, SortResult { runs: 3, passes: 2 });
+        assert_eq!(spilled, 3);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn temp_runs_removed_on_panic() {
+        // Arrange
+        let numbers: Vec<u16> = (0..10).rev().collect();
+        let dir = Mutex::new(None);
+
+        // Act
+        let result = std::panic::catch_unwind(|| {
+            let mut buf = [0u8; 8];
+            let mut arr = ExtArr::<u16, _>::new(tempfile::tempfile().unwrap());
+            arr.write(&numbers).unwrap();
+            arr.flush().unwrap();
+            arr.rewind().unwrap();
+            let runs = TempRuns::new().unwrap();
+            *dir.lock().unwrap() = Some(runs.path().to_path_buf());
+
+            ExtSorter::sort(&mut arr, &mut buf, |run| {
+                assert!(run < 2, "interrupted before run {run}");
+                runs.create(run)
+            })
+        });
+
+        // Assert
+        assert!(result.is_err());
+        assert!(!dir.into_inner().unwrap().unwrap().exists());
+    }
+
+    #[test]
+    fn sort_undersized_buffer() {
+        // Arrange
+        let mut buf = [0u8; 1];
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(vec![2, 0, 1, 0]));
+
+        // Act
+        let err = ExtSorter::sort(&mut arr, &mut buf, |_| {
+            Ok(ExtArr::new(Cursor::new(Vec::new())))
+        })
+        .unwrap_err();
+
+        // Assert
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn parallel_sort_undersized_buffer() {
+        // Arrange
+        let buf = Box::leak(Box::new([0u8; 6]));
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(vec![2, 0, 1, 0]));
+
+        // Act
+        let err = ExtSorter::parallel_sort(
+            &mut arr,
+            buf,
+            |_| Ok(ExtArr::new(Cursor::new(Vec::new()))),
+            NonZero::new(4).unwrap(),
+        )
+        .unwrap_err();
+
+        // Assert
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rayon_sort_undersized_buffer() {
+        // Arrange
+        let mut buf = [0u8; 6];
+        let mut sorter = RayonExtSorter::new(&mut buf, NonZero::new(4).unwrap());
+        let mut arr = ExtArr::<u16, _>::new(Cursor::new(vec![2, 0, 1, 0]));
+
+        // Act
+        let err = sorter
+            .sort(&mut arr, |_| Ok(ExtArr::new(Cursor::new(Vec::new()))))
+            .unwrap_err();
+        let linear_err = sorter
+            .sort_with_linear_merge(&mut arr, |_| Ok(ExtArr::new(Cursor::new(Vec::new()))))
+            .unwrap_err();
+
+        // Assert
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(linear_err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
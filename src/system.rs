@@ -1,20 +1,26 @@
-use std::io::{Cursor, Seek};
-use std::path::PathBuf;
+use std::ffi::{OsStr, OsString};
+use std::io::{Cursor, Read, Seek, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use anyhow::Result;
+use serde::{Deserialize, Serialize, Serializer};
 use tabled::Tabled;
 use thiserror::Error;
 
 use crate::complete_command::{
-    CatCommand, ChangeDirCommand, ExitCommand, HeadCommand, ListCommand, MakeDirCommand,
-    MoveCommand, RemoveCommand, SortCommand, TouchCommand,
+    CatCommand, ChangeDirCommand, CopyCommand, DebugCommand, DiffCommand, DiskFreeCommand,
+    EmptyTrashCommand, ExitCommand, FindCommand, HandlesCommand, HeadCommand, LinkCommand,
+    ListCommand, MakeDirCommand, MountCommand, MoveCommand, RemoveCommand, SortCommand,
+    StatCommand, TailCommand, TestCommand, TouchCommand, UseCommand, WordCountCommand,
 };
 use crate::error;
 use crate::ext_arr::ExtArr;
 use crate::fs::Filesystem;
+use crate::integers::{self, ConcatMode, Generator};
 use crate::mem::size::MB;
 use crate::mem::FixedSizeMem;
+use crate::progress::with_stderr_progress;
 use crate::sort::ExtSorter;
 use crate::vdisk::VDiskSize;
 
@@ -22,23 +28,95 @@ pub const DEFAULT_MEM_SIZE: usize = MB * 2;
 
 pub type Number = u16;
 
-#[derive(Debug, Clone, Eq, PartialEq, Tabled)]
+#[derive(Debug, Clone, Eq, PartialEq, Tabled, Serialize)]
 pub struct NodeInfo {
-    pub name: String,
+    /// Name as found on disk, which may not be valid UTF-8, rendered lossily when displayed
+    #[tabled(display = "display_lossy")]
+    #[serde(serialize_with = "serialize_lossy")]
+    pub name: OsString,
     #[tabled(skip)]
     pub size_in_bytes: VDiskSize,
     pub human_readable_size: String,
     pub is_dir: bool,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub struct ListCommandOutput {
     pub nodes: Vec<NodeInfo>,
-    pub total_disk_space_in_bytes: VDiskSize,
-    pub remaining_disk_space_in_bytes: VDiskSize,
+    pub total_disk_space_in_bytes: u64,
+    pub remaining_disk_space_in_bytes: u64,
+    /// True if the listed path is a file, in which case `nodes` only holds that file
+    #[serde(skip)]
+    pub is_single_file: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Tabled)]
+pub struct FileCount {
+    pub name: String,
+    pub integers: u64,
+    pub size_in_bytes: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WordCountCommandOutput {
+    pub counts: Vec<FileCount>,
+    pub total: FileCount,
+}
+
+/// The result of comparing two integer files
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DiffCommandOutput {
+    /// Both files hold the same integers
+    Identical,
+    /// The files first differ at `index`, a missing value means that file ends before it
+    Differ {
+        index: u64,
+        left: Option<Number>,
+        right: Option<Number>,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct DiskFreeCommandOutput {
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+    pub total_inodes: u64,
+    pub free_inodes: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct NodeStat {
+    pub name: String,
+    pub size_in_bytes: u64,
+    pub blocks: u64,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub modified_at: i64,
+    pub is_dir: bool,
 }
 
-#[derive(Debug, Error, Clone, Eq, PartialEq)]
+/// An open file handle as listed for debugging, with how many handles are open on its inode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tabled)]
+pub struct OpenHandle {
+    pub fh: u64,
+    pub ino: u64,
+    #[tabled(display = "display_open_flags")]
+    pub flags: i32,
+    pub read: bool,
+    pub write: bool,
+    pub open_count: usize,
+}
+
+fn display_open_flags(flags: &i32) -> String {
+    format!("{flags:#o}")
+}
+
+/// Result of every [`System`] command, whichever backend runs it
+pub type SystemResult<T> = Result<T, SystemError>;
+
+#[derive(Debug, Error)]
 pub enum SystemError {
     #[error("No such file or directory")]
     NoSuchFileOrDirectory,
@@ -54,6 +132,139 @@ pub enum SystemError {
     StartGreaterThanEnd,
     #[error("End greater than file size")]
     EndGreaterThanFileSize,
+    #[error("Files have mixed element widths")]
+    MixedElementWidths,
+    #[error("Files have elements of {0} bytes instead of integers")]
+    NotIntegerElements(u64),
+    #[error("File is not sorted, the element at index {0} is out of order")]
+    NotSorted(u64),
+    #[error("File is already in the trash")]
+    AlreadyInTrash,
+    #[error("Mount point {0} is not a directory")]
+    MountPointNotDirectory(PathBuf),
+    #[error("Mount point {0} is not empty")]
+    MountPointNotEmpty(PathBuf),
+    #[error("No mount named {0}")]
+    UnknownMount(String),
+    #[error("{0} is not supported by this system")]
+    Unsupported(&'static str),
+    #[error("A mount named {0} already exists")]
+    MountAlreadyExists(String),
+    #[error("File size of {0} bytes is not a whole number of elements")]
+    PartialElement(u64),
+    #[error("Failed to create {}", .0.join(", "))]
+    FilesNotCreated(Vec<String>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to encode or decode data: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Failed to encode JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Value out of range: {0}")]
+    OutOfRange(#[from] std::num::TryFromIntError),
+}
+
+impl From<nix::errno::Errno> for SystemError {
+    fn from(errno: nix::errno::Errno) -> Self {
+        Self::Io(errno.into())
+    }
+}
+
+fn display_lossy(name: &OsStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+fn serialize_lossy<S: Serializer>(name: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&name.to_string_lossy())
+}
+
+/// Prefix of a path addressing a specific mount, as in `@name:/path`
+pub const MOUNT_PATH_PREFIX: char = '@';
+
+/// Splits a path addressing a specific mount, `@name:/path`, into the name of the mount and the
+/// path inside it. Returns `None` for any other path
+pub fn split_mount_path(path: &Path) -> Option<(&str, &Path)> {
+    let bytes = path.as_os_str().as_bytes();
+    let rest = bytes.strip_prefix(&[MOUNT_PATH_PREFIX as u8])?;
+    let separator = rest.iter().position(|c| *c == b':')?;
+    let name = std::str::from_utf8(&rest[..separator]).ok()?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+
+    Some((name, Path::new(OsStr::from_bytes(&rest[separator + 1..]))))
+}
+
+/// Leading component of a path standing for the home directory, as in `~/path`
+pub const HOME_PATH_PREFIX: char = '~';
+
+/// Replaces the leading `~` component of a path with `home`. Returns `None` for any other path,
+/// `~user` included
+pub fn expand_home(path: &Path, home: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(HOME_PATH_PREFIX.to_string()).ok()?;
+    if rest.as_os_str().is_empty() {
+        return Some(home.to_path_buf());
+    }
+
+    Some(home.join(rest))
+}
+
+/// File name standing for the standard input when read from and the standard output when
+/// written to
+pub const STDIO_PATH: &str = "-";
+
+/// Whether the file stands for the standard streams, see [`STDIO_PATH`]
+pub fn is_stdio(file: impl AsRef<OsStr>) -> bool {
+    file.as_ref() == STDIO_PATH
+}
+
+/// The streams a system reads and writes in place of the [`STDIO_PATH`] file
+pub struct Stdio {
+    pub input: Box<dyn Read + Send>,
+    pub output: Box<dyn Write + Send>,
+}
+
+impl Stdio {
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+}
+
+impl Default for Stdio {
+    fn default() -> Self {
+        Self::new(std::io::stdin(), std::io::stdout())
+    }
+}
+
+impl std::fmt::Debug for Stdio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stdio").finish_non_exhaustive()
+    }
+}
+
+/// Runs `op` on each file in turn. The first failure is returned as is, unless `keep_going` is
+/// set, then every file is tried and the failures are reported together as
+/// [`SystemError::FilesNotCreated`]
+pub fn for_each_file<F>(files: &[OsString], keep_going: bool, mut op: F) -> SystemResult<()>
+where
+    F: FnMut(&Path) -> SystemResult<()>,
+{
+    let mut failures = Vec::new();
+    for file in files.iter().map(Path::new) {
+        match op(file) {
+            Ok(()) => {}
+            Err(e) if keep_going => failures.push(format!("{}: {e}", file.display())),
+            Err(e) => return Err(e),
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(SystemError::FilesNotCreated(failures));
+    }
+    Ok(())
 }
 
 /// A system that can execute commands
@@ -61,25 +272,96 @@ pub enum SystemError {
 /// This trait is used to define the interface for a system that can execute commands.
 pub trait System {
     /// Create a new file
-    fn touch(&mut self, cmd: &TouchCommand) -> Result<()>;
+    fn touch(&mut self, cmd: &TouchCommand) -> SystemResult<()>;
     /// Move a file from one location to another
-    fn mv(&mut self, cmd: &MoveCommand) -> Result<()>;
-    /// Create a new directory
-    fn make_dir(&mut self, cmd: &MakeDirCommand) -> Result<()>;
-    /// Remove a file from the system
-    fn remove(&mut self, cmd: &RemoveCommand) -> Result<()>;
-    /// Read the first `n` lines of a file
-    fn head(&self, cmd: &HeadCommand) -> Result<Vec<Number>>;
+    fn mv(&mut self, cmd: &MoveCommand) -> SystemResult<()>;
+    /// Copy a file to another location, replacing the destination if it exists
+    fn copy(&mut self, _cmd: &CopyCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Copying a file"))
+    }
+    /// Create a hard link named `link_name` to the `target` file
+    fn link(&mut self, _cmd: &LinkCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Hard linking a file"))
+    }
+    /// Create a symbolic link named `link_name` pointing to `target`
+    fn symlink(&mut self, _cmd: &LinkCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Symbolic linking a file"))
+    }
+    /// Create a directory and return every directory created, missing parents included
+    fn make_dir(&mut self, cmd: &MakeDirCommand) -> SystemResult<Vec<PathBuf>>;
+    /// Remove a file from the system and return every removed file and directory
+    fn remove(&mut self, cmd: &RemoveCommand) -> SystemResult<Vec<PathBuf>>;
+    /// Count the files and directories that `remove` would remove, without removing them
+    fn count_removed(&self, _cmd: &RemoveCommand) -> SystemResult<usize> {
+        Err(SystemError::Unsupported("Counting the removed entries"))
+    }
+    /// Permanently remove the contents of the trash and return every removed file and directory
+    fn empty_trash(&mut self, cmd: &EmptyTrashCommand) -> SystemResult<Vec<PathBuf>>;
+    /// Read the first `n` lines of a file, [`STDIO_PATH`] reads the standard input
+    fn head(&self, cmd: &HeadCommand) -> SystemResult<Vec<Number>>;
+    /// Read the last `n` lines of a file
+    fn tail(&self, cmd: &TailCommand) -> SystemResult<Vec<Number>>;
+    /// Follow the end of a file, calling `on_append` with its last `n` lines and then with the
+    /// values appended to it, until `on_append` returns false or Ctrl-C is pressed
+    fn follow(
+        &self,
+        _cmd: &TailCommand,
+        _on_append: &mut dyn FnMut(&[Number]) -> bool,
+    ) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Following a file"))
+    }
+    /// Read the first `--bytes` bytes of a file as they are stored, header included
+    fn head_bytes(&self, cmd: &HeadCommand) -> SystemResult<Vec<u8>>;
+    /// Read the last `--bytes` bytes of a file as they are stored
+    fn tail_bytes(&self, cmd: &TailCommand) -> SystemResult<Vec<u8>>;
     /// List the contents of a directory
-    fn list(&self, cmd: &ListCommand) -> Result<ListCommandOutput>;
-    /// Sort the file and return the sorted file
-    fn sort(&self, cmd: &SortCommand) -> Result<()>;
-    /// Concatenate files together and returns the file that the content is concatenad
-    fn cat(&self, cmd: &CatCommand) -> Result<PathBuf>;
+    fn list(&self, cmd: &ListCommand) -> SystemResult<ListCommandOutput>;
+    /// Sort the file, or with `check` only verify it is sorted failing with
+    /// [`SystemError::NotSorted`] otherwise. [`STDIO_PATH`] sorts the standard input into the
+    /// standard output
+    fn sort(&self, cmd: &SortCommand) -> SystemResult<()>;
+    /// Concatenate files together and returns the file that the content is concatenad.
+    /// [`STDIO_PATH`] reads the standard input, or writes to the standard output as the output
+    /// file or when the first file is the standard input and no output file is given
+    fn cat(&mut self, cmd: &CatCommand) -> SystemResult<PathBuf>;
+    /// Display the status of a file or directory, formatted by the command format or as JSON
+    fn stat(&self, cmd: &StatCommand) -> SystemResult<String>;
+    /// Check that a path exists, is a file or is a directory, as the command asks
+    fn test(&self, _cmd: &TestCommand) -> SystemResult<bool> {
+        Err(SystemError::Unsupported("Testing a path"))
+    }
+    /// Recursively search a directory for entries matching a name
+    fn find(&self, cmd: &FindCommand) -> SystemResult<Vec<PathBuf>>;
+    /// Expand a glob pattern with `*`, `?` and `**` into every matching path, sorted
+    fn glob(&self, pattern: &Path) -> SystemResult<Vec<PathBuf>>;
+    /// Compare two integer files element by element, stopping at the first difference
+    fn diff(&self, cmd: &DiffCommand) -> SystemResult<DiffCommandOutput>;
+    /// Count the integers of each file and their total
+    fn wc(&self, cmd: &WordCountCommand) -> SystemResult<WordCountCommandOutput>;
+    /// Report the disk space usage of the system
+    fn df(&self, cmd: &DiskFreeCommand) -> SystemResult<DiskFreeCommandOutput>;
     /// Exit the system with the given exit code
-    fn exit(&self, cmd: &ExitCommand) -> Result<()>;
-    fn chdir(&self, cmd: &ChangeDirCommand) -> Result<()> {
-        todo!()
+    fn exit(&self, cmd: &ExitCommand) -> SystemResult<()>;
+    /// Check that a directory exists to make it the working directory
+    fn chdir(&self, _cmd: &ChangeDirCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Changing the working directory"))
+    }
+    /// Make the mount with the given name the one paths without a mount name refer to
+    fn use_mount(&mut self, cmd: &UseCommand) -> SystemResult<()> {
+        Err(SystemError::UnknownMount(cmd.name.clone()))
+    }
+    /// Register the directory a file system is mounted at under a name, for `use` and `@name:`
+    /// paths
+    fn mount(&mut self, _cmd: &MountCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Registering a mount"))
+    }
+    /// List the open file handles of the mounted file system
+    fn handles(&self, _cmd: &HandlesCommand) -> SystemResult<Vec<OpenHandle>> {
+        Err(SystemError::Unsupported("Listing open file handles"))
+    }
+    /// Print the inode of a file or the superblock of the mounted file system
+    fn debug(&self, _cmd: &DebugCommand) -> SystemResult<String> {
+        Err(SystemError::Unsupported("Inspecting the file system"))
     }
 }
 
@@ -87,7 +369,6 @@ pub struct BasicSystem<F>
 where
     F: Filesystem,
 {
-    #[allow(dead_code)]
     file_system: F,
 }
 
@@ -98,34 +379,79 @@ where
     pub fn new(file_system: F) -> Self {
         Self { file_system }
     }
+
+    fn read_file(&self, file: &OsStr) -> SystemResult<Vec<u8>> {
+        let path = Path::new(file);
+        if !self.file_system.exists(path) {
+            return Err(SystemError::NoSuchFileOrDirectory);
+        }
+
+        Ok(self.file_system.read(path)?)
+    }
 }
 
 impl<F: Filesystem> System for BasicSystem<F> {
-    fn touch(&mut self, cmd: &TouchCommand) -> Result<()> {
-        todo!()
+    fn touch(&mut self, cmd: &TouchCommand) -> SystemResult<()> {
+        for_each_file(&cmd.files, cmd.keep_going, |path| {
+            if self.file_system.exists(path) {
+                return Err(SystemError::FileAlreadyExists);
+            }
+
+            let mut data = Cursor::new(Vec::new());
+            with_stderr_progress(cmd.progress, |on_progress| {
+                integers::write_random(
+                    &mut data,
+                    cmd.number_of_integers,
+                    Generator::from(cmd),
+                    cmd.raw,
+                    on_progress,
+                )
+            })?;
+
+            Ok(self.file_system.write(path, &data.into_inner())?)
+        })
+    }
+
+    fn mv(&mut self, _cmd: &MoveCommand) -> SystemResult<()> {
+        Err(SystemError::Unsupported("Moving a file"))
+    }
+
+    fn make_dir(&mut self, _cmd: &MakeDirCommand) -> SystemResult<Vec<PathBuf>> {
+        Err(SystemError::Unsupported("Creating a directory"))
+    }
+
+    fn remove(&mut self, _cmd: &RemoveCommand) -> SystemResult<Vec<PathBuf>> {
+        Err(SystemError::Unsupported("Removing a file"))
     }
 
-    fn mv(&mut self, cmd: &MoveCommand) -> Result<()> {
-        todo!()
+    fn empty_trash(&mut self, _cmd: &EmptyTrashCommand) -> SystemResult<Vec<PathBuf>> {
+        Err(SystemError::Unsupported("Emptying the trash"))
     }
 
-    fn make_dir(&mut self, cmd: &MakeDirCommand) -> Result<()> {
-        todo!()
+    fn head(&self, cmd: &HeadCommand) -> SystemResult<Vec<Number>> {
+        let data = Cursor::new(self.read_file(&cmd.file)?);
+        integers::head(data, cmd.start, cmd.end, cmd.raw)
     }
 
-    fn remove(&mut self, cmd: &RemoveCommand) -> Result<()> {
-        todo!()
+    fn tail(&self, _cmd: &TailCommand) -> SystemResult<Vec<Number>> {
+        Err(SystemError::Unsupported("Reading the end of a file"))
     }
 
-    fn head(&self, cmd: &HeadCommand) -> Result<Vec<Number>> {
-        todo!()
+    fn head_bytes(&self, cmd: &HeadCommand) -> SystemResult<Vec<u8>> {
+        let data = Cursor::new(self.read_file(&cmd.file)?);
+        integers::read_bytes(data, cmd.bytes.unwrap_or_default(), false)
     }
 
-    fn list(&self, cmd: &ListCommand) -> Result<ListCommandOutput> {
-        todo!()
+    fn tail_bytes(&self, cmd: &TailCommand) -> SystemResult<Vec<u8>> {
+        let data = Cursor::new(self.read_file(&cmd.file)?);
+        integers::read_bytes(data, cmd.bytes.unwrap_or_default(), true)
     }
 
-    fn sort(&self, cmd: &SortCommand) -> Result<()> {
+    fn list(&self, _cmd: &ListCommand) -> SystemResult<ListCommandOutput> {
+        Err(SystemError::Unsupported("Listing a directory"))
+    }
+
+    fn sort(&self, cmd: &SortCommand) -> SystemResult<()> {
         let mut mem = FixedSizeMem::<DEFAULT_MEM_SIZE>::new();
         let mut arr = ExtArr::<Number, _>::new(Cursor::new(Vec::new()));
 
@@ -143,13 +469,90 @@ impl<F: Filesystem> System for BasicSystem<F> {
         Ok(())
     }
 
-    fn cat(&self, cmd: &CatCommand) -> Result<PathBuf> {
-        todo!()
+    fn cat(&mut self, cmd: &CatCommand) -> SystemResult<PathBuf> {
+        if cmd.files.len() < 2 {
+            return Err(SystemError::TooLittleFiles);
+        }
+
+        let mut sources = Vec::with_capacity(cmd.files.len());
+        for file in &cmd.files {
+            let path = Path::new(file);
+            if !self.file_system.exists(path) {
+                return Err(SystemError::NoSuchFileOrDirectory);
+            }
+
+            sources.push(Cursor::new(self.file_system.read(path)?));
+        }
+
+        let new_file_path = match &cmd.output_file {
+            Some(output_file) => PathBuf::from(output_file),
+            None => {
+                let first_file = Path::new(&cmd.files[0]);
+                first_file.with_file_name(integers::concat_file_name(first_file))
+            }
+        };
+
+        if cmd.append && self.file_system.exists(&new_file_path) {
+            sources.insert(0, Cursor::new(self.file_system.read(&new_file_path)?));
+        }
+
+        let mode = if cmd.interleave {
+            ConcatMode::Interleave
+        } else if cmd.sorted_merge {
+            ConcatMode::SortedMerge {
+                reverse: cmd.reverse,
+            }
+        } else {
+            ConcatMode::Append
+        };
+
+        let mut data = Vec::new();
+        with_stderr_progress(cmd.progress, |on_progress| {
+            integers::concat(sources, &mut data, mode, cmd.raw, on_progress)
+        })?;
+        self.file_system.write(&new_file_path, &data)?;
+
+        Ok(new_file_path)
+    }
+
+    fn stat(&self, _cmd: &StatCommand) -> SystemResult<String> {
+        Err(SystemError::Unsupported("Displaying the status of a file"))
+    }
+
+    fn find(&self, _cmd: &FindCommand) -> SystemResult<Vec<PathBuf>> {
+        Err(SystemError::Unsupported("Searching a directory"))
+    }
+
+    fn glob(&self, _pattern: &Path) -> SystemResult<Vec<PathBuf>> {
+        Err(SystemError::Unsupported("Expanding a glob pattern"))
+    }
+
+    fn diff(&self, _cmd: &DiffCommand) -> SystemResult<DiffCommandOutput> {
+        Err(SystemError::Unsupported("Comparing two files"))
+    }
+
+    fn wc(&self, _cmd: &WordCountCommand) -> SystemResult<WordCountCommandOutput> {
+        Err(SystemError::Unsupported("Counting the integers of a file"))
+    }
+
+    fn df(&self, _cmd: &DiskFreeCommand) -> SystemResult<DiskFreeCommandOutput> {
+        Err(SystemError::Unsupported("Reporting the disk usage"))
     }
 
-    fn exit(&self, cmd: &ExitCommand) -> Result<()> {
+    fn exit(&self, cmd: &ExitCommand) -> SystemResult<()> {
         exit(cmd.code);
     }
+
+    fn chdir(&self, cmd: &ChangeDirCommand) -> SystemResult<()> {
+        let path = cmd.path.as_ref().map(PathBuf::from);
+        let path = path.unwrap_or_else(|| PathBuf::from("/"));
+
+        if !self.file_system.is_dir(&path) {
+            return Err(SystemError::DirectoryNotFound);
+        }
+
+        Ok(())
+    }
 }
 
 impl<F: Filesystem + Clone> Clone for BasicSystem<F> {
@@ -159,3 +562,135 @@ impl<F: Filesystem + Clone> Clone for BasicSystem<F> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::complete_command::{Distribution, NumberFormat, DEFAULT_STD_DEV};
+    use crate::fs::BasicFS;
+    use crate::vdisk::VDisk;
+
+    #[test]
+    fn split_mount_paths() {
+        // Arrange
+        let inputs = ["@a:/b/c", "@a:", "@:/b", "/@a:/b", "@a/b:/c", "a:/b"];
+        let outputs = [
+            Some(("a", Path::new("/b/c"))),
+            Some(("a", Path::new(""))),
+            None,
+            None,
+            None,
+            None,
+        ];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = split_mount_path(Path::new(input));
+
+            // Assert
+            assert_eq!(result, *output, "{input}");
+        }
+    }
+
+    #[test]
+    fn expand_home_paths() {
+        // Arrange
+        let inputs = ["~", "~/a/b", "~user/a", "/~/a", "a~"];
+        let outputs = [Some("/home"), Some("/home/a/b"), None, None, None];
+
+        for (input, output) in inputs.iter().zip(outputs.iter()) {
+            // Act
+            let result = expand_home(Path::new(input), Path::new("/home"));
+
+            // Assert
+            assert_eq!(result, output.map(PathBuf::from), "{input}");
+        }
+    }
+
+    #[test]
+    fn basic_system_touch_head_cat() -> anyhow::Result<()> {
+        // Arrange
+        let dir = tempdir()?;
+        let vdisk = VDisk::new(dir.path().join("disk.vd"), 1024 * 1024)?;
+        let mut system = BasicSystem::new(BasicFS::new(vdisk)?);
+        system.touch(&TouchCommand {
+            files: vec!["/a".into(), "/b".into()],
+            number_of_integers: 5,
+            progress: false,
+            keep_going: false,
+            raw: false,
+            dist: Distribution::Uniform,
+            std_dev: DEFAULT_STD_DEV,
+        })?;
+
+        // Act
+        let output = system.cat(&CatCommand {
+            files: vec!["/a".into(), "/b".into()],
+            output_file: None,
+            interleave: false,
+            sorted_merge: false,
+            reverse: false,
+            progress: false,
+            raw: false,
+            append: false,
+        })?;
+        let a = system.head(&HeadCommand {
+            file: "/a".into(),
+            start: 0,
+            end: 5,
+            bytes: None,
+            hex: false,
+            format: NumberFormat::Dec,
+            raw: false,
+        })?;
+        let concatenated = system.head(&HeadCommand {
+            file: output.clone().into(),
+            start: 0,
+            end: 10,
+            bytes: None,
+            hex: false,
+            format: NumberFormat::Dec,
+            raw: false,
+        })?;
+
+        // Assert
+        assert_eq!(output, PathBuf::from("/a.txt"));
+        assert_eq!(a.len(), 5);
+        assert_eq!(concatenated[..5], a[..]);
+        assert_eq!(concatenated.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn basic_fs_reopens_files() -> anyhow::Result<()> {
+        // Arrange
+        let dir = tempdir()?;
+        let path = dir.path().join("disk.vd");
+        let mut fs = BasicFS::new(VDisk::new(path.clone(), 1024 * 1024)?)?;
+        fs.write(Path::new("/d/a"), b"first")?;
+        fs.write(Path::new("/d/b"), b"second")?;
+        fs.write(Path::new("/d/a"), b"replaced")?;
+        drop(fs);
+
+        // Act
+        let system = BasicSystem::new(BasicFS::new(VDisk::new(path, 1024 * 1024)?)?);
+        let a = system.file_system.read(Path::new("/d/a"))?;
+        let b = system.file_system.read(Path::new("/d/b"))?;
+        let chdir = |dir: &str| {
+            system.chdir(&ChangeDirCommand {
+                path: Some(dir.into()),
+            })
+        };
+
+        // Assert
+        assert_eq!(a, b"replaced");
+        assert_eq!(b, b"second");
+        assert!(chdir("/").is_ok());
+        assert!(chdir("/d").is_ok());
+        assert!(matches!(chdir("/d/a"), Err(SystemError::DirectoryNotFound)));
+        assert!(matches!(chdir("/e"), Err(SystemError::DirectoryNotFound)));
+        Ok(())
+    }
+}
@@ -39,11 +39,7 @@ impl VDisk {
     }
 
     fn open(path: PathBuf) -> VDiskResult<VDisk> {
-        let disk = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .truncate(true)
-            .open(&path)?;
+        let disk = OpenOptions::new().read(true).write(true).open(&path)?;
 
         let metadata = disk.metadata()?;
         let size = metadata.size().try_into().expect("expected to get size");